@@ -1,206 +1,7018 @@
 //! Off-chain order book implementation for Octavium DSE
 //! This module handles order matching and management outside the blockchain
 //! for improved performance and reduced on-chain load.
+//!
+//! Methods that act on one side of the book take a `Side` (also exported as
+//! `OrderSide`), so `book.cancel_order(42, Side::Bid)` reads unambiguously where
+//! `book.cancel_order(42, true)` does not. Bools still convert through `From<bool>`,
+//! with `true` meaning `Side::Bid`, for existing callers. Crates that want to keep
+//! raw bools out of their own APIs can enable
+//! `#![warn(clippy::fn_params_excessive_bools)]`.
+//!
+//! # Stability
+//!
+//! Every public enum is `#[non_exhaustive]`, so new variants may be added in a minor
+//! release and matches outside this crate need a wildcard arm. `PlaceError`,
+//! `StopReason` and `CancelResult` also carry a hidden `_Unknown` variant for
+//! consumers that cannot write a wildcard, such as generated bindings; the book never
+//! returns it.
 
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::{self, Debug, Display};
+use std::io::Write;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/// Maximum number of fills that can be processed in a single matching operation
-const MAX_FILLS: usize = 100;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "tokio")]
+use tokio::sync::broadcast;
+
+/// Default maximum number of fills that can be processed in a single matching operation
+const DEFAULT_MAX_FILLS_PER_MATCH: usize = 100;
 /// Minimum price increment for orders
 const TICK_SIZE: u64 = 1;
 /// Minimum quantity increment for orders
 const LOT_SIZE: u64 = 1;
-/// Minimum order size allowed
+/// Default quantity step that orders and fills at a price must be multiples of
+const MIN_QTY_INCREMENT: u64 = 1;
+/// Minimum order size allowed, and the default minimum for a market
 const MIN_SIZE: u64 = 1;
+/// Scaling factor applied to prices, mirroring `FLOAT_SCALING` in the on-chain math module
+const FLOAT_SCALING: u64 = 1_000_000_000;
+/// Denominator for values expressed in basis points
+const BPS_DENOMINATOR: u64 = 10_000;
+/// Nanoseconds in one second, the unit of all book timestamps
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+/// Low bits of a book key sequence holding the arrival counter
+const SEQUENCE_BITS: u32 = 56;
+/// Largest sequence that fits below the venue rank in a book key
+const MAX_SEQUENCE: u64 = (1 << SEQUENCE_BITS) - 1;
+/// Price level events buffered for each subscriber before the slowest starts lagging
+#[cfg(feature = "tokio")]
+const LEVEL_EVENT_CAPACITY: usize = 1_024;
+/// Window of recent fills used to measure realized volatility
+const REALIZED_VOL_WINDOW_NS: u64 = 30 * NANOS_PER_SECOND;
+/// Minimum number of distinct fill times at one price to consider an owner's fills tranches
+const INSTITUTIONAL_MIN_TRANCHES: usize = 4;
+/// Largest coefficient of variation of inter-fill intervals treated as a regular pattern
+const INSTITUTIONAL_MAX_INTERVAL_CV: f64 = 0.2;
+/// Weight of each of the five components of a quote quality score
+const QUOTE_QUALITY_COMPONENT_WEIGHT: f64 = 0.2;
+/// Standard deviations of inventory value reported as value at risk
+const INVENTORY_VAR_Z_SCORE: f64 = 2.33;
+/// Distance from the mid within which the microstructure report measures depth
+const MICROSTRUCTURE_DEPTH_BAND_BPS: u64 = 10;
+/// Number of most recent fills used for Amihud illiquidity
+const AMIHUD_WINDOW_FILLS: usize = 10;
+/// Number of fills after a trade at which realized spread and price impact are measured
+const REALIZED_SPREAD_HORIZON_FILLS: usize = 5;
+/// Number of equal-volume buckets the fill history is split into for VPIN
+const VPIN_BUCKETS: usize = 10;
+/// Default number of expired resting orders tolerated before `place_order` purges them
+const DEFAULT_GC_THRESHOLD: usize = 1_000;
+/// Default number of undrained events held for market-data consumers
+const DEFAULT_EVENT_BUFFER_CAPACITY: usize = 10_000;
+/// Default number of recent fills retained for price discovery
+const MAX_FILL_HISTORY: usize = 10_000;
+/// Default window of fill prices averaged by the TWAP oracle
+const DEFAULT_TWAP_WINDOW_SECS: u64 = 300;
+/// Window over which order submissions are counted for rate limiting
+const ORDER_RATE_WINDOW_NS: u64 = NANOS_PER_SECOND;
+/// Seed for the generator driving Monte Carlo scenarios, fixed for reproducible results
+const SCENARIO_SEED: u64 = 0x5EED;
+/// Number of orders cancelled between yields during an async cancel-all
+#[cfg(feature = "tokio")]
+const CANCEL_CHUNK_SIZE: usize = 1_000;
+/// Maximum number of completed order lifetimes retained for distribution stats
+const MAX_COMPLETED_LIFETIMES: usize = 10_000;
+/// Market maker risk aversion (gamma) used for spread recommendations, per basis point
+const SPREAD_RISK_AVERSION: f64 = 0.1;
+/// Decay of taker arrival intensity with quote distance (kappa), per basis point
+const SPREAD_ORDER_INTENSITY: f64 = 1.5;
+/// Quoting horizon (T) used for spread recommendations, in seconds
+const SPREAD_HORIZON_SECS: f64 = 1.0;
+/// Number of recorded fills at which a spread recommendation has full confidence
+const SPREAD_CONFIDENCE_FILLS: usize = 100;
+/// Number of best price levels watched for layering
+const LAYERING_DEPTH_LEVELS: usize = 5;
+/// Maximum number of large-order events retained per owner for layering detection
+const MAX_LAYERING_EVENTS: usize = 10_000;
+/// Maximum number of changes retained for incremental replication
+const MAX_CHANGE_LOG: usize = 10_000;
+/// Maximum number of settled orders remembered for reporting cancel failures
+const MAX_SETTLED_ORDERS: usize = 10_000;
+
+/// Fixed-size owner address, copied without allocating
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Address(pub [u8; 32]);
+
+/// Reasons a hex string is not a valid address
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AddressError {
+    /// More than 64 hex digits, or none at all
+    InvalidLength(usize),
+    /// A character that is not a hex digit
+    InvalidHexDigit(char),
+}
+
+impl Address {
+    /// Parses a SUI-style hex address, with or without a `0x` prefix
+    ///
+    /// Short addresses such as `0x2` are left-padded with zeros.
+    pub fn from_hex(s: &str) -> Result<Self, AddressError> {
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        if digits.is_empty() || digits.len() > 64 {
+            return Err(AddressError::InvalidLength(digits.len()));
+        }
+
+        let mut bytes = [0u8; 32];
+        for (i, digit) in digits.chars().rev().enumerate() {
+            let nibble = digit.to_digit(16).ok_or(AddressError::InvalidHexDigit(digit))? as u8;
+            bytes[31 - i / 2] |= nibble << (4 * (i % 2));
+        }
+        Ok(Address(bytes))
+    }
+
+    /// Formats the address as `0x` followed by 64 lowercase hex digits
+    pub fn to_hex(&self) -> String {
+        let mut hex = String::with_capacity(66);
+        hex.push_str("0x");
+        for byte in self.0 {
+            hex.push_str(&format!("{:02x}", byte));
+        }
+        hex
+    }
+
+    /// The UTF-8 name the address was created from, if it was created from one
+    fn as_name(&self) -> Option<&str> {
+        let len = self.0.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+        let name = std::str::from_utf8(&self.0[..len]).ok()?;
+        (!name.is_empty() && !name.chars().any(char::is_control)).then_some(name)
+    }
+}
+
+impl From<&str> for Address {
+    /// Zero-pads the UTF-8 bytes of `name`, truncating anything beyond 32 bytes
+    fn from(name: &str) -> Self {
+        let mut bytes = [0u8; 32];
+        let len = name.len().min(32);
+        bytes[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Address(bytes)
+    }
+}
+
+impl Display for Address {
+    /// Writes names created with `From<&str>` as text and anything else as hex
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.as_name() {
+            Some(name) => f.write_str(name),
+            None => f.write_str(&self.to_hex()),
+        }
+    }
+}
+
+/// Hex in human-readable formats, so addresses can key JSON maps, and raw bytes otherwise
+#[cfg(feature = "serde")]
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_hex())
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let hex = String::deserialize(deserializer)?;
+            Address::from_hex(&hex).map_err(|error| serde::de::Error::custom(format!("invalid address {hex}: {error:?}")))
+        } else {
+            <[u8; 32]>::deserialize(deserializer).map(Address)
+        }
+    }
+}
 
 /// Represents a single order in the order book
+///
+/// Orders are built with `Order::new_limit` or `Order::new_market` and read through
+/// accessors, so every order a caller submits has passed basic validation.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Order {
     /// Unique identifier for the order
-    order_id: u128,
+    pub(crate) order_id: u128,
     /// Price per unit of base asset
-    price: u64,
+    pub(crate) price: u64,
     /// Total quantity of base asset to trade
-    quantity: u64,
+    pub(crate) quantity: u64,
     /// Amount of base asset that has been filled
-    filled_quantity: u64,
+    pub(crate) filled_quantity: u64,
     /// Address of the order owner
-    owner: String,
+    pub(crate) owner: Address,
     /// Timestamp after which the order is considered expired
-    expire_timestamp: u64,
-    /// True for buy orders, false for sell orders
-    is_bid: bool,
+    pub(crate) expire_timestamp: u64,
+    /// Whether the order buys or sells
+    pub(crate) side: Side,
+    /// Timestamp at which the book accepted the order
+    pub(crate) submission_timestamp: u64,
+    /// Timestamp at which the order was cancelled, if it was
+    pub(crate) cancellation_timestamp: Option<u64>,
+    /// Priority of the originating venue, lower is better; breaks ties at the same price
+    pub(crate) venue_priority: u8,
+    /// How the order matches and whether any remainder rests
+    pub(crate) order_type: OrderType,
+    /// Self-trade prevention applied when this order is the taker
+    pub(crate) stp_mode: STPMode,
+    /// Maximum number of fills this order may take, overriding the book's limit
+    pub(crate) max_fills: Option<usize>,
+    /// Quantity shown at a time while resting, if this is an iceberg order
+    pub(crate) iceberg_peak: Option<u64>,
+}
+
+/// Reasons an order cannot be constructed
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OrderError {
+    /// The price is zero
+    ZeroPrice,
+    /// The quantity is below the minimum order size
+    QuantityBelowMinimum,
+    /// The expiry timestamp is zero
+    ZeroExpiry,
+}
+
+/// Execution instructions for an order
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum OrderType {
+    /// Matches up to its limit price and rests any remainder
+    #[default]
+    Limit,
+    /// Matches at any available price; the remainder is cancelled
+    Market,
+    /// Matches up to its limit price; the remainder is cancelled
+    ImmediateOrCancel,
+    /// Matches up to its limit price only if it can be filled completely
+    FillOrKill,
+    /// Waits off the book until the last trade reaches the trigger, then becomes a market order
+    Stop { trigger_price: u64 },
+    /// Waits off the book until the last trade reaches the trigger, then becomes a
+    /// limit order at `limit_price`
+    StopLimit { trigger_price: u64, limit_price: u64 },
+}
+
+/// What happens when an order would trade against a resting order from the same owner
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum STPMode {
+    /// The orders trade with each other
+    #[default]
+    None,
+    /// The resting order is cancelled and matching continues
+    CancelMaker,
+    /// The incoming order is cancelled and matching stops
+    CancelTaker,
+    /// Both orders are cancelled and matching stops
+    CancelBoth,
+}
+
+/// Key ordering resting orders by price, then venue rank and arrival within a price
+///
+/// The derived order is ascending on both fields. Asks are read from the lowest key,
+/// so earlier arrivals come first. Bids are read from the highest key and store their
+/// venue rank and arrival inverted in `sequence`, so they come out by descending price
+/// and then ascending arrival.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct PriceTimeKey {
+    price: u64,
+    /// Venue rank in the top eight bits above the arrival sequence
+    sequence: u64,
+}
+
+impl PriceTimeKey {
+    /// Every key at a price
+    fn level(price: u64) -> std::ops::RangeInclusive<PriceTimeKey> {
+        PriceTimeKey { price, sequence: 0 }..=PriceTimeKey { price, sequence: u64::MAX }
+    }
 }
 
 /// Central order book maintaining separate bid and ask sides
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Book {
     /// Bid orders sorted by price-time priority (highest price first)
-    bids: BTreeMap<u128, Order>,
+    #[cfg_attr(feature = "serde", serde(with = "key_value_pairs"))]
+    bids: BTreeMap<PriceTimeKey, Order>,
     /// Ask orders sorted by price-time priority (lowest price first)
-    asks: BTreeMap<u128, Order>,
-    /// Counter for generating unique bid order IDs (counting down)
-    next_bid_order_id: u64,
-    /// Counter for generating unique ask order IDs (counting up)
-    next_ask_order_id: u64,
+    #[cfg_attr(feature = "serde", serde(with = "key_value_pairs"))]
+    asks: BTreeMap<PriceTimeKey, Order>,
+    /// Arrival counter shared by both sides, incremented for every resting order
+    sequence_counter: u64,
+    /// Lowest ID above every order ID the book has accepted, whether resting, held
+    /// or settled
+    next_order_id: u128,
+    /// Maps each resting order ID to its price-time key in `bids` or `asks`
+    order_keys: HashMap<u128, PriceTimeKey>,
+    /// Resting orders of each owner as `(is_bid, order_id)`
+    owner_index: HashMap<Address, BTreeSet<(bool, u128)>>,
+    /// Per-owner maker fill statistics bucketed by distance from the mid price
+    maker_fill_stats: HashMap<Address, MakerFillStats>,
+    /// Market configuration for this book
+    params: MarketParams,
+    /// Number of fills where the taker traded at a better price than its limit
+    fills_with_improvement: u64,
+    /// Number of fills where the taker traded exactly at its limit
+    fills_without_improvement: u64,
+    /// Sum of per-unit price improvement across improved fills
+    total_improvement_units: u64,
+    /// Sum of quote value saved by takers across improved fills
+    total_improvement_value: u64,
+    /// Most recent fills, oldest first
+    fill_history: FillHistory,
+    /// Fill prices within the TWAP window
+    twap_oracle: TwapOracle,
+    /// Source of the current time
+    #[cfg_attr(feature = "serde", serde(skip, default = "system_clock"))]
+    clock: Box<dyn Clock>,
+    /// Recent submissions per owner as (timestamp, is_bid, price, quantity), oldest first
+    recent_submissions: HashMap<Address, VecDeque<(u64, bool, u64, u64)>>,
+    /// Lifetimes of recently completed orders in nanoseconds, oldest first
+    completed_order_lifetimes: VecDeque<u64>,
+    /// Number of fills since the book was created
+    total_fills: u64,
+    /// Lifetime counters for monitoring, never reset
+    stats: BookStats,
+    /// Session OHLCV summary, reset with the market
+    ticker: Ticker,
+    /// Maker fees charged this session, in base units
+    total_maker_fees: u64,
+    /// Taker fees charged this session, in quote units
+    total_taker_fees: u64,
+    /// Base quantity traded since the book was created
+    session_volume: u64,
+    /// Per-owner timestamps of large orders placed within the best levels, oldest first
+    large_order_placements: HashMap<Address, VecDeque<u64>>,
+    /// Per-owner timestamps at which those large orders were cancelled, oldest first
+    large_order_cancellations: HashMap<Address, VecDeque<u64>>,
+    /// IDs of resting large orders that were placed within the best levels
+    tracked_large_orders: HashSet<u128>,
+    /// External validation run on every order before it touches the book
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pre_trade_hooks: PreTradeHooks,
+    /// Realized volatility threshold in basis points and the spread multiplier applied above it
+    spread_widening: Option<(u64, f64)>,
+    /// Minimum distance between a new order's bid and ask equivalent around the mid,
+    /// set while volatility is above the widening threshold
+    dynamic_min_spread: Option<u64>,
+    /// Per-owner quoting activity over the session
+    quote_activity: HashMap<Address, QuoteActivity>,
+    /// Owners at the front of the best bid and best ask, with the time they got there
+    best_quote_owners: [Option<(Address, u64)>; 2],
+    /// Whether orders are matching continuously or being collected for an auction
+    mode: BookMode,
+    /// Orders collected during a volatility auction, in arrival order
+    auction_orders: Vec<Order>,
+    /// Base quantity each owner has bought and sold over the session
+    positions: HashMap<Address, OwnerPosition>,
+    /// Market resets applied to the book, oldest first
+    market_resets: Vec<MarketReset>,
+    /// Publishes price level changes to subscribers
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(feature = "serde", serde(skip, default = "level_event_channel"))]
+    level_events: broadcast::Sender<PriceLevelEvent>,
+    /// Last published total quantity of each level, keyed by side and price;
+    /// only maintained while there are subscribers
+    #[cfg_attr(feature = "serde", serde(skip))]
+    level_qty_cache: HashMap<(bool, u64), u64>,
+    /// Levels touched since changes were last published
+    #[cfg_attr(feature = "serde", serde(skip))]
+    dirty_levels: Vec<(bool, u64)>,
+    /// Top levels seen by the last order flow imbalance call, as
+    /// `(bid_price, bid_qty, ask_price, ask_qty)` per level
+    ofi_state: Vec<(u64, u64, u64, u64)>,
+    /// Recent changes to resting orders for replicas, oldest first
+    change_log: VecDeque<(u64, OrderBookChange)>,
+    /// Sequence number of the latest change
+    change_seq: u64,
+    /// How recently settled orders left the book
+    settled_orders: SettledOrders,
+    /// Stop orders waiting for their trigger, in placement order
+    stop_orders: Vec<Order>,
+    /// Orders from `conditional_place` waiting for their condition, in placement order
+    conditional_orders: Vec<(Order, PlaceCondition)>,
+    /// Resting orders with an expiry as `(expire_timestamp, order_id, is_bid)`; entries
+    /// for orders that have since left the book are dropped when next visited
+    expiry_queue: BTreeSet<(u64, u128, bool)>,
+    /// Number of expired resting orders above which `place_order` purges them all
+    gc_threshold: usize,
+    /// Maximum number of fills a taker may take unless the order sets its own limit
+    max_fills_per_match: usize,
+    /// Halts matching at prices too far from a reference, if installed
+    circuit_breaker: Option<CircuitBreaker>,
+    /// Audit log and market-data buffer of placements, cancellations, expiries and fills
+    events: EventSink,
+    /// Fills from `place_order` not yet drained, oldest first
+    pending_fills: VecDeque<Fill>,
 }
 
-/// Represents a match between two orders
-#[derive(Debug)]
-pub struct Fill {
-    /// Order ID of the maker (passive order)
-    maker_order_id: u128,
-    /// Order ID of the taker (aggressive order)
-    taker_order_id: u128,
-    /// Amount of base asset traded
-    base_quantity: u64,
-    /// Amount of quote asset traded (base_quantity * price)
-    quote_quantity: u64,
-    /// Timestamp when the fill occurred
-    timestamp: u64,
+/// Destinations for book events: an optional audit log, an optional sequenced stream
+/// and a bounded market-data buffer
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct EventSink {
+    /// Every event since recording was enabled
+    log: Option<Vec<BookEvent>>,
+    /// Every event since streaming was enabled, stamped with its sequence and batch
+    #[cfg(feature = "serde")]
+    stream: Option<Vec<SequencedEvent>>,
+    /// Events not yet drained, oldest first
+    buffer: VecDeque<BookEvent>,
+    /// Most events held in the buffer; zero disables buffering
+    buffer_capacity: usize,
+    /// Events evicted from a full buffer before being drained
+    dropped: u64,
+    /// Sequence number of the last event built
+    sequence: u64,
+    /// ID of the current batch of events
+    batch_id: u64,
 }
 
-impl Book {
-    /// Creates a new empty order book
-    pub fn new() -> Self {
-        Book {
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-            next_bid_order_id: u64::MAX, // Start from max for bids (counting down)
-            next_ask_order_id: 1,        // Start from 1 for asks (counting up)
+impl EventSink {
+    /// Creates a sink buffering up to `buffer_capacity` events, with the audit log and
+    /// stream off
+    fn new(buffer_capacity: usize) -> Self {
+        EventSink {
+            log: None,
+            #[cfg(feature = "serde")]
+            stream: None,
+            buffer: VecDeque::new(),
+            buffer_capacity,
+            dropped: 0,
+            sequence: 0,
+            batch_id: 0,
         }
     }
 
-    /// Attempts to match an incoming order against existing orders
-    /// Returns a vector of fills created during matching
+    /// Whether any destination keeps events
+    fn is_enabled(&self) -> bool {
+        #[cfg(feature = "serde")]
+        if self.stream.is_some() {
+            return true;
+        }
+        self.log.is_some() || self.buffer_capacity > 0
+    }
+
+    /// Starts a new batch, so the events recorded until the next one share an ID
+    fn begin_batch(&mut self) {
+        self.batch_id += 1;
+    }
+
+    /// Records an event, building it only if the log, stream or buffer will keep it
+    fn record(&mut self, event: impl FnOnce() -> BookEvent) {
+        if !self.is_enabled() {
+            return;
+        }
+        let event = event();
+        self.sequence += 1;
+        #[cfg(feature = "serde")]
+        if let Some(stream) = &mut self.stream {
+            stream.push(SequencedEvent { sequence_number: self.sequence, batch_id: self.batch_id, event: event.clone() });
+        }
+        if self.buffer_capacity > 0 {
+            if self.buffer.len() == self.buffer_capacity {
+                self.buffer.pop_front();
+                self.dropped += 1;
+            }
+            self.buffer.push_back(event.clone());
+        }
+        if let Some(log) = &mut self.log {
+            log.push(event);
+        }
+    }
+}
+
+/// Quoting activity of one owner over the session, used to rank market makers
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct QuoteActivity {
+    /// Orders accepted by `place_order`
+    orders_submitted: u64,
+    /// Orders that came to rest in the book
+    quotes_posted: u64,
+    /// Resting orders the owner cancelled
+    quotes_cancelled: u64,
+    /// Fills received as maker
+    maker_fills: u64,
+    /// Orders currently resting in the book
+    resting_orders: usize,
+    /// Start of the current period with at least one resting order
+    quoting_since: Option<u64>,
+    /// Completed time with at least one resting order, in nanoseconds
+    uptime_ns: u64,
+    /// Completed time at the front of the best bid or ask, in nanoseconds
+    time_at_best_ns: u64,
+}
+
+impl QuoteActivity {
+    /// Counts a newly resting order, starting an uptime period if none was running
+    fn add_resting(&mut self, now: u64) {
+        if self.resting_orders == 0 {
+            self.quoting_since = Some(now);
+        }
+        self.resting_orders += 1;
+    }
+
+    /// Counts an order leaving the book, closing the uptime period once none remain
+    fn remove_resting(&mut self, now: u64) {
+        self.resting_orders = self.resting_orders.saturating_sub(1);
+        if self.resting_orders == 0 {
+            if let Some(since) = self.quoting_since.take() {
+                self.uptime_ns += now.saturating_sub(since);
+            }
+        }
+    }
+}
+
+/// Configuration of the market traded on a book
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MarketParams {
+    /// Whether to track price improvement received by takers
+    pub price_improvement: bool,
+    /// Whether `place_order` runs regulatory pre-trade checks
+    pub regulatory_mode: bool,
+    /// Limits applied by regulatory pre-trade checks
+    pub regulatory_risk: RegulatoryRiskParams,
+    /// Minimum quantity for a resting order to count as large in layering detection;
+    /// `u64::MAX` disables tracking
+    pub large_order_quantity: u64,
+    /// Minimum quoted spread in price units, widened during high volatility
+    pub min_spread: u64,
+    /// Time matching is suspended for once a volatility auction is triggered
+    pub auction_duration_ns: u64,
+    /// Quantity increment that order quantities must be a multiple of
+    pub lot_size: u64,
+    /// Quantity step that orders and the fills between them must be multiples of
+    pub min_qty_increment: u64,
+    /// Smallest quantity an order may be placed or amended to
+    pub min_size: u64,
+    /// Self-trade prevention applied to orders that do not choose a mode
+    pub default_stp_mode: STPMode,
+    /// Price increment that order prices must be a multiple of
+    pub tick_size: u64,
+    /// Highest price an order may be placed at
+    pub max_price: u64,
+    /// Fees charged to makers and takers
+    pub fee_schedule: FeeSchedule,
+    /// Number of most recent fills retained for analytics, fixed when the book is created
+    pub max_fill_history: usize,
+    /// Window of fill prices averaged by the TWAP oracle, fixed when the book is created
+    pub twap_window_secs: u64,
+}
+
+impl Default for MarketParams {
+    fn default() -> Self {
+        MarketParams {
+            price_improvement: false,
+            regulatory_mode: false,
+            regulatory_risk: RegulatoryRiskParams::default(),
+            large_order_quantity: u64::MAX,
+            min_spread: 0,
+            auction_duration_ns: 5 * NANOS_PER_SECOND,
+            lot_size: LOT_SIZE,
+            min_qty_increment: MIN_QTY_INCREMENT,
+            min_size: MIN_SIZE,
+            default_stp_mode: STPMode::None,
+            tick_size: TICK_SIZE,
+            max_price: u64::MAX,
+            fee_schedule: FeeSchedule::default(),
+            max_fill_history: MAX_FILL_HISTORY,
+            twap_window_secs: DEFAULT_TWAP_WINDOW_SECS,
+        }
+    }
+}
+
+impl MarketParams {
+    /// Checks that the tick, lot and minimum sizes and the quantity increment are
+    /// non-zero
+    fn validate(&self) -> Result<(), BuilderError> {
+        if self.tick_size == 0 {
+            return Err(BuilderError::ZeroTickSize);
+        }
+        if self.lot_size == 0 {
+            return Err(BuilderError::ZeroLotSize);
+        }
+        if self.min_size == 0 {
+            return Err(BuilderError::ZeroMinSize);
+        }
+        if self.min_qty_increment == 0 {
+            return Err(BuilderError::ZeroQtyIncrement);
+        }
+        Ok(())
+    }
+
+    /// Moves an order's limit price onto the tick, bids down and asks up, for a market
+    /// reset
     ///
-    /// # Arguments
-    /// * `taker_order` - The incoming order to match
-    /// * `timestamp` - Current timestamp for order expiration checks
-    pub fn match_order(&mut self, mut taker_order: Order, timestamp: u64) -> Vec<Fill> {
-        let mut fills = Vec::new();
-        
-        // Get the appropriate order book side
-        let book_side = if taker_order.is_bid {
-            &mut self.asks // Match bids against asks
+    /// Returns whether the price moved, or `None` if no valid price remains within
+    /// `max_price` or the quantity or iceberg peak breaks the size limits
+    fn revalidate(&self, order: &mut Order) -> Option<bool> {
+        let aligned = |quantity: u64| quantity.is_multiple_of(self.lot_size) && quantity.is_multiple_of(self.min_qty_increment);
+        let valid_quantity = order.quantity >= self.min_size
+            && aligned(order.quantity)
+            && order.remaining_quantity().is_multiple_of(self.min_qty_increment)
+            && order.iceberg_peak.is_none_or(aligned);
+        if !valid_quantity {
+            return None;
+        }
+
+        // Market and stop orders carry a sentinel price rather than a limit
+        let is_bid = order.is_bid();
+        let limit_price = match &mut order.order_type {
+            OrderType::StopLimit { limit_price, .. } => limit_price,
+            OrderType::Market | OrderType::Stop { .. } => return Some(false),
+            OrderType::Limit | OrderType::ImmediateOrCancel | OrderType::FillOrKill => &mut order.price,
+        };
+        let price = if is_bid {
+            *limit_price / self.tick_size * self.tick_size
         } else {
-            &mut self.bids // Match asks against bids
+            limit_price.div_ceil(self.tick_size) * self.tick_size
         };
+        if price < self.tick_size || price > self.max_price {
+            return None;
+        }
+        let moved = price != *limit_price;
+        *limit_price = price;
+        Some(moved)
+    }
 
-        // Keep matching until order is filled or no more matches possible
-        while taker_order.remaining_quantity() > 0 && !book_side.is_empty() && fills.len() < MAX_FILLS {
-            let best_order_id = if taker_order.is_bid {
-                book_side.first_key_value() // Lowest ask for bids
-            } else {
-                book_side.last_key_value() // Highest bid for asks
-            };
+    /// Rounds a fill quantity down to a multiple of the minimum quantity increment
+    fn trim_to_qty_increment(&self, quantity: u64) -> u64 {
+        quantity / self.min_qty_increment * self.min_qty_increment
+    }
+}
 
-            if let Some((order_id, maker_order)) = best_order_id {
-                // Check if maker order is expired
-                if maker_order.expire_timestamp < timestamp {
-                    book_side.remove(order_id);
-                    continue;
-                }
+/// Maker and taker fee rates in basis points
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FeeSchedule {
+    /// Fee charged to the resting side of a fill
+    pub maker_bps: u64,
+    /// Fee charged to the incoming side of a fill
+    pub taker_bps: u64,
+}
 
-                // Check if price matches
-                if !self.prices_match(&taker_order, maker_order) {
-                    break;
-                }
+impl FeeSchedule {
+    /// Maker fee on a fill, charged in base units and rounded down
+    pub fn maker_fee(&self, base_quantity: u64) -> u64 {
+        (base_quantity as u128 * self.maker_bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
 
-                // Calculate fill quantity
-                let fill_qty = std::cmp::min(
-                    taker_order.remaining_quantity(),
-                    maker_order.remaining_quantity()
-                );
+    /// Taker fee on a fill, charged in quote units and rounded down
+    pub fn taker_fee(&self, quote_quantity: u128) -> u64 {
+        saturate_u64(quote_quantity * self.taker_bps as u128 / BPS_DENOMINATOR as u128)
+    }
+}
 
-                if fill_qty == 0 {
-                    break;
-                }
+/// Limits enforced by regulatory pre-trade risk checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RegulatoryRiskParams {
+    /// Largest order quantity allowed under the regulatory position limit
+    pub position_limit: u64,
+    /// Largest order notional allowed under the daily volume threshold
+    pub daily_notional_threshold: u64,
+    /// Reference price that order prices are compared against, if any
+    pub reference_price: Option<u64>,
+    /// Maximum distance from the reference price in basis points
+    pub max_price_deviation_bps: u64,
+    /// Window in which an identical order from the same owner counts as a duplicate
+    pub duplicate_window_ns: u64,
+    /// Maximum number of orders an owner may submit per second
+    pub max_orders_per_second: usize,
+}
 
-                // Create fill
-                let fill = Fill {
-                    maker_order_id: *order_id,
-                    taker_order_id: taker_order.order_id,
-                    base_quantity: fill_qty,
-                    quote_quantity: fill_qty * maker_order.price,
-                    timestamp,
-                };
+impl Default for RegulatoryRiskParams {
+    fn default() -> Self {
+        RegulatoryRiskParams {
+            position_limit: u64::MAX,
+            daily_notional_threshold: u64::MAX,
+            reference_price: None,
+            max_price_deviation_bps: 2_000,     // +/-20%
+            duplicate_window_ns: 1_000_000,     // 1ms
+            max_orders_per_second: usize::MAX,
+        }
+    }
+}
 
-                // Update orders
-                taker_order.filled_quantity += fill_qty;
-                maker_order.filled_quantity += fill_qty;
+/// A regulatory pre-trade check failed by an order
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RegulatoryViolation {
+    /// Order quantity is above the regulatory position limit
+    PositionLimitExceeded { quantity: u64, limit: u64 },
+    /// Order notional is above the daily volume threshold
+    NotionalThresholdExceeded { notional: u64, threshold: u64 },
+    /// Order price is too far from the reference price
+    PriceOutsideReferenceBand { price: u64, reference_price: u64, max_deviation_bps: u64 },
+    /// An identical order from the same owner was submitted within the duplicate window
+    DuplicateOrder { previous_timestamp: u64 },
+    /// Owner has already submitted the maximum number of orders in the last second
+    OrderRateExceeded { orders_in_window: usize, limit: usize },
+}
 
-                // Remove fully filled maker orders
-                if maker_order.is_filled() {
-                    book_side.remove(order_id);
-                }
+/// Monte Carlo tail risk of the losses resting orders would take on a price move
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TailRiskExposure {
+    /// Value at risk: the loss not exceeded at the requested confidence level
+    pub var: u64,
+    /// Conditional value at risk: the average loss at or beyond `var`
+    pub cvar: u64,
+    /// Largest loss across all scenarios
+    pub max_loss: u64,
+}
 
-                fills.push(fill);
-            } else {
-                break;
-            }
+/// Distribution of how long orders rested before being cancelled, expiring or filling
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LifetimeDistribution {
+    /// Median lifetime in nanoseconds
+    pub median_ns: u64,
+    /// 90th percentile lifetime in nanoseconds
+    pub p90_ns: u64,
+    /// 99th percentile lifetime in nanoseconds
+    pub p99_ns: u64,
+    /// Non-empty power-of-ten buckets as (lower bound in nanoseconds, order count), shortest first
+    pub bucket_counts: Vec<(u64, u64)>,
+}
+
+/// Recommended quote offsets for a market maker
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpreadRecommendation {
+    /// Bid price relative to mid, in price units (negative is below mid)
+    pub bid_offset: i64,
+    /// Ask price relative to mid, in price units
+    pub ask_offset: i64,
+    /// Confidence in the recommendation from 0 to 1, based on recorded fill history
+    pub confidence: f64,
+}
+
+/// Kind of manipulation pattern detected
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AlertType {
+    /// Large orders placed near the top of book and cancelled before filling
+    Layering,
+}
+
+/// Owner whose order activity matches a manipulation pattern
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManipulationAlert {
+    /// Owner of the suspicious orders
+    pub owner: Address,
+    /// Fraction of large orders at the best levels that were cancelled
+    pub cancel_rate: f64,
+    /// Number of large orders placed at the best levels within the window
+    pub large_order_count: usize,
+    /// Pattern that triggered the alert
+    pub alert_type: AlertType,
+}
+
+/// Model predicting the price impact of trading a given quantity
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum MarketImpactModel {
+    /// Impact grows linearly with quantity
+    Linear { bps_per_unit: f64 },
+    /// Impact grows with the square root of quantity
+    SquareRoot { coefficient_bps: f64 },
+}
+
+impl MarketImpactModel {
+    /// Predicts the price impact in basis points of trading `quantity` base units
+    pub fn predict(&self, quantity: u64) -> f64 {
+        match self {
+            MarketImpactModel::Linear { bps_per_unit } => bps_per_unit * quantity as f64,
+            MarketImpactModel::SquareRoot { coefficient_bps } => coefficient_bps * (quantity as f64).sqrt(),
         }
+    }
+}
 
-        fills
+/// Orders removed by a completed cancel-all
+#[derive(Debug, Clone, Default)]
+pub struct CancelAllResult {
+    /// Every order that was resting in the book
+    pub cancelled: Vec<Order>,
+}
+
+/// Resting orders at a single price
+#[derive(Debug, Clone, Default)]
+pub struct PriceLevel {
+    /// Price shared by every order at this level
+    pub price: u64,
+    /// Remaining quantity summed across the level's orders
+    pub total_quantity: u64,
+    /// Number of orders resting at this price
+    pub order_count: usize,
+    /// Orders at this price in queue order
+    pub orders: Vec<Order>,
+}
+
+/// Aggregate of the resting orders at a single price, without the orders themselves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LevelSummary {
+    /// Price shared by every order at this level
+    pub price: u64,
+    /// Remaining quantity summed across the level's orders
+    pub total_quantity: u64,
+    /// Number of orders resting at this price
+    pub order_count: usize,
+}
+
+/// Where a resting order stands in the queue at its price
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueuePosition {
+    /// Zero-based position in the level, 0 being next to fill
+    pub position: usize,
+    /// Number of orders at the level that fill first
+    pub orders_ahead: usize,
+    /// Remaining quantity of the orders ahead
+    pub quantity_ahead: u64,
+    /// Number of orders resting at the level, including this one
+    pub total_at_level: usize,
+}
+
+/// Point-in-time view of the book, best level first on each side
+#[derive(Debug, Clone, Default)]
+pub struct BookSnapshot {
+    /// Bid levels from highest to lowest price
+    pub bids: Vec<PriceLevel>,
+    /// Ask levels from lowest to highest price
+    pub asks: Vec<PriceLevel>,
+    /// Time the snapshot was taken, in nanoseconds
+    pub timestamp: u64,
+}
+
+/// Errors returned when loading a snapshot into a book
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WarmUpError {
+    /// The best bid is at or above the best ask
+    CrossedBook,
+    /// The snapshot contains the same order ID more than once
+    DuplicateOrderId(u128),
+    /// A level or order breaks one of the book's constraints
+    ConstraintViolation(String),
+}
+
+/// Errors returned when exporting book data
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ExportError {
+    /// Writing to the destination failed
+    Io(std::io::Error),
+    /// A reported value did not fit its integer type
+    ArithmeticOverflow,
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(error: std::io::Error) -> Self {
+        ExportError::Io(error)
     }
+}
 
-    /// Checks if two orders' prices match for trading
-    ///
-    /// # Arguments
-    /// * `taker` - The incoming aggressive order
-    /// * `maker` - The resting passive order
-    fn prices_match(&self, taker: &Order, maker: &Order) -> bool {
-        if taker.is_bid {
-            taker.price >= maker.price // Bid must be greater than or equal to ask
-        } else {
-            taker.price <= maker.price // Ask must be less than or equal to bid
-        }
+/// Per-owner totals accumulated for a risk report
+#[derive(Debug, Default)]
+struct RiskReportRow {
+    open_bid_orders: usize,
+    open_ask_orders: usize,
+    total_bid_notional: u64,
+    total_ask_notional: u64,
+    fill_count: u64,
+    maker_volume: u64,
+    taker_volume: u64,
+}
+
+/// Trading state of a book
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum BookMode {
+    /// Orders match as they arrive
+    Continuous,
+    /// Matching is suspended and orders are collected until the auction ends
+    VolatilityAuction { end_timestamp: u64 },
+}
+
+/// Outcome of uncrossing the book in an auction
+#[derive(Debug, Clone, Default)]
+pub struct AuctionResult {
+    /// Single price every auction fill traded at; zero if nothing traded
+    pub clearing_price: u64,
+    /// Base quantity traded in the auction
+    pub matched_quantity: u64,
+    /// Fills executed by the uncross
+    pub fills: Vec<Fill>,
+}
+
+/// Base and quote quantities an owner has traded over the session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnerPosition {
+    /// Base quantity bought
+    pub bought_quantity: u64,
+    /// Base quantity sold
+    pub sold_quantity: u64,
+    /// Quote quantity paid for base bought
+    pub quote_spent: u128,
+    /// Quote quantity received for base sold
+    pub quote_received: u128,
+}
+
+impl OwnerPosition {
+    /// Bought minus sold quantity, saturating at the bounds of `i64`
+    pub fn net_quantity(&self) -> i64 {
+        saturating_net(self.bought_quantity as u128, self.sold_quantity as u128)
     }
 
-    /// Places a new order in the book, attempting to match it first
-    ///
-    /// # Arguments
-    /// * `order` - The new order to place
-    /// Returns a vector of fills if any matches occurred
-    pub fn place_order(&mut self, mut order: Order) -> Vec<Fill> {
-        // First try to match the order
-        let fills = self.match_order(order.clone(), order.expire_timestamp);
-        
-        // If order is not fully filled and not IOC, place it in the book
-        if !order.is_filled() {
-            let book_side = if order.is_bid {
-                &mut self.bids
-            } else {
-                &mut self.asks
-            };
-            
-            book_side.insert(order.order_id, order);
-        }
-        
-        fills
+    /// Quote received minus quote spent, saturating at the bounds of `i64`
+    pub fn net_quote(&self) -> i64 {
+        saturating_net(self.quote_received, self.quote_spent)
     }
+}
 
-    /// Cancels an existing order
-    ///
-    /// # Arguments
-    /// * `order_id` - ID of the order to cancel
-    /// * `is_bid` - Whether the order is a bid or ask
-    /// Returns the cancelled order if found
-    pub fn cancel_order(&mut self, order_id: u128, is_bid: bool) -> Option<Order> {
-        let book_side = if is_bid {
-            &mut self.bids
-        } else {
-            &mut self.asks
-        };
-        
-        book_side.remove(&order_id)
+/// Risk carried by an owner's accumulated inventory
+#[derive(Debug, Clone, PartialEq)]
+pub struct InventoryRisk {
+    /// Base quantity bought over the session
+    pub long_inventory: u64,
+    /// Base quantity sold over the session
+    pub short_inventory: u64,
+    /// Long minus short inventory
+    pub net_inventory: i64,
+    /// Variance of the inventory's value over one volatility period, in squared base units
+    pub variance: f64,
+    /// 2.33 standard deviations of the inventory's value, in base units
+    pub value_at_risk: u64,
+}
+
+/// Market microstructure measures of the book and its recent fills
+#[derive(Debug, Clone, PartialEq)]
+pub struct MicrostructureReport {
+    /// Best ask minus best bid
+    pub quoted_spread: Option<u64>,
+    /// Volume-weighted effective spread of recent fills, in basis points
+    pub effective_spread: Option<f64>,
+    /// Volume-weighted realized spread of recent fills, in basis points
+    pub realized_spread: Option<f64>,
+    /// Volume-weighted price impact of recent fills, in basis points
+    pub price_impact: Option<f64>,
+    /// Mid price change per unit of signed taker volume
+    pub kyle_lambda: Option<f64>,
+    /// Volume-synchronized probability of informed trading
+    pub vpin: f64,
+    /// Average absolute return per unit of quote volume over the last 10 fills
+    pub amihud_illiquidity: f64,
+    /// Resting bid minus ask quantity as a share of all resting quantity
+    pub order_imbalance: f64,
+    /// Base quantity resting within 10 bps of the mid
+    pub book_depth_bps: f64,
+}
+
+/// Errors returned when shutting down a book
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ShutdownError {
+    /// The deadline passed before every order was cancelled
+    Incomplete { cancelled: usize, remaining: usize },
+}
+
+/// Errors returned by book operations
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BookError {
+    /// The order failed one or more regulatory pre-trade checks
+    RegulatoryViolation(Vec<RegulatoryViolation>),
+    /// The order was rejected by an external pre-trade hook
+    Rejected(String),
+    /// The order would rest closer to the mid than half the dynamic minimum spread
+    MinSpreadViolation { price: u64, mid_price: u64, min_spread: u64 },
+    /// The quantity is not a multiple of the market's lot size
+    LotSizeViolation { submitted: u64, lot_size: u64 },
+    /// The quantity is not a multiple of the market's minimum quantity increment
+    QtyIncrementViolation { submitted: u64, min_qty_increment: u64 },
+    /// The quantity is below the market's minimum order size
+    QuantityBelowMinimum { submitted: u64, min_size: u64 },
+    /// No resting order has the given ID on the given side
+    OrderNotFound(u128),
+    /// The amended quantity would not exceed what has already been filled
+    QuantityBelowFilled { submitted: u64, filled_quantity: u64 },
+    /// The amendment was rejected for a reason with no more specific variant
+    AmendRejected(AmendError),
+    /// A market order arrived with no resting orders on the opposite side
+    NoLiquidity,
+}
+
+/// Trading pair identified by its base and quote asset symbols
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PairId(pub (String, String));
+
+impl PairId {
+    /// Creates a pair from its base and quote asset symbols
+    pub fn new(base: impl Into<String>, quote: impl Into<String>) -> Self {
+        PairId((base.into(), quote.into()))
     }
 }
 
-impl Order {
-    /// Returns the unfilled quantity of the order
-    pub fn remaining_quantity(&self) -> u64 {
-        self.quantity - self.filled_quantity
+/// Reasons a `BookBuilder` cannot build a book, or `Book::apply_market_reset` rejects
+/// new parameters
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuilderError {
+    /// The tick size is zero
+    ZeroTickSize,
+    /// The lot size is zero
+    ZeroLotSize,
+    /// The minimum order size is zero
+    ZeroMinSize,
+    /// The minimum quantity increment is zero
+    ZeroQtyIncrement,
+    /// The fill limit per match is zero
+    ZeroMaxFills,
+}
+
+/// Errors returned by pair-routed operations on a `MultiBook`
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PairError {
+    /// No book has been created for the pair
+    UnknownPair(PairId),
+    /// The pair's book rejected the order
+    Place(PlaceError),
+}
+
+impl From<PlaceError> for PairError {
+    fn from(error: PlaceError) -> Self {
+        PairError::Place(error)
+    }
+}
+
+/// Errors returned when placing an order
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PlaceError {
+    /// The price is below the tick size
+    PriceBelowMinimum,
+    /// The price is not a multiple of the tick size
+    PriceNotTickAligned,
+    /// The price is above the market's maximum price
+    PriceAboveMaximum,
+    /// The quantity is below the minimum order size
+    QuantityBelowMinimum,
+    /// The quantity is not a multiple of the lot size
+    QuantityNotLotAligned,
+    /// The quantity is not a multiple of the minimum quantity increment
+    QuantityNotIncrementAligned,
+    /// The iceberg peak is zero or not a multiple of the lot size and quantity increment
+    InvalidIcebergPeak,
+    /// An order with the same ID is already resting
+    OrderAlreadyExists,
+    /// The order passed validation but the book rejected it
+    Book(BookError),
+    /// The book could not construct the order from the given arguments
+    Order(OrderError),
+    /// Placeholder for consumers that cannot match with a wildcard; never returned
+    #[doc(hidden)]
+    _Unknown,
+}
+
+impl From<BookError> for PlaceError {
+    fn from(error: BookError) -> Self {
+        PlaceError::Book(error)
+    }
+}
+
+impl From<OrderError> for PlaceError {
+    fn from(error: OrderError) -> Self {
+        PlaceError::Order(error)
+    }
+}
+
+/// Outcome of one order in a batch
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    /// ID of the submitted order
+    pub order_id: u128,
+    /// Fills the order produced, empty if it was rejected
+    pub fills: Vec<Fill>,
+    /// Why the order was rejected, if it was
+    pub error: Option<PlaceError>,
+}
+
+/// Outcome of a `bulk_update`
+#[derive(Debug, Clone, Default)]
+pub struct BulkResult {
+    /// Orders cancelled, in the order their cancellations were requested
+    pub cancelled: Vec<Order>,
+    /// Fills produced by the placements, in placement order
+    pub fills: Vec<Fill>,
+    /// Placements that were rejected, with the reason
+    pub rejected: Vec<(Order, PlaceError)>,
+}
+
+/// Book state an order placed with `conditional_place` waits for
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum PlaceCondition {
+    /// The best ask is at or below the price
+    IfBestAskAtOrBelow(u64),
+    /// The best bid is at or above the price
+    IfBestBidAtOrAbove(u64),
+    /// Both sides are quoted and the best ask is at most this far above the best bid
+    IfSpreadAtOrBelow(u64),
+    /// No condition, so the order is placed straight away
+    Always,
+}
+
+/// Outcome of `conditional_place`
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ConditionalResult {
+    /// The condition held and the order was placed, producing these fills
+    Placed(Vec<Fill>),
+    /// The condition did not hold, so the order is held until it does
+    ConditionNotMet,
+    /// The order was rejected and will not be placed
+    Rejected(PlaceError),
+}
+
+/// Fills produced by placing an order, or the error that rejected it
+pub type PlaceOrderResult = Result<Vec<Fill>, PlaceError>;
+
+/// External validation run on an order before matching, e.g. a credit or KYC check
+pub type PreTradeHook = Box<dyn Fn(&Order) -> Result<(), BookError> + Send>;
+
+/// Pre-trade hooks in registration order
+#[derive(Default)]
+struct PreTradeHooks(Vec<PreTradeHook>);
+
+impl Debug for PreTradeHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PreTradeHooks({})", self.0.len())
+    }
+}
+
+/// Seeded linear congruential generator for deterministic simulations
+#[derive(Debug, Clone)]
+pub struct Lcg {
+    /// Current generator state
+    state: u64,
+}
+
+impl Lcg {
+    /// Creates a generator from a seed
+    pub fn new(seed: u64) -> Self {
+        Lcg { state: seed }
+    }
+
+    /// Returns the next pseudo-random 64-bit value
+    pub fn next_u64(&mut self) -> u64 {
+        // Knuth's MMIX multiplier and increment
+        self.state = self.state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        self.state
+    }
+
+    /// Returns a uniform sample in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a standard normal sample using the Box-Muller transform
+    pub fn next_gaussian(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64(); // (0, 1] so the logarithm is finite
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+/// Source of the current time, in nanoseconds, for book operations
+pub trait Clock: Debug + Send {
+    /// Returns the current timestamp in nanoseconds
+    fn now(&self) -> u64;
+}
+
+/// Clock reading the system wall time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    }
+}
+
+/// Manually driven clock for tests and simulations
+///
+/// Clones share the same underlying time, so a test can keep a handle while the
+/// book owns another.
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    /// Current time in nanoseconds
+    now: Arc<AtomicU64>,
+}
+
+impl MockClock {
+    /// Creates a clock starting at `timestamp`
+    pub fn new(timestamp: u64) -> Self {
+        MockClock {
+            now: Arc::new(AtomicU64::new(timestamp)),
+        }
+    }
+
+    /// Moves the clock to `timestamp`
+    pub fn set(&self, timestamp: u64) {
+        self.now.store(timestamp, Ordering::SeqCst);
+    }
+
+    /// Moves the clock forward by `nanos`
+    pub fn advance(&self, nanos: u64) {
+        self.now.fetch_add(nanos, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+/// Method used to estimate the fair value of the base asset
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum FairValueMethod {
+    /// Midpoint of the best bid and best ask
+    MidPrice,
+    /// Best bid and ask weighted by the quantity resting on the opposite side
+    WeightedMidPrice,
+    /// Volume-weighted average price over the last N fills
+    VWAP(usize),
+    /// Price of the most recent fill
+    LastTrade,
+    /// VWAP over the fill history shifted by an offset, reinterpreted as a signed value
+    OracleAdjusted(u64),
+}
+
+/// Signed distances in ticks from the book's prices to a fair value
+///
+/// Each distance is the fair value minus the price, so a negative distance means the
+/// fair value is below that price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FairValueSpread {
+    /// Ticks from the best bid; negative when bids are priced above fair value
+    pub distance_from_best_bid: i64,
+    /// Ticks from the best ask; positive when asks are priced below fair value
+    pub distance_from_best_ask: i64,
+    /// Ticks from the midpoint of the best bid and best ask
+    pub distance_from_mid: i64,
+}
+
+/// Direction of a cross-exchange arbitrage trade
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArbDirection {
+    /// Lift internal asks and sell into the external bid
+    BuyInternalSellExternal,
+    /// Hit internal bids and buy from the external ask
+    SellInternalBuyExternal,
+}
+
+/// Arbitrage opportunity between this book and an external venue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArbitrageSignal {
+    /// Which side to trade on each venue
+    pub direction: ArbDirection,
+    /// Best internal price on the side being traded
+    pub internal_price: u64,
+    /// External price on the opposite side of the trade
+    pub external_price: u64,
+    /// Quantity tradable at a profit on both venues
+    pub max_quantity: u64,
+    /// Profit at the best internal price, in basis points of the buy price
+    pub expected_profit_bps: u64,
+}
+
+/// Aggregate price improvement received by takers
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceImprovementStats {
+    /// Number of fills executed at a better price than the taker's limit
+    pub total_fills_with_improvement: u64,
+    /// Number of fills executed at the taker's limit price
+    pub total_fills_without: u64,
+    /// Average per-unit price improvement across improved fills
+    pub avg_improvement_units: f64,
+    /// Total quote value saved by takers
+    pub total_improvement_value: u64,
+}
+
+/// Represents a match between two orders
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Fill {
+    /// Order ID of the maker (passive order)
+    pub maker_order_id: u128,
+    /// Order ID of the taker (aggressive order)
+    pub taker_order_id: u128,
+    /// Owner of the maker order
+    pub maker_owner: Address,
+    /// Owner of the taker order
+    pub taker_owner: Address,
+    /// Whether the taker was buying
+    pub taker_is_bid: bool,
+    /// Execution price, taken from the maker order
+    pub price: u64,
+    /// Amount of base asset traded
+    pub base_quantity: u64,
+    /// Amount of quote asset traded (base_quantity * price)
+    pub quote_quantity: u128,
+    /// Timestamp when the fill occurred
+    pub timestamp: u64,
+    /// True if the taker traded at a better price than its limit
+    pub price_improved: bool,
+    /// Quote value saved by the taker relative to its limit price
+    pub improvement_amount: u64,
+    /// Mid price of the book when the taker arrived, if both sides were populated
+    pub mid_price: Option<u64>,
+    /// Fee charged to the maker, in base units
+    pub maker_fee: u64,
+    /// Fee charged to the taker, in quote units
+    pub taker_fee: u64,
+    /// Quantity the maker order had left to fill after this fill
+    pub maker_remaining_after: u64,
+    /// Quantity the taker order had left to fill after this fill
+    pub taker_remaining_after: u64,
+}
+
+impl Fill {
+    /// Whether this fill completed the maker order
+    pub fn maker_exhausted(&self) -> bool {
+        self.maker_remaining_after == 0
+    }
+
+    /// Whether this fill completed the taker order
+    pub fn taker_exhausted(&self) -> bool {
+        self.taker_remaining_after == 0
+    }
+}
+
+/// Reference price an execution is measured against
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BenchmarkType {
+    /// Price prevailing when the parent order arrived
+    ArrivalPrice(u64),
+    /// Time-weighted average price over the execution window
+    TWAP(u64),
+    /// Volume-weighted average price over the execution window
+    VWAP(u64),
+}
+
+/// Execution quality of a set of fills relative to a benchmark
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionQualityReport {
+    /// Signed deviation from the benchmark of the fill priced farthest from it, in basis points
+    pub slippage_bps: i64,
+    /// Signed deviation of the average fill price from the benchmark, in basis points
+    pub implementation_shortfall: i64,
+    /// Share of the session's traded volume accounted for by the fills
+    pub volume_participation_rate: f64,
+}
+
+/// Owner whose fills at one price look like a large order executed in regular tranches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstitutionalOrderCandidate {
+    /// Owner of the suspected parent order
+    pub owner: Address,
+    /// Price at which the tranches filled
+    pub price: u64,
+    /// Base quantity filled across the tranches
+    pub total_quantity_detected: u64,
+    /// Number of fills at this price
+    pub fill_count: usize,
+    /// Mean time between tranches in nanoseconds
+    pub avg_interval_ns: u64,
+}
+
+/// Halts matching once a fill would trade too far from a reference price
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CircuitBreaker {
+    /// Price deviations are measured from
+    pub reference_price: u64,
+    /// Largest distance of a fill price from the reference, in basis points
+    pub max_deviation_bps: u64,
+    /// Whether the breaker has tripped; no fills happen until it is reset
+    pub tripped: bool,
+}
+
+impl CircuitBreaker {
+    /// Whether a fill at `price` would be too far from the reference price
+    fn breached_by(&self, price: u64) -> bool {
+        (price.abs_diff(self.reference_price) as u128 * BPS_DENOMINATOR as u128)
+            .checked_div(self.reference_price as u128)
+            .is_none_or(|deviation_bps| deviation_bps > self.max_deviation_bps as u128)
+    }
+
+    /// Whether a fill at `price` is halted, tripping the breaker if it was not already
+    fn halts(&mut self, price: u64) -> bool {
+        self.tripped |= self.breached_by(price);
+        self.tripped
+    }
+}
+
+/// Protective constraints checked before each fill while matching
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchProtection {
+    /// Maximum number of fills to execute
+    pub max_fills: Option<usize>,
+    /// Maximum distance of a fill price from the best price when matching began, in basis points
+    pub max_slippage_bps: Option<u16>,
+    /// Smallest fill allowed; matching stops rather than execute a smaller one
+    pub min_fill_qty: Option<u64>,
+    /// Whether a taker left partially filled by the book is reported as cancelled
+    pub cancel_on_partial: bool,
+}
+
+/// Reason protected matching stopped
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TerminationReason {
+    /// The taker was filled or no further orders crossed its price
+    Complete,
+    /// The fill limit was reached
+    FillsCapped,
+    /// The next fill would have exceeded the slippage limit
+    SlippageBreached,
+    /// The next fill would have been smaller than the minimum fill quantity
+    MinQtyNotMet,
+    /// The book could only partially fill the taker and the remainder was cancelled
+    PartialCancelled,
+    /// The taker was cancelled by self-trade prevention
+    SelfTradePrevented,
+    /// The circuit breaker halted matching and the remainder was cancelled
+    CircuitBreakerTripped,
+}
+
+/// Reason matching an order stopped
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum StopReason {
+    /// The taker has no quantity left
+    TakerFullyFilled,
+    /// No resting order crosses the taker's price, or the opposite side is empty
+    #[default]
+    NoCrossableOrders,
+    /// The taker's or the book's fill limit was reached
+    MaxFillsReached,
+    /// The circuit breaker halted matching and the remainder was cancelled
+    CircuitBreakerTripped,
+    /// The taker's expiry had passed when it arrived, so it was not matched
+    TakerExpired,
+    /// Self-trade prevention cancelled the taker
+    SelfTradePrevented,
+    /// A `MatchProtection` limit stopped matching; see `TerminationReason`
+    ProtectionLimitReached,
+    /// Placeholder for consumers that cannot match with a wildcard; never returned
+    #[doc(hidden)]
+    _Unknown,
+}
+
+/// Fills from matching an order and the orders self-trade prevention cancelled
+#[derive(Debug, Clone, Default)]
+pub struct MatchResult {
+    /// Fills executed against the book
+    pub fills: Vec<Fill>,
+    /// Resting orders, and the taker itself, cancelled instead of self-trading
+    pub stp_cancelled: Vec<Order>,
+    /// Why matching stopped
+    pub stop_reason: StopReason,
+}
+
+/// Outcome of placing an order with `place_order`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaceOutcome {
+    /// Number of fills queued for `drain_fills`, including those of triggered stops
+    pub fill_count: usize,
+    /// Why matching the order stopped, or `None` if it was held as a stop or for an
+    /// auction instead of matching
+    pub stop_reason: Option<StopReason>,
+}
+
+/// Outcome of matching an order under protective constraints
+#[derive(Debug, Clone)]
+pub struct ProtectedMatchResult {
+    /// Fills executed before matching stopped
+    pub fills: Vec<Fill>,
+    /// Why matching stopped
+    pub termination_reason: TerminationReason,
+    /// Taker quantity left unfilled
+    pub remaining: u64,
+}
+
+/// Outcome of routing an order across a list of candidate limit prices
+#[derive(Debug, Clone, Default)]
+pub struct SmartRoutingResult {
+    /// Limit price the order was placed at, if any candidate had enough liquidity
+    pub filled_at_price: Option<u64>,
+    /// Fills executed at that price
+    pub fills: Vec<Fill>,
+    /// Number of candidate prices simulated, including the original
+    pub prices_tried: usize,
+}
+
+/// Book mutation recorded for audit and market data, reproducible with `Book::replay`
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum BookEvent {
+    /// An order passed pre-trade checks, stamped with its submission time
+    Placed(Order),
+    /// A resting order was cancelled
+    Cancelled { order_id: u128, is_bid: bool },
+    /// A resting order was removed after expiring
+    Expired { order_id: u128, is_bid: bool },
+    /// Two orders traded
+    Filled(Fill),
+    /// The best bid or best ask moved after a placement or cancellation
+    TopOfBookChanged(TopOfBookEvent),
+    /// The book switched to new market parameters
+    MarketReset(MarketReset),
+}
+
+/// Book event stamped for ordered delivery and catch-up by `Book::events_since`
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequencedEvent {
+    /// Position of the event in the book's history, increasing by one per event
+    pub sequence_number: u64,
+    /// Shared by every event from the same `place_order` or `cancel_order` call
+    pub batch_id: u64,
+    /// The event itself
+    pub event: BookEvent,
+}
+
+/// Best prices left by a placement or cancellation that moved either of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TopOfBookEvent {
+    /// Best bid after the operation, `None` if no bids rest
+    pub new_best_bid: Option<u64>,
+    /// Best ask after the operation, `None` if no asks rest
+    pub new_best_ask: Option<u64>,
+    /// Time of the operation
+    pub timestamp: u64,
+}
+
+/// Timestamped order entry or cancellation used to drive a replay
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum OrderEvent {
+    /// An order submitted at `timestamp`
+    Place { timestamp: u64, order: Order },
+    /// A cancellation submitted at `timestamp`
+    Cancel { timestamp: u64, order_id: u128, is_bid: bool },
+}
+
+impl OrderEvent {
+    /// Returns when the event was submitted
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            OrderEvent::Place { timestamp, .. } | OrderEvent::Cancel { timestamp, .. } => *timestamp,
+        }
+    }
+}
+
+/// Fills from replaying the same events with and without added latency
+#[derive(Debug, Clone, Default)]
+pub struct ReplayResult {
+    /// Fills when every event arrives at its submission time
+    pub fills_without_latency: Vec<Fill>,
+    /// Fills when every event arrives `latency_ns` late
+    pub fills_with_latency: Vec<Fill>,
+    /// Fills without a same-position counterpart of equal price and quantity in the other replay
+    pub fills_difference: usize,
+}
+
+/// Effect of a session changeover on the resting orders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MarketResetResult {
+    /// Resting and held orders still valid under the new parameters and left unchanged
+    pub orders_revalidated: usize,
+    /// Orders cancelled because no valid price or quantity exists for them
+    pub orders_cancelled_invalid: usize,
+    /// Orders moved to the new tick size
+    pub orders_repriced: usize,
+}
+
+/// Record of a market reset applied between sessions
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MarketReset {
+    /// When the reset was applied
+    pub timestamp: u64,
+    /// Parameters in force after the reset
+    pub params: MarketParams,
+    /// Effect on the resting orders
+    pub result: MarketResetResult,
+}
+
+/// Execution price estimate for sweeping the book with a market order
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImpactResult {
+    /// Volume-weighted price of the simulated fills, truncated
+    pub avg_price: u64,
+    /// Price of the last level the sweep reached
+    pub worst_price: u64,
+    /// Base quantity the book could fill
+    pub filled_quantity: u64,
+    /// Base quantity left once the sweep ran out of liquidity
+    pub unfilled_quantity: u64,
+}
+
+/// Pre-trade estimate of what placing an order would do
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FillSimulationReport {
+    /// Quantity that would match immediately
+    pub would_fill_qty: u64,
+    /// Quantity that would rest on the book afterwards
+    pub would_rest_qty: u64,
+    /// Volume-weighted fill price, 0 without fills
+    pub estimated_avg_price: u64,
+    /// Taker fee in quote units under the current fee schedule
+    pub estimated_taker_fee: u64,
+    /// Distance of the average fill price from the best opposite price, in basis points
+    pub estimated_market_impact_bps: f64,
+    /// Number of fills
+    pub fills_count: usize,
+    /// Number of distinct price levels filled against
+    pub crosses_levels: usize,
+    /// Spread after the order has matched and rested
+    pub post_trade_spread: Option<u64>,
+    /// Best bid after the order has matched and rested
+    pub post_trade_best_bid: Option<u64>,
+    /// Best ask after the order has matched and rested
+    pub post_trade_best_ask: Option<u64>,
+}
+
+/// Side of the book
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+/// Side an order buys or sells on, taken by order and book methods in place of an
+/// `is_bid` flag
+pub type OrderSide = Side;
+
+impl Side {
+    /// Returns the side an order rests on
+    pub fn of(is_bid: bool) -> Self {
+        if is_bid {
+            Side::Bid
+        } else {
+            Side::Ask
+        }
+    }
+}
+
+impl From<bool> for Side {
+    /// Converts an `is_bid` flag, so `true` is `Side::Bid`
+    fn from(is_bid: bool) -> Self {
+        Side::of(is_bid)
+    }
+}
+
+/// How the total quantity at a price level changed
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LevelChange {
+    /// Quantity was added to the level, which may have been empty
+    Added(u64),
+    /// Quantity was removed while leaving the level non-empty
+    Reduced(u64),
+    /// The level has no quantity left
+    Removed,
+}
+
+/// Update to the aggregate quantity resting at one price level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceLevelEvent {
+    /// Side of the level
+    pub side: Side,
+    /// Price of the level
+    pub price: u64,
+    /// Remaining quantity at the level after the change
+    pub new_total_qty: u64,
+    /// Quantity added or removed
+    pub change: LevelChange,
+}
+
+/// Order flow imbalance at the top of the book since the previous measurement
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OFISignal {
+    /// Imbalance at each level, best first
+    pub per_level: Vec<f64>,
+    /// Sum of the per-level imbalances
+    pub aggregate: f64,
+    /// `Bid` when buying pressure predicts a rise, `Ask` when selling pressure predicts a fall
+    pub predicted_direction: Option<Side>,
+}
+
+/// Change to the resting orders, as replayed by replicas
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum OrderBookChange {
+    /// An order started resting: `(order_id, price, remaining_quantity, is_bid)`
+    OrderPlaced(u128, u64, u64, bool),
+    /// An order left the book without filling, including on expiry
+    OrderCancelled(u128),
+    /// A resting order was partly filled: `(order_id, remaining_quantity)`
+    OrderPartialFill(u128, u64),
+    /// A resting order was completely filled and left the book
+    OrderFullFill(u128),
+}
+
+/// How an order that is no longer resting was settled
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum OrderStatus {
+    /// The order was completely filled
+    Filled,
+    /// The order was cancelled
+    Cancelled,
+    /// The order expired while resting
+    Expired,
+}
+
+/// Outcome of a cancellation request
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum CancelResult {
+    /// The order was removed from the book, possibly after partial fills
+    Cancelled(Order),
+    /// The order was recently filled completely
+    AlreadyFilled,
+    /// No resting or recently filled order has the ID on the given side
+    NotFound,
+    /// Placeholder for consumers that cannot match with a wildcard; never returned
+    #[doc(hidden)]
+    _Unknown,
+}
+
+impl CancelResult {
+    /// Returns the cancelled order, if the cancellation succeeded
+    pub fn into_order(self) -> Option<Order> {
+        match self {
+            CancelResult::Cancelled(order) => Some(order),
+            CancelResult::AlreadyFilled | CancelResult::NotFound | CancelResult::_Unknown => None,
+        }
+    }
+}
+
+/// Outcome of a successful cancel-replace
+#[derive(Debug, Clone)]
+pub struct ReplaceResult {
+    /// The original order as it was cancelled
+    pub cancelled: Order,
+    /// Fills the replacement took on arrival
+    pub fills: Vec<Fill>,
+    /// The replacement as it rests, or `None` if it did not rest
+    pub resting: Option<Order>,
+}
+
+/// Reasons a cancel-replace failed
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ReplaceError {
+    /// No resting order has the original ID on the given side; nothing changed
+    NotFound,
+    /// The replacement failed validation; nothing changed
+    Invalid(PlaceError),
+    /// The original was cancelled but a pre-trade check rejected the replacement
+    Rejected { cancelled: Box<Order>, error: PlaceError },
+}
+
+/// Outcome of a successful `two_sided_quote`
+#[derive(Debug, Clone, Default)]
+pub struct TwoSidedResult {
+    /// Fills the bid took on arrival
+    pub bid_fills: Vec<Fill>,
+    /// Fills the ask took on arrival
+    pub ask_fills: Vec<Fill>,
+    /// Whether any of the bid was left resting
+    pub bid_resting: bool,
+    /// Whether any of the ask was left resting
+    pub ask_resting: bool,
+}
+
+/// Reasons a two-sided quote failed
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum QuoteError {
+    /// The bid is priced at or above the ask; nothing changed
+    SelfCrossing,
+    /// The bid is a sell order or the ask a buy order; nothing changed
+    WrongSide,
+    /// One side failed validation or a pre-trade check; nothing changed
+    Invalid(PlaceError),
+    /// The bid was placed but a pre-trade check rejected the ask
+    AskRejected { bid_fills: Vec<Fill>, error: PlaceError },
+}
+
+/// Statuses of the most recently settled orders, forgetting the oldest beyond
+/// `MAX_SETTLED_ORDERS`
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct SettledOrders {
+    statuses: HashMap<u128, OrderStatus>,
+    /// Settled order IDs, oldest first
+    order_ids: VecDeque<u128>,
+}
+
+impl SettledOrders {
+    fn record(&mut self, order_id: u128, status: OrderStatus) {
+        if self.statuses.insert(order_id, status).is_some() {
+            return;
+        }
+        if self.order_ids.len() == MAX_SETTLED_ORDERS {
+            if let Some(oldest) = self.order_ids.pop_front() {
+                self.statuses.remove(&oldest);
+            }
+        }
+        self.order_ids.push_back(order_id);
+    }
+}
+
+/// Most recent fills, oldest first, forgetting the oldest beyond `max_fill_history`
+///
+/// Forgotten fills stay in the buffer until they outnumber the kept ones, so the kept
+/// fills are always one contiguous slice and trimming is amortized O(1).
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct FillHistory {
+    buffer: Vec<Fill>,
+    /// Index of the oldest kept fill in `buffer`
+    start: usize,
+    max_fill_history: usize,
+}
+
+impl FillHistory {
+    fn new(max_fill_history: usize) -> Self {
+        FillHistory { buffer: Vec::new(), start: 0, max_fill_history }
+    }
+
+    fn push(&mut self, fill: Fill) {
+        if self.max_fill_history == 0 {
+            return;
+        }
+        self.buffer.push(fill);
+        if self.buffer.len() - self.start > self.max_fill_history {
+            self.start += 1;
+        }
+        if self.start >= self.max_fill_history {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+    }
+
+    /// Kept fills, oldest first
+    fn fills(&self) -> &[Fill] {
+        &self.buffer[self.start..]
+    }
+
+    /// Volume-weighted average price of the last `window` fills
+    fn vwap(&self, window: usize) -> Option<u64> {
+        let (notional, volume) = self
+            .fills()
+            .iter()
+            .rev()
+            .take(window)
+            .fold((0u128, 0u128), |(notional, volume), fill| {
+                (notional + fill.price as u128 * fill.base_quantity as u128, volume + fill.base_quantity as u128)
+            });
+
+        if volume == 0 {
+            return None;
+        }
+        Some((notional / volume) as u64)
+    }
+
+    /// Fills timestamped within `start..=end`, found by binary search since fills are
+    /// kept in time order
+    fn between(&self, start: u64, end: u64) -> impl Iterator<Item = &Fill> {
+        let fills = self.fills();
+        let first = fills.partition_point(|fill| fill.timestamp < start);
+        let last = fills.partition_point(|fill| fill.timestamp <= end);
+        fills[first..last.max(first)].iter()
+    }
+
+    /// Base and quote volume of the fills timestamped within `start..=end`
+    fn volume_between(&self, start: u64, end: u64) -> (u64, u128) {
+        self.between(start, end)
+            .fold((0, 0), |(base, quote), fill| (base + fill.base_quantity, quote + fill.quote_quantity))
+    }
+}
+
+/// Key `export_fills` sorts its results by
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FillSortKey {
+    /// Oldest first
+    #[default]
+    Timestamp,
+    /// Smallest base quantity first
+    BaseQuantity,
+    /// Lowest price first
+    Price,
+}
+
+/// Criteria for `export_fills`; every criterion that is set must match
+#[derive(Debug, Clone, Default)]
+pub struct FillFilter {
+    /// Only fills where this owner was the maker or the taker
+    pub owner: Option<Address>,
+    /// Only fills at or after this timestamp
+    pub from_timestamp: Option<u64>,
+    /// Only fills at or before this timestamp
+    pub to_timestamp: Option<u64>,
+    /// Only fills of at least this base quantity
+    pub min_base_quantity: Option<u64>,
+    /// Order of the returned fills, ties kept in time order
+    pub sort_by: FillSortKey,
+}
+
+/// Lifetime activity counters for monitoring dashboards
+///
+/// Unlike the session totals, these survive market resets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BookStats {
+    /// Orders that passed pre-trade checks
+    pub total_orders_placed: u64,
+    /// Resting orders cancelled by their owner, a reset or self-trade prevention,
+    /// and takers cancelled by self-trade prevention
+    pub total_orders_cancelled: u64,
+    /// Fills executed, including auction fills
+    pub total_fills: u64,
+    /// Base quantity traded
+    pub total_base_volume: u128,
+    /// Quote quantity traded
+    pub total_quote_volume: u128,
+    /// Maker fees charged, in base units
+    pub total_maker_fees: u128,
+    /// Taker fees charged, in quote units
+    pub total_taker_fees: u128,
+}
+
+impl BookStats {
+    fn record_fill(&mut self, fill: &Fill) {
+        self.total_fills += 1;
+        self.total_base_volume += fill.base_quantity as u128;
+        self.total_quote_volume += fill.quote_quantity;
+        self.total_maker_fees += fill.maker_fee as u128;
+        self.total_taker_fees += fill.taker_fee as u128;
+    }
+}
+
+/// Session price and volume summary for the pair, as shown by tickers in UIs and APIs
+///
+/// Prices are zero until the first fill of the session.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Ticker {
+    /// Price of the first fill of the session
+    pub open: u64,
+    /// Highest fill price of the session
+    pub high: u64,
+    /// Lowest fill price of the session
+    pub low: u64,
+    /// Price of the most recent fill
+    pub last: u64,
+    /// Base quantity traded this session
+    pub volume_base: u128,
+    /// Quote quantity traded this session
+    pub volume_quote: u128,
+    /// Fills executed this session
+    pub num_trades: u64,
+    /// Highest resting bid price when the ticker was taken
+    pub best_bid: Option<u64>,
+    /// Lowest resting ask price when the ticker was taken
+    pub best_ask: Option<u64>,
+    /// Timestamp the ticker was taken at in nanoseconds
+    pub timestamp: u64,
+}
+
+impl Ticker {
+    fn record_fill(&mut self, fill: &Fill) {
+        if self.num_trades == 0 {
+            self.open = fill.price;
+            self.high = fill.price;
+            self.low = fill.price;
+        }
+        self.high = self.high.max(fill.price);
+        self.low = self.low.min(fill.price);
+        self.last = fill.price;
+        self.volume_base += fill.base_quantity as u128;
+        self.volume_quote += fill.quote_quantity;
+        self.num_trades += 1;
+    }
+}
+
+/// Fill prices sampled over a trailing window, for time-weighted average prices
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct TwapOracle {
+    window_secs: u64,
+    /// `(timestamp, price)` of each fill, oldest first
+    samples: VecDeque<(u64, u64)>,
+}
+
+impl TwapOracle {
+    fn new(window_secs: u64) -> Self {
+        TwapOracle { window_secs, samples: VecDeque::new() }
+    }
+
+    /// Earliest timestamp inside the window ending at `timestamp`
+    fn window_start(&self, timestamp: u64) -> u64 {
+        timestamp.saturating_sub(self.window_secs.saturating_mul(NANOS_PER_SECOND))
+    }
+
+    /// Records a fill price, forgetting samples that have left the window
+    fn push(&mut self, timestamp: u64, price: u64) {
+        let window_start = self.window_start(timestamp);
+        while self.samples.front().is_some_and(|&(sampled_at, _)| sampled_at < window_start) {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((timestamp, price));
+    }
+
+    /// Mean price of the samples inside the window ending at `timestamp`
+    fn twap(&self, timestamp: u64) -> Option<u64> {
+        let window_start = self.window_start(timestamp);
+        let (sum, count) = self
+            .samples
+            .iter()
+            .filter(|&&(sampled_at, _)| sampled_at >= window_start && sampled_at <= timestamp)
+            .fold((0u128, 0u128), |(sum, count), &(_, price)| (sum + price as u128, count + 1));
+        sum.checked_div(count).map(|twap| twap as u64)
+    }
+
+    /// Sum of each sample's price times the time until the next sample
+    fn cumulative(&self) -> u128 {
+        self.samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|(&(sampled_at, price), &(next_sampled_at, _))| price as u128 * (next_sampled_at - sampled_at) as u128)
+            .sum()
+    }
+}
+
+/// Reason an amendment was rejected
+///
+/// Non-exhaustive, see [Stability](self#stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AmendError {
+    /// No resting order has the ID on the given side
+    NotFound,
+    /// The new quantity would not exceed what has already been filled
+    QuantityBelowFilled { submitted: u64, filled_quantity: u64 },
+    /// The new price is off tick, out of bounds or crosses the opposite side
+    InvalidPrice,
+    /// The new quantity is below the minimum order size
+    QuantityBelowMinimum,
+    /// The new quantity is not a multiple of the lot size
+    QuantityNotLotAligned,
+    /// The new quantity is not a multiple of the minimum quantity increment
+    QuantityNotIncrementAligned,
+}
+
+/// Fill activity observed at a single distance from the mid price
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LevelFillStats {
+    /// Number of maker fills at this distance
+    pub fill_count: u64,
+    /// Amount of base asset filled at this distance
+    pub base_quantity: u64,
+}
+
+/// Maker fill history for one owner, used to estimate fill rates per spread
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MakerFillStats {
+    /// Fill statistics keyed by the maker's distance from mid in basis points
+    pub levels: BTreeMap<u64, LevelFillStats>,
+    /// Timestamp of the owner's first maker fill
+    pub first_fill_timestamp: u64,
+    /// Timestamp of the owner's most recent maker fill
+    pub last_fill_timestamp: u64,
+}
+
+impl Book {
+    /// Creates a new empty order book
+    pub fn new() -> Self {
+        Self::new_with_params(MarketParams::default())
+    }
+
+    /// Creates a new empty order book charging the given fees
+    pub fn new_with_fees(fee_schedule: FeeSchedule) -> Self {
+        Self::new_with_params(MarketParams { fee_schedule, ..Default::default() })
+    }
+
+    /// Creates a new empty order book for a market with the given parameters
+    ///
+    /// Panics if the tick size, lot size, minimum size or minimum quantity increment
+    /// is zero; `BookBuilder::build` reports these as errors instead.
+    pub fn new_with_params(params: MarketParams) -> Self {
+        if let Err(error) = params.validate() {
+            panic!("invalid market parameters: {error:?}");
+        }
+        let max_fill_history = params.max_fill_history;
+        let twap_window_secs = params.twap_window_secs;
+        Book {
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            sequence_counter: 0,
+            next_order_id: 0,
+            order_keys: HashMap::new(),
+            owner_index: HashMap::new(),
+            maker_fill_stats: HashMap::new(),
+            params,
+            fills_with_improvement: 0,
+            fills_without_improvement: 0,
+            total_improvement_units: 0,
+            total_improvement_value: 0,
+            fill_history: FillHistory::new(max_fill_history),
+            stats: BookStats::default(),
+            ticker: Ticker::default(),
+            twap_oracle: TwapOracle::new(twap_window_secs),
+            clock: system_clock(),
+            recent_submissions: HashMap::new(),
+            completed_order_lifetimes: VecDeque::new(),
+            total_fills: 0,
+            total_maker_fees: 0,
+            total_taker_fees: 0,
+            session_volume: 0,
+            large_order_placements: HashMap::new(),
+            large_order_cancellations: HashMap::new(),
+            tracked_large_orders: HashSet::new(),
+            pre_trade_hooks: PreTradeHooks::default(),
+            spread_widening: None,
+            dynamic_min_spread: None,
+            quote_activity: HashMap::new(),
+            best_quote_owners: [None, None],
+            mode: BookMode::Continuous,
+            auction_orders: Vec::new(),
+            positions: HashMap::new(),
+            market_resets: Vec::new(),
+            #[cfg(feature = "tokio")]
+            level_events: level_event_channel(),
+            level_qty_cache: HashMap::new(),
+            dirty_levels: Vec::new(),
+            ofi_state: Vec::new(),
+            change_log: VecDeque::new(),
+            change_seq: 0,
+            settled_orders: SettledOrders::default(),
+            stop_orders: Vec::new(),
+            conditional_orders: Vec::new(),
+            expiry_queue: BTreeSet::new(),
+            gc_threshold: DEFAULT_GC_THRESHOLD,
+            max_fills_per_match: DEFAULT_MAX_FILLS_PER_MATCH,
+            circuit_breaker: None,
+            events: EventSink::new(DEFAULT_EVENT_BUFFER_CAPACITY),
+            pending_fills: VecDeque::new(),
+        }
+    }
+
+    /// Replaces the book's time source, returning the updated book
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Sets how many expired resting orders may accumulate before `place_order`
+    /// purges them, returning the updated book
+    pub fn with_gc_threshold(mut self, gc_threshold: usize) -> Self {
+        self.gc_threshold = gc_threshold;
+        self
+    }
+
+    /// Sets the maximum number of fills a taker may take in one match, returning the
+    /// updated book; orders with their own `max_fills` ignore it
+    pub fn with_max_fills_per_match(mut self, max_fills_per_match: usize) -> Self {
+        self.max_fills_per_match = max_fills_per_match;
+        self
+    }
+
+    /// Installs a circuit breaker that halts matching at prices more than
+    /// `max_deviation_bps` from `reference_price`, returning the updated book
+    pub fn with_circuit_breaker(mut self, reference_price: u64, max_deviation_bps: u64) -> Self {
+        self.circuit_breaker = Some(CircuitBreaker { reference_price, max_deviation_bps, tripped: false });
+        self
+    }
+
+    /// Returns the installed circuit breaker, if any
+    pub fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.circuit_breaker.as_ref()
+    }
+
+    /// Resumes matching after the circuit breaker tripped, measuring deviations from a
+    /// new reference price from now on
+    ///
+    /// Does nothing if no circuit breaker is installed.
+    pub fn reset_circuit_breaker(&mut self, new_reference: u64) {
+        if let Some(breaker) = &mut self.circuit_breaker {
+            breaker.reference_price = new_reference;
+            breaker.tripped = false;
+        }
+    }
+
+    /// Records every placement, cancellation and expiry from now on, returning the
+    /// updated book
+    pub fn with_event_recording(mut self) -> Self {
+        self.events.log.get_or_insert_with(Vec::new);
+        self
+    }
+
+    /// Returns the recorded events, oldest first; empty unless recording is enabled
+    pub fn events(&self) -> &[BookEvent] {
+        self.events.log.as_deref().unwrap_or_default()
+    }
+
+    /// Stamps every event from now on with a sequence number and batch ID and keeps
+    /// it for `events_since`, returning the updated book
+    ///
+    /// Sequence numbers increase by one per event for the life of the book, so a
+    /// subscriber can tell it missed events and catch up. Events from one
+    /// `place_order` call, including fills and triggered orders, share a batch ID, as
+    /// do those from one `cancel_order`, `match_order`, `bulk_update`,
+    /// `conditional_place` or batched placement; other operations add to the latest
+    /// batch.
+    #[cfg(feature = "serde")]
+    pub fn with_event_stream(mut self) -> Self {
+        self.events.stream.get_or_insert_with(Vec::new);
+        self
+    }
+
+    /// Returns the streamed events after sequence number `sequence`, oldest first
+    ///
+    /// Pass the last sequence number already seen, or 0 for every event since
+    /// streaming was enabled. Empty unless `with_event_stream` was used.
+    #[cfg(feature = "serde")]
+    pub fn events_since(&self, sequence: u64) -> &[SequencedEvent] {
+        let stream = self.events.stream.as_deref().unwrap_or_default();
+        &stream[stream.partition_point(|event| event.sequence_number <= sequence)..]
+    }
+
+    /// Sets how many undrained events the market-data buffer holds before dropping
+    /// the oldest, returning the updated book; zero disables buffering
+    pub fn with_event_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.events.buffer_capacity = capacity;
+        while self.events.buffer.len() > capacity {
+            self.events.buffer.pop_front();
+            self.events.dropped += 1;
+        }
+        self
+    }
+
+    /// Removes and returns the buffered events, oldest first
+    ///
+    /// Meant to be polled by a market-data feed; events that arrive while the buffer
+    /// is full evict the oldest, which are counted by `dropped_event_count`.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = BookEvent> + '_ {
+        self.events.buffer.drain(..)
+    }
+
+    /// Number of buffered events evicted before they were drained
+    pub fn dropped_event_count(&self) -> u64 {
+        self.events.dropped
+    }
+
+    /// Registers a pre-trade hook, returning the updated book
+    pub fn with_pre_trade_hook(mut self, hook: PreTradeHook) -> Self {
+        self.add_pre_trade_hook(hook);
+        self
+    }
+
+    /// Registers a pre-trade hook to run after any previously registered hooks
+    pub fn add_pre_trade_hook(&mut self, hook: PreTradeHook) {
+        self.pre_trade_hooks.0.push(hook);
+    }
+
+    /// Attempts to match an incoming order against existing orders
+    /// Returns the fills created during matching and why matching stopped
+    ///
+    /// A limit taker whose expiry is before `timestamp` is not matched.
+    ///
+    /// # Arguments
+    /// * `taker_order` - The incoming order to match
+    /// * `timestamp` - Current timestamp for order expiration checks
+    pub fn match_order(&mut self, mut taker_order: Order, timestamp: u64) -> MatchResult {
+        self.events.begin_batch();
+        self.match_against_book(&mut taker_order, timestamp, &MatchProtection::default()).0
+    }
+
+    /// Computes the fills an order would receive without modifying the book
+    ///
+    /// Runs the same matching as `match_order` over a scratch copy of the makers it
+    /// reaches, so iceberg tranches requeue and self-trade prevention and the circuit
+    /// breaker stop it as they would live. Price-improvement and maker statistics are
+    /// not recorded.
+    ///
+    /// # Arguments
+    /// * `order` - The incoming order to simulate
+    /// * `timestamp` - Current timestamp for order expiration checks
+    pub fn simulate_match(&self, order: &Order, timestamp: u64) -> Vec<Fill> {
+        let mut taker = order.clone();
+        if taker.order_type != OrderType::Market && taker.expire_timestamp < timestamp {
+            return Vec::new();
+        }
+        let mut view = SimulatedMatch {
+            book: self,
+            taker_is_bid: order.is_bid(),
+            cursor: None,
+            current: None,
+            requeued: BTreeMap::new(),
+            sequence_counter: self.sequence_counter,
+            circuit_breaker: self.circuit_breaker,
+        };
+        let max_fills = self.max_fills_for(order);
+        let protection = MatchProtection::default();
+        match_resting_orders(&mut view, &mut taker, timestamp, self.mid_price(), max_fills, &protection).0
+    }
+
+    /// Estimates the execution price of a market order without modifying the book
+    ///
+    /// Runs the same walk as `simulate_match`, so expired makers are skipped and the
+    /// sweep stops at the book's per-match fill limit. Returns `None` if nothing would
+    /// fill.
+    ///
+    /// # Arguments
+    /// * `side` - Side of the incoming order; bids sweep the asks
+    /// * `quantity` - Base quantity to trade
+    pub fn calculate_impact_price(&self, side: Side, quantity: u64) -> Option<ImpactResult> {
+        let taker = Order::new_market(0, quantity, Address::default(), side == Side::Bid).ok()?;
+        let fills = self.simulate_match(&taker, self.clock.now());
+        let worst_price = fills.last()?.price;
+        let filled_quantity: u64 = fills.iter().map(|fill| fill.base_quantity).sum();
+        let notional: u128 = fills.iter().map(|fill| fill.base_quantity as u128 * fill.price as u128).sum();
+        Some(ImpactResult {
+            avg_price: (notional / filled_quantity as u128) as u64,
+            worst_price,
+            filled_quantity,
+            unfilled_quantity: quantity - filled_quantity,
+        })
+    }
+
+    /// Estimates the fills, costs and resulting top of book of placing an order
+    /// without modifying the book
+    ///
+    /// # Arguments
+    /// * `order` - The order to evaluate
+    /// * `timestamp` - Current timestamp for order expiration checks
+    pub fn fill_simulation_report(&self, order: &Order, timestamp: u64) -> FillSimulationReport {
+        let fills = self.simulate_match(order, timestamp);
+        let would_fill_qty: u64 = fills.iter().map(|fill| fill.base_quantity).sum();
+        // Only limit orders rest; every other type drops what it cannot fill
+        let would_rest_qty = match order.order_type {
+            OrderType::Limit => order.remaining_quantity() - would_fill_qty,
+            _ => 0,
+        };
+        let estimated_avg_price = volume_weighted(fills.iter().map(|fill| (fill.price as f64, fill.base_quantity)))
+            .map_or(0, |price| price.round() as u64);
+        let estimated_taker_fee = fills.iter().map(|fill| fill.taker_fee).sum();
+        let estimated_market_impact_bps = fills.first().map_or(0.0, |first| {
+            estimated_avg_price.abs_diff(first.price) as f64 * BPS_DENOMINATOR as f64 / first.price as f64
+        });
+        let mut crosses_levels = 0;
+        let mut last_price = None;
+        for fill in &fills {
+            if last_price != Some(fill.price) {
+                crosses_levels += 1;
+                last_price = Some(fill.price);
+            }
+        }
+
+        // The opposite side's new best is the first live order the fills leave quantity in
+        let mut filled: HashMap<u128, u64> = HashMap::new();
+        for fill in &fills {
+            *filled.entry(fill.maker_order_id).or_default() += fill.base_quantity;
+        }
+        let opposite_best = self
+            .iter_orders(!order.is_bid())
+            .find(|maker| {
+                let simulated_fill = filled.get(&maker.order_id).copied().unwrap_or_default();
+                maker.expire_timestamp >= timestamp && maker.remaining_quantity() > simulated_fill
+            })
+            .map(|maker| maker.price);
+        let own_best = self.iter_orders(order.is_bid()).next().map(|resting| resting.price);
+        let own_best = match (own_best, would_rest_qty > 0) {
+            (Some(price), true) if order.is_bid() => Some(price.max(order.price)),
+            (Some(price), true) => Some(price.min(order.price)),
+            (None, true) => Some(order.price),
+            (best, false) => best,
+        };
+        let (post_trade_best_bid, post_trade_best_ask) = if order.is_bid() {
+            (own_best, opposite_best)
+        } else {
+            (opposite_best, own_best)
+        };
+
+        FillSimulationReport {
+            would_fill_qty,
+            would_rest_qty,
+            estimated_avg_price,
+            estimated_taker_fee,
+            estimated_market_impact_bps,
+            fills_count: fills.len(),
+            crosses_levels,
+            post_trade_spread: post_trade_best_bid
+                .zip(post_trade_best_ask)
+                .map(|(bid, ask)| ask.saturating_sub(bid)),
+            post_trade_best_bid,
+            post_trade_best_ask,
+        }
+    }
+
+    /// Matches an order at the first candidate price with enough liquidity to fill it
+    ///
+    /// The original price is simulated first, then each alternative in order, skipping
+    /// any the order would be rejected at, e.g. off the tick grid. The order is placed
+    /// once, as an immediate-or-cancel order at the first price whose simulated fills
+    /// cover the full quantity, and goes through the same checks as `place_order`. If
+    /// no candidate has enough liquidity nothing is placed.
+    ///
+    /// # Arguments
+    /// * `order` - The incoming order, priced at its preferred limit
+    /// * `alternative_prices` - Fallback limit prices, tried in order
+    ///
+    /// Returns the routing outcome, or the error that rejected the order at the chosen
+    /// price
+    pub fn place_order_with_smart_routing(
+        &mut self,
+        mut order: Order,
+        alternative_prices: &[u64],
+    ) -> Result<SmartRoutingResult, PlaceError> {
+        let timestamp = self.clock.now();
+        let quantity = order.remaining_quantity();
+        order.order_type = OrderType::ImmediateOrCancel;
+        if order.stp_mode == STPMode::None {
+            order.stp_mode = self.params.default_stp_mode;
+        }
+        let mut result = SmartRoutingResult::default();
+        for price in std::iter::once(order.price).chain(alternative_prices.iter().copied()) {
+            let candidate = Order { price, ..order.clone() };
+            if self.validate_order(&candidate).is_err() {
+                continue;
+            }
+            result.prices_tried += 1;
+            let simulated: u64 = self.simulate_match(&candidate, timestamp).iter().map(|fill| fill.base_quantity).sum();
+            if simulated >= quantity {
+                result.filled_at_price = Some(price);
+                result.fills = self.place_order_sync(candidate)?;
+                break;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Replays events on empty books with this book's parameters, once as submitted
+    /// and once with every event delayed by `latency_ns`
+    ///
+    /// Expiry timestamps are absolute, so delayed takers can miss makers that expire
+    /// in the meantime. Rejected orders produce no fills.
+    ///
+    /// # Arguments
+    /// * `events` - Events in submission order
+    /// * `latency_ns` - Delay added to each event's arrival time
+    pub fn replay_with_latency_injection(&self, events: &[OrderEvent], latency_ns: u64) -> ReplayResult {
+        let fills_without_latency = self.replay_events(events, 0);
+        let fills_with_latency = self.replay_events(events, latency_ns);
+        let matching = fills_without_latency
+            .iter()
+            .zip(&fills_with_latency)
+            .filter(|(original, delayed)| {
+                original.price == delayed.price && original.base_quantity == delayed.base_quantity
+            })
+            .count();
+        let fills_difference = fills_without_latency.len().max(fills_with_latency.len()) - matching;
+        ReplayResult {
+            fills_without_latency,
+            fills_with_latency,
+            fills_difference,
+        }
+    }
+
+    /// Rebuilds a book from recorded events, returning it with every fill in order
+    ///
+    /// Each placement runs at its submission time on a mock clock with default market
+    /// parameters, so matching, expiries and purges recur on their own; an expiry event
+    /// only removes an order the replay has not already evicted. Market resets are
+    /// reapplied; amendments and auctions are not recorded. The rebuilt book records
+    /// its own events.
+    ///
+    /// # Arguments
+    /// * `events` - Events from `Book::events`, oldest first
+    pub fn replay(events: &[BookEvent]) -> (Book, Vec<Fill>) {
+        let clock = MockClock::default();
+        let mut book = Book::new().with_clock(clock.clone()).with_event_recording();
+        let mut fills = Vec::new();
+        for event in events {
+            match event {
+                BookEvent::Placed(order) => {
+                    clock.set(order.submission_timestamp);
+                    fills.extend(book.place_order_sync(order.clone()).unwrap_or_default());
+                }
+                BookEvent::Cancelled { order_id, is_bid } => {
+                    book.cancel_order(*order_id, *is_bid);
+                }
+                BookEvent::MarketReset(reset) => {
+                    clock.set(reset.timestamp);
+                    book.apply_market_reset(reset.params.clone(), reset.timestamp)
+                        .expect("recorded parameters were accepted");
+                }
+                // Fills recur from the placements that caused them
+                BookEvent::Filled(_) | BookEvent::TopOfBookChanged(_) => {}
+                BookEvent::Expired { order_id, is_bid } => {
+                    let book_side = if *is_bid { &book.bids } else { &book.asks };
+                    let Some(&key) = book.order_keys.get(order_id).filter(|key| book_side.contains_key(key)) else {
+                        continue;
+                    };
+                    let now = clock.now();
+                    book.remove_expired(*is_bid, key, now);
+                    book.refresh_best_quote_owners(now);
+                    book.publish_level_changes();
+                }
+            }
+        }
+        (book, fills)
+    }
+
+    /// Replays events on an empty book driven by a mock clock, returning all fills
+    fn replay_events(&self, events: &[OrderEvent], latency_ns: u64) -> Vec<Fill> {
+        let clock = MockClock::default();
+        let mut book = Book::new_with_params(self.params.clone()).with_clock(clock.clone());
+        let mut fills = Vec::new();
+        for event in events {
+            clock.set(event.timestamp().saturating_add(latency_ns));
+            match event {
+                OrderEvent::Place { order, .. } => fills.extend(book.place_order_sync(order.clone()).unwrap_or_default()),
+                OrderEvent::Cancel { order_id, is_bid, .. } => {
+                    book.cancel_order(*order_id, *is_bid);
+                }
+            }
+        }
+        fills
+    }
+
+    /// Matches an incoming order, stopping early when a protective constraint is hit
+    ///
+    /// # Arguments
+    /// * `taker` - The incoming order to match
+    /// * `timestamp` - Current timestamp for order expiration checks
+    /// * `protection` - Constraints checked before each fill
+    ///
+    /// Returns the fills, why matching stopped and the taker's unfilled quantity
+    pub fn match_order_with_protection(
+        &mut self,
+        mut taker: Order,
+        timestamp: u64,
+        protection: MatchProtection,
+    ) -> ProtectedMatchResult {
+        let (result, termination_reason) = self.match_against_book(&mut taker, timestamp, &protection);
+        ProtectedMatchResult {
+            fills: result.fills,
+            termination_reason,
+            remaining: taker.remaining_quantity(),
+        }
+    }
+
+    /// Matches the taker against the opposite side, updating its filled quantity in place
+    ///
+    /// Returns the fills and self-trade cancellations, and the reason matching stopped
+    fn match_against_book(
+        &mut self,
+        taker_order: &mut Order,
+        timestamp: u64,
+        protection: &MatchProtection,
+    ) -> (MatchResult, TerminationReason) {
+        let mid_price = self.mid_price();
+        let max_fills = self.max_fills_for(taker_order);
+        let max_fills = protection.max_fills.map_or(max_fills, |protected| protected.min(max_fills));
+
+        // Market orders carry no expiry; any other taker past its expiry is not matched
+        if taker_order.order_type != OrderType::Market && taker_order.expire_timestamp < timestamp {
+            let result = MatchResult { fills: Vec::new(), stp_cancelled: Vec::new(), stop_reason: StopReason::TakerExpired };
+            return (result, TerminationReason::Complete);
+        }
+
+        let mut view = LiveMatch { taker_is_bid: taker_order.is_bid(), book: self, skipped_through: None };
+        let (fills, stp_cancelled, mut termination_reason) =
+            match_resting_orders(&mut view, taker_order, timestamp, mid_price, max_fills, protection);
+
+        if taker_order.is_filled() {
+            self.settled_orders.record(taker_order.order_id, OrderStatus::Filled);
+        }
+        let partially_filled = taker_order.filled_quantity > 0 && !taker_order.is_filled();
+        if termination_reason == TerminationReason::Complete && protection.cancel_on_partial && partially_filled {
+            termination_reason = TerminationReason::PartialCancelled;
+        }
+        let stop_reason = match termination_reason {
+            TerminationReason::FillsCapped => StopReason::MaxFillsReached,
+            TerminationReason::CircuitBreakerTripped => StopReason::CircuitBreakerTripped,
+            TerminationReason::SelfTradePrevented => StopReason::SelfTradePrevented,
+            TerminationReason::SlippageBreached | TerminationReason::MinQtyNotMet => StopReason::ProtectionLimitReached,
+            TerminationReason::Complete | TerminationReason::PartialCancelled if taker_order.is_filled() => {
+                StopReason::TakerFullyFilled
+            }
+            TerminationReason::Complete | TerminationReason::PartialCancelled => StopReason::NoCrossableOrders,
+        };
+        self.refresh_best_quote_owners(timestamp);
+        self.publish_level_changes();
+        (MatchResult { fills, stp_cancelled, stop_reason }, termination_reason)
+    }
+
+    /// Maximum number of fills a taker may take: its own limit, else the book's
+    fn max_fills_for(&self, taker: &Order) -> usize {
+        taker.max_fills.unwrap_or(self.max_fills_per_match)
+    }
+
+    /// Checks if two orders' prices match for trading
+    ///
+    /// # Arguments
+    /// * `taker` - The incoming aggressive order
+    /// * `maker` - The resting passive order
+    fn prices_match(taker: &Order, maker: &Order) -> bool {
+        if taker.order_type == OrderType::Market {
+            true // Market orders take whatever price is available
+        } else if taker.is_bid() {
+            taker.price >= maker.price // Bid must be greater than or equal to ask
+        } else {
+            taker.price <= maker.price // Ask must be less than or equal to bid
+        }
+    }
+
+    /// Places an order originating from a venue, ahead of same-price orders from
+    /// lower-priority venues
+    ///
+    /// # Arguments
+    /// * `venue_priority` - Priority of the originating venue, lower is better
+    /// * `order` - The new order to place
+    ///
+    /// Returns the same result as `place_order_sync`
+    pub fn place_order_with_venue_priority(&mut self, venue_priority: u8, order: Order) -> PlaceOrderResult {
+        self.place_order_sync(Order { venue_priority, ..order })
+    }
+
+    /// Places a new order in the book, attempting to match it first
+    ///
+    /// Only limit orders rest. Market and immediate-or-cancel remainders are
+    /// cancelled, fill-or-kill orders that cannot fill completely return no fills
+    /// without matching, and during an auction only limit orders are collected.
+    /// Stop orders are held off the book; after each placement, stops triggered by
+    /// the last trade are submitted and their fills appended.
+    ///
+    /// # Arguments
+    /// * `order` - The new order to place
+    ///
+    /// Fills are queued for `drain_fills` rather than returned.
+    ///
+    /// Returns the number of fills queued and why matching stopped, the first
+    /// validation error if the price
+    /// or quantity breaks the market's tick, lot or size limits or the ID is already
+    /// resting, the first error raised by a pre-trade hook, or the regulatory
+    /// violations that rejected the order when `regulatory_mode` is enabled
+    pub fn place_order(&mut self, order: Order) -> Result<PlaceOutcome, PlaceError> {
+        let timestamp = self.clock.now();
+        self.events.begin_batch();
+        let top = (self.best_bid(), self.best_ask());
+        let (fills, stop_reason) = self.place_order_at(order, timestamp)?;
+        let fill_count = fills.len();
+        self.pending_fills.extend(fills);
+        self.record_top_of_book_change(top, timestamp);
+        Ok(PlaceOutcome { fill_count, stop_reason })
+    }
+
+    /// Records a `TopOfBookChanged` event if the best prices differ from `previous`
+    fn record_top_of_book_change(&mut self, previous: (Option<u64>, Option<u64>), timestamp: u64) {
+        let (new_best_bid, new_best_ask) = (self.best_bid(), self.best_ask());
+        if (new_best_bid, new_best_ask) != previous {
+            self.events
+                .record(|| BookEvent::TopOfBookChanged(TopOfBookEvent { new_best_bid, new_best_ask, timestamp }));
+        }
+    }
+
+    /// Places an order as `place_order` does and takes its fills straight back off
+    /// the queue
+    ///
+    /// Fills queued by earlier `place_order` calls are left for `drain_fills`.
+    ///
+    /// # Arguments
+    /// * `order` - The new order to place
+    ///
+    /// Returns a vector of fills if any matches occurred, or the error that rejected
+    /// the order
+    pub fn place_order_sync(&mut self, order: Order) -> PlaceOrderResult {
+        let fill_count = self.place_order(order)?.fill_count;
+        let first = self.pending_fills.len() - fill_count;
+        Ok(self.pending_fills.drain(first..).collect())
+    }
+
+    /// Removes and returns the fills queued by `place_order`, oldest first
+    pub fn drain_fills(&mut self) -> impl Iterator<Item = Fill> + '_ {
+        self.pending_fills.drain(..)
+    }
+
+    /// Places orders one after another, each matching against the book left by those
+    /// before it
+    ///
+    /// Every order is stamped with `timestamp` instead of the clock's time. Rejected
+    /// orders leave the book as it was, and the rest of the batch is still placed.
+    ///
+    /// # Arguments
+    /// * `orders` - Orders in submission order
+    /// * `timestamp` - Submission time for every order in the batch
+    ///
+    /// Returns one result per order, in the same order
+    pub fn place_batch(&mut self, orders: Vec<Order>, timestamp: u64) -> Vec<BatchResult> {
+        orders
+            .into_iter()
+            .map(|order| {
+                let order_id = order.order_id;
+                self.events.begin_batch();
+                match self.place_order_at(order, timestamp) {
+                    Ok((fills, _)) => BatchResult { order_id, fills, error: None },
+                    Err(error) => BatchResult { order_id, fills: Vec::new(), error: Some(error) },
+                }
+            })
+            .collect()
+    }
+
+    /// Places a market maker's bid and ask together, bid first
+    ///
+    /// Both orders are validated before either is placed. Either side may trade on
+    /// arrival, and any remainder rests as for `place_order`.
+    ///
+    /// # Arguments
+    /// * `bid` - Buy order, priced below `ask`
+    /// * `ask` - Sell order
+    /// * `timestamp` - Submission time of both orders
+    ///
+    /// Returns the fills and resting state of each side, or why the quote failed
+    pub fn two_sided_quote(&mut self, bid: Order, ask: Order, timestamp: u64) -> Result<TwoSidedResult, QuoteError> {
+        if !bid.is_bid() || ask.is_bid() {
+            return Err(QuoteError::WrongSide);
+        }
+        if bid.price >= ask.price {
+            return Err(QuoteError::SelfCrossing);
+        }
+        self.validate_order(&bid).map_err(QuoteError::Invalid)?;
+        self.validate_order(&ask).map_err(QuoteError::Invalid)?;
+        if bid.order_id == ask.order_id {
+            return Err(QuoteError::Invalid(PlaceError::OrderAlreadyExists));
+        }
+
+        self.events.begin_batch();
+        let top = (self.best_bid(), self.best_ask());
+        let (bid_id, ask_id) = (bid.order_id, ask.order_id);
+        let bid_fills = self.place_order_at(bid, timestamp).map_err(QuoteError::Invalid)?.0;
+        let result = match self.place_order_at(ask, timestamp) {
+            Ok((ask_fills, _)) => Ok(TwoSidedResult {
+                bid_resting: self.order_status(bid_id, Side::Bid).is_some(),
+                ask_resting: self.order_status(ask_id, Side::Ask).is_some(),
+                bid_fills,
+                ask_fills,
+            }),
+            Err(error) => Err(QuoteError::AskRejected { bid_fills, error }),
+        };
+        self.record_top_of_book_change(top, timestamp);
+        result
+    }
+
+    /// Cancels a set of orders and then places a set of orders in one call, e.g. to
+    /// refresh a market maker's quote ladder
+    ///
+    /// Every cancellation happens before any placement, so new quotes never match
+    /// the quotes they replace. IDs that are no longer resting are skipped. A
+    /// rejected placement leaves the book as it was and the rest are still placed.
+    ///
+    /// # Arguments
+    /// * `cancels` - IDs and sides of the orders to cancel
+    /// * `places` - Orders to place, in submission order
+    /// * `timestamp` - Time of every cancellation and placement
+    pub fn bulk_update(&mut self, cancels: Vec<(u128, OrderSide)>, places: Vec<Order>, timestamp: u64) -> BulkResult {
+        self.events.begin_batch();
+        let top = (self.best_bid(), self.best_ask());
+        let mut result = BulkResult::default();
+        for (order_id, side) in cancels {
+            if let Some(order) = self.cancel_order_at(order_id, side == Side::Bid, timestamp).into_order() {
+                result.cancelled.push(order);
+            }
+        }
+        for order in places {
+            match self.place_order_at(order.clone(), timestamp) {
+                Ok((fills, _)) => result.fills.extend(fills),
+                Err(error) => result.rejected.push((order, error)),
+            }
+        }
+        self.record_top_of_book_change(top, timestamp);
+        result
+    }
+
+    /// Quotes `levels` bids and `levels` asks for one owner, spaced a tick apart and
+    /// mirrored around `mid_price`
+    ///
+    /// The best bid is `mid_price * (1 - spread_bps / 20000)` rounded down to the tick
+    /// and the best ask `mid_price * (1 + spread_bps / 20000)` rounded up, so they sit
+    /// `spread_bps` apart. Orders take IDs above every ID the book has accepted, including
+    /// held stop, conditional and auction orders and settled ones, and are placed best
+    /// bid, best ask, then outwards; any fills are queued as by `place_order`.
+    ///
+    /// # Arguments
+    /// * `owner` - Owner of every ladder order
+    /// * `mid_price` - Price the ladder is centred on
+    /// * `spread_bps` - Distance between the best bid and best ask in basis points
+    /// * `levels` - Number of orders on each side
+    /// * `quantity_per_level` - Quantity of every ladder order
+    /// * `expire_timestamp` - Expiry of every ladder order
+    ///
+    /// Returns the placed order IDs, `PriceBelowMinimum` without placing anything if
+    /// the lowest bid would fall below one tick, or the first error that rejected an
+    /// order, leaving the ones before it placed
+    pub fn price_ladder(
+        &mut self,
+        owner: Address,
+        mid_price: u64,
+        spread_bps: u64,
+        levels: usize,
+        quantity_per_level: u64,
+        expire_timestamp: u64,
+    ) -> Result<Vec<u128>, PlaceError> {
+        let tick_size = self.params.tick_size;
+        let half_spread = |sign: i128| {
+            (mid_price as i128 * (2 * BPS_DENOMINATOR as i128 + sign * spread_bps as i128) / (2 * BPS_DENOMINATOR as i128))
+                .max(0) as u64
+        };
+        let best_bid = half_spread(-1) / tick_size * tick_size;
+        let best_ask = half_spread(1).div_ceil(tick_size) * tick_size;
+        let depth = (levels as u64).saturating_sub(1).saturating_mul(tick_size);
+        if best_bid < depth.saturating_add(tick_size) {
+            return Err(PlaceError::PriceBelowMinimum);
+        }
+
+        let mut order_id = self.next_order_id;
+        let mut placed = Vec::with_capacity(2 * levels);
+        for level in 0..levels as u64 {
+            for (side, price) in [(Side::Bid, best_bid - level * tick_size), (Side::Ask, best_ask + level * tick_size)] {
+                let order = Order::new_limit(order_id, price, quantity_per_level, owner, expire_timestamp, side)?;
+                self.place_order(order)?;
+                placed.push(order_id);
+                order_id += 1;
+            }
+        }
+        Ok(placed)
+    }
+
+    /// Places an order as `place_order` does, submitted at the given time
+    fn place_order_at(&mut self, mut order: Order, timestamp: u64) -> Result<(Vec<Fill>, Option<StopReason>), PlaceError> {
+        self.validate_order(&order)?;
+        if order.order_type == OrderType::Market && self.iter_orders(!order.is_bid()).next().is_none() {
+            return Err(BookError::NoLiquidity.into());
+        }
+
+        // Hooks run before anything is recorded so a rejection leaves the book untouched
+        for hook in &self.pre_trade_hooks.0 {
+            hook(&order)?;
+        }
+        self.check_dynamic_min_spread(&order)?;
+
+        if self.params.regulatory_mode {
+            let violations = self.apply_regulatory_pre_trade_check(&order, &self.params.regulatory_risk);
+            self.record_submission(&order);
+            if !violations.is_empty() {
+                return Err(BookError::RegulatoryViolation(violations).into());
+            }
+        }
+
+        self.quote_activity.entry(order.owner).or_default().orders_submitted += 1;
+        self.stats.total_orders_placed += 1;
+        self.reserve_order_id(order.order_id);
+
+        order.submission_timestamp = timestamp;
+        if order.stp_mode == STPMode::None {
+            order.stp_mode = self.params.default_stp_mode;
+        }
+        self.events.record(|| BookEvent::Placed(order.clone()));
+        if self.count_expired_resting(timestamp) > self.gc_threshold {
+            self.purge_expired(timestamp);
+        }
+
+        // Stops wait for their trigger, and orders arriving during an auction for the uncross
+        let (mut fills, stop_reason) = match (self.mode, order.order_type) {
+            (_, OrderType::Stop { .. } | OrderType::StopLimit { .. }) => {
+                self.stop_orders.push(order);
+                (Vec::new(), None)
+            }
+            (BookMode::VolatilityAuction { .. }, order_type) => {
+                if order_type == OrderType::Limit {
+                    self.auction_orders.push(order);
+                }
+                return Ok((Vec::new(), None));
+            }
+            (BookMode::Continuous, _) => {
+                let (fills, stop_reason) = self.execute_order(order, timestamp);
+                (fills, Some(stop_reason))
+            }
+        };
+        fills.extend(self.activate_triggered_stops(timestamp));
+        fills.extend(self.fire_conditional_orders(timestamp));
+        Ok((fills, stop_reason))
+    }
+
+    /// Checks an incoming order against the market's price and size limits and the
+    /// resting order IDs
+    fn validate_order(&self, order: &Order) -> Result<(), PlaceError> {
+        // Market orders carry a sentinel price rather than a limit
+        let limit_price = match order.order_type {
+            OrderType::StopLimit { limit_price, .. } => Some(limit_price),
+            OrderType::Market => None,
+            _ => Some(order.price),
+        };
+        if let Some(price) = limit_price {
+            if price < self.params.tick_size {
+                return Err(PlaceError::PriceBelowMinimum);
+            }
+            if !price.is_multiple_of(self.params.tick_size) {
+                return Err(PlaceError::PriceNotTickAligned);
+            }
+            if price > self.params.max_price {
+                return Err(PlaceError::PriceAboveMaximum);
+            }
+        }
+        if order.quantity < self.params.min_size {
+            return Err(PlaceError::QuantityBelowMinimum);
+        }
+        if !order.quantity.is_multiple_of(self.params.lot_size) {
+            return Err(PlaceError::QuantityNotLotAligned);
+        }
+        if !order.quantity.is_multiple_of(self.params.min_qty_increment) {
+            return Err(PlaceError::QuantityNotIncrementAligned);
+        }
+        let aligned = |peak: u64| peak.is_multiple_of(self.params.lot_size) && peak.is_multiple_of(self.params.min_qty_increment);
+        if order.iceberg_peak.is_some_and(|peak| peak == 0 || !aligned(peak)) {
+            return Err(PlaceError::InvalidIcebergPeak);
+        }
+        if self.order_keys.contains_key(&order.order_id) {
+            return Err(PlaceError::OrderAlreadyExists);
+        }
+        Ok(())
+    }
+
+    /// Matches an order that has passed pre-trade checks and rests any limit remainder
+    fn execute_order(&mut self, mut order: Order, timestamp: u64) -> (Vec<Fill>, StopReason) {
+        // The simulation stops where matching would, at self-trade prevention, the
+        // breaker or the fill limit, so a fill-or-kill order never partially fills
+        if order.order_type == OrderType::FillOrKill {
+            let fillable: u64 = self.simulate_match(&order, timestamp).iter().map(|fill| fill.base_quantity).sum();
+            if fillable < order.remaining_quantity() {
+                return (Vec::new(), StopReason::NoCrossableOrders);
+            }
+        }
+
+        // First try to match the order
+        let (result, termination_reason) = self.match_against_book(&mut order, timestamp, &MatchProtection::default());
+
+        // If a limit order is not fully filled, place it in the book. A remainder the
+        // breaker halted still crosses the book, and an expired one has lapsed, so
+        // they are dropped instead.
+        let cancelled = matches!(
+            termination_reason,
+            TerminationReason::SelfTradePrevented | TerminationReason::CircuitBreakerTripped
+        ) || result.stop_reason == StopReason::TakerExpired;
+        if !order.is_filled() && !cancelled && order.order_type == OrderType::Limit {
+            self.rest_order(order, timestamp);
+        }
+
+        (result.fills, result.stop_reason)
+    }
+
+    /// Submits every stop order whose trigger the last trade has reached, repeating
+    /// while activated stops trade through further triggers
+    ///
+    /// Buy stops trigger when the last trade is at or above the trigger price, sell
+    /// stops when it is at or below. Stops activate in the order they were placed.
+    fn activate_triggered_stops(&mut self, timestamp: u64) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        if let BookMode::VolatilityAuction { .. } = self.mode {
+            return fills;
+        }
+        while let Some(last_trade_price) = self.last_trade_price() {
+            let (triggered, waiting): (Vec<Order>, Vec<Order>) =
+                std::mem::take(&mut self.stop_orders).into_iter().partition(|order| match order.order_type {
+                    OrderType::Stop { trigger_price } | OrderType::StopLimit { trigger_price, .. } => {
+                        if order.is_bid() {
+                            last_trade_price >= trigger_price
+                        } else {
+                            last_trade_price <= trigger_price
+                        }
+                    }
+                    _ => true,
+                });
+            self.stop_orders = waiting;
+            if triggered.is_empty() {
+                break;
+            }
+
+            for mut order in triggered {
+                match order.order_type {
+                    OrderType::StopLimit { limit_price, .. } => {
+                        order.price = limit_price;
+                        order.order_type = OrderType::Limit;
+                    }
+                    _ => order.order_type = OrderType::Market,
+                }
+                fills.extend(self.execute_order(order, timestamp).0);
+            }
+        }
+        fills
+    }
+
+    /// Places an order only once the book meets a condition, e.g. to join the bid
+    /// only after the spread has tightened
+    ///
+    /// The order is checked against the market's limits straight away. If the
+    /// condition holds it is placed as by `place_order`, otherwise it is held off the
+    /// book, invisible to other traders, and retried after every later placement.
+    /// A held order rejected when it finally fires, e.g. because its ID has since
+    /// been used, is dropped.
+    ///
+    /// # Arguments
+    /// * `order` - The order to place
+    /// * `condition` - Book state to wait for
+    /// * `timestamp` - Submission time if the order is placed now
+    ///
+    /// Returns the fills if the order was placed, `ConditionNotMet` if it is held, or
+    /// the error that rejected it
+    pub fn conditional_place(&mut self, order: Order, condition: PlaceCondition, timestamp: u64) -> ConditionalResult {
+        if let Err(error) = self.validate_order(&order) {
+            return ConditionalResult::Rejected(error);
+        }
+        if !self.condition_met(condition) {
+            self.reserve_order_id(order.order_id);
+            self.conditional_orders.push((order, condition));
+            return ConditionalResult::ConditionNotMet;
+        }
+
+        self.events.begin_batch();
+        let top = (self.best_bid(), self.best_ask());
+        let result = match self.place_order_at(order, timestamp) {
+            Ok((fills, _)) => ConditionalResult::Placed(fills),
+            Err(error) => ConditionalResult::Rejected(error),
+        };
+        self.record_top_of_book_change(top, timestamp);
+        result
+    }
+
+    /// Orders from `conditional_place` still waiting for their condition, in
+    /// placement order
+    pub fn conditional_orders(&self) -> &[(Order, PlaceCondition)] {
+        &self.conditional_orders
+    }
+
+    /// Checks a `conditional_place` condition against the current best prices
+    fn condition_met(&self, condition: PlaceCondition) -> bool {
+        match condition {
+            PlaceCondition::IfBestAskAtOrBelow(price) => self.best_ask().is_some_and(|best_ask| best_ask <= price),
+            PlaceCondition::IfBestBidAtOrAbove(price) => self.best_bid().is_some_and(|best_bid| best_bid >= price),
+            PlaceCondition::IfSpreadAtOrBelow(spread) => {
+                self.best_bid().zip(self.best_ask()).is_some_and(|(bid, ask)| ask.saturating_sub(bid) <= spread)
+            }
+            PlaceCondition::Always => true,
+        }
+    }
+
+    /// Places every held conditional order whose condition now holds, repeating while
+    /// placed orders move the book into further conditions
+    fn fire_conditional_orders(&mut self, timestamp: u64) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        if let BookMode::VolatilityAuction { .. } = self.mode {
+            return fills;
+        }
+        loop {
+            let (fired, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.conditional_orders)
+                .into_iter()
+                .partition(|&(_, condition)| self.condition_met(condition));
+            self.conditional_orders = waiting;
+            if fired.is_empty() {
+                break;
+            }
+
+            for (order, _) in fired {
+                if let Ok((order_fills, _)) = self.place_order_at(order, timestamp) {
+                    fills.extend(order_fills);
+                }
+            }
+        }
+        fills
+    }
+
+    /// Stop orders waiting for their trigger, in placement order
+    pub fn stop_orders(&self) -> &[Order] {
+        &self.stop_orders
+    }
+
+    /// Returns the price of the most recent fill still retained in the fill history
+    pub fn last_trade_price(&self) -> Option<u64> {
+        self.fill_history.fills().last().map(|fill| fill.price)
+    }
+
+    /// Returns the most recent fill still retained in the fill history
+    pub fn last_fill(&self) -> Option<&Fill> {
+        self.fill_history.fills().last()
+    }
+
+    /// Returns the last `n` retained fills oldest first, or every retained fill if
+    /// fewer are available
+    pub fn last_n_fills(&self, n: usize) -> &[Fill] {
+        let fills = self.fill_history.fills();
+        &fills[fills.len().saturating_sub(n)..]
+    }
+
+    /// Returns a retained fill by position, 0 being the oldest still in the fill
+    /// history
+    ///
+    /// Positions shift down as the oldest fills are forgotten beyond `max_fill_history`.
+    pub fn fill_at_index(&self, index: usize) -> Option<&Fill> {
+        self.fill_history.fills().get(index)
+    }
+
+    /// Returns the volume-weighted average price of the last `window` retained fills,
+    /// or of every retained fill if fewer are available
+    ///
+    /// Returns `None` if there are no fills in the window
+    pub fn vwap(&self, window: usize) -> Option<u64> {
+        self.fill_history.vwap(window)
+    }
+
+    /// Returns the base and quote volume traded in the `window_secs` seconds up to and
+    /// including `current_timestamp`
+    ///
+    /// Only fills still in the fill history are counted, so windows reaching further
+    /// back than `max_fill_history` fills undercount.
+    ///
+    /// # Arguments
+    /// * `current_timestamp` - End of the window in nanoseconds
+    /// * `window_secs` - Length of the window in seconds
+    pub fn rolling_volume(&self, current_timestamp: u64, window_secs: u64) -> (u64, u128) {
+        let window_start = current_timestamp.saturating_sub(window_secs.saturating_mul(NANOS_PER_SECOND));
+        self.fill_history.volume_between(window_start, current_timestamp)
+    }
+
+    /// Returns the fills in the fill history matching `filter`, e.g. for accounting
+    ///
+    /// The time range is found by binary search; the other criteria are checked fill
+    /// by fill within it. Fills older than the last `max_fill_history` are not kept.
+    ///
+    /// # Arguments
+    /// * `filter` - Criteria the fills must all match, and the order to return them in
+    pub fn export_fills(&self, filter: FillFilter) -> Vec<&Fill> {
+        let from_timestamp = filter.from_timestamp.unwrap_or(0);
+        let to_timestamp = filter.to_timestamp.unwrap_or(u64::MAX);
+        let mut fills: Vec<&Fill> = self
+            .fill_history
+            .between(from_timestamp, to_timestamp)
+            .filter(|fill| filter.owner.is_none_or(|owner| fill.maker_owner == owner || fill.taker_owner == owner))
+            .filter(|fill| filter.min_base_quantity.is_none_or(|min| fill.base_quantity >= min))
+            .collect();
+        match filter.sort_by {
+            FillSortKey::Timestamp => {}
+            FillSortKey::BaseQuantity => fills.sort_by_key(|fill| fill.base_quantity),
+            FillSortKey::Price => fills.sort_by_key(|fill| fill.price),
+        }
+        fills
+    }
+
+    /// Estimates how long until a resting order is completely filled, as a heuristic
+    /// for display rather than a prediction to trade on
+    ///
+    /// Assumes takers keep consuming the order's side at the rate seen over the fill
+    /// history, i.e. taker arrivals per second times their average fill size, and
+    /// that no orders are cancelled ahead of it. The order must wait for everything
+    /// with better priority: better prices, then earlier orders at its own price.
+    ///
+    /// Returns `None` if the order is not resting on `side`, or if fewer than 10 fills
+    /// on that side up to `current_timestamp` remain in the fill history.
+    ///
+    /// # Arguments
+    /// * `order_id` - ID of the resting order
+    /// * `side` - Side the order rests on
+    /// * `current_timestamp` - Time to estimate from, in nanoseconds
+    pub fn expected_fill_time(&self, order_id: u128, side: impl Into<Side>, current_timestamp: u64) -> Option<Duration> {
+        const MIN_FILLS: usize = 10;
+
+        let is_bid = side.into() == Side::Bid;
+        let order = self.order_status(order_id, is_bid)?;
+        // Makers on this side are filled by takers from the other side
+        let fills: Vec<&Fill> = self
+            .fill_history
+            .fills()
+            .iter()
+            .filter(|fill| fill.timestamp <= current_timestamp && fill.taker_is_bid != is_bid)
+            .collect();
+        if fills.len() < MIN_FILLS {
+            return None;
+        }
+
+        let elapsed = current_timestamp - fills[0].timestamp;
+        let takers = fills.iter().map(|fill| fill.taker_order_id).collect::<HashSet<_>>().len() as u128;
+        let volume: u128 = fills.iter().map(|fill| fill.base_quantity as u128).sum();
+        if elapsed == 0 || volume == 0 {
+            return None;
+        }
+        let quantity_ahead: u128 = self
+            .iter_orders(is_bid)
+            .take_while(|resting| resting.order_id != order_id)
+            .map(|resting| resting.remaining_quantity() as u128)
+            .sum();
+
+        // Base consumed per nanosecond is takers per nanosecond times base per taker
+        let average_fill_size = volume / takers;
+        let remaining = quantity_ahead + order.remaining_quantity() as u128;
+        let nanos = remaining * elapsed as u128 / (takers * average_fill_size).max(1);
+        Some(Duration::from_nanos(nanos.min(u64::MAX as u128) as u64))
+    }
+
+    /// Returns the lifetime order, fill, volume and fee counters
+    pub fn statistics(&self) -> &BookStats {
+        &self.stats
+    }
+
+    /// Returns the session's OHLCV summary together with the current best bid and ask
+    ///
+    /// # Arguments
+    /// * `current_timestamp` - Timestamp to stamp the ticker with, in nanoseconds
+    pub fn ticker(&self, current_timestamp: u64) -> Ticker {
+        Ticker {
+            best_bid: self.best_bid(),
+            best_ask: self.best_ask(),
+            timestamp: current_timestamp,
+            ..self.ticker
+        }
+    }
+
+    /// Returns the mean price of the fills in the TWAP window ending at `current_timestamp`
+    ///
+    /// Returns `None` if no fill happened in the window
+    pub fn twap(&self, current_timestamp: u64) -> Option<u64> {
+        self.twap_oracle.twap(current_timestamp)
+    }
+
+    /// Returns the sum of each retained fill price times the nanoseconds until the
+    /// next fill, for consumers computing their own time-weighted averages
+    ///
+    /// Only fills still inside the TWAP window of the latest fill are retained, and
+    /// the latest fill contributes nothing until another follows it.
+    pub fn twap_cumulative(&self) -> u128 {
+        self.twap_oracle.cumulative()
+    }
+
+    /// Keeps `price_ladder` from allocating an ID the book has already accepted
+    fn reserve_order_id(&mut self, order_id: u128) {
+        self.next_order_id = self.next_order_id.max(order_id.saturating_add(1));
+    }
+
+    /// Rests an order that did not fully match, recording it for layering and quote stats
+    fn rest_order(&mut self, order: Order, timestamp: u64) {
+        self.record_large_order_placement(&order, timestamp);
+        let activity = self.quote_activity.entry(order.owner).or_default();
+        activity.quotes_posted += 1;
+        activity.add_resting(timestamp);
+        self.insert_resting(order);
+        self.refresh_best_quote_owners(timestamp);
+        self.publish_level_changes();
+    }
+
+    /// Switches the book to new market parameters between trading sessions
+    ///
+    /// Resting orders are revalidated in priority order. Prices off the new tick are
+    /// moved away from the spread, bids down and asks up, and requeued behind
+    /// orders already at the new price. Orders are cancelled if their quantity is
+    /// below the new minimum size or not a multiple of the new lot size or quantity
+    /// increment, or if no valid price within `max_price` remains. Held stop,
+    /// conditional and auction orders are revalidated the same way. Session volume, fill counts, fee totals and the ticker are
+    /// reset, and the reset is recorded in `market_resets` and as a `MarketReset` event.
+    /// Orders cancelled by the reset do not count as cancellations by their owners.
+    ///
+    /// # Arguments
+    /// * `new_params` - Parameters for the next session, including its fee schedule
+    /// * `timestamp` - Time the new session starts
+    ///
+    /// Returns the effect on the resting orders, or why the parameters were rejected,
+    /// leaving the book unchanged
+    pub fn apply_market_reset(&mut self, new_params: MarketParams, timestamp: u64) -> Result<MarketResetResult, BuilderError> {
+        new_params.validate()?;
+        self.events.begin_batch();
+        let top = (self.best_bid(), self.best_ask());
+        self.params = new_params;
+        let mut result = MarketResetResult::default();
+
+        for mut order in self.clone_side(true).into_iter().chain(self.clone_side(false)) {
+            let key = self.order_keys[&order.order_id];
+            match self.params.revalidate(&mut order) {
+                None => {
+                    self.remove_invalidated(order.is_bid(), key, timestamp);
+                    result.orders_cancelled_invalid += 1;
+                }
+                Some(true) => {
+                    let book_side = if order.is_bid() { &mut self.bids } else { &mut self.asks };
+                    let previous = book_side.remove(&key).expect("indexed order is resting");
+                    self.dirty_levels.push((previous.is_bid(), previous.price));
+                    record_change(&mut self.change_log, &mut self.change_seq, OrderBookChange::OrderCancelled(order.order_id));
+                    self.insert_resting(order);
+                    result.orders_repriced += 1;
+                }
+                Some(false) => result.orders_revalidated += 1,
+            }
+        }
+
+        // Held orders are checked the same way, so none activates under stale limits
+        let params = &self.params;
+        let settled_orders = &mut self.settled_orders;
+        let mut keep = |order: &mut Order| match params.revalidate(order) {
+            None => {
+                settled_orders.record(order.order_id, OrderStatus::Cancelled);
+                result.orders_cancelled_invalid += 1;
+                false
+            }
+            Some(moved) => {
+                if moved {
+                    result.orders_repriced += 1;
+                } else {
+                    result.orders_revalidated += 1;
+                }
+                true
+            }
+        };
+        self.stop_orders.retain_mut(&mut keep);
+        self.auction_orders.retain_mut(&mut keep);
+        self.conditional_orders.retain_mut(|(order, _)| keep(order));
+
+        self.refresh_best_quote_owners(timestamp);
+        self.publish_level_changes();
+        self.total_fills = 0;
+        self.session_volume = 0;
+        self.ticker = Ticker::default();
+        self.total_maker_fees = 0;
+        self.total_taker_fees = 0;
+        let reset = MarketReset {
+            timestamp,
+            params: self.params.clone(),
+            result,
+        };
+        self.events.record(|| BookEvent::MarketReset(reset.clone()));
+        self.market_resets.push(reset);
+        self.record_top_of_book_change(top, timestamp);
+        Ok(result)
+    }
+
+    /// Removes a resting order that new market parameters no longer allow
+    ///
+    /// Its owner did not cancel it, so cancellation counts, quote activity and
+    /// layering records are left as they were.
+    fn remove_invalidated(&mut self, is_bid: bool, key: PriceTimeKey, timestamp: u64) {
+        let book_side = if is_bid { &mut self.bids } else { &mut self.asks };
+        let order = book_side.remove(&key).expect("indexed order is resting");
+        self.order_keys.remove(&order.order_id);
+        unindex_owner(&mut self.owner_index, &order);
+        self.tracked_large_orders.remove(&order.order_id);
+        release_quote(&mut self.quote_activity, &order.owner, timestamp);
+        self.dirty_levels.push((is_bid, order.price));
+        record_change(&mut self.change_log, &mut self.change_seq, OrderBookChange::OrderCancelled(order.order_id));
+        self.settled_orders.record(order.order_id, OrderStatus::Cancelled);
+        record_lifetime(&mut self.completed_order_lifetimes, timestamp.saturating_sub(order.submission_timestamp));
+    }
+
+    /// Fees charged across the session, as `(maker_fees, taker_fees)` in base and quote units
+    pub fn total_fees_collected(&self) -> (u64, u64) {
+        (self.total_maker_fees, self.total_taker_fees)
+    }
+
+    /// Market resets applied to the book, oldest first
+    pub fn market_resets(&self) -> &[MarketReset] {
+        &self.market_resets
+    }
+
+    /// Subscribes to changes in the total quantity resting at each price level
+    ///
+    /// Events are published after every mutation, relative to the book as it was
+    /// when the first current subscriber joined. Subscribers that fall more than
+    /// `LEVEL_EVENT_CAPACITY` events behind miss the oldest ones.
+    #[cfg(feature = "tokio")]
+    pub fn subscribe_to_price_level_changes(&mut self) -> broadcast::Receiver<PriceLevelEvent> {
+        if self.level_events.receiver_count() == 0 {
+            self.level_qty_cache.clear();
+            for is_bid in [true, false] {
+                for price in self.level_prices(is_bid) {
+                    let quantity = self.level_quantity(is_bid, price);
+                    self.level_qty_cache.insert((is_bid, price), quantity);
+                }
+            }
+        }
+        self.level_events.subscribe()
+    }
+
+    /// Changes to the resting orders after sequence `from_seq`, oldest first
+    ///
+    /// Only the latest `MAX_CHANGE_LOG` changes are retained; a replica whose
+    /// `from_seq` is older than the first returned sequence minus one has missed
+    /// changes and must resynchronise from a snapshot.
+    ///
+    /// # Arguments
+    /// * `from_seq` - Sequence of the last change the replica applied, 0 for none
+    pub fn snapshot_changelog(&self, from_seq: u64) -> Vec<(u64, OrderBookChange)> {
+        let start = self.change_log.partition_point(|(seq, _)| *seq <= from_seq);
+        self.change_log.range(start..).copied().collect()
+    }
+
+    /// Returns whether the book is matching continuously or collecting orders for an auction
+    pub fn mode(&self) -> BookMode {
+        self.mode
+    }
+
+    /// Starts a volatility auction if the last trade moved too far, or concludes a running one
+    ///
+    /// In continuous mode, the latest fill is compared with the fill before it. A move
+    /// of more than `vol_bps` suspends matching for `MarketParams::auction_duration_ns`;
+    /// orders placed meanwhile are collected without matching. While an auction is
+    /// running this behaves like `check_auction_end` at the current time.
+    ///
+    /// # Arguments
+    /// * `vol_bps` - Largest trade-to-trade move in basis points tolerated without an auction
+    ///
+    /// Returns the uncross result once an auction has concluded
+    pub fn apply_volatility_auction(&mut self, vol_bps: u64) -> Option<AuctionResult> {
+        let now = self.clock.now();
+        if let BookMode::VolatilityAuction { .. } = self.mode {
+            return self.check_auction_end(now);
+        }
+
+        let mut recent_fills = self.fill_history.fills().iter().rev();
+        let (Some(latest), Some(previous)) = (recent_fills.next(), recent_fills.next()) else {
+            return None;
+        };
+        let move_bps = latest.price.abs_diff(previous.price) as u128 * BPS_DENOMINATOR as u128 / previous.price as u128;
+        if move_bps > vol_bps as u128 {
+            self.mode = BookMode::VolatilityAuction {
+                end_timestamp: now.saturating_add(self.params.auction_duration_ns),
+            };
+        }
+        None
+    }
+
+    /// Ends a volatility auction whose period has elapsed and returns to continuous matching
+    ///
+    /// Orders collected during the auction join the book without matching, and the
+    /// book is then uncrossed with `execute_opening_auction`.
+    ///
+    /// # Arguments
+    /// * `current_timestamp` - Current time in nanoseconds
+    ///
+    /// Returns the uncross result, or `None` if no auction has reached its end
+    pub fn check_auction_end(&mut self, current_timestamp: u64) -> Option<AuctionResult> {
+        match self.mode {
+            BookMode::VolatilityAuction { end_timestamp } if current_timestamp >= end_timestamp => {}
+            _ => return None,
+        }
+
+        self.mode = BookMode::Continuous;
+        for order in std::mem::take(&mut self.auction_orders) {
+            self.rest_order(order, current_timestamp);
+        }
+        Some(self.execute_opening_auction(current_timestamp))
+    }
+
+    /// Uncrosses the book at the single price that maximises executed quantity
+    ///
+    /// Ties are broken by the smallest imbalance between bid and ask quantity at the
+    /// price, then by closeness to the last trade, then by the lower price. Every
+    /// bid at or above and ask at or below the clearing price trades at that price in
+    /// priority order; asks are reported as makers and bids as takers.
+    ///
+    /// # Arguments
+    /// * `timestamp` - Time recorded on the auction fills
+    pub fn execute_opening_auction(&mut self, timestamp: u64) -> AuctionResult {
+        let Some(clearing_price) = self.auction_clearing_price() else {
+            return AuctionResult::default();
+        };
+
+        let mut fills = Vec::new();
+        while let (Some((&bid_key, bid)), Some((&ask_key, ask))) = (self.bids.last_key_value(), self.asks.first_key_value()) {
+            if bid.price < clearing_price || ask.price > clearing_price {
+                break;
+            }
+
+            let quantity = bid.remaining_quantity().min(ask.remaining_quantity());
+            let quote_quantity = quote_for(quantity, clearing_price);
+            let fill = Fill {
+                maker_order_id: ask.order_id,
+                taker_order_id: bid.order_id,
+                maker_owner: ask.owner,
+                taker_owner: bid.owner,
+                taker_is_bid: true,
+                price: clearing_price,
+                base_quantity: quantity,
+                quote_quantity,
+                timestamp,
+                price_improved: false,
+                improvement_amount: 0,
+                mid_price: None,
+                maker_fee: self.params.fee_schedule.maker_fee(quantity),
+                taker_fee: self.params.fee_schedule.taker_fee(quote_quantity),
+                maker_remaining_after: ask.remaining_quantity() - quantity,
+                taker_remaining_after: bid.remaining_quantity() - quantity,
+            };
+            self.total_maker_fees += fill.maker_fee;
+            self.total_taker_fees += fill.taker_fee;
+            self.fill_auction_order(bid_key, true, quantity, timestamp);
+            self.fill_auction_order(ask_key, false, quantity, timestamp);
+            if let Some(activity) = self.quote_activity.get_mut(&fill.maker_owner) {
+                activity.maker_fills += 1;
+            }
+            record_position_fill(&mut self.positions, &fill.taker_owner, &fill.maker_owner, &fill);
+
+            self.total_fills += 1;
+            self.stats.record_fill(&fill);
+            self.ticker.record_fill(&fill);
+            self.session_volume += quantity;
+            self.twap_oracle.push(timestamp, fill.price);
+            self.fill_history.push(fill.clone());
+            self.events.record(|| BookEvent::Filled(fill.clone()));
+            fills.push(fill);
+        }
+        self.refresh_best_quote_owners(timestamp);
+        self.publish_level_changes();
+
+        AuctionResult {
+            clearing_price,
+            matched_quantity: fills.iter().map(|fill| fill.base_quantity).sum(),
+            fills,
+        }
+    }
+
+    /// Price maximising executable quantity if the book were uncrossed, or `None` if it is not crossed
+    fn auction_clearing_price(&self) -> Option<u64> {
+        let last_trade = self.fill_history.fills().last().map(|fill| fill.price);
+        let mut candidates: Vec<u64> = self.level_prices(true);
+        candidates.extend(self.level_prices(false));
+
+        candidates
+            .into_iter()
+            .filter_map(|price| {
+                let demand: u64 = self.bids.values().filter(|bid| bid.price >= price).map(Order::remaining_quantity).sum();
+                let supply: u64 = self.asks.values().filter(|ask| ask.price <= price).map(Order::remaining_quantity).sum();
+                let executable = demand.min(supply);
+                let distance = last_trade.map_or(0, |last_trade| price.abs_diff(last_trade));
+                (executable > 0).then_some((executable, demand.abs_diff(supply), distance, price))
+            })
+            .min_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)).then(a.3.cmp(&b.3)))
+            .map(|(.., price)| price)
+    }
+
+    /// Applies an auction fill to a resting order, removing it once filled
+    fn fill_auction_order(&mut self, key: PriceTimeKey, is_bid: bool, quantity: u64, timestamp: u64) {
+        let book_side = if is_bid { &mut self.bids } else { &mut self.asks };
+        let order = book_side.get_mut(&key).expect("auction order is resting");
+        order.filled_quantity += quantity;
+        self.dirty_levels.push((is_bid, order.price));
+        record_change(&mut self.change_log, &mut self.change_seq, fill_change(order));
+        if !order.is_filled() {
+            return;
+        }
+
+        let order = book_side.remove(&key).expect("auction order is resting");
+        self.settled_orders.record(order.order_id, OrderStatus::Filled);
+        self.order_keys.remove(&order.order_id);
+        unindex_owner(&mut self.owner_index, &order);
+        self.tracked_large_orders.remove(&order.order_id);
+        release_quote(&mut self.quote_activity, &order.owner, timestamp);
+        record_lifetime(&mut self.completed_order_lifetimes, timestamp.saturating_sub(order.submission_timestamp));
+    }
+
+    /// Rejects limit orders closer to the mid than half the dynamic minimum spread;
+    /// other order types never rest
+    fn check_dynamic_min_spread(&self, order: &Order) -> Result<(), BookError> {
+        let (Some(min_spread), Some(mid_price)) = (self.dynamic_min_spread, self.mid_price()) else {
+            return Ok(());
+        };
+        if order.order_type != OrderType::Limit {
+            return Ok(());
+        }
+
+        let distance = if order.is_bid() {
+            mid_price.saturating_sub(order.price)
+        } else {
+            order.price.saturating_sub(mid_price)
+        };
+        if distance < min_spread / 2 {
+            return Err(BookError::MinSpreadViolation { price: order.price, mid_price, min_spread });
+        }
+        Ok(())
+    }
+
+    /// Enables volatility-driven spread widening and applies it to the current market
+    ///
+    /// Realized volatility is measured over the last 30 seconds of fills. While it is
+    /// above `vol_threshold_bps`, orders must rest at least half of `min_spread` times
+    /// `widening_pct` away from the mid.
+    ///
+    /// # Arguments
+    /// * `vol_threshold_bps` - Realized volatility above which the spread is widened
+    /// * `widening_pct` - Multiplier applied to the market's minimum spread, e.g. 2.0 to double it
+    pub fn apply_spread_widening_on_volatility(&mut self, vol_threshold_bps: u64, widening_pct: f64) {
+        self.spread_widening = Some((vol_threshold_bps, widening_pct));
+        let current_vol_bps = self.realized_volatility_bps(REALIZED_VOL_WINDOW_NS);
+        self.update_volatility_regime(current_vol_bps);
+    }
+
+    /// Widens or restores the minimum spread for the given realized volatility
+    ///
+    /// Has no effect until spread widening is enabled with
+    /// `apply_spread_widening_on_volatility`.
+    ///
+    /// # Arguments
+    /// * `current_vol_bps` - Latest realized volatility in basis points
+    pub fn update_volatility_regime(&mut self, current_vol_bps: u64) {
+        let Some((vol_threshold_bps, widening_pct)) = self.spread_widening else {
+            return;
+        };
+
+        self.dynamic_min_spread = if current_vol_bps > vol_threshold_bps {
+            Some((self.params.min_spread as f64 * widening_pct).round() as u64)
+        } else {
+            None
+        };
+    }
+
+    /// Realized volatility of fill prices within `window_ns` of now, in basis points
+    ///
+    /// Computed as the square root of the summed squared log returns between
+    /// consecutive fills.
+    fn realized_volatility_bps(&self, window_ns: u64) -> u64 {
+        let now = self.clock.now();
+        let prices: Vec<f64> = self
+            .fill_history
+            .fills()
+            .iter()
+            .filter(|fill| now.saturating_sub(fill.timestamp) <= window_ns)
+            .map(|fill| fill.price as f64)
+            .collect();
+
+        let sum_squared_returns: f64 = prices
+            .windows(2)
+            .map(|pair| (pair[1] / pair[0]).ln().powi(2))
+            .sum();
+        (sum_squared_returns.sqrt() * BPS_DENOMINATOR as f64).round() as u64
+    }
+
+    /// Records a large order about to rest within the best levels for layering detection
+    fn record_large_order_placement(&mut self, order: &Order, timestamp: u64) {
+        if order.remaining_quantity() < self.params.large_order_quantity {
+            return;
+        }
+
+        // Stop once enough better-priced levels are seen to rule out the top of book
+        let book_side: Box<dyn Iterator<Item = &Order>> = if order.is_bid() {
+            Box::new(self.bids.values().rev().take_while(|resting| resting.price > order.price))
+        } else {
+            Box::new(self.asks.values().take_while(|resting| resting.price < order.price))
+        };
+        let mut better_levels = 0;
+        let mut last_price = None;
+        for resting in book_side {
+            if last_price != Some(resting.price) {
+                better_levels += 1;
+                last_price = Some(resting.price);
+                if better_levels >= LAYERING_DEPTH_LEVELS {
+                    return;
+                }
+            }
+        }
+
+        self.tracked_large_orders.insert(order.order_id);
+        push_capped(self.large_order_placements.entry(order.owner).or_default(), timestamp, MAX_LAYERING_EVENTS);
+    }
+
+    /// Flags owners who cancel most of the large orders they place near the top of book
+    ///
+    /// For each owner, the cancel rate is the number of large orders placed within the
+    /// best `LAYERING_DEPTH_LEVELS` levels and cancelled in the last `window_ns`, over the
+    /// number of such orders placed in the same window. Orders count as large from
+    /// `MarketParams::large_order_quantity`, and their level is taken at placement time.
+    ///
+    /// # Arguments
+    /// * `threshold_ratio` - Cancel rate above which an owner is flagged
+    /// * `window_ns` - Lookback window ending at the book's current time
+    pub fn detect_order_book_manipulation(&self, threshold_ratio: f64, window_ns: u64) -> Vec<ManipulationAlert> {
+        let window_start = self.clock.now().saturating_sub(window_ns);
+        let mut alerts: Vec<ManipulationAlert> = self
+            .large_order_placements
+            .iter()
+            .filter_map(|(owner, placements)| {
+                let large_order_count = placements.iter().filter(|timestamp| **timestamp >= window_start).count();
+                let cancelled = self
+                    .large_order_cancellations
+                    .get(owner)
+                    .map_or(0, |cancellations| cancellations.iter().filter(|timestamp| **timestamp >= window_start).count());
+                if large_order_count == 0 {
+                    return None;
+                }
+
+                let cancel_rate = cancelled as f64 / large_order_count as f64;
+                (cancel_rate > threshold_ratio).then_some(ManipulationAlert {
+                    owner: *owner,
+                    cancel_rate,
+                    large_order_count,
+                    alert_type: AlertType::Layering,
+                })
+            })
+            .collect();
+
+        alerts.sort_by_key(|alert| alert.owner);
+        alerts
+    }
+
+    /// Runs regulatory pre-trade risk checks against an order
+    ///
+    /// Every failed check is reported rather than stopping at the first. Duplicate
+    /// and rate checks compare against submissions recorded by `place_order` while
+    /// `regulatory_mode` is enabled, using the book's clock.
+    ///
+    /// # Arguments
+    /// * `order` - The order about to be placed
+    /// * `risk_params` - The limits to check against
+    pub fn apply_regulatory_pre_trade_check(
+        &self,
+        order: &Order,
+        risk_params: &RegulatoryRiskParams,
+    ) -> Vec<RegulatoryViolation> {
+        let mut violations = Vec::new();
+        let now = self.clock.now();
+
+        if order.quantity > risk_params.position_limit {
+            violations.push(RegulatoryViolation::PositionLimitExceeded {
+                quantity: order.quantity,
+                limit: risk_params.position_limit,
+            });
+        }
+
+        let notional = (order.quantity as u128 * order.price as u128 / FLOAT_SCALING as u128) as u64;
+        if notional > risk_params.daily_notional_threshold {
+            violations.push(RegulatoryViolation::NotionalThresholdExceeded {
+                notional,
+                threshold: risk_params.daily_notional_threshold,
+            });
+        }
+
+        // A zero reference price leaves no band, so every price falls outside it
+        if let Some(reference_price) = risk_params.reference_price {
+            let outside_band = (order.price.abs_diff(reference_price) as u128 * BPS_DENOMINATOR as u128)
+                .checked_div(reference_price as u128)
+                .is_none_or(|deviation_bps| deviation_bps > risk_params.max_price_deviation_bps as u128);
+            if outside_band {
+                violations.push(RegulatoryViolation::PriceOutsideReferenceBand {
+                    price: order.price,
+                    reference_price,
+                    max_deviation_bps: risk_params.max_price_deviation_bps,
+                });
+            }
+        }
+
+        if let Some(submissions) = self.recent_submissions.get(&order.owner) {
+            let duplicate = submissions.iter().rev().find(|(timestamp, is_bid, price, quantity)| {
+                now.saturating_sub(*timestamp) <= risk_params.duplicate_window_ns
+                    && *is_bid == order.is_bid()
+                    && *price == order.price
+                    && *quantity == order.quantity
+            });
+            if let Some((previous_timestamp, ..)) = duplicate {
+                violations.push(RegulatoryViolation::DuplicateOrder {
+                    previous_timestamp: *previous_timestamp,
+                });
+            }
+
+            let orders_in_window = submissions
+                .iter()
+                .filter(|(timestamp, ..)| now.saturating_sub(*timestamp) < ORDER_RATE_WINDOW_NS)
+                .count();
+            if orders_in_window >= risk_params.max_orders_per_second {
+                violations.push(RegulatoryViolation::OrderRateExceeded {
+                    orders_in_window,
+                    limit: risk_params.max_orders_per_second,
+                });
+            }
+        }
+
+        violations
+    }
+
+    /// Records an order submission for duplicate and rate checks, dropping entries older than the rate window
+    fn record_submission(&mut self, order: &Order) {
+        let now = self.clock.now();
+        let submissions = self.recent_submissions.entry(order.owner).or_default();
+        while submissions.front().is_some_and(|(timestamp, ..)| now.saturating_sub(*timestamp) >= ORDER_RATE_WINDOW_NS) {
+            submissions.pop_front();
+        }
+        submissions.push_back((now, order.is_bid(), order.price, order.quantity));
+    }
+
+    /// Inserts an order into its side of the book under a fresh price-venue-time key
+    ///
+    /// The caller's `order_id` plays no part in priority
+    fn insert_resting(&mut self, order: Order) {
+        self.reserve_order_id(order.order_id);
+        self.sequence_counter += 1;
+        let key = queue_key(&order, self.sequence_counter);
+
+        self.order_keys.insert(order.order_id, key);
+        self.owner_index.entry(order.owner).or_default().insert((order.is_bid(), order.order_id));
+        if order.expire_timestamp != u64::MAX {
+            self.expiry_queue.insert((order.expire_timestamp, order.order_id, order.is_bid()));
+        }
+        self.dirty_levels.push((order.is_bid(), order.price));
+        record_change(
+            &mut self.change_log,
+            &mut self.change_seq,
+            OrderBookChange::OrderPlaced(order.order_id, order.price, order.remaining_quantity(), order.is_bid()),
+        );
+        let book_side = if order.is_bid() {
+            &mut self.bids
+        } else {
+            &mut self.asks
+        };
+
+        book_side.insert(key, order);
+    }
+
+    /// Captures the best `depth` price levels on each side, best first
+    ///
+    /// Each level carries its orders in queue order, so a full-depth snapshot can be
+    /// passed to `warm_up_with_snapshot`. Level totals count only the shown tranche of
+    /// iceberg orders.
+    ///
+    /// # Arguments
+    /// * `depth` - Maximum number of price levels per side
+    /// * `timestamp` - Time recorded on the snapshot
+    pub fn snapshot(&self, depth: usize, timestamp: u64) -> BookSnapshot {
+        let levels = |is_bid: bool| {
+            let mut levels: Vec<PriceLevel> = Vec::new();
+            for order in self.iter_orders(is_bid) {
+                if levels.last().is_none_or(|level| level.price != order.price) {
+                    if levels.len() == depth {
+                        break;
+                    }
+                    levels.push(PriceLevel {
+                        price: order.price,
+                        ..Default::default()
+                    });
+                }
+                let level = levels.last_mut().expect("level was just ensured");
+                level.total_quantity += order.visible_quantity();
+                level.order_count += 1;
+                level.orders.push(order.clone());
+            }
+            levels
+        };
+        BookSnapshot {
+            bids: levels(true),
+            asks: levels(false),
+            timestamp,
+        }
+    }
+
+    /// Empties the book and its trading history while keeping its configuration
+    ///
+    /// Resting, stop and auction orders, queued fills and events, the fill history,
+    /// positions and every statistic are cleared. Market parameters and fees, the
+    /// clock, pre-trade hooks, the circuit breaker (untripped), fill and GC limits,
+    /// event settings and level subscribers are kept. Replicas are told every resting
+    /// order was cancelled, and subscribers that every published level was removed.
+    pub fn flush(&mut self) {
+        let resting: Vec<u128> = self.unfilled_order_ids().collect();
+        for order_id in resting {
+            record_change(&mut self.change_log, &mut self.change_seq, OrderBookChange::OrderCancelled(order_id));
+        }
+
+        let flushed = Book::new_with_params(self.params.clone());
+        let previous = std::mem::replace(self, flushed);
+        self.clock = previous.clock;
+        self.pre_trade_hooks = previous.pre_trade_hooks;
+        self.spread_widening = previous.spread_widening;
+        #[cfg(feature = "tokio")]
+        {
+            self.level_events = previous.level_events;
+        }
+        self.level_qty_cache = previous.level_qty_cache;
+        self.change_log = previous.change_log;
+        self.change_seq = previous.change_seq;
+        self.gc_threshold = previous.gc_threshold;
+        self.max_fills_per_match = previous.max_fills_per_match;
+        self.circuit_breaker = previous.circuit_breaker.map(|breaker| CircuitBreaker { tripped: false, ..breaker });
+        self.events = EventSink {
+            log: previous.events.log.map(|_| Vec::new()),
+            #[cfg(feature = "serde")]
+            stream: previous.events.stream.map(|_| Vec::new()),
+            sequence: previous.events.sequence,
+            batch_id: previous.events.batch_id,
+            ..EventSink::new(previous.events.buffer_capacity)
+        };
+
+        self.dirty_levels.extend(self.level_qty_cache.keys().copied());
+        self.publish_level_changes();
+    }
+
+    /// Captures every resting level, then flushes the book as `flush` does
+    ///
+    /// Returns the snapshot of the book as it was before the flush
+    pub fn flush_with_snapshot(&mut self) -> BookSnapshot {
+        let snapshot = self.snapshot(usize::MAX, self.clock.now());
+        self.flush();
+        snapshot
+    }
+
+    /// Replaces the resting orders with those in a persisted snapshot
+    ///
+    /// Orders are inserted directly in queue order, bypassing matching and pre-trade
+    /// hooks. On error the book is left unchanged.
+    ///
+    /// # Arguments
+    /// * `snapshot` - Snapshot holding every resting order, grouped by level
+    /// * `validate` - Whether to check level totals and the book invariants after loading
+    pub fn warm_up_with_snapshot(&mut self, snapshot: BookSnapshot, validate: bool) -> Result<(), WarmUpError> {
+        let bids = std::mem::take(&mut self.bids);
+        let asks = std::mem::take(&mut self.asks);
+        let order_keys = std::mem::take(&mut self.order_keys);
+        let owner_index = std::mem::take(&mut self.owner_index);
+        let expiry_queue = std::mem::take(&mut self.expiry_queue);
+        let sequence_counter = std::mem::take(&mut self.sequence_counter);
+        let change_log = std::mem::take(&mut self.change_log);
+        let change_seq = self.change_seq;
+        let next_order_id = self.next_order_id;
+
+        let result = self.load_snapshot(snapshot, validate);
+        if result.is_err() {
+            self.bids = bids;
+            self.asks = asks;
+            self.order_keys = order_keys;
+            self.owner_index = owner_index;
+            self.expiry_queue = expiry_queue;
+            self.sequence_counter = sequence_counter;
+            self.change_log = change_log;
+            self.change_seq = change_seq;
+            self.next_order_id = next_order_id;
+        } else {
+            // Replicas drop the replaced orders before adding the loaded ones
+            let loaded = std::mem::replace(&mut self.change_log, change_log);
+            self.change_seq = change_seq;
+            for order in bids.values().chain(asks.values()) {
+                record_change(&mut self.change_log, &mut self.change_seq, OrderBookChange::OrderCancelled(order.order_id));
+            }
+            for (_, change) in loaded {
+                record_change(&mut self.change_log, &mut self.change_seq, change);
+            }
+            self.tracked_large_orders.clear();
+            self.reset_quote_activity();
+            // Every previously published level and every loaded level may have changed
+            self.dirty_levels.extend(self.level_qty_cache.keys().copied());
+            for is_bid in [true, false] {
+                let prices = self.level_prices(is_bid);
+                self.dirty_levels.extend(prices.into_iter().map(|price| (is_bid, price)));
+            }
+        }
+        self.publish_level_changes();
+
+        result
+    }
+
+    /// Inserts every order in a snapshot into the emptied book
+    fn load_snapshot(&mut self, snapshot: BookSnapshot, validate: bool) -> Result<(), WarmUpError> {
+        let levels = snapshot
+            .bids
+            .into_iter()
+            .map(|level| (true, level))
+            .chain(snapshot.asks.into_iter().map(|level| (false, level)));
+
+        for (is_bid, level) in levels {
+            if validate {
+                check_level(&level, is_bid)?;
+            }
+            for order in level.orders {
+                if self.order_keys.contains_key(&order.order_id) {
+                    return Err(WarmUpError::DuplicateOrderId(order.order_id));
+                }
+                self.insert_resting(order);
+            }
+        }
+
+        if validate {
+            self.invariant_check()?;
+            self.enforce_price_priority_invariant()?;
+        }
+        Ok(())
+    }
+
+    /// Checks that every resting order is live, aligned and indexed under its own key
+    fn invariant_check(&self) -> Result<(), WarmUpError> {
+        let sides = [(true, &self.bids), (false, &self.asks)];
+        for (is_bid, book_side) in sides {
+            for (key, order) in book_side {
+                if order.is_bid() != is_bid {
+                    return Err(WarmUpError::ConstraintViolation(format!(
+                        "order {} rests on the wrong side",
+                        order.order_id
+                    )));
+                }
+                if order.is_filled() {
+                    return Err(WarmUpError::ConstraintViolation(format!(
+                        "order {} has no remaining quantity",
+                        order.order_id
+                    )));
+                }
+                if !order.price.is_multiple_of(self.params.tick_size) || !order.quantity.is_multiple_of(self.params.lot_size) {
+                    return Err(WarmUpError::ConstraintViolation(format!(
+                        "order {} is not aligned to the tick and lot sizes",
+                        order.order_id
+                    )));
+                }
+                if self.order_keys.get(&order.order_id) != Some(key) {
+                    return Err(WarmUpError::ConstraintViolation(format!(
+                        "order {} is not indexed under its key",
+                        order.order_id
+                    )));
+                }
+            }
+        }
+
+        if self.order_keys.len() != self.bids.len() + self.asks.len() {
+            return Err(WarmUpError::ConstraintViolation(
+                "order index does not match the resting orders".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that keys order each side by price and that the book is not crossed
+    fn enforce_price_priority_invariant(&self) -> Result<(), WarmUpError> {
+        if let Some((key, order)) = self
+            .bids
+            .iter()
+            .chain(self.asks.iter())
+            .find(|(key, order)| key.price != order.price)
+        {
+            return Err(WarmUpError::ConstraintViolation(format!(
+                "order {} at price {} is keyed at price {}",
+                order.order_id,
+                order.price,
+                key.price
+            )));
+        }
+
+        match (self.best_level(true), self.best_level(false)) {
+            (Some((best_bid, _)), Some((best_ask, _))) if best_bid >= best_ask => Err(WarmUpError::CrossedBook),
+            _ => Ok(()),
+        }
+    }
+
+    /// Iterates over one side's resting orders from best to worst priority
+    ///
+    /// # Arguments
+    /// * `side` - Side to iterate
+    pub fn iter_orders(&self, side: impl Into<Side>) -> impl Iterator<Item = &Order> + '_ {
+        let is_bid = side.into() == Side::Bid;
+        let orders: Box<dyn Iterator<Item = &Order>> = if is_bid {
+            Box::new(self.bids.values().rev())
+        } else {
+            Box::new(self.asks.values())
+        };
+        orders
+    }
+
+    /// Iterates over one side's resting order IDs from best to worst priority
+    ///
+    /// # Arguments
+    /// * `side` - Side to iterate
+    pub fn iter_order_ids(&self, side: impl Into<Side>) -> impl Iterator<Item = u128> + '_ {
+        self.iter_orders(side).map(|order| order.order_id)
+    }
+
+    /// Returns one side's resting order IDs from best to worst priority
+    ///
+    /// # Arguments
+    /// * `side` - Side to list
+    pub fn get_all_order_ids_by_side(&self, side: impl Into<Side>) -> Vec<u128> {
+        self.iter_order_ids(side).collect()
+    }
+
+    /// Returns a resting order without removing it, or `None` once it has left the book
+    ///
+    /// # Arguments
+    /// * `order_id` - ID of the order to look up
+    /// * `side` - Side the order rests on
+    pub fn order_status(&self, order_id: u128, side: impl Into<Side>) -> Option<&Order> {
+        let is_bid = side.into() == Side::Bid;
+        let key = self.order_keys.get(&order_id)?;
+        let book_side = if is_bid { &self.bids } else { &self.asks };
+        book_side.get(key)
+    }
+
+    /// Returns every resting order at one price in queue order, first to fill first
+    ///
+    /// Finding the level is O(log n); collecting it is O(k) in the orders at the price.
+    ///
+    /// # Arguments
+    /// * `price` - Exact price of the level
+    /// * `side` - Side the level is on
+    pub fn get_price_level_detail(&self, price: u64, side: impl Into<Side>) -> Vec<&Order> {
+        match side.into() {
+            Side::Bid => self.bids.range(PriceTimeKey::level(price)).rev().map(|(_, order)| order).collect(),
+            Side::Ask => self.asks.range(PriceTimeKey::level(price)).map(|(_, order)| order).collect(),
+        }
+    }
+
+    /// Returns how many orders, and how much quantity, fill before a resting order at
+    /// its price
+    ///
+    /// Returns `None` if the order is not resting on `side`.
+    ///
+    /// # Arguments
+    /// * `order_id` - ID of the resting order
+    /// * `side` - Side the order rests on
+    pub fn order_queue_position(&self, order_id: u128, side: impl Into<Side>) -> Option<QueuePosition> {
+        let side = side.into();
+        let price = self.order_status(order_id, side)?.price;
+        let level = self.get_price_level_detail(price, side);
+        let position = level.iter().position(|order| order.order_id == order_id)?;
+        Some(QueuePosition {
+            position,
+            orders_ahead: position,
+            quantity_ahead: level[..position].iter().map(|order| order.remaining_quantity()).sum(),
+            total_at_level: level.len(),
+        })
+    }
+
+    /// Iterates over an owner's resting orders, bids from best to worst followed by asks
+    ///
+    /// # Arguments
+    /// * `owner` - Owner whose orders to yield
+    pub fn active_orders_for_owner(&self, owner: Address) -> impl Iterator<Item = &Order> {
+        self.unfilled_orders().filter(move |order| order.owner == owner)
+    }
+
+    /// Iterates over every resting order, bids from best to worst followed by asks
+    /// from best to worst
+    pub fn unfilled_orders(&self) -> impl Iterator<Item = &Order> + '_ {
+        self.bids.values().rev().chain(self.asks.values())
+    }
+
+    /// Iterates over every resting order ID in the same order as `unfilled_orders`
+    pub fn unfilled_order_ids(&self) -> impl Iterator<Item = u128> + '_ {
+        self.unfilled_orders().map(|order| order.order_id)
+    }
+
+    /// Iterates over an owner's resting orders using the owner index, asks before
+    /// bids and by order ID within a side
+    ///
+    /// Unlike `active_orders_for_owner` this does not scan the book, so it stays cheap
+    /// however many orders other owners have resting.
+    ///
+    /// # Arguments
+    /// * `owner` - Owner whose orders to yield
+    pub fn orders_for_owner(&self, owner: Address) -> impl Iterator<Item = &Order> {
+        self.owner_index
+            .get(&owner)
+            .into_iter()
+            .flatten()
+            .filter_map(|&(is_bid, order_id)| self.order_status(order_id, is_bid))
+    }
+
+    /// Copies one side's resting orders from best to worst priority
+    ///
+    /// # Arguments
+    /// * `side` - Side to copy
+    pub fn clone_side(&self, side: impl Into<Side>) -> Vec<Order> {
+        self.iter_orders(side).cloned().collect()
+    }
+
+    /// Hashes every resting order with BLAKE3 for comparing book state across nodes
+    ///
+    /// Orders are serialized in `order_id` order, so the fingerprint does not depend
+    /// on insertion history, only on the orders themselves.
+    #[cfg(feature = "fingerprint")]
+    pub fn get_order_book_fingerprint(&self) -> [u8; 32] {
+        let mut orders: Vec<&Order> = self.iter_orders(true).chain(self.iter_orders(false)).collect();
+        orders.sort_unstable_by_key(|order| order.order_id);
+
+        let mut hasher = blake3::Hasher::new();
+        for order in orders {
+            hasher.update(&order.order_id.to_le_bytes());
+            hasher.update(&[order.is_bid() as u8]);
+            hasher.update(&order.price.to_le_bytes());
+            hasher.update(&order.quantity.to_le_bytes());
+            hasher.update(&order.filled_quantity.to_le_bytes());
+            hasher.update(&order.expire_timestamp.to_le_bytes());
+            hasher.update(&order.owner.0);
+        }
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Returns whether another book holds the same resting orders as this one
+    ///
+    /// # Arguments
+    /// * `other` - Book to compare against
+    #[cfg(feature = "fingerprint")]
+    pub fn fingerprint_matches(&self, other: &Book) -> bool {
+        self.get_order_book_fingerprint() == other.get_order_book_fingerprint()
+    }
+
+    /// Serializes the whole book to JSON for checkpointing
+    ///
+    /// The clock, pre-trade hooks and level event subscribers are not saved.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// Restores a book checkpointed with `to_json`, on the system clock
+    ///
+    /// # Arguments
+    /// * `s` - JSON produced by `to_json`
+    #[cfg(feature = "serde")]
+    pub fn from_json(s: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(s)
+    }
+
+    /// Serializes the whole book to a compact binary checkpoint
+    ///
+    /// The clock, pre-trade hooks and level event subscribers are not saved.
+    #[cfg(feature = "serde")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Restores a book checkpointed with `to_bytes`, on the system clock
+    ///
+    /// # Arguments
+    /// * `bytes` - Checkpoint produced by `to_bytes`
+    #[cfg(feature = "serde")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(bytes)
+    }
+
+    /// Cancels an existing order
+    ///
+    /// # Arguments
+    /// * `order_id` - ID of the order to cancel
+    /// * `side` - Side the order rests on
+    ///
+    /// Returns the cancelled order, or why no order could be cancelled
+    pub fn cancel_order(&mut self, order_id: u128, side: impl Into<Side>) -> CancelResult {
+        let is_bid = side.into() == Side::Bid;
+        let now = self.clock.now();
+        self.events.begin_batch();
+        let top = (self.best_bid(), self.best_ask());
+        let result = self.cancel_order_at(order_id, is_bid, now);
+        self.record_top_of_book_change(top, now);
+        result
+    }
+
+    /// Cancels every resting order from an owner, e.g. when their session disconnects
+    ///
+    /// # Arguments
+    /// * `owner` - Owner whose orders to cancel
+    ///
+    /// Returns the cancelled orders, bids from best to worst followed by asks
+    pub fn cancel_all_for_owner(&mut self, owner: Address) -> Vec<Order> {
+        let now = self.clock.now();
+        let resting: Vec<(u128, bool)> = self
+            .unfilled_orders()
+            .filter(|order| order.owner == owner)
+            .map(|order| (order.order_id, order.is_bid()))
+            .collect();
+        resting
+            .into_iter()
+            .filter_map(|(order_id, is_bid)| self.cancel_order_at(order_id, is_bid, now).into_order())
+            .collect()
+    }
+
+    /// Removes every resting order that expired before `timestamp`
+    ///
+    /// The orders are recorded as expired rather than cancelled, exactly as
+    /// `purge_expired` does.
+    ///
+    /// # Arguments
+    /// * `timestamp` - Time against which expiry is checked
+    pub fn cancel_all_expired(&mut self, timestamp: u64) -> Vec<Order> {
+        self.purge_expired(timestamp)
+    }
+
+    /// Cancels a resting order and places its replacement in one call
+    ///
+    /// The replacement is validated before anything changes, so a missing original or
+    /// an invalid replacement leaves the book untouched. The replacement may reuse the
+    /// original's ID. It joins the back of its price level and may match on arrival.
+    ///
+    /// # Arguments
+    /// * `old_order_id` - ID of the resting order to cancel
+    /// * `side` - Side the order rests on
+    /// * `new_order` - Order placed once the original is cancelled
+    /// * `timestamp` - Time of both the cancellation and the placement
+    pub fn replace_order(
+        &mut self,
+        old_order_id: u128,
+        side: impl Into<Side>,
+        new_order: Order,
+        timestamp: u64,
+    ) -> Result<ReplaceResult, ReplaceError> {
+        let is_bid = side.into() == Side::Bid;
+        let book_side = if is_bid { &self.bids } else { &self.asks };
+        if !self.order_keys.get(&old_order_id).is_some_and(|key| book_side.contains_key(key)) {
+            return Err(ReplaceError::NotFound);
+        }
+        match self.validate_order(&new_order) {
+            Err(PlaceError::OrderAlreadyExists) if new_order.order_id == old_order_id => {}
+            Err(error) => return Err(ReplaceError::Invalid(error)),
+            Ok(()) => {}
+        }
+
+        let cancelled = self.cancel_order_at(old_order_id, is_bid, timestamp).into_order().expect("original is resting");
+        let (new_order_id, new_is_bid) = (new_order.order_id, new_order.is_bid());
+        match self.place_order_at(new_order, timestamp) {
+            Ok((fills, _)) => Ok(ReplaceResult {
+                cancelled,
+                fills,
+                resting: self.order_status(new_order_id, new_is_bid).cloned(),
+            }),
+            Err(error) => Err(ReplaceError::Rejected { cancelled: Box::new(cancelled), error }),
+        }
+    }
+
+    /// Cancels an order as `cancel_order` does, at the given time
+    fn cancel_order_at(&mut self, order_id: u128, is_bid: bool, now: u64) -> CancelResult {
+        let Some(&key) = self.order_keys.get(&order_id) else {
+            return match self.settled_orders.statuses.get(&order_id) {
+                Some(OrderStatus::Filled) => CancelResult::AlreadyFilled,
+                _ => CancelResult::NotFound,
+            };
+        };
+        let book_side = if is_bid {
+            &mut self.bids
+        } else {
+            &mut self.asks
+        };
+
+        let Some(mut order) = book_side.remove(&key) else {
+            return CancelResult::NotFound;
+        };
+        self.order_keys.remove(&order_id);
+        unindex_owner(&mut self.owner_index, &order);
+        self.settled_orders.record(order_id, OrderStatus::Cancelled);
+        self.stats.total_orders_cancelled += 1;
+        self.dirty_levels.push((is_bid, order.price));
+        record_change(&mut self.change_log, &mut self.change_seq, OrderBookChange::OrderCancelled(order_id));
+        self.events.record(|| BookEvent::Cancelled { order_id, is_bid });
+
+        order.cancellation_timestamp = Some(now);
+        if self.tracked_large_orders.remove(&order_id) {
+            push_capped(self.large_order_cancellations.entry(order.owner).or_default(), now, MAX_LAYERING_EVENTS);
+        }
+        if let Some(activity) = self.quote_activity.get_mut(&order.owner) {
+            activity.quotes_cancelled += 1;
+            activity.remove_resting(now);
+        }
+        self.refresh_best_quote_owners(now);
+        self.publish_level_changes();
+        record_lifetime(&mut self.completed_order_lifetimes, now.saturating_sub(order.submission_timestamp));
+        CancelResult::Cancelled(order)
+    }
+
+    /// Removes every resting order with `expire_timestamp < timestamp` from both sides
+    ///
+    /// Matching only evicts expired orders it reaches at the top of book, so deeper
+    /// ones stay until purged. Returns the removed orders so reserved funds can be
+    /// refunded.
+    ///
+    /// # Arguments
+    /// * `timestamp` - Time against which expiry is checked
+    pub fn purge_expired(&mut self, timestamp: u64) -> Vec<Order> {
+        let expired_keys: Vec<(bool, PriceTimeKey)> = self
+            .bids
+            .iter()
+            .map(|(key, order)| (true, key, order))
+            .chain(self.asks.iter().map(|(key, order)| (false, key, order)))
+            .filter(|(.., order)| order.expire_timestamp < timestamp)
+            .map(|(is_bid, key, _)| (is_bid, *key))
+            .collect();
+
+        let purged = expired_keys
+            .into_iter()
+            .map(|(is_bid, key)| self.remove_expired(is_bid, key, timestamp))
+            .collect();
+        self.expiry_queue = self.expiry_queue.split_off(&(timestamp, 0, false));
+
+        self.refresh_best_quote_owners(timestamp);
+        self.publish_level_changes();
+        purged
+    }
+
+    /// Removes an expired resting order and records its expiry
+    fn remove_expired(&mut self, is_bid: bool, key: PriceTimeKey, timestamp: u64) -> Order {
+        let book_side = if is_bid { &mut self.bids } else { &mut self.asks };
+        let expired = book_side.remove(&key).expect("expired key is present");
+        self.order_keys.remove(&expired.order_id);
+        unindex_owner(&mut self.owner_index, &expired);
+        self.tracked_large_orders.remove(&expired.order_id);
+        release_quote(&mut self.quote_activity, &expired.owner, timestamp);
+        self.dirty_levels.push((is_bid, expired.price));
+        record_change(&mut self.change_log, &mut self.change_seq, OrderBookChange::OrderCancelled(expired.order_id));
+        self.settled_orders.record(expired.order_id, OrderStatus::Expired);
+        self.events.record(|| BookEvent::Expired { order_id: expired.order_id, is_bid });
+        record_lifetime(
+            &mut self.completed_order_lifetimes,
+            expired.expire_timestamp.saturating_sub(expired.submission_timestamp),
+        );
+        expired
+    }
+
+    /// Counts expired resting orders, stopping once the GC threshold is exceeded
+    ///
+    /// Queue entries for orders that already left the book are dropped along the way.
+    fn count_expired_resting(&mut self, timestamp: u64) -> usize {
+        let mut expired = 0;
+        let mut departed = Vec::new();
+        for &(expire_timestamp, order_id, is_bid) in self.expiry_queue.range(..(timestamp, 0, false)) {
+            if expired > self.gc_threshold {
+                break;
+            }
+            let book_side = if is_bid { &self.bids } else { &self.asks };
+            let resting = self
+                .order_keys
+                .get(&order_id)
+                .and_then(|key| book_side.get(key))
+                .is_some_and(|order| order.expire_timestamp == expire_timestamp);
+            if resting {
+                expired += 1;
+            } else {
+                departed.push((expire_timestamp, order_id, is_bid));
+            }
+        }
+        for entry in departed {
+            self.expiry_queue.remove(&entry);
+        }
+        expired
+    }
+
+    /// Changes the price and/or total quantity of a resting order
+    ///
+    /// Reducing the quantity at the same price keeps the order's queue position;
+    /// any other change requeues it behind orders already at its price. The new
+    /// price must be a valid tick that does not cross the opposite side.
+    ///
+    /// # Arguments
+    /// * `order_id` - ID of the order to amend
+    /// * `side` - Side the order rests on
+    /// * `new_price` - New limit price, or `None` to keep the current one
+    /// * `new_quantity` - New total quantity including any already filled, or `None` to keep the current one
+    pub fn amend_order(
+        &mut self,
+        order_id: u128,
+        side: impl Into<Side>,
+        new_price: Option<u64>,
+        new_quantity: Option<u64>,
+    ) -> Result<(), AmendError> {
+        let is_bid = side.into() == Side::Bid;
+        let key = *self.order_keys.get(&order_id).ok_or(AmendError::NotFound)?;
+        let book_side = if is_bid { &self.bids } else { &self.asks };
+        let order = book_side.get(&key).ok_or(AmendError::NotFound)?;
+        let price = new_price.unwrap_or(order.price);
+        let quantity = new_quantity.unwrap_or(order.quantity);
+
+        if price != order.price {
+            let crosses = self.iter_orders(!is_bid).next().is_some_and(|best| {
+                if is_bid {
+                    price >= best.price
+                } else {
+                    price <= best.price
+                }
+            });
+            let tick_size = self.params.tick_size;
+            if price < tick_size || !price.is_multiple_of(tick_size) || price > self.params.max_price || crosses {
+                return Err(AmendError::InvalidPrice);
+            }
+        }
+        if quantity < self.params.min_size {
+            return Err(AmendError::QuantityBelowMinimum);
+        }
+        if !quantity.is_multiple_of(self.params.lot_size) {
+            return Err(AmendError::QuantityNotLotAligned);
+        }
+        if !quantity.is_multiple_of(self.params.min_qty_increment) {
+            return Err(AmendError::QuantityNotIncrementAligned);
+        }
+        if quantity <= order.filled_quantity {
+            return Err(AmendError::QuantityBelowFilled {
+                submitted: quantity,
+                filled_quantity: order.filled_quantity,
+            });
+        }
+
+        let keeps_priority = price == order.price && quantity <= order.quantity;
+        let book_side = if is_bid { &mut self.bids } else { &mut self.asks };
+        if keeps_priority {
+            let order = book_side.get_mut(&key).expect("indexed order is resting");
+            order.quantity = quantity;
+            self.dirty_levels.push((is_bid, price));
+            // Replicas see the amendment as the order being replaced in place
+            record_change(&mut self.change_log, &mut self.change_seq, OrderBookChange::OrderCancelled(order_id));
+            record_change(
+                &mut self.change_log,
+                &mut self.change_seq,
+                OrderBookChange::OrderPlaced(order_id, price, order.remaining_quantity(), is_bid),
+            );
+        } else {
+            let mut order = book_side.remove(&key).expect("indexed order is resting");
+            self.dirty_levels.push((is_bid, order.price));
+            record_change(&mut self.change_log, &mut self.change_seq, OrderBookChange::OrderCancelled(order_id));
+            order.price = price;
+            order.quantity = quantity;
+            self.insert_resting(order);
+        }
+
+        self.refresh_best_quote_owners(self.clock.now());
+        self.publish_level_changes();
+        Ok(())
+    }
+
+    /// Changes the total quantity of a resting order, rejecting quantities that are
+    /// below the minimum size or not a multiple of the lot size and quantity increment
+    ///
+    /// Queue position follows the rules of `amend_order`.
+    ///
+    /// # Arguments
+    /// * `order_id` - ID of the order to amend
+    /// * `side` - Side the order rests on
+    /// * `new_quantity` - New total quantity, including any already filled
+    pub fn enforce_lot_size_on_amendment(&mut self, order_id: u128, side: impl Into<Side>, new_quantity: u64) -> Result<(), BookError> {
+        let is_bid = side.into() == Side::Bid;
+        match self.amend_order(order_id, is_bid, None, Some(new_quantity)) {
+            Ok(()) => Ok(()),
+            Err(AmendError::NotFound) => Err(BookError::OrderNotFound(order_id)),
+            Err(AmendError::QuantityBelowMinimum) => Err(BookError::QuantityBelowMinimum {
+                submitted: new_quantity,
+                min_size: self.params.min_size,
+            }),
+            Err(AmendError::QuantityNotLotAligned) => Err(BookError::LotSizeViolation {
+                submitted: new_quantity,
+                lot_size: self.params.lot_size,
+            }),
+            Err(AmendError::QuantityNotIncrementAligned) => Err(BookError::QtyIncrementViolation {
+                submitted: new_quantity,
+                min_qty_increment: self.params.min_qty_increment,
+            }),
+            Err(AmendError::QuantityBelowFilled { submitted, filled_quantity }) => {
+                Err(BookError::QuantityBelowFilled { submitted, filled_quantity })
+            }
+            Err(error @ AmendError::InvalidPrice) => Err(BookError::AmendRejected(error)),
+        }
+    }
+
+    /// Rounds a quantity down to the nearest multiple of the lot size
+    ///
+    /// # Arguments
+    /// * `quantity` - Quantity to round
+    pub fn round_to_lot_size(&self, quantity: u64) -> u64 {
+        quantity / self.params.lot_size * self.params.lot_size
+    }
+
+    /// Summarises how long recently completed orders rested in the book
+    ///
+    /// Lifetimes run from submission to cancellation, expiry or full fill, and are
+    /// kept for the last `MAX_COMPLETED_LIFETIMES` completed orders. Percentiles use
+    /// the nearest-rank method. Returns an empty distribution if no order has completed.
+    pub fn compute_order_lifetime_distribution(&self) -> LifetimeDistribution {
+        if self.completed_order_lifetimes.is_empty() {
+            return LifetimeDistribution::default();
+        }
+
+        let mut lifetimes: Vec<u64> = self.completed_order_lifetimes.iter().copied().collect();
+        lifetimes.sort_unstable();
+        let percentile = |p: f64| {
+            let rank = (p * lifetimes.len() as f64).ceil() as usize;
+            lifetimes[rank.clamp(1, lifetimes.len()) - 1]
+        };
+
+        let mut buckets = BTreeMap::new();
+        for lifetime in &lifetimes {
+            let lower_bound = 10u64.pow(lifetime.max(&1).ilog10());
+            *buckets.entry(lower_bound).or_insert(0u64) += 1;
+        }
+
+        LifetimeDistribution {
+            median_ns: percentile(0.5),
+            p90_ns: percentile(0.9),
+            p99_ns: percentile(0.99),
+            bucket_counts: buckets.into_iter().collect(),
+        }
+    }
+
+    /// Cancels every resting order for a graceful shutdown, giving up at a deadline
+    ///
+    /// Orders are cancelled one by one as by `cancel_order`, in chunks of
+    /// `CANCEL_CHUNK_SIZE`, yielding to the runtime between chunks so other tasks keep
+    /// making progress. The deadline is checked at
+    /// each chunk boundary as well as enforced by `tokio::time::timeout`. Orders
+    /// cancelled before the deadline stay cancelled.
+    ///
+    /// # Arguments
+    /// * `timeout` - Time allowed for cancelling all orders
+    #[cfg(feature = "tokio")]
+    pub async fn async_cancel_all_with_timeout(&mut self, timeout: Duration) -> Result<CancelAllResult, ShutdownError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let now = self.clock.now();
+        let mut cancelled = Vec::with_capacity(self.bids.len() + self.asks.len());
+        self.events.begin_batch();
+        let top = (self.best_bid(), self.best_ask());
+        let outcome = tokio::time::timeout(timeout, async {
+            loop {
+                for _ in 0..CANCEL_CHUNK_SIZE {
+                    let next = self.bids.values().next().map(|order| (order.order_id, true));
+                    let Some((order_id, is_bid)) = next.or_else(|| self.asks.values().next().map(|order| (order.order_id, false)))
+                    else {
+                        return true;
+                    };
+                    cancelled.extend(self.cancel_order_at(order_id, is_bid, now).into_order());
+                }
+                if tokio::time::Instant::now() >= deadline {
+                    return false;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await;
+        self.record_top_of_book_change(top, now);
+
+        match outcome {
+            Ok(true) => Ok(CancelAllResult { cancelled }),
+            Ok(false) | Err(_) => Err(ShutdownError::Incomplete {
+                cancelled: cancelled.len(),
+                remaining: self.bids.len() + self.asks.len(),
+            }),
+        }
+    }
+
+    /// Estimates the bid and ask prices that let `owner` reach `target_fill_rate`
+    /// maker fills per second while quoting as wide as possible
+    ///
+    /// Assumes the fill rate falls linearly as quotes move away from the mid price:
+    /// `rate(d) = a - b * d` for a distance `d` in basis points. The line is fitted by
+    /// least squares over the owner's per-level fill stats, where each level's rate is
+    /// its fill count divided by the owner's observation window. The widest distance
+    /// still achieving the target is applied symmetrically around the current mid,
+    /// rounding the bid down and the ask up to the tick size.
+    ///
+    /// Returns `None` if the owner has fills at fewer than two distances, the
+    /// observation window is empty, the fitted rate does not fall with distance, the
+    /// target is unreachable at a non-negative spread, or either side of the book is empty.
+    ///
+    /// # Arguments
+    /// * `owner` - Market maker whose fill history is used
+    /// * `target_fill_rate` - Desired number of maker fills per second
+    pub fn calculate_optimal_spread_for_owner(&self, owner: Address, target_fill_rate: f64) -> Option<(u64, u64)> {
+        let stats = self.maker_fill_stats.get(&owner)?;
+        if stats.levels.len() < 2 {
+            return None;
+        }
+
+        let window_secs = (stats.last_fill_timestamp - stats.first_fill_timestamp) as f64 / NANOS_PER_SECOND as f64;
+        if window_secs <= 0.0 {
+            return None;
+        }
+
+        // Least-squares fit of fill rate against distance from mid
+        let points: Vec<(f64, f64)> = stats
+            .levels
+            .iter()
+            .map(|(distance_bps, level)| (*distance_bps as f64, level.fill_count as f64 / window_secs))
+            .collect();
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        let slope = covariance / variance;
+        if slope >= 0.0 {
+            return None;
+        }
+
+        let intercept = mean_y - slope * mean_x;
+        let distance_bps = (target_fill_rate - intercept) / slope;
+        if distance_bps < 0.0 {
+            return None;
+        }
+
+        let mid_price = self.mid_price()?;
+        let offset = (mid_price as f64 * distance_bps / BPS_DENOMINATOR as f64).round() as u64;
+        let tick_size = self.params.tick_size;
+        let bid_price = mid_price.saturating_sub(offset) / tick_size * tick_size;
+        let ask_price = (mid_price + offset).div_ceil(tick_size) * tick_size;
+
+        Some((bid_price, ask_price))
+    }
+
+    /// Recommends quote offsets from the mid price using a simplified Avellaneda-Stoikov model
+    ///
+    /// The half spread in basis points is `gamma * sigma^2 * T + ln(1 + gamma / kappa)`,
+    /// and both quotes shift by `q * gamma * sigma^2 * T` against the current inventory
+    /// `q`, so a long maker quotes lower to shed inventory. Assumed constants are
+    /// `SPREAD_RISK_AVERSION` (gamma = 0.1), `SPREAD_ORDER_INTENSITY` (kappa = 1.5) and
+    /// `SPREAD_HORIZON_SECS` (T = 1s). Offsets are converted to price units at the mid,
+    /// falling back to the last trade price; with neither, offsets are zero.
+    ///
+    /// # Arguments
+    /// * `volatility_estimate` - Per-second standard deviation of the price in basis points
+    /// * `inventory_skew` - Fraction of maximum inventory held, clamped to `[-1.0, 1.0]`
+    pub fn get_adaptive_spread_recommendation(&self, volatility_estimate: f64, inventory_skew: f64) -> SpreadRecommendation {
+        let confidence = (self.fill_history.fills().len() as f64 / SPREAD_CONFIDENCE_FILLS as f64).min(1.0);
+        let Some(reference_price) = self.mid_price().or_else(|| self.fill_history.fills().last().map(|fill| fill.price)) else {
+            return SpreadRecommendation {
+                bid_offset: 0,
+                ask_offset: 0,
+                confidence: 0.0,
+            };
+        };
+
+        let variance_term = SPREAD_RISK_AVERSION * volatility_estimate.powi(2) * SPREAD_HORIZON_SECS;
+        let half_spread_bps = variance_term + (1.0 + SPREAD_RISK_AVERSION / SPREAD_ORDER_INTENSITY).ln();
+        let reservation_shift_bps = inventory_skew.clamp(-1.0, 1.0) * variance_term;
+        let to_price = |bps: f64| (bps * reference_price as f64 / BPS_DENOMINATOR as f64).round() as i64;
+
+        SpreadRecommendation {
+            bid_offset: to_price(-half_spread_bps - reservation_shift_bps),
+            ask_offset: to_price(half_spread_bps - reservation_shift_bps),
+            confidence,
+        }
+    }
+
+    /// Renders the current top of book and session totals as an InfluxDB line protocol record
+    ///
+    /// Price and quantity fields are integers. `best_bid`, `best_ask`, `mid_price`,
+    /// `spread`, `bid_qty` and `ask_qty` are omitted while the side they depend on is
+    /// empty; `bid_qty` and `ask_qty` are the quantities at the best level.
+    ///
+    /// # Arguments
+    /// * `measurement` - Measurement name, optionally followed by tags, e.g. `orderbook,pair=SUI-USDC`
+    /// * `timestamp_ns` - Record timestamp in nanoseconds
+    pub fn export_to_influxdb_line_protocol(&self, measurement: &str, timestamp_ns: u64) -> String {
+        let best_bid = self.best_level(true);
+        let best_ask = self.best_level(false);
+        let mut fields = Vec::new();
+
+        if let Some((price, _)) = best_bid {
+            fields.push(format!("best_bid={}i", price));
+        }
+        if let Some((price, _)) = best_ask {
+            fields.push(format!("best_ask={}i", price));
+        }
+        if let Some(mid_price) = self.mid_price() {
+            fields.push(format!("mid_price={}i", mid_price));
+        }
+        if let (Some((bid_price, _)), Some((ask_price, _))) = (best_bid, best_ask) {
+            fields.push(format!("spread={}i", ask_price as i128 - bid_price as i128));
+        }
+        if let Some((_, quantity)) = best_bid {
+            fields.push(format!("bid_qty={}i", quantity));
+        }
+        if let Some((_, quantity)) = best_ask {
+            fields.push(format!("ask_qty={}i", quantity));
+        }
+        fields.push(format!("bid_levels={}i", count_levels(self.bids.values())));
+        fields.push(format!("ask_levels={}i", count_levels(self.asks.values())));
+        fields.push(format!("total_fills={}i", self.total_fills));
+        fields.push(format!("session_volume={}i", self.session_volume));
+
+        format!("{} {} {}", measurement, fields.join(","), timestamp_ns)
+    }
+
+    /// Writes a per-owner risk report as CSV with a header row and `\r\n` line endings
+    ///
+    /// Notionals are remaining quantity times price in quote units, and
+    /// `net_exposure` is bid notional minus ask notional. Fill counts and volumes
+    /// come from the fill history, counting the owner as maker or taker. `otr_ratio`
+    /// is accepted orders per fill, `margin_required` the larger side's notional, and
+    /// `effective_spread` twice the owner's volume-weighted maker fill distance from
+    /// the mid, in basis points. Rows are sorted by owner.
+    ///
+    /// # Arguments
+    /// * `writer` - Destination for the CSV
+    pub fn export_risk_report_csv(&self, mut writer: impl Write) -> Result<(), ExportError> {
+        let mut rows: BTreeMap<Address, RiskReportRow> = BTreeMap::new();
+        for order in self.bids.values().chain(self.asks.values()) {
+            let row = rows.entry(order.owner).or_default();
+            let notional = u64::try_from(order.remaining_quantity() as u128 * order.price as u128 / FLOAT_SCALING as u128)
+                .map_err(|_| ExportError::ArithmeticOverflow)?;
+            let (open_orders, total_notional) = if order.is_bid() {
+                (&mut row.open_bid_orders, &mut row.total_bid_notional)
+            } else {
+                (&mut row.open_ask_orders, &mut row.total_ask_notional)
+            };
+            *open_orders += 1;
+            *total_notional = total_notional.checked_add(notional).ok_or(ExportError::ArithmeticOverflow)?;
+        }
+        for fill in self.fill_history.fills() {
+            let maker = rows.entry(fill.maker_owner).or_default();
+            maker.fill_count += 1;
+            maker.maker_volume = maker.maker_volume.checked_add(fill.base_quantity).ok_or(ExportError::ArithmeticOverflow)?;
+            let taker = rows.entry(fill.taker_owner).or_default();
+            if fill.taker_owner != fill.maker_owner {
+                taker.fill_count += 1;
+            }
+            taker.taker_volume = taker.taker_volume.checked_add(fill.base_quantity).ok_or(ExportError::ArithmeticOverflow)?;
+        }
+        for owner in self.quote_activity.keys() {
+            rows.entry(*owner).or_default();
+        }
+
+        write!(
+            writer,
+            "owner,open_bid_orders,open_ask_orders,total_bid_notional,total_ask_notional,net_exposure,\
+             fill_count,maker_volume,taker_volume,otr_ratio,margin_required,effective_spread\r\n"
+        )?;
+        for (owner, row) in rows {
+            let net_exposure = i64::try_from(row.total_bid_notional as i128 - row.total_ask_notional as i128)
+                .map_err(|_| ExportError::ArithmeticOverflow)?;
+            let orders_submitted = self.quote_activity.get(&owner).map_or(0, |activity| activity.orders_submitted);
+            let otr_ratio = orders_submitted as f64 / row.fill_count.max(1) as f64;
+            let effective_spread = self.maker_fill_stats.get(&owner).map_or(0, |stats| {
+                let (weighted_distance, quantity) = stats.levels.iter().fold((0u128, 0u128), |(weighted, total), (bps, level)| {
+                    (weighted + *bps as u128 * level.base_quantity as u128, total + level.base_quantity as u128)
+                });
+                (2 * weighted_distance).checked_div(quantity).unwrap_or(0)
+            });
+
+            write!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{:.4},{},{}\r\n",
+                csv_field(&owner.to_string()),
+                row.open_bid_orders,
+                row.open_ask_orders,
+                row.total_bid_notional,
+                row.total_ask_notional,
+                net_exposure,
+                row.fill_count,
+                row.maker_volume,
+                row.taker_volume,
+                otr_ratio,
+                row.total_bid_notional.max(row.total_ask_notional),
+                effective_spread
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns how often and by how much takers traded better than their limit price
+    ///
+    /// Only fills matched while `MarketParams::price_improvement` is enabled are counted.
+    pub fn get_price_improvement_statistics(&self) -> PriceImprovementStats {
+        let avg_improvement_units = if self.fills_with_improvement == 0 {
+            0.0
+        } else {
+            self.total_improvement_units as f64 / self.fills_with_improvement as f64
+        };
+
+        PriceImprovementStats {
+            total_fills_with_improvement: self.fills_with_improvement,
+            total_fills_without: self.fills_without_improvement,
+            avg_improvement_units,
+            total_improvement_value: self.total_improvement_value,
+        }
+    }
+
+    /// Estimates the fair value of the base asset using the given method
+    ///
+    /// Returns `None` when the book or fill history lacks the data the method needs.
+    /// `OracleAdjusted` offsets are clamped so the result never falls below the tick size.
+    ///
+    /// # Arguments
+    /// * `method` - The fair value estimation method
+    pub fn infer_fair_value(&self, method: FairValueMethod) -> Option<u64> {
+        match method {
+            FairValueMethod::MidPrice => self.mid_price(),
+            FairValueMethod::WeightedMidPrice => {
+                let (bid_price, bid_qty) = self.best_level(true)?;
+                let (ask_price, ask_qty) = self.best_level(false)?;
+                let weighted = (bid_price as u128 * ask_qty as u128 + ask_price as u128 * bid_qty as u128)
+                    / (bid_qty as u128 + ask_qty as u128);
+                Some(weighted as u64)
+            }
+            FairValueMethod::VWAP(window) => self.vwap(window),
+            FairValueMethod::LastTrade => self.fill_history.fills().last().map(|fill| fill.price),
+            FairValueMethod::OracleAdjusted(offset) => {
+                let vwap = self.vwap(self.fill_history.fills().len())?;
+                let adjusted = (vwap as i64).saturating_add(offset as i64);
+                Some(adjusted.max(self.params.tick_size as i64) as u64)
+            }
+        }
+    }
+
+    /// Measures how far the best prices are from an externally supplied fair value,
+    /// e.g. to spot quotes left stale by a move in the wider market
+    ///
+    /// Distances are rounded towards zero to whole ticks. They are computed in `i64`
+    /// so a crossed book still gives meaningful signs.
+    ///
+    /// Returns `None` if either side of the book is empty
+    ///
+    /// # Arguments
+    /// * `fair_value` - Fair value of the base asset in price units
+    pub fn fair_value_spread(&self, fair_value: u64) -> Option<FairValueSpread> {
+        let best_bid = self.best_bid()? as i64;
+        let best_ask = self.best_ask()? as i64;
+        let tick_size = self.params.tick_size as i64;
+        let fair_value = fair_value as i64;
+        let mid = best_bid + (best_ask - best_bid) / 2;
+        Some(FairValueSpread {
+            distance_from_best_bid: (fair_value - best_bid) / tick_size,
+            distance_from_best_ask: (fair_value - best_ask) / tick_size,
+            distance_from_mid: (fair_value - mid) / tick_size,
+        })
+    }
+
+    /// Detects an arbitrage opportunity against an external venue's best bid and ask
+    ///
+    /// Trading internally is profitable when the external bid is above the best
+    /// internal ask, or the best internal bid is above the external ask. The tradable
+    /// quantity covers every internal order priced through the external quote, capped
+    /// at `external_max_qty`. Returns `None` when neither spread crosses, or when the
+    /// crossing leg is priced at zero and has no defined profit.
+    ///
+    /// # Arguments
+    /// * `external_bid` - Best bid on the external venue
+    /// * `external_ask` - Best ask on the external venue
+    /// * `external_max_qty` - Quantity available at the external quote
+    pub fn cross_exchange_spread_arbitrage(
+        &self,
+        external_bid: u64,
+        external_ask: u64,
+        external_max_qty: u64,
+    ) -> Option<ArbitrageSignal> {
+        let buy_internal = self.asks.values().next().filter(|ask| ask.price < external_bid).and_then(|ask| {
+            let depth = self
+                .asks
+                .values()
+                .take_while(|order| order.price < external_bid)
+                .map(Order::remaining_quantity)
+                .sum::<u64>();
+            Some(ArbitrageSignal {
+                direction: ArbDirection::BuyInternalSellExternal,
+                internal_price: ask.price,
+                external_price: external_bid,
+                max_quantity: depth.min(external_max_qty),
+                expected_profit_bps: profit_bps(external_bid - ask.price, ask.price)?,
+            })
+        });
+        let sell_internal = self.bids.values().next_back().filter(|bid| bid.price > external_ask).and_then(|bid| {
+            let depth = self
+                .bids
+                .values()
+                .rev()
+                .take_while(|order| order.price > external_ask)
+                .map(Order::remaining_quantity)
+                .sum::<u64>();
+            Some(ArbitrageSignal {
+                direction: ArbDirection::SellInternalBuyExternal,
+                internal_price: bid.price,
+                external_price: external_ask,
+                max_quantity: depth.min(external_max_qty),
+                expected_profit_bps: profit_bps(bid.price - external_ask, external_ask)?,
+            })
+        });
+
+        // A crossed pair of venues can offer both; take the more profitable leg
+        match (buy_internal, sell_internal) {
+            (Some(buy), Some(sell)) if sell.expected_profit_bps > buy.expected_profit_bps => Some(sell),
+            (Some(buy), _) => Some(buy),
+            (None, sell) => sell,
+        }
+    }
+
+    /// Estimates the volatility of the fill price as the standard deviation of log
+    /// returns between consecutive fills in the history
+    ///
+    /// Returns `0.0` when fewer than two fills have been recorded.
+    pub fn historical_volatility_proxy(&self) -> f64 {
+        if self.fill_history.fills().len() < 2 {
+            return 0.0;
+        }
+
+        let returns: Vec<f64> = self
+            .fill_history
+            .fills()
+            .iter()
+            .zip(self.fill_history.fills().iter().skip(1))
+            .map(|(previous, next)| (next.price as f64 / previous.price as f64).ln())
+            .collect();
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
+        variance.sqrt()
+    }
+
+    /// Simulates the tail loss resting orders would take on a one-step price move
+    ///
+    /// Each of `n_scenarios` draws a new price from the mid with geometric Brownian
+    /// motion, `S' = S * exp(-sigma^2 / 2 + sigma * Z)`, using the historical volatility
+    /// proxy as `sigma`. A resting bid above `S'` or ask below `S'` would be filled at a
+    /// loss of its price gap times remaining quantity, in quote units. Scenarios come
+    /// from a fixed-seed LCG, so results are reproducible for a given book state.
+    ///
+    /// Returns all zeros when either side of the book is empty or no scenarios are run.
+    ///
+    /// # Arguments
+    /// * `confidence_level` - Quantile of the loss distribution for `var`, e.g. 0.99
+    /// * `n_scenarios` - Number of simulated price moves
+    pub fn compute_tail_risk_exposure(&self, confidence_level: f64, n_scenarios: usize) -> TailRiskExposure {
+        let Some(mid_price) = self.mid_price() else {
+            return TailRiskExposure::default();
+        };
+        if n_scenarios == 0 {
+            return TailRiskExposure::default();
+        }
+
+        let sigma = self.historical_volatility_proxy();
+        let mut rng = Lcg::new(SCENARIO_SEED);
+        let mut losses: Vec<u64> = (0..n_scenarios)
+            .map(|_| {
+                let shock = -0.5 * sigma * sigma + sigma * rng.next_gaussian();
+                let scenario_price = (mid_price as f64 * shock.exp()).round() as u64;
+                self.loss_at_price(scenario_price)
+            })
+            .collect();
+        losses.sort_unstable();
+
+        let var_index = ((confidence_level * n_scenarios as f64).ceil() as usize).clamp(1, n_scenarios) - 1;
+        let tail = &losses[var_index..];
+        let cvar = (tail.iter().map(|loss| *loss as u128).sum::<u128>() / tail.len() as u128) as u64;
+
+        TailRiskExposure {
+            var: losses[var_index],
+            cvar,
+            max_loss: losses[n_scenarios - 1],
+        }
+    }
+
+    /// Quote value lost by resting orders that a move to `price` would fill
+    fn loss_at_price(&self, price: u64) -> u64 {
+        let bid_loss = self
+            .bids
+            .values()
+            .filter(|bid| bid.price > price)
+            .map(|bid| (bid.price - price) as u128 * bid.remaining_quantity() as u128);
+        let ask_loss = self
+            .asks
+            .values()
+            .filter(|ask| ask.price < price)
+            .map(|ask| (price - ask.price) as u128 * ask.remaining_quantity() as u128);
+
+        (bid_loss.chain(ask_loss).sum::<u128>() / FLOAT_SCALING as u128) as u64
+    }
+
+    /// Estimates the cost of unwinding all of an owner's resting orders at once
+    ///
+    /// Each order's cost is its remaining quantity times the impact predicted for that
+    /// quantity: `quantity * impact_bps / 10_000`. Returns the sum over the owner's
+    /// orders.
+    ///
+    /// # Arguments
+    /// * `owner` - Owner whose resting orders are unwound
+    /// * `price_impact_model` - Model predicting the impact of each order's quantity
+    pub fn compute_expected_shortfall_on_unwind(&self, owner: Address, price_impact_model: &MarketImpactModel) -> u64 {
+        self.bids
+            .values()
+            .chain(self.asks.values())
+            .filter(|order| order.owner == owner)
+            .map(|order| {
+                let quantity = order.remaining_quantity();
+                let impact_bps = price_impact_model.predict(quantity);
+                (quantity as f64 * impact_bps / BPS_DENOMINATOR as f64).round() as u64
+            })
+            .sum()
+    }
+
+    /// Spread averaged across depth, discounting levels farther from the top of book
+    ///
+    /// The spread at depth `i` is the `i`th best ask minus the `i`th best bid, weighted
+    /// by `exp(-i / half_life_levels)` and normalized by the sum of weights. Only depths
+    /// present on both sides are included.
+    ///
+    /// # Arguments
+    /// * `half_life_levels` - Decay scale in levels; `f64::INFINITY` weights every level equally
+    ///
+    /// Returns `None` if either side of the book is empty
+    pub fn compute_decay_weighted_spread(&self, half_life_levels: f64) -> Option<u64> {
+        let bid_prices = self.level_prices(true);
+        let ask_prices = self.level_prices(false);
+        if bid_prices.is_empty() || ask_prices.is_empty() {
+            return None;
+        }
+
+        let (weighted_spread, total_weight) = bid_prices
+            .iter()
+            .zip(&ask_prices)
+            .enumerate()
+            .fold((0.0, 0.0), |(weighted_spread, total_weight), (level, (bid, ask))| {
+                let weight = (-(level as f64) / half_life_levels).exp();
+                let spread = ask.saturating_sub(*bid) as f64;
+                (weighted_spread + spread * weight, total_weight + weight)
+            });
+        Some((weighted_spread / total_weight).round() as u64)
+    }
+
+    /// Distinct prices on one side of the book, best first
+    fn level_prices(&self, is_bid: bool) -> Vec<u64> {
+        let mut prices: Vec<u64> = Vec::new();
+        for order in self.iter_orders(is_bid) {
+            if prices.last() != Some(&order.price) {
+                prices.push(order.price);
+            }
+        }
+        prices
+    }
+
+    /// Compares the prices achieved by a set of fills against a benchmark
+    ///
+    /// Deviations are `(price - benchmark) * 10_000 / benchmark`, so negative values
+    /// are better for buys and worse for sells.
+    ///
+    /// # Arguments
+    /// * `fills` - Fills making up the execution
+    /// * `benchmark` - Reference price to measure against
+    pub fn compare_execution_to_benchmark(&self, fills: &[Fill], benchmark: BenchmarkType) -> ExecutionQualityReport {
+        let benchmark_price = match benchmark {
+            BenchmarkType::ArrivalPrice(price) | BenchmarkType::TWAP(price) | BenchmarkType::VWAP(price) => price,
+        };
+        let deviation_bps = |price: i128| {
+            if benchmark_price == 0 {
+                return 0;
+            }
+            ((price - benchmark_price as i128) * BPS_DENOMINATOR as i128 / benchmark_price as i128) as i64
+        };
+
+        let total_quantity: u64 = fills.iter().map(|fill| fill.base_quantity).sum();
+        let implementation_shortfall = if total_quantity == 0 {
+            0
+        } else {
+            let notional: u128 = fills.iter().map(|fill| fill.price as u128 * fill.base_quantity as u128).sum();
+            deviation_bps((notional / total_quantity as u128) as i128)
+        };
+        let slippage_bps = fills
+            .iter()
+            .map(|fill| deviation_bps(fill.price as i128))
+            .max_by_key(|deviation| deviation.abs())
+            .unwrap_or(0);
+        let volume_participation_rate = if self.session_volume == 0 {
+            0.0
+        } else {
+            total_quantity as f64 / self.session_volume as f64
+        };
+
+        ExecutionQualityReport {
+            slippage_bps,
+            implementation_shortfall,
+            volume_participation_rate,
+        }
+    }
+
+    /// Detects large orders being worked in tranches from the fill history
+    ///
+    /// Fills are grouped by owner, on either side of the trade, and price. Fills sharing
+    /// a timestamp count as one tranche. A group with at least four tranches whose
+    /// intervals have a coefficient of variation of at most 0.2 is reported.
+    ///
+    /// Returns candidates sorted by detected quantity, largest first
+    pub fn get_institutional_order_detection(&self) -> Vec<InstitutionalOrderCandidate> {
+        let mut groups: HashMap<(Address, u64), Vec<&Fill>> = HashMap::new();
+        for fill in self.fill_history.fills() {
+            groups.entry((fill.maker_owner, fill.price)).or_default().push(fill);
+            if fill.taker_owner != fill.maker_owner {
+                groups.entry((fill.taker_owner, fill.price)).or_default().push(fill);
+            }
+        }
+
+        let mut candidates: Vec<InstitutionalOrderCandidate> = groups
+            .into_iter()
+            .filter_map(|((owner, price), fills)| {
+                let mut tranche_times: Vec<u64> = fills.iter().map(|fill| fill.timestamp).collect();
+                tranche_times.dedup();
+                if tranche_times.len() < INSTITUTIONAL_MIN_TRANCHES {
+                    return None;
+                }
+
+                let intervals: Vec<f64> = tranche_times
+                    .windows(2)
+                    .map(|pair| pair[1].saturating_sub(pair[0]) as f64)
+                    .collect();
+                let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+                if mean == 0.0 {
+                    return None;
+                }
+                let variance = intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / intervals.len() as f64;
+                if variance.sqrt() / mean > INSTITUTIONAL_MAX_INTERVAL_CV {
+                    return None;
+                }
+
+                Some(InstitutionalOrderCandidate {
+                    owner,
+                    price,
+                    total_quantity_detected: fills.iter().map(|fill| fill.base_quantity).sum(),
+                    fill_count: fills.len(),
+                    avg_interval_ns: mean.round() as u64,
+                })
+            })
+            .collect();
+
+        candidates.sort_by(|a, b| {
+            b.total_quantity_detected
+                .cmp(&a.total_quantity_detected)
+                .then_with(|| a.owner.cmp(&b.owner))
+                .then(a.price.cmp(&b.price))
+        });
+        candidates
+    }
+
+    /// Scores an owner's quote quality between 0 and 1 for market maker ranking
+    ///
+    /// The score weighs five components equally: time at the front of the best bid
+    /// or ask, maker fills per posted quote, quoted spread, time with at least one
+    /// resting order, and cancels per maker fill. The spread is between the owner's
+    /// best bid and ask, and one-sided owners score zero on it. Each component is
+    /// divided by the best value among owners that have quoted this session, so the
+    /// top performer on every component scores 1.
+    ///
+    /// Returns `0.0` for an owner that has never rested an order
+    pub fn compute_quote_quality_score(&self, owner: Address) -> f64 {
+        self.quote_quality_scores().remove(&owner).unwrap_or(0.0)
+    }
+
+    /// Ranks every owner that has quoted this session by quote quality score, best first
+    pub fn rank_market_makers(&self) -> Vec<(Address, f64)> {
+        let mut ranking: Vec<(Address, f64)> = self.quote_quality_scores().into_iter().collect();
+        ranking.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranking
+    }
+
+    /// Quote quality score of every owner that has quoted this session
+    fn quote_quality_scores(&self) -> HashMap<Address, f64> {
+        let now = self.clock.now();
+        let mut best_quotes: HashMap<Address, (Option<u64>, Option<u64>)> = HashMap::new();
+        for order in self.bids.values() {
+            let (best_bid, _) = best_quotes.entry(order.owner).or_default();
+            *best_bid = Some(best_bid.map_or(order.price, |price| price.max(order.price)));
+        }
+        for order in self.asks.values() {
+            let (_, best_ask) = best_quotes.entry(order.owner).or_default();
+            *best_ask = Some(best_ask.map_or(order.price, |price| price.min(order.price)));
+        }
+
+        let components: Vec<(Address, [f64; 5])> = self
+            .quote_activity
+            .iter()
+            .filter(|(_, activity)| activity.quotes_posted > 0)
+            .map(|(owner, activity)| {
+                let pending_at_best: u64 = self
+                    .best_quote_owners
+                    .iter()
+                    .flatten()
+                    .filter(|(holder, _)| holder == owner)
+                    .map(|(_, since)| now.saturating_sub(*since))
+                    .sum();
+                let pending_uptime = activity.quoting_since.map_or(0, |since| now.saturating_sub(since));
+                let spread_quality = match best_quotes.get(owner) {
+                    Some((Some(bid), Some(ask))) if ask > bid => 1.0 / (ask - bid) as f64,
+                    _ => 0.0,
+                };
+                let fill_rate = if activity.quotes_posted == 0 {
+                    0.0
+                } else {
+                    (activity.maker_fills as f64 / activity.quotes_posted as f64).min(1.0)
+                };
+                let cancel_to_trade = activity.quotes_cancelled as f64 / activity.maker_fills.max(1) as f64;
+
+                (*owner, [
+                    (activity.time_at_best_ns + pending_at_best) as f64,
+                    fill_rate,
+                    spread_quality,
+                    (activity.uptime_ns + pending_uptime) as f64,
+                    1.0 / (1.0 + cancel_to_trade),
+                ])
+            })
+            .collect();
+
+        let mut top_performer = [0.0f64; 5];
+        for (_, values) in &components {
+            for (top, value) in top_performer.iter_mut().zip(values) {
+                *top = top.max(*value);
+            }
+        }
+
+        components
+            .into_iter()
+            .map(|(owner, values)| {
+                let score: f64 = values
+                    .iter()
+                    .zip(&top_performer)
+                    .map(|(value, top)| if *top > 0.0 { value / top } else { 0.0 })
+                    .sum();
+                (owner, score * QUOTE_QUALITY_COMPONENT_WEIGHT)
+            })
+            .collect()
+    }
+
+    /// Credits time at the best quotes to the owners leaving the front of each side
+    fn refresh_best_quote_owners(&mut self, now: u64) {
+        let front_owners = [
+            self.bids.last_key_value().map(|(_, order)| order.owner),
+            self.asks.first_key_value().map(|(_, order)| order.owner),
+        ];
+
+        for (holder, front_owner) in self.best_quote_owners.iter_mut().zip(front_owners) {
+            if holder.map(|(owner, _)| owner) == front_owner {
+                continue;
+            }
+            if let Some((owner, since)) = holder.take() {
+                self.quote_activity.entry(owner).or_default().time_at_best_ns += now.saturating_sub(since);
+            }
+            *holder = front_owner.map(|owner| (owner, now));
+        }
+    }
+
+    /// Total remaining quantity resting at a price on one side
+    fn level_quantity(&self, is_bid: bool, price: u64) -> u64 {
+        let book_side = if is_bid { &self.bids } else { &self.asks };
+        book_side
+            .range(PriceTimeKey::level(price))
+            .map(|(_, order)| order.visible_quantity())
+            .sum()
+    }
+
+    /// Publishes an event for each touched level whose total quantity changed
+    #[cfg(feature = "tokio")]
+    fn publish_level_changes(&mut self) {
+        let dirty_levels = std::mem::take(&mut self.dirty_levels);
+        if self.level_events.receiver_count() == 0 {
+            self.level_qty_cache.clear();
+            return;
+        }
+
+        for (is_bid, price) in dirty_levels {
+            let new_total_qty = self.level_quantity(is_bid, price);
+            let previous_qty = if new_total_qty == 0 {
+                self.level_qty_cache.remove(&(is_bid, price))
+            } else {
+                self.level_qty_cache.insert((is_bid, price), new_total_qty)
+            }
+            .unwrap_or_default();
+
+            let change = if new_total_qty == previous_qty {
+                continue;
+            } else if new_total_qty == 0 {
+                LevelChange::Removed
+            } else if new_total_qty > previous_qty {
+                LevelChange::Added(new_total_qty - previous_qty)
+            } else {
+                LevelChange::Reduced(previous_qty - new_total_qty)
+            };
+            // Sending only fails once every subscriber has been dropped
+            let _ = self.level_events.send(PriceLevelEvent {
+                side: Side::of(is_bid),
+                price,
+                new_total_qty,
+                change,
+            });
+        }
+    }
+
+    /// Forgets touched levels, since without the broadcast channel there are no subscribers
+    #[cfg(not(feature = "tokio"))]
+    fn publish_level_changes(&mut self) {
+        self.dirty_levels.clear();
+    }
+
+    /// Restarts uptime and time-at-best tracking from the orders now resting in the book
+    fn reset_quote_activity(&mut self) {
+        let now = self.clock.now();
+        for activity in self.quote_activity.values_mut() {
+            if let Some(since) = activity.quoting_since.take() {
+                activity.uptime_ns += now.saturating_sub(since);
+            }
+            activity.resting_orders = 0;
+        }
+        for order in self.bids.values().chain(self.asks.values()) {
+            self.quote_activity.entry(order.owner).or_default().add_resting(now);
+        }
+        self.refresh_best_quote_owners(now);
+    }
+
+    /// Returns the base quantity an owner has bought and sold over the session
+    pub fn get_owner_position(&self, owner: Address) -> OwnerPosition {
+        self.positions.get(&owner).copied().unwrap_or_default()
+    }
+
+    /// Returns an owner's traded base and quote quantities, or `None` if the owner
+    /// has not traded this session
+    pub fn position_for_owner(&self, owner: Address) -> Option<&OwnerPosition> {
+        self.positions.get(&owner)
+    }
+
+    /// Base quantity an owner has bought minus sold over the session
+    pub fn net_base(&self, owner: Address) -> i64 {
+        self.positions.get(&owner).map_or(0, OwnerPosition::net_quantity)
+    }
+
+    /// Measures the risk of an owner's net inventory for a given volatility
+    ///
+    /// `variance = net_inventory^2 * (volatility_bps / 10_000)^2` and
+    /// `value_at_risk = 2.33 * sqrt(variance)`.
+    ///
+    /// # Arguments
+    /// * `owner` - Owner whose position is measured
+    /// * `volatility_bps` - Volatility of the base asset over the risk horizon, in basis points
+    pub fn compute_market_maker_inventory_risk(&self, owner: Address, volatility_bps: u64) -> InventoryRisk {
+        let position = self.get_owner_position(owner);
+        let net_inventory = position.net_quantity();
+        let volatility = volatility_bps as f64 / BPS_DENOMINATOR as f64;
+        let variance = (net_inventory as f64).powi(2) * volatility.powi(2);
+
+        InventoryRisk {
+            long_inventory: position.bought_quantity,
+            short_inventory: position.sold_quantity,
+            net_inventory,
+            variance,
+            value_at_risk: (INVENTORY_VAR_Z_SCORE * variance.sqrt()).round() as u64,
+        }
+    }
+
+    /// Target inventory balancing inventory risk against the cost of trading toward it
+    ///
+    /// Minimises `risk_aversion * sigma^2 * q^2 + h * |q - q0|`, where `q0` is the
+    /// current net inventory, `sigma` the volatility as a fraction and `h` the
+    /// relative half spread of the book. Reducing inventory pays off until the
+    /// marginal risk `2 * risk_aversion * sigma^2 * |q|` falls to `h`, so the target is
+    /// `q0` capped in magnitude at `h / (2 * risk_aversion * sigma^2)`. An empty or
+    /// one-sided book has no spread to pay, so the target is flat.
+    ///
+    /// # Arguments
+    /// * `owner` - Owner whose inventory is targeted
+    /// * `vol_bps` - Volatility of the base asset, in basis points
+    /// * `risk_aversion` - Penalty per unit of inventory variance
+    pub fn optimal_inventory_skew(&self, owner: Address, vol_bps: u64, risk_aversion: f64) -> i64 {
+        let net_inventory = self.get_owner_position(owner).net_quantity();
+        let volatility = vol_bps as f64 / BPS_DENOMINATOR as f64;
+        let marginal_risk = 2.0 * risk_aversion * volatility.powi(2);
+        if marginal_risk <= 0.0 {
+            return net_inventory;
+        }
+
+        let half_spread = match (self.best_level(true), self.best_level(false), self.mid_price()) {
+            (Some((bid, _)), Some((ask, _)), Some(mid)) if mid > 0 => ask.saturating_sub(bid) as f64 / mid as f64 / 2.0,
+            _ => 0.0,
+        };
+        let max_inventory = half_spread / marginal_risk;
+        if (net_inventory as f64).abs() <= max_inventory {
+            net_inventory
+        } else {
+            (max_inventory.round() as i64).saturating_mul(net_inventory.signum())
+        }
+    }
+
+    /// Collects the book's microstructure measures into one report
+    ///
+    /// Each field matches the standalone method of the same name, with depth measured
+    /// within 10 bps of the mid.
+    pub fn order_book_microstructure_report(&self) -> MicrostructureReport {
+        MicrostructureReport {
+            quoted_spread: self.quoted_spread(),
+            effective_spread: self.effective_spread(),
+            realized_spread: self.realized_spread(),
+            price_impact: self.price_impact(),
+            kyle_lambda: self.kyle_lambda(),
+            vpin: self.vpin(),
+            amihud_illiquidity: self.amihud_illiquidity(),
+            order_imbalance: self.order_imbalance(),
+            book_depth_bps: self.book_depth_bps(MICROSTRUCTURE_DEPTH_BAND_BPS),
+        }
+    }
+
+    /// Best ask minus best bid, or `None` if either side is empty
+    pub fn quoted_spread(&self) -> Option<u64> {
+        let (best_bid, _) = self.best_level(true)?;
+        let (best_ask, _) = self.best_level(false)?;
+        Some(best_ask.saturating_sub(best_bid))
+    }
+
+    /// Volume-weighted `2 * |price - mid| / mid` of retained fills, in basis points
+    ///
+    /// Returns `None` if no fill executed against a two-sided book
+    pub fn effective_spread(&self) -> Option<f64> {
+        volume_weighted(self.fill_history.fills().iter().filter_map(|fill| {
+            let mid_price = fill.mid_price? as f64;
+            let spread = 2.0 * (fill.price as f64 - mid_price).abs() / mid_price;
+            Some((spread * BPS_DENOMINATOR as f64, fill.base_quantity))
+        }))
+    }
+
+    /// Volume-weighted `2 * d * (price - later_mid) / mid` of retained fills, in basis points
+    ///
+    /// `d` is +1 for taker buys and -1 for taker sells, and `later_mid` is the mid
+    /// recorded five fills later. Returns `None` if no fill has a later mid to compare.
+    pub fn realized_spread(&self) -> Option<f64> {
+        volume_weighted(self.fills_with_later_mid().map(|(fill, mid_price, later_mid)| {
+            let spread = 2.0 * trade_sign(fill) * (fill.price as f64 - later_mid) / mid_price;
+            (spread * BPS_DENOMINATOR as f64, fill.base_quantity)
+        }))
+    }
+
+    /// Volume-weighted `2 * d * (later_mid - mid) / mid` of retained fills, in basis points
+    ///
+    /// Uses the same fills and horizon as `realized_spread`, so effective spread is
+    /// realized spread plus price impact when both are measured over the same fills.
+    pub fn price_impact(&self) -> Option<f64> {
+        volume_weighted(self.fills_with_later_mid().map(|(fill, mid_price, later_mid)| {
+            let impact = 2.0 * trade_sign(fill) * (later_mid - mid_price) / mid_price;
+            (impact * BPS_DENOMINATOR as f64, fill.base_quantity)
+        }))
+    }
+
+    /// Slope of the mid price change between consecutive fills regressed on the
+    /// signed taker quantity of the earlier fill
+    ///
+    /// Returns `None` with fewer than two observations or no variation in volume
+    pub fn kyle_lambda(&self) -> Option<f64> {
+        let observations: Vec<(f64, f64)> = self
+            .fill_history
+            .fills()
+            .iter()
+            .zip(self.fill_history.fills().iter().skip(1))
+            .filter_map(|(fill, next)| {
+                let mid_change = next.mid_price? as f64 - fill.mid_price? as f64;
+                Some((trade_sign(fill) * fill.base_quantity as f64, mid_change))
+            })
+            .collect();
+        if observations.len() < 2 {
+            return None;
+        }
+
+        let n = observations.len() as f64;
+        let mean_volume = observations.iter().map(|(volume, _)| volume).sum::<f64>() / n;
+        let mean_change = observations.iter().map(|(_, change)| change).sum::<f64>() / n;
+        let covariance: f64 = observations.iter().map(|(v, c)| (v - mean_volume) * (c - mean_change)).sum();
+        let variance: f64 = observations.iter().map(|(v, _)| (v - mean_volume).powi(2)).sum();
+        (variance > 0.0).then(|| covariance / variance)
+    }
+
+    /// Volume-synchronized probability of informed trading over the retained fills
+    ///
+    /// The fill history is split into 10 buckets of equal base volume, and the result
+    /// is the mean of `|buy volume - sell volume| / bucket volume` across buckets.
+    /// Returns `0.0` if nothing has traded.
+    pub fn vpin(&self) -> f64 {
+        let total_volume: u64 = self.fill_history.fills().iter().map(|fill| fill.base_quantity).sum();
+        if total_volume == 0 {
+            return 0.0;
+        }
+
+        let bucket_volume = total_volume as f64 / VPIN_BUCKETS as f64;
+        let mut imbalances = Vec::with_capacity(VPIN_BUCKETS);
+        let (mut buy_volume, mut sell_volume) = (0.0, 0.0);
+        for fill in self.fill_history.fills() {
+            let mut unassigned = fill.base_quantity as f64;
+            while unassigned > 0.0 && imbalances.len() < VPIN_BUCKETS {
+                let capacity = bucket_volume - buy_volume - sell_volume;
+                let assigned = unassigned.min(capacity);
+                if fill.taker_is_bid {
+                    buy_volume += assigned;
+                } else {
+                    sell_volume += assigned;
+                }
+                unassigned -= assigned;
+                if assigned >= capacity {
+                    imbalances.push((buy_volume - sell_volume).abs() / bucket_volume);
+                    (buy_volume, sell_volume) = (0.0, 0.0);
+                }
+            }
+        }
+        // Rounding can leave the last bucket a hair short of full
+        if imbalances.len() < VPIN_BUCKETS && buy_volume + sell_volume > 0.0 {
+            imbalances.push((buy_volume - sell_volume).abs() / bucket_volume);
+        }
+        imbalances.iter().sum::<f64>() / imbalances.len() as f64
+    }
+
+    /// Mean of `|return| / quote volume` between consecutive fills among the last 10
+    ///
+    /// Returns `0.0` with fewer than two fills
+    pub fn amihud_illiquidity(&self) -> f64 {
+        let start = self.fill_history.fills().len().saturating_sub(AMIHUD_WINDOW_FILLS);
+        let window: Vec<&Fill> = self.fill_history.fills()[start..].iter().collect();
+        let ratios: Vec<f64> = window
+            .windows(2)
+            .filter(|pair| pair[1].quote_quantity > 0)
+            .map(|pair| {
+                let price_change = (pair[1].price as f64 - pair[0].price as f64).abs() / pair[0].price as f64;
+                price_change / pair[1].quote_quantity as f64
+            })
+            .collect();
+
+        if ratios.is_empty() {
+            0.0
+        } else {
+            ratios.iter().sum::<f64>() / ratios.len() as f64
+        }
+    }
+
+    /// Resting bid quantity minus ask quantity, divided by all resting quantity
+    ///
+    /// Returns `0.0` for an empty book
+    pub fn order_imbalance(&self) -> f64 {
+        let bid_quantity: u64 = self.bids.values().map(Order::remaining_quantity).sum();
+        let ask_quantity: u64 = self.asks.values().map(Order::remaining_quantity).sum();
+        imbalance(bid_quantity.into(), ask_quantity.into())
+    }
+
+    /// Bid quantity minus ask quantity over the top `levels` price levels of each
+    /// side, divided by their sum
+    ///
+    /// # Arguments
+    /// * `levels` - Number of price levels to include per side
+    ///
+    /// Returns a value in `[-1.0, 1.0]`, or `0.0` when both sides are empty
+    pub fn market_depth_imbalance(&self, levels: usize) -> f64 {
+        let (bid_quantity, ask_quantity) = self.depth_quantities(levels);
+        imbalance(bid_quantity, ask_quantity)
+    }
+
+    /// Bid quantity divided by ask quantity over the top `levels` price levels of each side
+    ///
+    /// # Arguments
+    /// * `levels` - Number of price levels to include per side
+    ///
+    /// Returns `f64::INFINITY` when only the ask side is empty and `0.0` when both are
+    pub fn bid_ask_ratio(&self, levels: usize) -> f64 {
+        match self.depth_quantities(levels) {
+            (0, 0) => 0.0,
+            (_, 0) => f64::INFINITY,
+            (bid_quantity, ask_quantity) => bid_quantity as f64 / ask_quantity as f64,
+        }
+    }
+
+    /// Total visible resting quantity at each price level of one side, keyed by price
+    pub fn price_distribution(&self, side: impl Into<Side>) -> BTreeMap<u64, u64> {
+        let orders = if side.into() == Side::Bid { &self.bids } else { &self.asks };
+        summarize_levels(orders.values()).map(|level| (level.price, level.total_quantity)).collect()
+    }
+
+    /// Quantity-weighted average price of the best `max_levels` price levels of one side,
+    /// rounded down
+    ///
+    /// Returns `None` if the side is empty or `max_levels` is zero
+    pub fn quantity_weighted_average_price(&self, side: impl Into<Side>, max_levels: usize) -> Option<u64> {
+        if side.into() == Side::Bid {
+            quantity_weighted_price(self.bid_levels().take(max_levels))
+        } else {
+            quantity_weighted_price(self.ask_levels().take(max_levels))
+        }
+    }
+
+    /// Resting quantity in the top `levels` price levels of the bid and ask sides
+    fn depth_quantities(&self, levels: usize) -> (u128, u128) {
+        let bid_quantity = self.bid_levels().take(levels).map(|level| level.total_quantity as u128).sum();
+        let ask_quantity = self.ask_levels().take(levels).map(|level| level.total_quantity as u128).sum();
+        (bid_quantity, ask_quantity)
+    }
+
+    /// Order imbalance near the touch and further out, measured around the mid
+    ///
+    /// Near levels are within `near_bps` basis points of the mid; far levels are
+    /// beyond `near_bps` and within `far_bps`. Each imbalance is bid quantity minus
+    /// ask quantity, divided by their sum, or `0.0` without any quantity.
+    ///
+    /// # Arguments
+    /// * `near_bps` - Outer edge of the near band in basis points
+    /// * `far_bps` - Outer edge of the far band in basis points
+    ///
+    /// Returns `(near_imbalance, far_imbalance)`, or `(0.0, 0.0)` unless both sides are populated
+    pub fn get_depth_imbalance_by_price_range(&self, near_bps: u64, far_bps: u64) -> (f64, f64) {
+        let Some(mid_price) = self.mid_price() else {
+            return (0.0, 0.0);
+        };
+
+        let band = |bps: u64| (mid_price as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        let (near_band, far_band) = (band(near_bps), band(far_bps));
+        let mut near = (0, 0);
+        let mut far = (0, 0);
+        for order in self.bids.values().chain(self.asks.values()) {
+            let distance = order.price.abs_diff(mid_price);
+            let range = if distance <= near_band {
+                &mut near
+            } else if distance <= far_band {
+                &mut far
+            } else {
+                continue;
+            };
+            if order.is_bid() {
+                range.0 += order.remaining_quantity();
+            } else {
+                range.1 += order.remaining_quantity();
+            }
+        }
+        (imbalance(near.0.into(), near.1.into()), imbalance(far.0.into(), far.1.into()))
+    }
+
+    /// Base quantity resting on both sides within `band_bps` basis points of the mid
+    ///
+    /// Returns `0.0` unless both sides are populated
+    pub fn book_depth_bps(&self, band_bps: u64) -> f64 {
+        let Some(mid_price) = self.mid_price() else {
+            return 0.0;
+        };
+
+        let band = (mid_price as u128 * band_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        let bid_depth: u64 = self
+            .bids
+            .values()
+            .rev()
+            .take_while(|bid| bid.price >= mid_price.saturating_sub(band))
+            .map(Order::remaining_quantity)
+            .sum();
+        let ask_depth: u64 = self
+            .asks
+            .values()
+            .take_while(|ask| ask.price <= mid_price.saturating_add(band))
+            .map(Order::remaining_quantity)
+            .sum();
+        (bid_depth + ask_depth) as f64
+    }
+
+    /// Measures order flow imbalance at the top `levels` price levels since the last call
+    ///
+    /// For level `i`, `ofi_i = delta_bid_qty_i - delta_ask_qty_i`. Following Cont,
+    /// Kukanov and Stoikov, a level whose price improved contributes its whole new
+    /// quantity, one whose price worsened contributes minus its previous quantity,
+    /// and an unchanged price contributes the change in quantity. The first call
+    /// after creation or `reset_ofi_state` records a baseline and reports zero
+    /// imbalance.
+    ///
+    /// # Arguments
+    /// * `levels` - Number of price levels per side to measure
+    pub fn compute_order_flow_imbalance_signal(&mut self, levels: usize) -> OFISignal {
+        let top = |prices: Vec<u64>, is_bid: bool| {
+            let mut top: Vec<(u64, u64)> = prices
+                .into_iter()
+                .take(levels)
+                .map(|price| (price, self.level_quantity(is_bid, price)))
+                .collect();
+            // Missing levels sit at the worst possible price with no quantity
+            top.resize(levels, (if is_bid { 0 } else { u64::MAX }, 0));
+            top
+        };
+        let bids = top(self.level_prices(true), true);
+        let asks = top(self.level_prices(false), false);
+        let state: Vec<(u64, u64, u64, u64)> = bids
+            .into_iter()
+            .zip(asks)
+            .map(|((bid_price, bid_qty), (ask_price, ask_qty))| (bid_price, bid_qty, ask_price, ask_qty))
+            .collect();
+
+        let flow = |price: u64, qty: u64, prev_price: u64, prev_qty: u64, improved: bool| {
+            if price == prev_price {
+                qty as f64 - prev_qty as f64
+            } else if improved {
+                qty as f64
+            } else {
+                -(prev_qty as f64)
+            }
+        };
+        let per_level: Vec<f64> = state
+            .iter()
+            .enumerate()
+            .map(|(level, &(bid_price, bid_qty, ask_price, ask_qty))| {
+                let Some(&(prev_bid_price, prev_bid_qty, prev_ask_price, prev_ask_qty)) = self.ofi_state.get(level) else {
+                    return 0.0;
+                };
+                flow(bid_price, bid_qty, prev_bid_price, prev_bid_qty, bid_price > prev_bid_price)
+                    - flow(ask_price, ask_qty, prev_ask_price, prev_ask_qty, ask_price < prev_ask_price)
+            })
+            .collect();
+        self.ofi_state = state;
+
+        let aggregate: f64 = per_level.iter().sum();
+        let predicted_direction = if aggregate > 0.0 {
+            Some(Side::Bid)
+        } else if aggregate < 0.0 {
+            Some(Side::Ask)
+        } else {
+            None
+        };
+        OFISignal {
+            per_level,
+            aggregate,
+            predicted_direction,
+        }
+    }
+
+    /// Discards the top of book recorded by the last order flow imbalance call, so
+    /// the next call starts a new epoch
+    pub fn reset_ofi_state(&mut self) {
+        self.ofi_state.clear();
+    }
+
+    /// Skewness of resting prices on each side, weighted by remaining quantity
+    ///
+    /// Negative values mean the weight sits at higher prices with a tail towards
+    /// lower ones. A side with fewer than 3 price levels or no price dispersion
+    /// has skewness 0.0.
+    ///
+    /// Returns `(bid_skewness, ask_skewness)`
+    pub fn compute_order_book_skewness(&self) -> (f64, f64) {
+        (self.side_skewness(true), self.side_skewness(false))
+    }
+
+    /// Third standardized moment of one side's quantity-weighted prices
+    fn side_skewness(&self, is_bid: bool) -> f64 {
+        let prices = self.level_prices(is_bid);
+        if prices.len() < 3 {
+            return 0.0;
+        }
+
+        // Offsets from the lowest price keep nearly equal prices from losing precision
+        let floor = prices.iter().copied().min().unwrap_or_default();
+        let samples: Vec<(f64, f64)> = self
+            .iter_orders(is_bid)
+            .map(|order| ((order.price - floor) as f64, order.remaining_quantity() as f64))
+            .collect();
+        let total_weight: f64 = samples.iter().map(|(_, weight)| weight).sum();
+        if total_weight == 0.0 {
+            return 0.0;
+        }
+        let mean = samples.iter().map(|(offset, weight)| offset * weight).sum::<f64>() / total_weight;
+        let central_moment = |power: i32| {
+            samples.iter().map(|(offset, weight)| weight * (offset - mean).powi(power)).sum::<f64>() / total_weight
+        };
+        let variance = central_moment(2);
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        central_moment(3) / variance.powf(1.5)
+    }
+
+    /// Retained fills paired with their arrival mid and the mid recorded five fills later
+    fn fills_with_later_mid(&self) -> impl Iterator<Item = (&Fill, f64, f64)> {
+        self.fill_history
+            .fills()
+            .iter()
+            .zip(self.fill_history.fills().iter().skip(REALIZED_SPREAD_HORIZON_FILLS))
+            .filter_map(|(fill, later)| Some((fill, fill.mid_price? as f64, later.mid_price? as f64)))
+    }
+
+    /// Returns the price and total remaining quantity of the best level on one side
+    fn best_level(&self, is_bid: bool) -> Option<(u64, u64)> {
+        let best = if is_bid { self.bid_levels().next() } else { self.ask_levels().next() }?;
+        Some((best.price, best.total_quantity))
+    }
+
+    /// Iterates over the bid levels from highest to lowest price
+    ///
+    /// Levels are aggregated lazily while walking the book, without copying orders.
+    pub fn bid_levels(&self) -> impl Iterator<Item = LevelSummary> + '_ {
+        summarize_levels(self.bids.values().rev())
+    }
+
+    /// Iterates over the ask levels from lowest to highest price
+    ///
+    /// Levels are aggregated lazily while walking the book, without copying orders.
+    pub fn ask_levels(&self) -> impl Iterator<Item = LevelSummary> + '_ {
+        summarize_levels(self.asks.values())
+    }
+
+    /// Returns the highest resting bid price in O(log n)
+    pub fn best_bid(&self) -> Option<u64> {
+        self.bids.last_key_value().map(|(_, order)| order.price)
+    }
+
+    /// Returns the lowest resting ask price in O(log n)
+    pub fn best_ask(&self) -> Option<u64> {
+        self.asks.first_key_value().map(|(_, order)| order.price)
+    }
+
+    /// Returns the best ask minus the best bid in O(log n)
+    ///
+    /// Returns `None` if either side is empty or the book is crossed
+    pub fn spread(&self) -> Option<u64> {
+        self.best_ask()?.checked_sub(self.best_bid()?)
+    }
+
+    /// Returns the midpoint of the best bid and best ask, rounded down, in O(log n)
+    ///
+    /// Returns `None` unless both sides are populated
+    pub fn mid_price(&self) -> Option<u64> {
+        Some(self.best_bid()?.midpoint(self.best_ask()?))
+    }
+
+    /// Returns the total remaining base quantity resting on one side in O(n)
+    ///
+    /// # Arguments
+    /// * `side` - Side to sum
+    pub fn total_resting_quantity(&self, side: impl Into<Side>) -> u64 {
+        self.iter_orders(side).map(|order| order.remaining_quantity()).sum()
+    }
+
+    /// Returns the sum of remaining quantity times price over one side in O(n)
+    ///
+    /// The value is in base units times price units; divide by `FLOAT_SCALING` for
+    /// quote units.
+    ///
+    /// # Arguments
+    /// * `side` - Side to value
+    pub fn total_resting_value(&self, side: impl Into<Side>) -> u128 {
+        self.iter_orders(side)
+            .map(|order| order.remaining_quantity() as u128 * order.price as u128)
+            .sum()
+    }
+
+    /// Returns the number of resting bids and asks
+    pub fn order_count(&self) -> (usize, usize) {
+        (self.bids.len(), self.asks.len())
+    }
+}
+
+impl Default for Book {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Configures and validates the parameters of a new `Book`
+///
+/// The default builder produces the same book as `Book::new`.
+#[derive(Debug, Clone)]
+pub struct BookBuilder {
+    params: MarketParams,
+    max_fills_per_match: usize,
+    event_buffer_capacity: usize,
+}
+
+impl Default for BookBuilder {
+    fn default() -> Self {
+        BookBuilder {
+            params: MarketParams::default(),
+            max_fills_per_match: DEFAULT_MAX_FILLS_PER_MATCH,
+            event_buffer_capacity: DEFAULT_EVENT_BUFFER_CAPACITY,
+        }
+    }
+}
+
+impl BookBuilder {
+    /// Sets the price increment order prices must be a multiple of
+    pub fn tick_size(mut self, tick_size: u64) -> Self {
+        self.params.tick_size = tick_size;
+        self
+    }
+
+    /// Sets the quantity increment order quantities must be a multiple of
+    pub fn lot_size(mut self, lot_size: u64) -> Self {
+        self.params.lot_size = lot_size;
+        self
+    }
+
+    /// Sets the smallest quantity an order may be placed with
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.params.min_size = min_size;
+        self
+    }
+
+    /// Sets the step order quantities and fills must be a multiple of
+    pub fn min_qty_increment(mut self, min_qty_increment: u64) -> Self {
+        self.params.min_qty_increment = min_qty_increment;
+        self
+    }
+
+    /// Sets how many fills a taker may take unless the order sets its own limit
+    pub fn max_fills(mut self, max_fills: usize) -> Self {
+        self.max_fills_per_match = max_fills;
+        self
+    }
+
+    /// Sets the fees charged to makers and takers
+    pub fn fee_schedule(mut self, fee_schedule: FeeSchedule) -> Self {
+        self.params.fee_schedule = fee_schedule;
+        self
+    }
+
+    /// Sets how many events the market-data buffer holds before evicting the oldest
+    pub fn event_buffer(mut self, capacity: usize) -> Self {
+        self.event_buffer_capacity = capacity;
+        self
+    }
+
+    /// Sets the self-trade prevention applied to orders that do not choose a mode
+    pub fn stp_mode(mut self, stp_mode: STPMode) -> Self {
+        self.params.default_stp_mode = stp_mode;
+        self
+    }
+
+    /// Builds an empty book, or returns the first parameter that cannot work
+    pub fn build(self) -> Result<Book, BuilderError> {
+        self.params.validate()?;
+        if self.max_fills_per_match == 0 {
+            return Err(BuilderError::ZeroMaxFills);
+        }
+        Ok(Book::new_with_params(self.params)
+            .with_max_fills_per_match(self.max_fills_per_match)
+            .with_event_buffer_capacity(self.event_buffer_capacity))
+    }
+}
+
+/// Independent books for many trading pairs, each matching only its own orders
+#[derive(Debug, Default)]
+pub struct MultiBook {
+    /// Book for each pair created so far
+    books: HashMap<PairId, Book>,
+}
+
+impl MultiBook {
+    /// Creates a multi-pair book with no pairs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the pair's book, creating an empty one with default parameters if needed
+    pub fn get_or_create_book(&mut self, pair: PairId) -> &mut Book {
+        self.books.entry(pair).or_default()
+    }
+
+    /// Returns the pair's book, if it has been created
+    pub fn book(&self, pair: &PairId) -> Option<&Book> {
+        self.books.get(pair)
+    }
+
+    /// Places an order on an existing pair's book
+    ///
+    /// # Arguments
+    /// * `pair` - Pair to trade, which must have been created with `get_or_create_book`
+    /// * `order` - Order to place
+    pub fn place_order(&mut self, pair: PairId, order: Order) -> Result<Vec<Fill>, PairError> {
+        match self.books.get_mut(&pair) {
+            Some(book) => Ok(book.place_order_sync(order)?),
+            None => Err(PairError::UnknownPair(pair)),
+        }
+    }
+
+    /// Captures the best `depth` levels of a pair's book, stamped with the book's time
+    ///
+    /// Returns `None` if the pair has no book.
+    pub fn aggregate_snapshot(&self, pair: PairId, depth: usize) -> Option<BookSnapshot> {
+        let book = self.books.get(&pair)?;
+        Some(book.snapshot(depth, book.clock.now()))
+    }
+}
+
+/// Groups consecutive orders at the same price into level summaries
+fn summarize_levels<'a>(orders: impl Iterator<Item = &'a Order>) -> impl Iterator<Item = LevelSummary> {
+    let mut orders = orders.peekable();
+    std::iter::from_fn(move || {
+        let first = orders.next()?;
+        let mut level = LevelSummary { price: first.price, total_quantity: first.visible_quantity(), order_count: 1 };
+        while let Some(order) = orders.next_if(|order| order.price == level.price) {
+            level.total_quantity += order.visible_quantity();
+            level.order_count += 1;
+        }
+        Some(level)
+    })
+}
+
+/// Averages level prices weighted by their quantities, or `None` without any quantity
+fn quantity_weighted_price(levels: impl Iterator<Item = LevelSummary>) -> Option<u64> {
+    let (weighted, quantity) = levels.fold((0u128, 0u128), |(weighted, quantity), level| {
+        (weighted + level.price as u128 * level.total_quantity as u128, quantity + level.total_quantity as u128)
+    });
+    (quantity > 0).then(|| (weighted / quantity) as u64)
+}
+
+/// Counts distinct price levels among orders iterated in price order
+fn count_levels<'a>(orders: impl Iterator<Item = &'a Order>) -> usize {
+    let mut levels = 0;
+    let mut last_price = None;
+    for order in orders {
+        if last_price != Some(order.price) {
+            levels += 1;
+            last_price = Some(order.price);
+        }
+    }
+    levels
+}
+
+/// Appends a completed order's lifetime, evicting the oldest once the buffer is full
+fn record_lifetime(lifetimes: &mut VecDeque<u64>, lifetime: u64) {
+    push_capped(lifetimes, lifetime, MAX_COMPLETED_LIFETIMES);
+}
+
+/// Checks that a snapshot level's orders sit on its side and price and match its totals
+fn check_level(level: &PriceLevel, is_bid: bool) -> Result<(), WarmUpError> {
+    if let Some(order) = level.orders.iter().find(|order| order.is_bid() != is_bid || order.price != level.price) {
+        return Err(WarmUpError::ConstraintViolation(format!(
+            "order {} does not belong to the level at price {}",
+            order.order_id, level.price
+        )));
+    }
+
+    let total_quantity: u64 = level.orders.iter().map(Order::visible_quantity).sum();
+    if level.order_count != level.orders.len() || level.total_quantity != total_quantity {
+        return Err(WarmUpError::ConstraintViolation(format!(
+            "level at price {} does not match its orders",
+            level.price
+        )));
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field if it contains a delimiter, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Adds a fill's base and quote quantities to the buyer's and seller's positions
+fn record_position_fill(positions: &mut HashMap<Address, OwnerPosition>, buyer: &Address, seller: &Address, fill: &Fill) {
+    for (owner, is_buyer) in [(buyer, true), (seller, false)] {
+        let position = positions.entry(*owner).or_default();
+        if is_buyer {
+            position.bought_quantity += fill.base_quantity;
+            position.quote_spent += fill.quote_quantity;
+        } else {
+            position.sold_quantity += fill.base_quantity;
+            position.quote_received += fill.quote_quantity;
+        }
+    }
+}
+
+/// `plus` minus `minus`, saturating at the bounds of `i64`
+fn saturating_net(plus: u128, minus: u128) -> i64 {
+    if plus >= minus {
+        0i64.saturating_add_unsigned(saturate_u64(plus - minus))
+    } else {
+        0i64.saturating_sub_unsigned(saturate_u64(minus - plus))
+    }
+}
+
+/// `gain` as basis points of `cost`, saturating at `u64::MAX`, or `None` for a zero cost
+fn profit_bps(gain: u64, cost: u64) -> Option<u64> {
+    (gain as u128 * BPS_DENOMINATOR as u128).checked_div(cost as u128).map(saturate_u64)
+}
+
+/// Quote value of `base_quantity` at `price`, widened so the product cannot overflow
+fn quote_for(base_quantity: u64, price: u64) -> u128 {
+    base_quantity as u128 * price as u128 / FLOAT_SCALING as u128
+}
+
+/// Narrows to `u64`, clamping at `u64::MAX`
+fn saturate_u64(value: u128) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+/// Direction of a fill's taker: +1 for buys and -1 for sells
+fn trade_sign(fill: &Fill) -> f64 {
+    if fill.taker_is_bid {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+/// Average of values weighted by quantity, or `None` if there is no quantity
+fn volume_weighted(values: impl Iterator<Item = (f64, u64)>) -> Option<f64> {
+    let (weighted_sum, total_quantity) = values.fold((0.0, 0u64), |(sum, total), (value, quantity)| {
+        (sum + value * quantity as f64, total + quantity)
+    });
+    (total_quantity > 0).then(|| weighted_sum / total_quantity as f64)
+}
+
+/// Bid quantity minus ask quantity as a fraction of both, `0.0` when both are zero
+fn imbalance(bid_quantity: u128, ask_quantity: u128) -> f64 {
+    let total = bid_quantity + ask_quantity;
+    if total == 0 {
+        0.0
+    } else {
+        (bid_quantity as f64 - ask_quantity as f64) / total as f64
+    }
+}
+
+/// Default time source for new and restored books
+fn system_clock() -> Box<dyn Clock> {
+    Box::new(SystemClock)
+}
+
+/// Sender for price level events with no subscribers yet
+#[cfg(feature = "tokio")]
+fn level_event_channel() -> broadcast::Sender<PriceLevelEvent> {
+    broadcast::channel(LEVEL_EVENT_CAPACITY).0
+}
+
+/// Serializes a book side as a sequence of key-order pairs, since formats such as
+/// JSON only allow string map keys
+#[cfg(feature = "serde")]
+mod key_value_pairs {
+    use super::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<K: Serialize, V: Serialize, S: Serializer>(
+        map: &BTreeMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Vec::<(K, V)>::deserialize(deserializer).map(|pairs| pairs.into_iter().collect())
+    }
+}
+
+/// Drops an order that left the book from its owner's index entry
+fn unindex_owner(owner_index: &mut HashMap<Address, BTreeSet<(bool, u128)>>, order: &Order) {
+    if let Some(orders) = owner_index.get_mut(&order.owner) {
+        orders.remove(&(order.is_bid(), order.order_id));
+        if orders.is_empty() {
+            owner_index.remove(&order.owner);
+        }
+    }
+}
+
+/// Records that one of an owner's orders left the book
+fn release_quote(quote_activity: &mut HashMap<Address, QuoteActivity>, owner: &Address, now: u64) {
+    if let Some(activity) = quote_activity.get_mut(owner) {
+        activity.remove_resting(now);
+    }
+}
+
+/// Appends a value, evicting the oldest once the buffer holds `capacity` entries
+fn push_capped<T>(buffer: &mut VecDeque<T>, value: T, capacity: usize) {
+    if buffer.len() == capacity {
+        buffer.pop_front();
+    }
+    buffer.push_back(value);
+}
+
+/// Change recorded for a maker after a fill has been applied to it
+fn fill_change(maker: &Order) -> OrderBookChange {
+    if maker.is_filled() {
+        OrderBookChange::OrderFullFill(maker.order_id)
+    } else {
+        OrderBookChange::OrderPartialFill(maker.order_id, maker.remaining_quantity())
+    }
+}
+
+/// Appends a change to the replication log under the next sequence number
+fn record_change(change_log: &mut VecDeque<(u64, OrderBookChange)>, change_seq: &mut u64, change: OrderBookChange) {
+    *change_seq += 1;
+    push_capped(change_log, (*change_seq, change), MAX_CHANGE_LOG);
+}
+
+/// Builds a book key ordering orders by price, then venue rank, then arrival sequence
+fn order_key(price: u64, venue_rank: u8, sequence: u64) -> PriceTimeKey {
+    PriceTimeKey { price, sequence: ((venue_rank as u64) << SEQUENCE_BITS) | sequence }
+}
+
+/// Key placing an order behind everything already at its price and venue rank, given
+/// a fresh arrival sequence
+fn queue_key(order: &Order, sequence: u64) -> PriceTimeKey {
+    // Bids are read from the highest key, so their venue rank and arrival are inverted
+    if order.is_bid() {
+        order_key(order.price, u8::MAX - order.venue_priority, MAX_SEQUENCE - sequence)
+    } else {
+        order_key(order.price, order.venue_priority, sequence)
+    }
+}
+
+/// Opposite side of the book as the matching loop sees it
+///
+/// `match_resting_orders` decides every fill, skip and stop; a view carries those
+/// decisions out, either on the live book or on a scratch copy of the makers reached.
+trait MatchView {
+    /// Market the book trades
+    fn params(&self) -> &MarketParams;
+    /// Key of the best remaining maker
+    fn best_key(&mut self) -> Option<PriceTimeKey>;
+    /// Maker resting at `key`
+    fn maker(&self, key: PriceTimeKey) -> &Order;
+    /// Passes over a maker that stays on the book but cannot trade
+    fn skip(&mut self, key: PriceTimeKey);
+    /// Removes a maker past its expiry
+    fn expire(&mut self, key: PriceTimeKey, timestamp: u64);
+    /// Removes a maker cancelled by self-trade prevention and returns it
+    fn cancel_maker(&mut self, key: PriceTimeKey, timestamp: u64) -> Order;
+    /// Records the taker's cancellation by self-trade prevention
+    fn cancel_taker(&mut self, taker: &Order);
+    /// Whether the circuit breaker halts a fill at `price`, tripping it if so
+    fn halts(&mut self, price: u64) -> bool;
+    /// Applies a fill to the maker at `key`, removing it once filled and requeueing an
+    /// iceberg whose shown tranche is used up
+    fn fill(&mut self, key: PriceTimeKey, fill: &Fill, improvement_units: u64);
+}
+
+/// Matches the taker against a view of the opposite side, updating its filled quantity
+/// in place
+///
+/// Returns the fills, the orders self-trade prevention cancelled and why matching stopped
+fn match_resting_orders(
+    view: &mut impl MatchView,
+    taker_order: &mut Order,
+    timestamp: u64,
+    mid_price: Option<u64>,
+    max_fills: usize,
+    protection: &MatchProtection,
+) -> (Vec<Fill>, Vec<Order>, TerminationReason) {
+    let mut fills = Vec::new();
+    let mut stp_cancelled = Vec::new();
+    let mut termination_reason = TerminationReason::Complete;
+    let mut reference_price = None;
+
+    // Keep matching until order is filled or no more matches possible
+    while taker_order.remaining_quantity() > 0 {
+        let Some(key) = view.best_key() else {
+            break;
+        };
+        if fills.len() >= max_fills {
+            termination_reason = TerminationReason::FillsCapped;
+            break;
+        }
+        let maker_order = view.maker(key);
+
+        // Check if maker order is expired
+        if maker_order.expire_timestamp < timestamp {
+            view.expire(key, timestamp);
+            continue;
+        }
+
+        // Check if price matches
+        if !Book::prices_match(taker_order, maker_order) {
+            break;
+        }
+
+        if maker_order.owner == taker_order.owner && taker_order.stp_mode != STPMode::None {
+            if matches!(taker_order.stp_mode, STPMode::CancelMaker | STPMode::CancelBoth) {
+                stp_cancelled.push(view.cancel_maker(key, timestamp));
+            }
+            if matches!(taker_order.stp_mode, STPMode::CancelTaker | STPMode::CancelBoth) {
+                taker_order.cancellation_timestamp = Some(timestamp);
+                view.cancel_taker(taker_order);
+                stp_cancelled.push(taker_order.clone());
+                termination_reason = TerminationReason::SelfTradePrevented;
+                break;
+            }
+            continue;
+        }
+
+        // Slippage is measured from the best price available when matching began
+        let maker_price = maker_order.price;
+        let reference_price = *reference_price.get_or_insert(maker_price);
+        if let Some(max_slippage_bps) = protection.max_slippage_bps {
+            let slippage_bps =
+                maker_price.abs_diff(reference_price) as u128 * BPS_DENOMINATOR as u128 / reference_price as u128;
+            if slippage_bps > max_slippage_bps as u128 {
+                termination_reason = TerminationReason::SlippageBreached;
+                break;
+            }
+        }
+
+        // A tripped breaker stops matching until it is reset
+        if view.halts(maker_price) {
+            termination_reason = TerminationReason::CircuitBreakerTripped;
+            break;
+        }
+
+        // Calculate fill quantity, trimmed to the increment in case either side
+        // predates it
+        let params = view.params();
+        let maker_order = view.maker(key);
+        let fill_qty = params.trim_to_qty_increment(taker_order.remaining_quantity().min(maker_order.visible_quantity()));
+        if fill_qty == 0 {
+            // A maker showing less than one increment cannot trade, but those behind it can
+            if params.trim_to_qty_increment(taker_order.remaining_quantity()) == 0 {
+                break;
+            }
+            view.skip(key);
+            continue;
+        }
+        if protection.min_fill_qty.is_some_and(|min_fill_qty| fill_qty < min_fill_qty) {
+            termination_reason = TerminationReason::MinQtyNotMet;
+            break;
+        }
+
+        // Takers trade at the maker's price, so any gap to their limit is improvement
+        let improvement_units = if taker_order.order_type == OrderType::Market {
+            0 // A market order has no limit to improve on
+        } else {
+            taker_order.price.abs_diff(maker_price)
+        };
+        let price_improved = params.price_improvement && improvement_units > 0;
+        let quote_quantity = quote_for(fill_qty, maker_price);
+        let fill = Fill {
+            maker_order_id: maker_order.order_id,
+            taker_order_id: taker_order.order_id,
+            maker_owner: maker_order.owner,
+            taker_owner: taker_order.owner,
+            taker_is_bid: taker_order.is_bid(),
+            price: maker_price,
+            base_quantity: fill_qty,
+            quote_quantity,
+            timestamp,
+            price_improved,
+            improvement_amount: if price_improved { saturate_u64(quote_for(fill_qty, improvement_units)) } else { 0 },
+            mid_price,
+            maker_fee: params.fee_schedule.maker_fee(fill_qty),
+            taker_fee: params.fee_schedule.taker_fee(quote_quantity),
+            maker_remaining_after: maker_order.remaining_quantity() - fill_qty,
+            taker_remaining_after: taker_order.remaining_quantity() - fill_qty,
+        };
+        taker_order.filled_quantity += fill_qty;
+        view.fill(key, &fill, improvement_units);
+        fills.push(fill);
+    }
+    (fills, stp_cancelled, termination_reason)
+}
+
+/// First maker after `after` in the order takers read a side, bids from the highest
+/// key and asks from the lowest
+fn next_maker(
+    book_side: &BTreeMap<PriceTimeKey, Order>,
+    after: Option<PriceTimeKey>,
+    from_highest: bool,
+) -> Option<(&PriceTimeKey, &Order)> {
+    match (after, from_highest) {
+        (None, true) => book_side.last_key_value(),
+        (None, false) => book_side.first_key_value(),
+        (Some(after), true) => book_side.range(..after).next_back(),
+        (Some(after), false) => book_side.range((Bound::Excluded(after), Bound::Unbounded)).next(),
+    }
+}
+
+/// Matching view over the live book, recording every effect of each fill
+struct LiveMatch<'a> {
+    book: &'a mut Book,
+    /// Side of the taker; it matches against the other
+    taker_is_bid: bool,
+    /// Key of the last maker passed over; only makers after it are read
+    skipped_through: Option<PriceTimeKey>,
+}
+
+impl LiveMatch<'_> {
+    /// Side the taker matches against
+    fn book_side(&mut self) -> &mut BTreeMap<PriceTimeKey, Order> {
+        if self.taker_is_bid {
+            &mut self.book.asks
+        } else {
+            &mut self.book.bids
+        }
+    }
+}
+
+impl MatchView for LiveMatch<'_> {
+    fn params(&self) -> &MarketParams {
+        &self.book.params
+    }
+
+    fn best_key(&mut self) -> Option<PriceTimeKey> {
+        let book_side = if self.taker_is_bid { &self.book.asks } else { &self.book.bids };
+        next_maker(book_side, self.skipped_through, !self.taker_is_bid).map(|(key, _)| *key)
+    }
+
+    fn skip(&mut self, key: PriceTimeKey) {
+        self.skipped_through = Some(key);
+    }
+
+    fn maker(&self, key: PriceTimeKey) -> &Order {
+        let book_side = if self.taker_is_bid { &self.book.asks } else { &self.book.bids };
+        &book_side[&key]
+    }
+
+    fn expire(&mut self, key: PriceTimeKey, timestamp: u64) {
+        self.book.remove_expired(!self.taker_is_bid, key, timestamp);
+    }
+
+    fn cancel_maker(&mut self, key: PriceTimeKey, timestamp: u64) -> Order {
+        let mut cancelled = self.book_side().remove(&key).expect("best key is present");
+        let book = &mut *self.book;
+        cancelled.cancellation_timestamp = Some(timestamp);
+        book.order_keys.remove(&cancelled.order_id);
+        unindex_owner(&mut book.owner_index, &cancelled);
+        book.tracked_large_orders.remove(&cancelled.order_id);
+        release_quote(&mut book.quote_activity, &cancelled.owner, timestamp);
+        book.dirty_levels.push((cancelled.is_bid(), cancelled.price));
+        record_change(&mut book.change_log, &mut book.change_seq, OrderBookChange::OrderCancelled(cancelled.order_id));
+        book.settled_orders.record(cancelled.order_id, OrderStatus::Cancelled);
+        book.stats.total_orders_cancelled += 1;
+        record_lifetime(&mut book.completed_order_lifetimes, timestamp.saturating_sub(cancelled.submission_timestamp));
+        cancelled
+    }
+
+    fn cancel_taker(&mut self, taker: &Order) {
+        self.book.settled_orders.record(taker.order_id, OrderStatus::Cancelled);
+        self.book.stats.total_orders_cancelled += 1;
+    }
+
+    fn halts(&mut self, price: u64) -> bool {
+        self.book.circuit_breaker.as_mut().is_some_and(|breaker| breaker.halts(price))
+    }
+
+    fn fill(&mut self, key: PriceTimeKey, fill: &Fill, improvement_units: u64) {
+        let timestamp = fill.timestamp;
+        let book = &mut *self.book;
+        if fill.price_improved {
+            book.fills_with_improvement += 1;
+            book.total_improvement_units += improvement_units;
+            book.total_improvement_value += fill.improvement_amount;
+        } else if book.params.price_improvement {
+            book.fills_without_improvement += 1;
+        }
+        book.total_maker_fees += fill.maker_fee;
+        book.total_taker_fees += fill.taker_fee;
+
+        // Update the maker
+        let book_side = if self.taker_is_bid { &mut book.asks } else { &mut book.bids };
+        let maker_order = book_side.get_mut(&key).expect("best key is present");
+        maker_order.filled_quantity += fill.base_quantity;
+        book.dirty_levels.push((maker_order.is_bid(), maker_order.price));
+        record_change(&mut book.change_log, &mut book.change_seq, fill_change(maker_order));
+
+        if let Some(mid_price) = fill.mid_price {
+            let distance_bps = fill.price.abs_diff(mid_price) * BPS_DENOMINATOR / mid_price;
+            let stats = book
+                .maker_fill_stats
+                .entry(fill.maker_owner)
+                .or_insert_with(|| MakerFillStats {
+                    first_fill_timestamp: timestamp,
+                    ..Default::default()
+                });
+            let level = stats.levels.entry(distance_bps).or_default();
+            level.fill_count += 1;
+            level.base_quantity += fill.base_quantity;
+            stats.last_fill_timestamp = timestamp;
+        }
+        if let Some(activity) = book.quote_activity.get_mut(&fill.maker_owner) {
+            activity.maker_fills += 1;
+        }
+        let (buyer, seller) = if fill.taker_is_bid {
+            (&fill.taker_owner, &fill.maker_owner)
+        } else {
+            (&fill.maker_owner, &fill.taker_owner)
+        };
+        record_position_fill(&mut book.positions, buyer, seller, fill);
+
+        // Remove fully filled maker orders
+        if maker_order.is_filled() {
+            let filled = book_side.remove(&key).expect("best key is present");
+            let lifetime = timestamp.saturating_sub(filled.submission_timestamp);
+            book.settled_orders.record(filled.order_id, OrderStatus::Filled);
+            book.order_keys.remove(&filled.order_id);
+            unindex_owner(&mut book.owner_index, &filled);
+            book.tracked_large_orders.remove(&filled.order_id);
+            release_quote(&mut book.quote_activity, &filled.owner, timestamp);
+            record_lifetime(&mut book.completed_order_lifetimes, lifetime);
+        } else if maker_order.iceberg_peak.is_some_and(|peak| maker_order.filled_quantity.is_multiple_of(peak)) {
+            // The shown tranche of an iceberg filled, so the next joins the back of
+            // the queue
+            let iceberg = book_side.remove(&key).expect("best key is present");
+            book.sequence_counter += 1;
+            let requeued = queue_key(&iceberg, book.sequence_counter);
+            book.order_keys.insert(iceberg.order_id, requeued);
+            book_side.insert(requeued, iceberg);
+        }
+
+        book.total_fills += 1;
+        book.stats.record_fill(fill);
+        book.ticker.record_fill(fill);
+        book.session_volume += fill.base_quantity;
+        book.twap_oracle.push(timestamp, fill.price);
+        book.fill_history.push(fill.clone());
+        book.events.record(|| BookEvent::Filled(fill.clone()));
+    }
+}
+
+/// Matching view over a read-only book, applying fills to copies of the makers reached
+struct SimulatedMatch<'a> {
+    book: &'a Book,
+    /// Side of the taker; it matches against the other
+    taker_is_bid: bool,
+    /// Key of the last maker read from the book; those before it have been used up
+    cursor: Option<PriceTimeKey>,
+    /// Maker at `cursor` with the simulated fills applied, until it leaves
+    current: Option<(PriceTimeKey, Order)>,
+    /// Icebergs the simulation moved to the back of their level
+    requeued: BTreeMap<PriceTimeKey, Order>,
+    /// Arrival counter continuing the book's, for requeued icebergs
+    sequence_counter: u64,
+    /// Copy of the book's circuit breaker
+    circuit_breaker: Option<CircuitBreaker>,
+}
+
+impl SimulatedMatch<'_> {
+    /// Removes the maker at `key` from the simulation
+    fn take(&mut self, key: PriceTimeKey) -> Order {
+        match self.current.take() {
+            Some((current_key, maker)) if current_key == key => maker,
+            current => {
+                self.current = current;
+                self.requeued.remove(&key).expect("best key is present")
+            }
+        }
+    }
+}
+
+impl MatchView for SimulatedMatch<'_> {
+    fn params(&self) -> &MarketParams {
+        &self.book.params
+    }
+
+    fn best_key(&mut self) -> Option<PriceTimeKey> {
+        // Bids are read from the highest key and asks from the lowest
+        let from_highest = !self.taker_is_bid;
+        if self.current.is_none() {
+            let book_side = if self.taker_is_bid { &self.book.asks } else { &self.book.bids };
+            if let Some((&key, maker)) = next_maker(book_side, self.cursor, from_highest) {
+                self.cursor = Some(key);
+                self.current = Some((key, maker.clone()));
+            }
+        }
+        let requeued = if from_highest {
+            self.requeued.last_key_value()
+        } else {
+            self.requeued.first_key_value()
+        };
+        let candidates = self.current.as_ref().map(|(key, _)| *key).into_iter().chain(requeued.map(|(key, _)| *key));
+        if from_highest {
+            candidates.max()
+        } else {
+            candidates.min()
+        }
+    }
+
+    fn maker(&self, key: PriceTimeKey) -> &Order {
+        match &self.current {
+            Some((current_key, maker)) if *current_key == key => maker,
+            _ => &self.requeued[&key],
+        }
+    }
+
+    fn skip(&mut self, key: PriceTimeKey) {
+        self.take(key);
+    }
+
+    fn expire(&mut self, key: PriceTimeKey, _timestamp: u64) {
+        self.take(key);
+    }
+
+    fn cancel_maker(&mut self, key: PriceTimeKey, timestamp: u64) -> Order {
+        let mut cancelled = self.take(key);
+        cancelled.cancellation_timestamp = Some(timestamp);
+        cancelled
+    }
+
+    fn cancel_taker(&mut self, _taker: &Order) {}
+
+    fn halts(&mut self, price: u64) -> bool {
+        self.circuit_breaker.as_mut().is_some_and(|breaker| breaker.halts(price))
+    }
+
+    fn fill(&mut self, key: PriceTimeKey, fill: &Fill, _improvement_units: u64) {
+        let mut maker = self.take(key);
+        maker.filled_quantity += fill.base_quantity;
+        if maker.is_filled() {
+            return;
+        }
+        if maker.iceberg_peak.is_some_and(|peak| maker.filled_quantity.is_multiple_of(peak)) {
+            self.sequence_counter += 1;
+            self.requeued.insert(queue_key(&maker, self.sequence_counter), maker);
+        } else if self.cursor == Some(key) {
+            self.current = Some((key, maker));
+        } else {
+            self.requeued.insert(key, maker);
+        }
+    }
+}
+
+impl Order {
+    /// Creates a limit order that rests any unfilled remainder
+    ///
+    /// # Arguments
+    /// * `order_id` - Unique identifier for the order
+    /// * `price` - Limit price per unit of base asset
+    /// * `quantity` - Base quantity to trade
+    /// * `owner` - Owner of the order
+    /// * `expire_timestamp` - Time after which the order expires; `u64::MAX` never expires
+    /// * `side` - Whether to buy or sell; `true` converts to `Side::Bid`
+    pub fn new_limit(
+        order_id: u128,
+        price: u64,
+        quantity: u64,
+        owner: impl Into<Address>,
+        expire_timestamp: u64,
+        side: impl Into<Side>,
+    ) -> Result<Order, OrderError> {
+        if price == 0 {
+            return Err(OrderError::ZeroPrice);
+        }
+        if expire_timestamp == 0 {
+            return Err(OrderError::ZeroExpiry);
+        }
+        Order::new(order_id, price, quantity, owner.into(), expire_timestamp, side.into(), OrderType::Limit)
+    }
+
+    /// Creates a market order that trades against any price on the opposite side
+    ///
+    /// Bids are priced at `u64::MAX` and asks at zero. Market orders never rest, so
+    /// any quantity left once the opposite side is exhausted is dropped.
+    ///
+    /// # Arguments
+    /// * `order_id` - Unique identifier for the order
+    /// * `quantity` - Base quantity to trade
+    /// * `owner` - Owner of the order
+    /// * `side` - Whether to buy or sell; `true` converts to `Side::Bid`
+    pub fn new_market(order_id: u128, quantity: u64, owner: impl Into<Address>, side: impl Into<Side>) -> Result<Order, OrderError> {
+        let side = side.into();
+        let price = if side == Side::Bid { u64::MAX } else { 0 };
+        Order::new(order_id, price, quantity, owner.into(), 0, side, OrderType::Market)
+    }
+
+    /// Builds an unfilled order with default priority and protections once its
+    /// quantity has been checked against the minimum size
+    fn new(
+        order_id: u128,
+        price: u64,
+        quantity: u64,
+        owner: Address,
+        expire_timestamp: u64,
+        side: Side,
+        order_type: OrderType,
+    ) -> Result<Order, OrderError> {
+        if quantity < MIN_SIZE {
+            return Err(OrderError::QuantityBelowMinimum);
+        }
+        Ok(Order {
+            order_id,
+            price,
+            quantity,
+            filled_quantity: 0,
+            owner,
+            expire_timestamp,
+            side,
+            submission_timestamp: 0,
+            cancellation_timestamp: None,
+            venue_priority: 0,
+            order_type,
+            stp_mode: STPMode::None,
+            max_fills: None,
+            iceberg_peak: None,
+        })
+    }
+
+    /// Replaces the execution instructions, returning the updated order
+    pub fn with_order_type(mut self, order_type: OrderType) -> Self {
+        self.order_type = order_type;
+        self
+    }
+
+    /// Sets the self-trade prevention applied when the order takes, returning the
+    /// updated order
+    pub fn with_stp_mode(mut self, stp_mode: STPMode) -> Self {
+        self.stp_mode = stp_mode;
+        self
+    }
+
+    /// Caps the fills the order may take, overriding the book's limit, and returns the
+    /// updated order
+    pub fn with_max_fills(mut self, max_fills: usize) -> Self {
+        self.max_fills = Some(max_fills);
+        self
+    }
+
+    /// Makes the order an iceberg that shows `peak` at a time while resting, and
+    /// returns the updated order
+    ///
+    /// Each time the shown tranche fills, the next one is shown at the back of the
+    /// queue at the same price.
+    pub fn with_iceberg_peak(mut self, peak: u64) -> Self {
+        self.iceberg_peak = Some(peak);
+        self
+    }
+
+    /// Unique identifier for the order
+    pub fn order_id(&self) -> u128 {
+        self.order_id
+    }
+
+    /// Price per unit of base asset
+    pub fn price(&self) -> u64 {
+        self.price
+    }
+
+    /// Total quantity of base asset to trade
+    pub fn quantity(&self) -> u64 {
+        self.quantity
+    }
+
+    /// Amount of base asset that has been filled
+    pub fn filled_quantity(&self) -> u64 {
+        self.filled_quantity
+    }
+
+    /// Address of the order owner
+    pub fn owner(&self) -> Address {
+        self.owner
+    }
+
+    /// Timestamp after which the order is considered expired
+    pub fn expire_timestamp(&self) -> u64 {
+        self.expire_timestamp
+    }
+
+    /// Whether the order buys or sells
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// True for buy orders, false for sell orders
+    pub fn is_bid(&self) -> bool {
+        self.side == Side::Bid
+    }
+
+    /// Timestamp at which the book accepted the order
+    pub fn submission_timestamp(&self) -> u64 {
+        self.submission_timestamp
+    }
+
+    /// Timestamp at which the order was cancelled, if it was
+    pub fn cancellation_timestamp(&self) -> Option<u64> {
+        self.cancellation_timestamp
+    }
+
+    /// Priority of the originating venue, lower is better
+    pub fn venue_priority(&self) -> u8 {
+        self.venue_priority
+    }
+
+    /// How the order matches and whether any remainder rests
+    pub fn order_type(&self) -> OrderType {
+        self.order_type
+    }
+
+    /// Self-trade prevention applied when this order is the taker
+    pub fn stp_mode(&self) -> STPMode {
+        self.stp_mode
+    }
+
+    /// Maximum number of fills this order may take, if it overrides the book's limit
+    pub fn max_fills(&self) -> Option<usize> {
+        self.max_fills
+    }
+
+    /// Quantity shown at a time while resting, if this is an iceberg order
+    pub fn iceberg_peak(&self) -> Option<u64> {
+        self.iceberg_peak
+    }
+
+    /// Returns the unfilled quantity of the current tranche, or the whole remaining
+    /// quantity if this is not an iceberg order
+    pub fn visible_quantity(&self) -> u64 {
+        match self.iceberg_peak {
+            Some(peak) if peak > 0 => self.remaining_quantity().min(peak - self.filled_quantity % peak),
+            _ => self.remaining_quantity(),
+        }
+    }
+
+    /// Returns the unfilled quantity of the order
+    pub fn remaining_quantity(&self) -> u64 {
+        self.quantity - self.filled_quantity
+    }
+
+    /// Returns the quote value of the unfilled quantity at the order's price, such as
+    /// the quote a resting bid has locked
+    ///
+    /// The value is in base units times price units, computed in `u128` so it cannot
+    /// overflow at full base and price precision; divide by `FLOAT_SCALING` for quote
+    /// units, which rounds down as fills do.
+    pub fn remaining_quote(&self) -> u128 {
+        self.remaining_quantity() as u128 * self.price as u128
+    }
+
+    /// Returns the quote value of the filled quantity at the order's price, in the same
+    /// unscaled units as `remaining_quote`
+    ///
+    /// Fills at a better price than the order's limit traded for less than this.
+    pub fn filled_quote(&self) -> u128 {
+        self.filled_quantity as u128 * self.price as u128
     }
 
     /// Checks if the order has been completely filled
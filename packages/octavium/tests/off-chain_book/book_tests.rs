@@ -1,231 +1,4015 @@
-use crate::book::{Book, Order, Fill};
+use crate::book::{
+    Address, AddressError, AlertType, AmendError, ArbDirection, ArbitrageSignal, BenchmarkType, Book, BookBuilder,
+    BookError, BookEvent, BookMode, BookSnapshot, BookStats, BuilderError, CancelResult, ConditionalResult,
+    ExecutionQualityReport, ExportError, FairValueMethod, FairValueSpread, FeeSchedule, Fill, FillFilter,
+    FillSimulationReport, FillSortKey, InstitutionalOrderCandidate, Lcg, LevelSummary, LifetimeDistribution,
+    ManipulationAlert, MarketImpactModel, MarketParams, MarketResetResult, MatchProtection, MatchResult,
+    MicrostructureReport, MockClock, MultiBook, OFISignal, Order, OrderBookChange, OrderError, OrderEvent, OrderSide,
+    OrderType, OwnerPosition, PairError, PairId, PlaceCondition, PlaceError, PlaceOutcome, PreTradeHook,
+    PriceImprovementStats, PriceLevel, ProtectedMatchResult, QueuePosition, QuoteError, RegulatoryRiskParams,
+    RegulatoryViolation, ReplaceError, STPMode, Side, SpreadRecommendation, StopReason, TailRiskExposure,
+    TerminationReason, Ticker, TopOfBookEvent, WarmUpError,
+};
+#[cfg(feature = "tokio")]
+use crate::book::{CancelAllResult, LevelChange, PriceLevelEvent, ShutdownError};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 const USDC_DECIMALS: u64 = 1_000_000;      // 6 decimals
 const SUI_DECIMALS: u64 = 1_000_000_000;   // 9 decimals
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
 const FLOAT_SCALING: u64 = 1_000_000_000;  // 9 decimals
 const MAKER_FEE: u64 = 50;                 // 0.05% = 5 bps
 const TAKER_FEE: u64 = 100;                // 0.10% = 10 bps
 
 #[test]
 fn test_partial_fill_bid() {
-    let mut book = Book::new();
+    let mut book = BookBuilder::default().build().unwrap();
     
     // Create a bid order: Buy 10 SUI at $5/SUI
-    let taker_order = Order {
+    let taker_order = Order::new_limit(1, 5 * USDC_DECIMALS, 10 * SUI_DECIMALS, "alice", u64::MAX, true).unwrap();
+
+    // Create an ask order: Sell 5 SUI at $5/SUI
+    let maker_order = Order::new_limit(2, 5 * USDC_DECIMALS, 5 * SUI_DECIMALS, "bob", u64::MAX, false).unwrap();
+
+    // Place the maker order
+    book.place_order(maker_order).unwrap();
+    
+    // Match the taker order
+    let fills = book.match_order(taker_order.clone(), 0).fills;
+    
+    assert_eq!(fills.len(), 1);
+    assert_eq!(fills[0].base_quantity, 5 * SUI_DECIMALS);
+    assert_eq!(fills[0].quote_quantity, 25 * USDC_DECIMALS as u128);
+    assert_eq!(fills[0].maker_order_id, 2);
+    assert_eq!(fills[0].taker_order_id, 1);
+    assert_eq!((fills[0].maker_remaining_after, fills[0].taker_remaining_after), (0, 5 * SUI_DECIMALS));
+    assert!(fills[0].maker_exhausted() && !fills[0].taker_exhausted());
+}
+
+#[test]
+fn test_full_fill_bid() {
+    let mut book = BookBuilder::default().build().unwrap();
+    
+    // Create a bid order: Buy 10 SUI at $5/SUI
+    let taker_order = Order::new_limit(1, 5 * USDC_DECIMALS, 10 * SUI_DECIMALS, "alice", u64::MAX, true).unwrap();
+
+    // Create an ask order: Sell 50 SUI at $5/SUI
+    let maker_order = Order::new_limit(2, 5 * USDC_DECIMALS, 50 * SUI_DECIMALS, "bob", u64::MAX, false).unwrap();
+
+    book.place_order(maker_order).unwrap();
+    let fills = book.match_order(taker_order.clone(), 0).fills;
+    
+    assert_eq!(fills.len(), 1);
+    assert_eq!(fills[0].base_quantity, 10 * SUI_DECIMALS);
+    assert_eq!(fills[0].quote_quantity, 50 * USDC_DECIMALS as u128);
+    assert_eq!((fills[0].maker_remaining_after, fills[0].taker_remaining_after), (40 * SUI_DECIMALS, 0));
+    assert!(!fills[0].maker_exhausted() && fills[0].taker_exhausted());
+}
+
+#[test]
+fn test_precision_matching() {
+    let mut book = BookBuilder::default().build().unwrap();
+    
+    // Create a bid order: Buy 10.86 SUI at $1.234/SUI
+    let taker_order = Order::new_limit(
+        1,
+        1_234_000, // $1.234
+        10_860_000_000, // 10.86 SUI
+        "alice",
+        u64::MAX,
+        true,
+    ).unwrap();
+
+    // Create an ask order: Sell 10.86 SUI at $1.234/SUI
+    let maker_order = Order::new_limit(2, 1_234_000, 10_860_000_000, "bob", u64::MAX, false).unwrap();
+
+    book.place_order(maker_order).unwrap();
+    let fills = book.match_order(taker_order.clone(), 0).fills;
+    
+    assert_eq!(fills.len(), 1);
+    assert_eq!(fills[0].base_quantity, 10_860_000_000);
+    assert_eq!(fills[0].quote_quantity, 13_401_240); // 10.86 * $1.234
+}
+
+#[test]
+fn test_order_quote_values() {
+    let mut book = BookBuilder::default().build().unwrap();
+
+    // 4 of 10.86 SUI at $1.234 fill, leaving 6.86 SUI worth $8.46524
+    book.place_order(Order::new_limit(1, 1_234_000, 10_860_000_000, "bob", u64::MAX, false).unwrap()).unwrap();
+    book.place_order(Order::new_limit(2, 1_234_000, 4 * SUI_DECIMALS, "alice", u64::MAX, true).unwrap()).unwrap();
+    let maker = book.order_status(1, false).unwrap();
+    assert_eq!(maker.remaining_quote(), 8_465_240 * FLOAT_SCALING as u128);
+    assert_eq!(maker.filled_quote(), 4_936_000 * FLOAT_SCALING as u128);
+    assert_eq!(maker.remaining_quote() + maker.filled_quote(), 13_401_240 * FLOAT_SCALING as u128);
+
+    // Full-precision values that overflow u64
+    let order = Order::new_limit(3, u64::MAX, u64::MAX, "alice", u64::MAX, true).unwrap();
+    assert_eq!(order.remaining_quote(), u64::MAX as u128 * u64::MAX as u128);
+    assert_eq!(order.filled_quote(), 0);
+}
+
+#[test]
+fn test_fill_quote_overflow() {
+    let mut book = BookBuilder::default().tick_size(1).lot_size(1).min_size(1).build().unwrap();
+    let half = u64::MAX / 2;
+    book.place_order(Order::new_limit(1, half, half, "bob", u64::MAX, false).unwrap()).unwrap();
+    book.place_order(Order::new_limit(2, half, half, "alice", u64::MAX, true).unwrap()).unwrap();
+
+    let fill = book.last_fill().unwrap();
+    let quote = half as u128 * half as u128 / FLOAT_SCALING as u128;
+    assert!(quote > u64::MAX as u128);
+    assert_eq!((fill.base_quantity, fill.quote_quantity), (half, quote));
+    assert_eq!(book.statistics().total_quote_volume, quote);
+    let position = book.position_for_owner("alice".into()).unwrap();
+    assert_eq!((position.quote_spent, position.net_quote()), (quote, i64::MIN));
+}
+
+#[test]
+fn test_multiple_fills() {
+    let mut book = BookBuilder::default().build().unwrap();
+    
+    // Taker: ask order with quantity 10 at price $1
+    let taker_order = Order::new_limit(
+        1,
+        USDC_DECIMALS, // $1
+        10 * SUI_DECIMALS,
+        "alice",
+        u64::MAX,
+        false,
+    ).unwrap();
+
+    // Maker1: bid order with quantity 1.001001 at price $1.001
+    let maker_order1 = Order::new_limit(2, 1_001_000, 1_001_001_000, "bob", u64::MAX, true).unwrap();
+
+    // Maker2: bid order with quantity 1 at price $1
+    let maker_order2 = Order::new_limit(3, USDC_DECIMALS, SUI_DECIMALS, "charlie", u64::MAX, true).unwrap();
+
+    book.place_order(maker_order1).unwrap();
+    book.place_order(maker_order2).unwrap();
+    let fills = book.match_order(taker_order.clone(), 0).fills;
+    
+    assert_eq!(fills.len(), 2);
+    // First fill should be at better price ($1.001)
+    assert_eq!(fills[0].base_quantity, 1_001_001_000);
+    assert_eq!(fills[0].quote_quantity, 1_002_002); // 1.001001 * $1.001, truncated to USDC decimals
+    // Second fill at $1
+    assert_eq!(fills[1].base_quantity, SUI_DECIMALS);
+    assert_eq!(fills[1].quote_quantity, USDC_DECIMALS as u128);
+    // Both makers are used up while the taker keeps 10 - 2.001001 SUI
+    assert_eq!(fills[0].taker_remaining_after, 10 * SUI_DECIMALS - 1_001_001_000);
+    assert_eq!(fills[1].taker_remaining_after, 10 * SUI_DECIMALS - 2_001_001_000);
+    assert!(fills.iter().all(|fill| fill.maker_exhausted() && !fill.taker_exhausted()));
+}
+
+#[test]
+fn test_fill_tape() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert!(book.last_fill().is_none() && book.last_n_fills(5).is_empty());
+
+    // The scenario from test_multiple_fills: an ask sweeps bids at $1.001 and $1
+    book.place_order(Order::new_limit(2, 1_001_000, 1_001_001_000, "bob", u64::MAX, true).unwrap()).unwrap();
+    book.place_order(Order::new_limit(3, USDC_DECIMALS, SUI_DECIMALS, "charlie", u64::MAX, true).unwrap()).unwrap();
+    let taker = Order::new_limit(1, USDC_DECIMALS, 10 * SUI_DECIMALS, "alice", u64::MAX, false).unwrap();
+    let fills = book.match_order(taker, 0).fills;
+
+    let tape: Vec<(u128, u64, u64)> =
+        book.last_n_fills(5).iter().map(|fill| (fill.maker_order_id, fill.price, fill.base_quantity)).collect();
+    assert_eq!(tape, vec![(2, 1_001_000, 1_001_001_000), (3, USDC_DECIMALS, SUI_DECIMALS)]);
+    assert_eq!(format!("{:?}", book.last_n_fills(2)), format!("{:?}", fills));
+    assert_eq!(book.last_n_fills(1)[0].maker_order_id, 3);
+    assert_eq!(book.last_fill().map(|fill| fill.maker_order_id), Some(3));
+    assert_eq!(book.fill_at_index(0).map(|fill| fill.maker_order_id), Some(2));
+    assert!(book.fill_at_index(2).is_none());
+
+    // Only the last three fills are retained, still oldest first
+    let mut book = Book::new_with_params(MarketParams { max_fill_history: 3, ..Default::default() });
+    for order_id in (1..=20).step_by(2) {
+        trade_at(&mut book, order_id, "bob", "carol", USDC_DECIMALS, SUI_DECIMALS);
+    }
+    let makers: Vec<u128> = book.last_n_fills(10).iter().map(|fill| fill.maker_order_id).collect();
+    assert_eq!(makers, vec![15, 17, 19]);
+    assert_eq!(book.fill_at_index(0).unwrap().maker_order_id, 15);
+}
+
+#[test]
+fn test_calculate_impact_price() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!(book.calculate_impact_price(Side::Ask, 10 * SUI_DECIMALS), None);
+
+    // The bids from test_multiple_fills
+    let maker_order1 = Order { owner: "bob".into(), ..alice_bid(2, 1_001_000, 1_001_001_000) };
+    let maker_order2 = Order { owner: "charlie".into(), ..alice_bid(3, USDC_DECIMALS, SUI_DECIMALS) };
+    book.place_order(maker_order1).unwrap();
+    book.place_order(maker_order2).unwrap();
+
+    let impact = book.calculate_impact_price(Side::Ask, 10 * SUI_DECIMALS).unwrap();
+    assert_eq!(impact.worst_price, USDC_DECIMALS);
+    assert_eq!(impact.filled_quantity, 2_001_001_000);
+    assert_eq!(impact.unfilled_quantity, 10 * SUI_DECIMALS - 2_001_001_000);
+    // (1.001001 * 1.001 + 1 * 1) / 2.001001, truncated
+    assert_eq!(impact.avg_price, 1_000_500);
+
+    // A sweep within the first level stays at its price, and the book is untouched
+    let impact = book.calculate_impact_price(Side::Ask, SUI_DECIMALS).unwrap();
+    assert_eq!((impact.avg_price, impact.worst_price, impact.unfilled_quantity), (1_001_000, 1_001_000, 0));
+    assert_eq!(book.total_resting_quantity(true), 2_001_001_000);
+    assert_eq!(book.calculate_impact_price(Side::Bid, SUI_DECIMALS), None);
+}
+
+#[test]
+fn test_simulate_match_matches_live_matching() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(2, 1_010_000, SUI_DECIMALS) }).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(3, 1_010_000, 2 * SUI_DECIMALS) }).unwrap();
+    book.place_order(Order { owner: "carol".into(), ..snapshot_ask(4, 1_020_000, 3 * SUI_DECIMALS) }).unwrap();
+
+    let taker = alice_bid(5, 1_020_000, 4 * SUI_DECIMALS);
+    let simulated = book.simulate_match(&taker, 0);
+    let summary = |fills: &[Fill]| {
+        fills.iter().map(|fill| (fill.maker_order_id, fill.price, fill.base_quantity)).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        summary(&simulated),
+        vec![(2, 1_010_000, SUI_DECIMALS), (3, 1_010_000, 2 * SUI_DECIMALS), (4, 1_020_000, SUI_DECIMALS)]
+    );
+    assert_eq!((book.best_bid(), book.best_ask()), (Some(990_000), Some(1_010_000)));
+    assert_eq!(book.total_resting_quantity(false), 6 * SUI_DECIMALS);
+
+    let matched = book.match_order(taker, 0).fills;
+    assert_eq!(format!("{:?}", simulated), format!("{:?}", matched));
+    assert_eq!(book.best_ask(), Some(1_020_000));
+
+    // A filled iceberg tranche requeues behind later makers in the simulation too
+    let mut book = BookBuilder::default().build().unwrap();
+    let iceberg = Order { owner: "bob".into(), ..snapshot_ask(1, USDC_DECIMALS, 3 * SUI_DECIMALS) };
+    book.place_order(iceberg.with_iceberg_peak(SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "carol".into(), ..snapshot_ask(2, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+    let taker = alice_bid(3, USDC_DECIMALS, 3 * SUI_DECIMALS);
+    let simulated = book.simulate_match(&taker, 0);
+    assert_eq!(
+        summary(&simulated),
+        vec![(1, USDC_DECIMALS, SUI_DECIMALS), (2, USDC_DECIMALS, SUI_DECIMALS), (1, USDC_DECIMALS, SUI_DECIMALS)]
+    );
+    assert_eq!(format!("{:?}", simulated), format!("{:?}", book.match_order(taker, 0).fills));
+
+    // Self-trade prevention skips a cancelled maker and stops at a cancelled taker
+    let self_trade_book = || {
+        let mut book = BookBuilder::default().build().unwrap();
+        book.place_order(snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+        book.place_order(Order { owner: "bob".into(), ..snapshot_ask(2, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+        book
+    };
+    for (stp_mode, expected) in [
+        (STPMode::CancelMaker, vec![(2, USDC_DECIMALS, SUI_DECIMALS)]),
+        (STPMode::CancelTaker, vec![]),
+    ] {
+        let mut book = self_trade_book();
+        let taker = Order { stp_mode, ..alice_bid(3, USDC_DECIMALS, SUI_DECIMALS) };
+        let simulated = book.simulate_match(&taker, 0);
+        assert_eq!(summary(&simulated), expected);
+        assert_eq!(book.order_count(), (0, 2));
+        assert_eq!(format!("{:?}", simulated), format!("{:?}", book.match_order(taker, 0).fills));
+    }
+}
+
+#[test]
+fn test_invalid_price() {
+    let mut book = BookBuilder::default().build().unwrap();
+    
+    // Built field by field, since the constructor rejects it too
+    let order = Order {
+        order_id: 1,
+        price: 0, // Invalid price
+        quantity: SUI_DECIMALS,
+        filled_quantity: 0,
+        owner: "alice".into(),
+        expire_timestamp: u64::MAX,
+        side: Side::Bid,
+        submission_timestamp: 0,
+        cancellation_timestamp: None,
+        venue_priority: 0,
+        order_type: OrderType::Limit,
+        stp_mode: STPMode::None,
+        max_fills: None,
+        iceberg_peak: None,
+    };
+
+    let result = book.place_order(order);
+    assert!(matches!(result, Err(PlaceError::PriceBelowMinimum)));
+    assert!(matches!(Order::new_limit(1, 0, SUI_DECIMALS, "alice", u64::MAX, true), Err(OrderError::ZeroPrice)));
+}
+
+#[test]
+fn test_invalid_quantity() {
+    let mut book = BookBuilder::default().build().unwrap();
+    
+    // Built field by field, since the constructor rejects it too
+    let order = Order {
         order_id: 1,
-        price: 5 * USDC_DECIMALS,
-        quantity: 10 * SUI_DECIMALS,
+        price: USDC_DECIMALS,
+        quantity: 0, // Invalid quantity
         filled_quantity: 0,
-        owner: "alice".to_string(),
+        owner: "alice".into(),
         expire_timestamp: u64::MAX,
-        is_bid: true,
+        side: Side::Bid,
+        submission_timestamp: 0,
+        cancellation_timestamp: None,
+        venue_priority: 0,
+        order_type: OrderType::Limit,
+        stp_mode: STPMode::None,
+        max_fills: None,
+        iceberg_peak: None,
+    };
+
+    let result = book.place_order(order);
+    assert!(matches!(result, Err(PlaceError::QuantityBelowMinimum)));
+    let result = Order::new_limit(1, USDC_DECIMALS, 0, "alice", u64::MAX, true);
+    assert!(matches!(result, Err(OrderError::QuantityBelowMinimum)));
+}
+
+#[test]
+fn test_order_constructors() {
+    assert!(matches!(Order::new_limit(1, USDC_DECIMALS, SUI_DECIMALS, "alice", 0, true), Err(OrderError::ZeroExpiry)));
+
+    let order = Order::new_limit(1, USDC_DECIMALS, SUI_DECIMALS, "alice", 500, false)
+        .unwrap()
+        .with_order_type(OrderType::ImmediateOrCancel)
+        .with_stp_mode(STPMode::CancelTaker)
+        .with_max_fills(3);
+    assert_eq!((order.order_id(), order.price(), order.quantity()), (1, USDC_DECIMALS, SUI_DECIMALS));
+    assert_eq!((order.owner(), order.expire_timestamp(), order.is_bid()), ("alice".into(), 500, false));
+    assert_eq!(order.filled_quantity(), 0);
+    assert_eq!(order.order_type(), OrderType::ImmediateOrCancel);
+    assert_eq!((order.stp_mode(), order.max_fills()), (STPMode::CancelTaker, Some(3)));
+    assert_eq!((order.venue_priority(), order.cancellation_timestamp()), (0, None));
+}
+
+#[test]
+fn test_tick_and_lot_alignment() {
+    let mut book = Book::new_with_params(MarketParams {
+        tick_size: 1_000,
+        lot_size: 1_000_000,
+        max_price: 100 * USDC_DECIMALS,
+        ..MarketParams::default()
+    });
+
+    let result = book.place_order(alice_bid(1, 990_500, SUI_DECIMALS));
+    assert!(matches!(result, Err(PlaceError::PriceNotTickAligned)));
+    let result = book.place_order(alice_bid(2, 990_000, SUI_DECIMALS + 500_000));
+    assert!(matches!(result, Err(PlaceError::QuantityNotLotAligned)));
+    let result = book.place_order(alice_bid(3, 200 * USDC_DECIMALS, SUI_DECIMALS));
+    assert!(matches!(result, Err(PlaceError::PriceAboveMaximum)));
+    assert_eq!(book.order_count(), (0, 0));
+
+    book.place_order(alice_bid(4, 990_000, SUI_DECIMALS)).unwrap();
+    let result = book.place_order(alice_bid(4, 980_000, SUI_DECIMALS));
+    assert!(matches!(result, Err(PlaceError::OrderAlreadyExists)));
+    assert_eq!(book.order_status(4, true).unwrap().price, 990_000);
+}
+
+#[test]
+#[should_panic(expected = "ZeroQtyIncrement")]
+fn test_zero_qty_increment_rejected() {
+    Book::new_with_params(MarketParams { min_qty_increment: 0, ..MarketParams::default() });
+}
+
+#[test]
+fn test_min_qty_increment() {
+    let params = MarketParams { min_qty_increment: SUI_DECIMALS, ..MarketParams::default() };
+    let mut book = Book::new_with_params(params.clone());
+
+    book.place_order(snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(2, USDC_DECIMALS, 3 * SUI_DECIMALS)).unwrap();
+    let result = book.place_order(alice_bid(3, USDC_DECIMALS, SUI_DECIMALS / 2));
+    assert!(matches!(result, Err(PlaceError::QuantityNotIncrementAligned)));
+    let result = book.place_order(alice_bid(4, USDC_DECIMALS, 3 * SUI_DECIMALS / 2));
+    assert!(matches!(result, Err(PlaceError::QuantityNotIncrementAligned)));
+    assert_eq!(book.order_count(), (0, 2));
+
+    // Introducing the increment cancels resting orders that are not a whole number of steps
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(snapshot_ask(5, USDC_DECIMALS, 3 * SUI_DECIMALS / 2)).unwrap();
+    book.place_order(snapshot_ask(6, 990_000, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(7, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..alice_bid(8, 990_000, SUI_DECIMALS / 2) }).unwrap();
+    let result = book.apply_market_reset(params, 0).unwrap();
+    assert_eq!((result.orders_cancelled_invalid, result.orders_revalidated), (2, 1));
+    assert_eq!(book.get_all_order_ids_by_side(false), vec![7]);
+
+    let zero_increment = MarketParams { min_qty_increment: 0, ..Default::default() };
+    assert_eq!(book.apply_market_reset(zero_increment, 0), Err(BuilderError::ZeroQtyIncrement));
+
+    // A maker showing less than one increment is passed over rather than stopping matching
+    let mut book = BookBuilder::default().min_qty_increment(SUI_DECIMALS).build().unwrap();
+    let snapshot = BookSnapshot {
+        asks: vec![snapshot_level(USDC_DECIMALS, vec![
+            snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS / 2),
+            Order { owner: "bob".into(), ..snapshot_ask(2, USDC_DECIMALS, SUI_DECIMALS) },
+        ])],
+        ..BookSnapshot::default()
+    };
+    book.warm_up_with_snapshot(snapshot, false).unwrap();
+    let taker = Order { owner: "carol".into(), ..alice_bid(3, USDC_DECIMALS, SUI_DECIMALS) };
+    assert_eq!(book.simulate_match(&taker, 0).len(), 1);
+    let result = book.match_order(taker, 0);
+    assert_eq!(result.fills.iter().map(|fill| fill.maker_order_id).collect::<Vec<_>>(), vec![2]);
+    assert_eq!(result.stop_reason, StopReason::TakerFullyFilled);
+    assert_eq!(book.order_status(1, false).unwrap().remaining_quantity(), SUI_DECIMALS / 2);
+
+    // An iceberg peak below the increment would leave such a tranche
+    let iceberg = alice_bid(4, USDC_DECIMALS, 2 * SUI_DECIMALS).with_iceberg_peak(SUI_DECIMALS / 2);
+    assert_eq!(book.place_order(iceberg), Err(PlaceError::InvalidIcebergPeak));
+}
+
+/// Places a one-unit ask for `owner` and lifts it with a taker bid at `timestamp`
+fn lift_ask(book: &mut Book, order_id: u128, owner: &str, price: u64, timestamp: u64) {
+    book.place_order(Order::new_limit(order_id, price, 1, owner, u64::MAX, false).unwrap()).unwrap();
+    let fills = book.match_order(Order::new_limit(order_id + 1_000, price, 1, "taker", u64::MAX, true).unwrap(), timestamp).fills;
+    assert_eq!(fills.len(), 1);
+}
+
+#[test]
+fn test_optimal_spread_for_owner() {
+    let mut book = BookBuilder::default().build().unwrap();
+    let anchor_bid = |order_id: u128, price: u64| Order::new_limit(order_id, price, SUI_DECIMALS, "lp", u64::MAX, true).unwrap();
+
+    // Four fills 100 bps from a 10_000 mid within the first 0.6s
+    book.place_order(anchor_bid(1, 9_900)).unwrap();
+    for (i, timestamp) in [0, 200_000_000, 400_000_000, 600_000_000].into_iter().enumerate() {
+        lift_ask(&mut book, 10 + i as u128, "mm", 10_100, timestamp);
+    }
+    assert!(book.calculate_optimal_spread_for_owner("mm".into(), 3.0).is_none()); // single level
+
+    // Two fills 200 bps from a 10_000 mid, closing a 1s observation window
+    book.cancel_order(1, true);
+    book.place_order(anchor_bid(2, 9_800)).unwrap();
+    lift_ask(&mut book, 20, "mm", 10_200, 800_000_000);
+    lift_ask(&mut book, 21, "mm", 10_200, 1_000_000_000);
+
+    // Rates of 4/s at 100 bps and 2/s at 200 bps give rate = 6 - 0.02 * bps
+    book.place_order(Order::new_limit(40, 10_200, 1, "mm", u64::MAX, false).unwrap()).unwrap();
+    assert_eq!(book.calculate_optimal_spread_for_owner("mm".into(), 3.0), Some((9_850, 10_150)));
+    assert_eq!(book.calculate_optimal_spread_for_owner("mm".into(), 5.0), Some((9_950, 10_050)));
+
+    // Unreachable target and unknown owners have no recommendation
+    assert!(book.calculate_optimal_spread_for_owner("mm".into(), 7.0).is_none());
+    assert!(book.calculate_optimal_spread_for_owner("nobody".into(), 3.0).is_none());
+}
+
+/// Lifts two asks at $5.00 and $5.20 with a taker bid limited at $5.20
+fn run_price_improvement_scenario(params: MarketParams) -> (Book, Vec<Fill>) {
+    let mut book = Book::new_with_params(params);
+    for (order_id, price) in [(1, 5_000_000), (2, 5_200_000)] {
+        book.place_order(Order::new_limit(order_id, price, 5 * SUI_DECIMALS, "bob", u64::MAX, false).unwrap()).unwrap();
+    }
+
+    let fills = book.match_order(Order::new_limit(3, 5_200_000, 10 * SUI_DECIMALS, "alice", u64::MAX, true).unwrap(), 0).fills;
+    (book, fills)
+}
+
+#[test]
+fn test_price_improvement_statistics() {
+    let (book, fills) = run_price_improvement_scenario(MarketParams {
+        price_improvement: true,
+        ..Default::default()
+    });
+
+    assert_eq!(fills.len(), 2);
+    assert!(fills[0].price_improved);
+    assert_eq!(fills[0].improvement_amount, USDC_DECIMALS); // $0.20 better on 5 SUI
+    assert!(!fills[1].price_improved);
+    assert_eq!(fills[1].improvement_amount, 0);
+    assert_eq!(book.get_price_improvement_statistics(), PriceImprovementStats {
+        total_fills_with_improvement: 1,
+        total_fills_without: 1,
+        avg_improvement_units: 200_000.0,
+        total_improvement_value: USDC_DECIMALS,
+    });
+
+    let (book, fills) = run_price_improvement_scenario(MarketParams::default());
+
+    assert_eq!(fills.len(), 2);
+    assert!(fills.iter().all(|fill| !fill.price_improved && fill.improvement_amount == 0));
+    assert_eq!(book.get_price_improvement_statistics(), PriceImprovementStats {
+        total_fills_with_improvement: 0,
+        total_fills_without: 0,
+        avg_improvement_units: 0.0,
+        total_improvement_value: 0,
+    });
+}
+
+#[test]
+fn test_infer_fair_value() {
+    let mut book = BookBuilder::default().build().unwrap();
+    let order = |order_id: u128, price: u64, quantity: u64, is_bid: bool| Order::new_limit(order_id, price, quantity, "alice", u64::MAX, is_bid).unwrap();
+
+    assert_eq!(book.infer_fair_value(FairValueMethod::MidPrice), None);
+    assert_eq!(book.infer_fair_value(FairValueMethod::LastTrade), None);
+    assert_eq!(book.infer_fair_value(FairValueMethod::VWAP(10)), None);
+
+    // Fills of 2 @ 100 then 1 @ 110, leaving 1 @ 110 on the ask side
+    book.place_order(order(1, 100, 2, false)).unwrap();
+    book.place_order(order(2, 110, 2, false)).unwrap();
+    book.match_order(order(3, 110, 3, true), 0);
+    book.place_order(order(4, 90, 3, true)).unwrap();
+    book.place_order(order(5, 90, 1, true)).unwrap();
+
+    assert_eq!(book.infer_fair_value(FairValueMethod::MidPrice), Some(100));
+    // (90 * 1 + 110 * 4) / 5
+    assert_eq!(book.infer_fair_value(FairValueMethod::WeightedMidPrice), Some(106));
+    // 310 / 3, rounded down
+    assert_eq!(book.infer_fair_value(FairValueMethod::VWAP(2)), Some(103));
+    assert_eq!(book.infer_fair_value(FairValueMethod::VWAP(1)), Some(110));
+    assert_eq!(book.infer_fair_value(FairValueMethod::LastTrade), Some(110));
+    assert_eq!(book.infer_fair_value(FairValueMethod::OracleAdjusted(5)), Some(108));
+    assert_eq!(book.infer_fair_value(FairValueMethod::OracleAdjusted(-3i64 as u64)), Some(100));
+    assert_eq!(book.infer_fair_value(FairValueMethod::OracleAdjusted(-200i64 as u64)), Some(1));
+}
+
+/// Builds a book with bids of 3 @ 9_900 and 2 @ 9_800 and asks of 4 @ 10_000 and 6 @ 10_100
+fn arbitrage_book() -> Book {
+    let mut book = BookBuilder::default().build().unwrap();
+    for (order_id, price, quantity, is_bid) in
+        [(1, 9_900, 3, true), (2, 9_800, 2, true), (3, 10_000, 4, false), (4, 10_100, 6, false)]
+    {
+        book.place_order(Order::new_limit(order_id, price, quantity, "mm", u64::MAX, is_bid).unwrap()).unwrap();
+    }
+    book
+}
+
+#[test]
+fn test_arbitrage_buy_internal_sell_external() {
+    let book = arbitrage_book();
+
+    // External bid at 10_050 is above only the 10_000 ask level
+    assert_eq!(book.cross_exchange_spread_arbitrage(10_050, 10_200, 100), Some(ArbitrageSignal {
+        direction: ArbDirection::BuyInternalSellExternal,
+        internal_price: 10_000,
+        external_price: 10_050,
+        max_quantity: 4,
+        expected_profit_bps: 50,
+    }));
+    // Both ask levels are below 10_200, but the external side only takes 7
+    let signal = book.cross_exchange_spread_arbitrage(10_200, 10_300, 7).unwrap();
+    assert_eq!(signal.max_quantity, 7);
+    assert_eq!(signal.expected_profit_bps, 200);
+}
+
+#[test]
+fn test_arbitrage_sell_internal_buy_external() {
+    let book = arbitrage_book();
+
+    assert_eq!(book.cross_exchange_spread_arbitrage(9_600, 9_850, 100), Some(ArbitrageSignal {
+        direction: ArbDirection::SellInternalBuyExternal,
+        internal_price: 9_900,
+        external_price: 9_850,
+        max_quantity: 3,
+        expected_profit_bps: 50,
+    }));
+    assert_eq!(book.cross_exchange_spread_arbitrage(9_600, 9_700, 100).unwrap().max_quantity, 5);
+}
+
+#[test]
+fn test_no_arbitrage_when_spreads_do_not_cross() {
+    let book = arbitrage_book();
+
+    assert_eq!(book.cross_exchange_spread_arbitrage(9_950, 10_050, 100), None);
+    assert_eq!(book.cross_exchange_spread_arbitrage(10_000, 9_900, 100), None);
+    assert_eq!(BookBuilder::default().build().unwrap().cross_exchange_spread_arbitrage(10_000, 10_100, 100), None);
+}
+
+#[test]
+fn test_arbitrage_extreme_external_quotes() {
+    // A zero external ask has no meaningful profit and must not divide by zero
+    assert_eq!(arbitrage_book().cross_exchange_spread_arbitrage(0, 0, 100), None);
+
+    // Profit is computed in u128, so prices near u64::MAX do not overflow
+    let mut book = BookBuilder::default().build().unwrap();
+    let price = u64::MAX / 4;
+    book.place_order(Order::new_limit(1, price, 1, "mm", u64::MAX, false).unwrap()).unwrap();
+    let signal = book.cross_exchange_spread_arbitrage(2 * price, u64::MAX, 100).unwrap();
+    assert_eq!((signal.direction, signal.expected_profit_bps), (ArbDirection::BuyInternalSellExternal, 10_000));
+}
+
+/// Builds a book running regulatory checks with the given limits on a mock clock at zero
+fn regulatory_book(regulatory_risk: RegulatoryRiskParams) -> (Book, MockClock) {
+    let clock = MockClock::new(0);
+    let params = MarketParams {
+        regulatory_mode: true,
+        regulatory_risk,
+        ..Default::default()
+    };
+    (Book::new_with_params(params).with_clock(clock.clone()), clock)
+}
+
+/// Creates a resting bid for alice
+fn alice_bid(order_id: u128, price: u64, quantity: u64) -> Order {
+    Order::new_limit(order_id, price, quantity, "alice", u64::MAX, true).unwrap()
+}
+
+#[test]
+fn test_regulatory_position_limit() {
+    let (mut book, _) = regulatory_book(RegulatoryRiskParams {
+        position_limit: 5 * SUI_DECIMALS,
+        ..Default::default()
+    });
+
+    let result = book.place_order(alice_bid(1, 5 * USDC_DECIMALS, 10 * SUI_DECIMALS));
+    assert_eq!(result.unwrap_err(), PlaceError::Book(BookError::RegulatoryViolation(vec![
+        RegulatoryViolation::PositionLimitExceeded { quantity: 10 * SUI_DECIMALS, limit: 5 * SUI_DECIMALS },
+    ])));
+    assert!(matches!(book.cancel_order(1, true), CancelResult::NotFound));
+    assert!(book.place_order(alice_bid(2, 5 * USDC_DECIMALS, 5 * SUI_DECIMALS)).is_ok());
+}
+
+#[test]
+fn test_regulatory_notional_threshold() {
+    let (mut book, _) = regulatory_book(RegulatoryRiskParams {
+        daily_notional_threshold: 40 * USDC_DECIMALS,
+        ..Default::default()
+    });
+
+    let result = book.place_order(alice_bid(1, 5 * USDC_DECIMALS, 10 * SUI_DECIMALS));
+    assert_eq!(result.unwrap_err(), PlaceError::Book(BookError::RegulatoryViolation(vec![
+        RegulatoryViolation::NotionalThresholdExceeded { notional: 50 * USDC_DECIMALS, threshold: 40 * USDC_DECIMALS },
+    ])));
+    assert!(book.place_order(alice_bid(2, 5 * USDC_DECIMALS, 8 * SUI_DECIMALS)).is_ok());
+}
+
+#[test]
+fn test_regulatory_reference_price_band() {
+    let (mut book, _) = regulatory_book(RegulatoryRiskParams {
+        reference_price: Some(5 * USDC_DECIMALS),
+        ..Default::default()
+    });
+
+    let result = book.place_order(alice_bid(1, 6_500_000, SUI_DECIMALS));
+    assert_eq!(result.unwrap_err(), PlaceError::Book(BookError::RegulatoryViolation(vec![
+        RegulatoryViolation::PriceOutsideReferenceBand {
+            price: 6_500_000,
+            reference_price: 5 * USDC_DECIMALS,
+            max_deviation_bps: 2_000,
+        },
+    ])));
+    assert!(book.place_order(alice_bid(2, 4 * USDC_DECIMALS, SUI_DECIMALS)).is_ok()); // exactly -20%
+}
+
+#[test]
+fn test_regulatory_duplicate_order() {
+    let (mut book, clock) = regulatory_book(RegulatoryRiskParams::default());
+
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    clock.advance(500_000);
+    let result = book.place_order(alice_bid(2, USDC_DECIMALS, SUI_DECIMALS));
+    assert_eq!(result.unwrap_err(), PlaceError::Book(BookError::RegulatoryViolation(vec![
+        RegulatoryViolation::DuplicateOrder { previous_timestamp: 0 },
+    ])));
+
+    // A different quantity is not a duplicate, and the window expires after 1ms
+    assert!(book.place_order(alice_bid(3, USDC_DECIMALS, 2 * SUI_DECIMALS)).is_ok());
+    clock.advance(1_600_000);
+    assert!(book.place_order(alice_bid(4, USDC_DECIMALS, SUI_DECIMALS)).is_ok());
+}
+
+#[test]
+fn test_regulatory_order_rate_limit() {
+    let (mut book, clock) = regulatory_book(RegulatoryRiskParams {
+        max_orders_per_second: 2,
+        ..Default::default()
+    });
+
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    clock.advance(2_000_000);
+    book.place_order(alice_bid(2, USDC_DECIMALS, 2 * SUI_DECIMALS)).unwrap();
+    clock.advance(2_000_000);
+    let result = book.place_order(alice_bid(3, USDC_DECIMALS, 3 * SUI_DECIMALS));
+    assert_eq!(result.unwrap_err(), PlaceError::Book(BookError::RegulatoryViolation(vec![
+        RegulatoryViolation::OrderRateExceeded { orders_in_window: 2, limit: 2 },
+    ])));
+
+    clock.advance(NANOS_PER_SECOND);
+    assert!(book.place_order(alice_bid(4, USDC_DECIMALS, 4 * SUI_DECIMALS)).is_ok());
+}
+
+#[test]
+fn test_regulatory_check_reports_all_violations() {
+    let book = BookBuilder::default().build().unwrap().with_clock(MockClock::new(0));
+    let risk_params = RegulatoryRiskParams {
+        position_limit: SUI_DECIMALS,
+        daily_notional_threshold: USDC_DECIMALS,
+        reference_price: Some(USDC_DECIMALS),
+        ..Default::default()
     };
 
-    // Create an ask order: Sell 5 SUI at $5/SUI
-    let maker_order = Order {
-        order_id: 2,
-        price: 5 * USDC_DECIMALS,
-        quantity: 5 * SUI_DECIMALS,
-        filled_quantity: 0,
-        owner: "bob".to_string(),
-        expire_timestamp: u64::MAX,
-        is_bid: false,
+    let violations = book.apply_regulatory_pre_trade_check(&alice_bid(1, 2 * USDC_DECIMALS, 2 * SUI_DECIMALS), &risk_params);
+    assert_eq!(violations.len(), 3);
+    assert!(matches!(violations[0], RegulatoryViolation::PositionLimitExceeded { .. }));
+    assert!(matches!(violations[1], RegulatoryViolation::NotionalThresholdExceeded { .. }));
+    assert!(matches!(violations[2], RegulatoryViolation::PriceOutsideReferenceBand { .. }));
+
+    // A zero reference price leaves no band rather than dividing by zero
+    let risk_params = RegulatoryRiskParams { reference_price: Some(0), ..Default::default() };
+    let violations = book.apply_regulatory_pre_trade_check(&alice_bid(1, USDC_DECIMALS, SUI_DECIMALS), &risk_params);
+    assert!(matches!(violations[..], [RegulatoryViolation::PriceOutsideReferenceBand { reference_price: 0, .. }]));
+
+    // Checks only reject orders when the book runs in regulatory mode
+    let mut book = BookBuilder::default().build().unwrap();
+    assert!(book.place_order(alice_bid(1, 2 * USDC_DECIMALS, 2 * SUI_DECIMALS)).is_ok());
+}
+
+/// Builds a two-sided book around $1 whose fill history swings between $0.90 and $1.10
+fn volatile_book() -> Book {
+    let mut book = BookBuilder::default().build().unwrap();
+    for (i, price) in [1_000_000, 1_100_000, 900_000, 1_050_000, 950_000].into_iter().enumerate() {
+        let order_id = 100 + 2 * i as u128;
+        book.place_order(Order::new_limit(order_id, price, SUI_DECIMALS, "bob", u64::MAX, false).unwrap()).unwrap();
+        book.match_order(alice_bid(order_id + 1, price, SUI_DECIMALS), 0);
+    }
+    for level in 1..=5 {
+        book.place_order(alice_bid(level, USDC_DECIMALS - level as u64 * 10_000, 10 * SUI_DECIMALS)).unwrap();
+        book.place_order(Order::new_limit(
+            10 + level,
+            USDC_DECIMALS + level as u64 * 10_000,
+            10 * SUI_DECIMALS,
+            "bob",
+            u64::MAX,
+            false,
+        ).unwrap()).unwrap();
+    }
+    book
+}
+
+#[test]
+fn test_tail_risk_cvar_at_least_var() {
+    let book = volatile_book();
+    assert!(book.historical_volatility_proxy() > 0.0);
+
+    for confidence_level in [0.5, 0.9, 0.95, 0.99, 1.0] {
+        let exposure = book.compute_tail_risk_exposure(confidence_level, 1_000);
+        assert!(exposure.cvar >= exposure.var);
+        assert!(exposure.max_loss >= exposure.cvar);
+    }
+    let exposure = book.compute_tail_risk_exposure(0.99, 1_000);
+    assert!(exposure.var > 0);
+    assert_eq!(exposure, book.compute_tail_risk_exposure(0.99, 1_000)); // seeded, so deterministic
+}
+
+#[test]
+fn test_tail_risk_zero_volatility() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, 990_000, 10 * SUI_DECIMALS)).unwrap();
+    book.place_order(Order::new_limit(2, 1_010_000, 10 * SUI_DECIMALS, "bob", u64::MAX, false).unwrap()).unwrap();
+
+    assert_eq!(book.historical_volatility_proxy(), 0.0);
+    assert_eq!(book.compute_tail_risk_exposure(0.99, 1_000), TailRiskExposure { var: 0, cvar: 0, max_loss: 0 });
+    assert_eq!(BookBuilder::default().build().unwrap().compute_tail_risk_exposure(0.99, 1_000), TailRiskExposure::default());
+}
+
+/// Fills a book with `count` non-crossing orders split between bids and asks
+#[cfg(feature = "tokio")]
+fn book_with_resting_orders(count: u64) -> Book {
+    let mut book = BookBuilder::default().build().unwrap();
+    for i in 0..count {
+        let is_bid = i % 2 == 0;
+        book.place_order(Order::new_limit(
+            i as u128,
+            if is_bid { 900_000 } else { 1_100_000 },
+            SUI_DECIMALS,
+            format!("trader_{}", i).as_str(),
+            u64::MAX,
+            is_bid,
+        ).unwrap()).unwrap();
+    }
+    book
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_cancel_all_completes() {
+    let mut book = book_with_resting_orders(2_500);
+
+    let CancelAllResult { cancelled } = book.async_cancel_all_with_timeout(Duration::from_secs(10)).await.unwrap();
+    assert_eq!(cancelled.len(), 2_500);
+    assert!(matches!(book.cancel_order(0, true), CancelResult::NotFound));
+    assert!(matches!(book.cancel_order(1, false), CancelResult::NotFound));
+    assert!(cancelled.iter().all(|order| order.cancellation_timestamp.is_some()));
+    assert_ne!(book.compute_order_lifetime_distribution(), LifetimeDistribution::default());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_cancel_all_records_cancellations() {
+    let mut book = BookBuilder::default().build().unwrap().with_event_recording();
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(2, 1_010_000, SUI_DECIMALS)).unwrap();
+
+    book.async_cancel_all_with_timeout(Duration::from_secs(10)).await.unwrap();
+    let events = book.events();
+    assert_eq!(events.iter().filter(|event| matches!(event, BookEvent::Cancelled { .. })).count(), 2);
+    assert!(matches!(
+        events.last(),
+        Some(BookEvent::TopOfBookChanged(TopOfBookEvent { new_best_bid: None, new_best_ask: None, .. }))
+    ));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_cancel_all_incomplete_on_deadline() {
+    let mut book = book_with_resting_orders(2_500);
+
+    // The first chunk is cancelled before the zero deadline is checked
+    let result = book.async_cancel_all_with_timeout(Duration::ZERO).await;
+    assert_eq!(result.unwrap_err(), ShutdownError::Incomplete { cancelled: 1_000, remaining: 1_500 });
+
+    let CancelAllResult { cancelled } = book.async_cancel_all_with_timeout(Duration::from_secs(10)).await.unwrap();
+    assert_eq!(cancelled.len(), 1_500);
+}
+
+#[test]
+fn test_order_lifetime_distribution() {
+    const MILLIS: u64 = 1_000_000;
+    let clock = MockClock::new(0);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone());
+    assert_eq!(book.compute_order_lifetime_distribution(), LifetimeDistribution::default());
+
+    // Ten bids placed at t=0 and cancelled after 1ms, 2ms, ..., 10ms
+    for order_id in 1..=10 {
+        book.place_order(alice_bid(order_id, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    }
+    for order_id in 1..=10 {
+        clock.set(order_id as u64 * MILLIS);
+        let cancelled = book.cancel_order(order_id, true).into_order().unwrap();
+        assert_eq!(cancelled.submission_timestamp, 0);
+        assert_eq!(cancelled.cancellation_timestamp, Some(order_id as u64 * MILLIS));
+    }
+
+    assert_eq!(book.compute_order_lifetime_distribution(), LifetimeDistribution {
+        median_ns: 5 * MILLIS,
+        p90_ns: 9 * MILLIS,
+        p99_ns: 10 * MILLIS,
+        bucket_counts: vec![(MILLIS, 9), (10 * MILLIS, 1)],
+    });
+}
+
+#[test]
+fn test_order_lifetime_includes_full_fills() {
+    let clock = MockClock::new(1_000);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone());
+
+    book.place_order(Order::new_limit(1, USDC_DECIMALS, SUI_DECIMALS, "bob", u64::MAX, false).unwrap()).unwrap();
+    clock.advance(250);
+    book.place_order(alice_bid(2, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+
+    let distribution = book.compute_order_lifetime_distribution();
+    assert_eq!(distribution.median_ns, 250);
+    assert_eq!(distribution.bucket_counts, vec![(100, 1)]);
+}
+
+#[test]
+fn test_adaptive_spread_recommendation() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!(book.get_adaptive_spread_recommendation(10.0, 0.0), SpreadRecommendation {
+        bid_offset: 0,
+        ask_offset: 0,
+        confidence: 0.0,
+    });
+
+    // Mid of $1; at 10 bps volatility the half spread is 10 + ln(1 + 0.1 / 1.5) = 10.0645 bps
+    book.place_order(alice_bid(1, 999_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order::new_limit(2, 1_001_000, SUI_DECIMALS, "bob", u64::MAX, false).unwrap()).unwrap();
+
+    let neutral = book.get_adaptive_spread_recommendation(10.0, 0.0);
+    assert_eq!((neutral.bid_offset, neutral.ask_offset), (-1_006, 1_006));
+    assert_eq!(neutral.confidence, 0.0);
+
+    // A full long inventory shifts both quotes down by 10 bps to attract buyers
+    let long = book.get_adaptive_spread_recommendation(10.0, 1.0);
+    assert_eq!((long.bid_offset, long.ask_offset), (-2_006, 6));
+    let short = book.get_adaptive_spread_recommendation(10.0, -1.0);
+    assert_eq!((short.bid_offset, short.ask_offset), (-6, 2_006));
+
+    // Skew beyond the inventory limit is clamped
+    assert_eq!(book.get_adaptive_spread_recommendation(10.0, 5.0), long);
+}
+
+#[test]
+fn test_export_to_influxdb_line_protocol() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!(
+        book.export_to_influxdb_line_protocol("orderbook", 7),
+        "orderbook bid_levels=0i,ask_levels=0i,total_fills=0i,session_volume=0i 7"
+    );
+
+    book.place_order(alice_bid(1, 5_000_000, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, 5_000_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(3, 4_999_000, SUI_DECIMALS)).unwrap();
+    for (order_id, price) in [(4, 5_001_000), (5, 5_002_000)] {
+        book.place_order(Order::new_limit(order_id, price, 2 * SUI_DECIMALS, "bob", u64::MAX, false).unwrap()).unwrap();
+    }
+    book.match_order(alice_bid(6, 5_001_000, SUI_DECIMALS), 0);
+
+    let line = book.export_to_influxdb_line_protocol("orderbook,pair=SUI-USDC", 1_705_312_000_000_000_000);
+    let mut parts = line.split(' ');
+    assert_eq!(parts.next(), Some("orderbook,pair=SUI-USDC"));
+    let fields: Vec<(&str, &str)> = parts.next().unwrap().split(',').map(|field| field.split_once('=').unwrap()).collect();
+    assert_eq!(parts.next(), Some("1705312000000000000"));
+    assert_eq!(parts.next(), None);
+
+    assert_eq!(fields, vec![
+        ("best_bid", "5000000i"),
+        ("best_ask", "5001000i"),
+        ("mid_price", "5000500i"),
+        ("spread", "1000i"),
+        ("bid_qty", "3000000000i"),
+        ("ask_qty", "1000000000i"),
+        ("bid_levels", "2i"),
+        ("ask_levels", "2i"),
+        ("total_fills", "1i"),
+        ("session_volume", "1000000000i"),
+    ]);
+    for (_, value) in fields {
+        assert!(value.trim_end_matches('i').parse::<i64>().is_ok());
+    }
+}
+
+/// Places a bid for `owner` with the given size, returning its order ID
+fn place_owner_bid(book: &mut Book, order_id: u128, owner: &str, price: u64, quantity: u64) -> u128 {
+    book.place_order(Order::new_limit(order_id, price, quantity, owner, u64::MAX, true).unwrap()).unwrap();
+    order_id
+}
+
+#[test]
+fn test_detect_layering() {
+    const MILLIS: u64 = 1_000_000;
+    let clock = MockClock::new(0);
+    let params = MarketParams {
+        large_order_quantity: 10 * SUI_DECIMALS,
+        ..Default::default()
+    };
+    let mut book = Book::new_with_params(params).with_clock(clock.clone());
+
+    // An old layering episode that falls outside the window
+    let stale = place_owner_bid(&mut book, 1, "spoofer", 1_000_000, 50 * SUI_DECIMALS);
+    book.cancel_order(stale, true);
+    clock.set(100 * MILLIS);
+
+    // Spoofer stacks five large bids at the top and pulls four of them
+    let spoofed: Vec<u128> = (0..5)
+        .map(|i| place_owner_bid(&mut book, 10 + i, "spoofer", 1_000_000 - i as u64 * 1_000, 50 * SUI_DECIMALS))
+        .collect();
+    clock.advance(MILLIS);
+    for order_id in &spoofed[..4] {
+        book.cancel_order(*order_id, true);
+    }
+
+    // An honest maker cancels one of four large bids; small orders are ignored
+    let honest: Vec<u128> = (0..4)
+        .map(|i| place_owner_bid(&mut book, 20 + i, "honest", 999_000 - i as u64 * 1_000, 20 * SUI_DECIMALS))
+        .collect();
+    book.cancel_order(honest[0], true);
+    for i in 0..3 {
+        let order_id = place_owner_bid(&mut book, 30 + i, "small", 1_001_000, SUI_DECIMALS);
+        book.cancel_order(order_id, true);
+    }
+
+    assert_eq!(book.detect_order_book_manipulation(0.5, 10 * MILLIS), vec![ManipulationAlert {
+        owner: "spoofer".into(),
+        cancel_rate: 0.8,
+        large_order_count: 5,
+        alert_type: AlertType::Layering,
+    }]);
+    assert_eq!(book.detect_order_book_manipulation(0.2, 10 * MILLIS).len(), 2);
+    assert!(book.detect_order_book_manipulation(0.9, 10 * MILLIS).is_empty());
+}
+
+#[test]
+fn test_layering_ignores_orders_deep_in_book() {
+    let params = MarketParams {
+        large_order_quantity: 10 * SUI_DECIMALS,
+        ..Default::default()
+    };
+    let mut book = Book::new_with_params(params).with_clock(MockClock::new(0));
+
+    for i in 0..5 {
+        place_owner_bid(&mut book, i, "lp", 1_000_000 - i as u64 * 1_000, SUI_DECIMALS);
+    }
+    let deep = place_owner_bid(&mut book, 10, "spoofer", 900_000, 50 * SUI_DECIMALS);
+    book.cancel_order(deep, true);
+    let top = place_owner_bid(&mut book, 11, "spoofer", 996_000, 50 * SUI_DECIMALS);
+    book.cancel_order(top, true);
+
+    let alerts = book.detect_order_book_manipulation(0.0, NANOS_PER_SECOND);
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].large_order_count, 1);
+}
+
+#[test]
+fn test_expected_shortfall_on_unwind() {
+    let mut book = BookBuilder::default().build().unwrap();
+    let model = MarketImpactModel::Linear { bps_per_unit: 1e-8 };
+    assert_eq!(book.compute_expected_shortfall_on_unwind("mm".into(), &model), 0);
+
+    // 100 SUI with 1_000 bps impact, and 50 SUI with 500 bps impact
+    place_owner_bid(&mut book, 1, "mm", 2 * USDC_DECIMALS, 100 * SUI_DECIMALS);
+    book.place_order(Order::new_limit(
+        2,
+        4 * USDC_DECIMALS,
+        50 * SUI_DECIMALS,
+        "mm",
+        u64::MAX,
+        false,
+    ).unwrap()).unwrap();
+    place_owner_bid(&mut book, 3, "other", USDC_DECIMALS, 100 * SUI_DECIMALS);
+
+    assert!((model.predict(100 * SUI_DECIMALS) - 1_000.0).abs() < 1e-6);
+    assert!((model.predict(50 * SUI_DECIMALS) - 500.0).abs() < 1e-6);
+    // 100 * 1_000 / 10_000 + 50 * 500 / 10_000 = 12.5 SUI
+    assert_eq!(book.compute_expected_shortfall_on_unwind("mm".into(), &model), 12 * SUI_DECIMALS + SUI_DECIMALS / 2);
+    // 100 * 1_000 / 10_000 = 10 SUI
+    assert_eq!(book.compute_expected_shortfall_on_unwind("other".into(), &model), 10 * SUI_DECIMALS);
+}
+
+/// Hook that counts its calls and rejects orders above `max_quantity`
+fn quantity_limit_hook(calls: &Arc<AtomicUsize>, max_quantity: u64) -> PreTradeHook {
+    let calls = Arc::clone(calls);
+    Box::new(move |order: &Order| {
+        calls.fetch_add(1, Ordering::SeqCst);
+        if order.quantity > max_quantity {
+            return Err(BookError::Rejected(format!("quantity {} above credit limit", order.quantity)));
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_pre_trade_hook_passes() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut book = BookBuilder::default().build().unwrap().with_pre_trade_hook(quantity_limit_hook(&calls, 10 * SUI_DECIMALS));
+
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(matches!(book.cancel_order(1, true), CancelResult::Cancelled(_)));
+}
+
+#[test]
+fn test_pre_trade_hook_rejects_without_side_effects() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut book = BookBuilder::default().build().unwrap().with_pre_trade_hook(quantity_limit_hook(&calls, 10 * SUI_DECIMALS));
+    book.place_order(Order { side: Side::Ask, ..alice_bid(1, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+
+    // A crossing bid above the limit is rejected before it can match
+    let result = book.place_order(alice_bid(2, USDC_DECIMALS, 20 * SUI_DECIMALS));
+    assert_eq!(
+        result.unwrap_err(),
+        PlaceError::Book(BookError::Rejected(format!("quantity {} above credit limit", 20 * SUI_DECIMALS)))
+    );
+    assert!(matches!(book.cancel_order(2, true), CancelResult::NotFound));
+    let resting_ask = book.cancel_order(1, false).into_order().unwrap();
+    assert_eq!(resting_ask.filled_quantity, 0);
+    let stats = book.get_price_improvement_statistics();
+    assert_eq!(stats.total_fills_with_improvement + stats.total_fills_without, 0);
+}
+
+#[test]
+fn test_pre_trade_hook_chain_short_circuits() {
+    let first_calls = Arc::new(AtomicUsize::new(0));
+    let second_calls = Arc::new(AtomicUsize::new(0));
+    let third_calls = Arc::new(AtomicUsize::new(0));
+    let mut book = BookBuilder::default().build().unwrap().with_pre_trade_hook(quantity_limit_hook(&first_calls, 10 * SUI_DECIMALS));
+    book.add_pre_trade_hook(quantity_limit_hook(&second_calls, 5 * SUI_DECIMALS));
+    book.add_pre_trade_hook(quantity_limit_hook(&third_calls, 0));
+
+    let result = book.place_order(alice_bid(1, USDC_DECIMALS, 8 * SUI_DECIMALS));
+    assert_eq!(
+        result.unwrap_err(),
+        PlaceError::Book(BookError::Rejected(format!("quantity {} above credit limit", 8 * SUI_DECIMALS)))
+    );
+    assert_eq!(first_calls.load(Ordering::SeqCst), 1);
+    assert_eq!(second_calls.load(Ordering::SeqCst), 1);
+    assert_eq!(third_calls.load(Ordering::SeqCst), 0);
+    assert!(matches!(book.cancel_order(1, true), CancelResult::NotFound));
+}
+
+/// Builds a snapshot level whose totals match the given orders
+fn snapshot_level(price: u64, orders: Vec<Order>) -> PriceLevel {
+    PriceLevel {
+        price,
+        total_quantity: orders.iter().map(Order::remaining_quantity).sum(),
+        order_count: orders.len(),
+        orders,
+    }
+}
+
+fn snapshot_ask(order_id: u128, price: u64, quantity: u64) -> Order {
+    Order { side: Side::Ask, ..alice_bid(order_id, price, quantity) }
+}
+
+/// Two bid levels and one ask level around a $1 spread
+fn valid_snapshot() -> BookSnapshot {
+    BookSnapshot {
+        bids: vec![
+            snapshot_level(2 * USDC_DECIMALS, vec![
+                alice_bid(1, 2 * USDC_DECIMALS, SUI_DECIMALS),
+                alice_bid(2, 2 * USDC_DECIMALS, 2 * SUI_DECIMALS),
+            ]),
+            snapshot_level(USDC_DECIMALS, vec![alice_bid(3, USDC_DECIMALS, SUI_DECIMALS)]),
+        ],
+        asks: vec![snapshot_level(3 * USDC_DECIMALS, vec![snapshot_ask(4, 3 * USDC_DECIMALS, SUI_DECIMALS)])],
+        timestamp: 0,
+    }
+}
+
+#[test]
+fn test_warm_up_with_valid_snapshot() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.warm_up_with_snapshot(valid_snapshot(), true).unwrap();
+    assert_eq!(book.infer_fair_value(FairValueMethod::MidPrice), Some(2_500_000));
+
+    // Queue order within a level is preserved: order 1 fills before order 2
+    let fills = book.place_order_sync(snapshot_ask(5, 2 * USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert_eq!(fills.len(), 1);
+    assert_eq!(fills[0].maker_order_id, 1);
+    assert!(matches!(book.cancel_order(2, true), CancelResult::Cancelled(_)));
+    assert!(matches!(book.cancel_order(3, true), CancelResult::Cancelled(_)));
+}
+
+#[test]
+fn test_warm_up_rejects_crossed_book() {
+    let mut snapshot = valid_snapshot();
+    snapshot.asks = vec![snapshot_level(USDC_DECIMALS, vec![snapshot_ask(4, USDC_DECIMALS, SUI_DECIMALS)])];
+
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!(book.warm_up_with_snapshot(snapshot.clone(), true), Err(WarmUpError::CrossedBook));
+    assert_eq!(book.infer_fair_value(FairValueMethod::MidPrice), None);
+
+    // Skipping validation loads the snapshot as-is
+    book.warm_up_with_snapshot(snapshot, false).unwrap();
+    assert_eq!(book.infer_fair_value(FairValueMethod::MidPrice), Some(1_500_000));
+}
+
+#[test]
+fn test_warm_up_rejects_duplicate_order_id() {
+    let mut snapshot = valid_snapshot();
+    snapshot.bids[1].orders[0] = Order { expire_timestamp: 5, ..alice_bid(100, USDC_DECIMALS, SUI_DECIMALS) };
+    snapshot.asks = vec![snapshot_level(3 * USDC_DECIMALS, vec![snapshot_ask(2, 3 * USDC_DECIMALS, SUI_DECIMALS)])];
+
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(9, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.warm_up_with_snapshot(snapshot, false), Err(WarmUpError::DuplicateOrderId(2)));
+
+    // The book still holds its previous orders, and IDs seen in the rejected
+    // snapshot are not reserved
+    assert_eq!(book.price_ladder("mm".into(), 2 * USDC_DECIMALS, 100, 1, SUI_DECIMALS, u64::MAX).unwrap(), vec![10, 11]);
+    assert!(matches!(book.cancel_order(9, true), CancelResult::Cancelled(_)));
+    assert!(matches!(book.cancel_order(1, true), CancelResult::NotFound));
+}
+
+#[test]
+fn test_warm_up_rejects_constraint_violations() {
+    let mut mismatched_total = valid_snapshot();
+    mismatched_total.bids[1].total_quantity += 1;
+
+    let mut filled_order = valid_snapshot();
+    filled_order.bids[1] = snapshot_level(USDC_DECIMALS, vec![Order {
+        filled_quantity: SUI_DECIMALS,
+        ..alice_bid(3, USDC_DECIMALS, SUI_DECIMALS)
+    }]);
+
+    let mut wrong_side = valid_snapshot();
+    wrong_side.asks = vec![snapshot_level(3 * USDC_DECIMALS, vec![alice_bid(4, 3 * USDC_DECIMALS, SUI_DECIMALS)])];
+
+    for snapshot in [mismatched_total, filled_order, wrong_side] {
+        let mut book = BookBuilder::default().build().unwrap();
+        let result = book.warm_up_with_snapshot(snapshot, true);
+        assert!(matches!(result, Err(WarmUpError::ConstraintViolation(_))), "{:?}", result);
+        assert_eq!(book.infer_fair_value(FairValueMethod::MidPrice), None);
+    }
+}
+
+#[test]
+fn test_decay_weighted_spread() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!(book.compute_decay_weighted_spread(1.0), None);
+    book.place_order(alice_bid(1, 2 * USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.compute_decay_weighted_spread(1.0), None);
+
+    // A single level matches the simple spread for any half-life
+    book.place_order(snapshot_ask(2, 3 * USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.compute_decay_weighted_spread(1.0), Some(USDC_DECIMALS));
+    assert_eq!(book.compute_decay_weighted_spread(f64::INFINITY), Some(USDC_DECIMALS));
+
+    // Second level spread is $3; equal weights average to $2
+    book.place_order(alice_bid(3, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(4, 4 * USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.compute_decay_weighted_spread(f64::INFINITY), Some(2 * USDC_DECIMALS));
+
+    // Weights 1 and e^-1 favour the top of book
+    let decay = (-1.0f64).exp();
+    let expected = ((USDC_DECIMALS as f64 + 3.0 * USDC_DECIMALS as f64 * decay) / (1.0 + decay)).round() as u64;
+    assert_eq!(book.compute_decay_weighted_spread(1.0), Some(expected));
+}
+
+/// Builds a fill of `base_quantity` at `price`
+fn execution_fill(price: u64, base_quantity: u64) -> Fill {
+    Fill {
+        maker_order_id: 1,
+        taker_order_id: 2,
+        maker_owner: "bob".into(),
+        taker_owner: "alice".into(),
+        taker_is_bid: true,
+        price,
+        base_quantity,
+        quote_quantity: (base_quantity * price / FLOAT_SCALING) as u128,
+        timestamp: 0,
+        price_improved: false,
+        improvement_amount: 0,
+        mid_price: None,
+        maker_fee: 0,
+        taker_fee: 0,
+        maker_remaining_after: 0,
+        taker_remaining_after: 0,
+    }
+}
+
+#[test]
+fn test_compare_execution_to_benchmark() {
+    // Session volume of 4 SUI
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(snapshot_ask(1, USDC_DECIMALS, 4 * SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, USDC_DECIMALS, 4 * SUI_DECIMALS)).unwrap();
+
+    // Two 1 SUI fills averaging $1.02
+    let fills = [execution_fill(1_010_000, SUI_DECIMALS), execution_fill(1_030_000, SUI_DECIMALS)];
+
+    assert_eq!(
+        book.compare_execution_to_benchmark(&fills, BenchmarkType::ArrivalPrice(USDC_DECIMALS)),
+        ExecutionQualityReport { slippage_bps: 300, implementation_shortfall: 200, volume_participation_rate: 0.5 }
+    );
+    // 0.01 / 1.02 = 98 bps either side; the later fill wins the tie
+    assert_eq!(
+        book.compare_execution_to_benchmark(&fills, BenchmarkType::TWAP(1_020_000)),
+        ExecutionQualityReport { slippage_bps: 98, implementation_shortfall: 0, volume_participation_rate: 0.5 }
+    );
+    // -0.02 / 1.04 = -192 bps average, -0.03 / 1.04 = -288 bps worst fill
+    assert_eq!(
+        book.compare_execution_to_benchmark(&fills, BenchmarkType::VWAP(1_040_000)),
+        ExecutionQualityReport { slippage_bps: -288, implementation_shortfall: -192, volume_participation_rate: 0.5 }
+    );
+    assert_eq!(
+        book.compare_execution_to_benchmark(&[], BenchmarkType::VWAP(1_040_000)),
+        ExecutionQualityReport { slippage_bps: 0, implementation_shortfall: 0, volume_participation_rate: 0.0 }
+    );
+}
+
+#[test]
+fn test_venue_priority_breaks_price_ties() {
+    let clock = MockClock::new(NANOS_PER_SECOND);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock);
+
+    // Simultaneous asks at the same price; venue 2 arrives first but venue 1 fills first
+    book.place_order_with_venue_priority(2, snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order_with_venue_priority(1, snapshot_ask(2, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    let fills = book.place_order_sync(alice_bid(3, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert_eq!(fills.len(), 1);
+    assert_eq!(fills[0].maker_order_id, 2);
+
+    // The same holds for bids
+    book.place_order_with_venue_priority(2, alice_bid(4, USDC_DECIMALS / 2, SUI_DECIMALS)).unwrap();
+    book.place_order_with_venue_priority(1, alice_bid(5, USDC_DECIMALS / 2, SUI_DECIMALS)).unwrap();
+    let fills = book.place_order_sync(snapshot_ask(6, USDC_DECIMALS / 2, SUI_DECIMALS)).unwrap();
+    assert_eq!(fills.len(), 1);
+    assert_eq!(fills[0].maker_order_id, 5);
+}
+
+/// Book with a $0.10 minimum spread quoted $0.99 / $1.01
+fn min_spread_book(clock: &MockClock) -> Book {
+    let params = MarketParams { min_spread: 100_000, ..Default::default() };
+    let mut book = Book::new_with_params(params).with_clock(clock.clone());
+    book.place_order(alice_bid(1, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(2, 1_010_000, SUI_DECIMALS)).unwrap();
+    book
+}
+
+#[test]
+fn test_spread_widening_calm_regime() {
+    let clock = MockClock::new(NANOS_PER_SECOND);
+    let mut book = min_spread_book(&clock);
+    book.apply_spread_widening_on_volatility(50, 2.0);
+
+    // No fills means no realized volatility, so orders may rest near the mid
+    book.place_order(alice_bid(3, 999_000, SUI_DECIMALS)).unwrap();
+    book.update_volatility_regime(50);
+    book.place_order(snapshot_ask(4, 1_001_000, SUI_DECIMALS)).unwrap();
+}
+
+#[test]
+fn test_spread_widening_volatile_regime() {
+    let clock = MockClock::new(NANOS_PER_SECOND);
+    let mut book = min_spread_book(&clock);
+
+    // Fills at $1.01 then $1.111 within 30 seconds: ln(1.1) = 953 bps
+    book.place_order(alice_bid(3, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(4, 1_111_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(5, 1_111_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(6, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.apply_spread_widening_on_volatility(50, 2.0);
+
+    // Mid is $1.00 and the widened spread $0.20, so bids must rest at or below $0.90
+    let result = book.place_order(alice_bid(7, 950_000, SUI_DECIMALS));
+    assert_eq!(
+        result.unwrap_err(),
+        PlaceError::Book(BookError::MinSpreadViolation { price: 950_000, mid_price: 1_000_000, min_spread: 200_000 })
+    );
+    assert!(matches!(book.cancel_order(7, true), CancelResult::NotFound));
+    book.place_order(alice_bid(8, 900_000, SUI_DECIMALS)).unwrap();
+
+    // Volatility falling back below the threshold lifts the restriction
+    book.update_volatility_regime(10);
+    book.place_order(alice_bid(9, 950_000, SUI_DECIMALS)).unwrap();
+}
+
+/// Rests an ask for `owner` and lifts it with a bid from `taker`
+fn trade_at(book: &mut Book, order_id: u128, owner: &str, taker: &str, price: u64, quantity: u64) {
+    let ask = Order { owner: owner.into(), ..snapshot_ask(order_id, price, quantity) };
+    book.place_order(ask).unwrap();
+    let bid = Order { owner: taker.into(), ..alice_bid(order_id + 1, price, quantity) };
+    assert_eq!(book.place_order(bid).unwrap().fill_count, 1);
+}
+
+#[test]
+fn test_institutional_order_detection() {
+    let clock = MockClock::new(NANOS_PER_SECOND);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone());
+
+    // "whale" refills a 3 SUI iceberg every 10s and "desk" a 1 SUI one every 5s
+    for tranche in 0..6u64 {
+        let order_id = 100 * tranche as u128;
+        trade_at(&mut book, order_id, "whale", &format!("taker{}", tranche), USDC_DECIMALS, 3 * SUI_DECIMALS);
+        trade_at(&mut book, order_id + 10, "desk", &format!("buyer{}", tranche), 990_000, SUI_DECIMALS);
+        clock.advance(5 * NANOS_PER_SECOND);
+        trade_at(&mut book, order_id + 20, "desk", &format!("lifter{}", tranche), 990_000, SUI_DECIMALS);
+        clock.advance(5 * NANOS_PER_SECOND);
+    }
+
+    // "noisy" trades as often at the same price but at irregular intervals
+    for (i, gap) in [1, 20, 2, 30, 3].into_iter().enumerate() {
+        trade_at(&mut book, 10_000 + 2 * i as u128, "noisy", "taker", 1_010_000, SUI_DECIMALS);
+        clock.advance(gap * NANOS_PER_SECOND);
+    }
+
+    let candidates = book.get_institutional_order_detection();
+    assert_eq!(candidates, vec![
+        InstitutionalOrderCandidate {
+            owner: "whale".into(),
+            price: USDC_DECIMALS,
+            total_quantity_detected: 18 * SUI_DECIMALS,
+            fill_count: 6,
+            avg_interval_ns: 10 * NANOS_PER_SECOND,
+        },
+        InstitutionalOrderCandidate {
+            owner: "desk".into(),
+            price: 990_000,
+            total_quantity_detected: 12 * SUI_DECIMALS,
+            fill_count: 12,
+            avg_interval_ns: 5 * NANOS_PER_SECOND,
+        },
+    ]);
+}
+
+/// Asks of 1 SUI at $1.00, 1 SUI at $1.01 and 2 SUI at $1.05
+fn protection_book() -> Book {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(2, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(3, 1_050_000, 2 * SUI_DECIMALS)).unwrap();
+    book
+}
+
+/// Matches a $1.10 bid for `quantity` against a fresh protection book
+fn protected_bid(quantity: u64, protection: MatchProtection) -> ProtectedMatchResult {
+    protection_book().match_order_with_protection(alice_bid(10, 1_100_000, quantity), 0, protection)
+}
+
+#[test]
+fn test_match_protection_termination_reasons() {
+    let result = protected_bid(4 * SUI_DECIMALS, MatchProtection::default());
+    assert_eq!(result.termination_reason, TerminationReason::Complete);
+    assert_eq!((result.fills.len(), result.remaining), (3, 0));
+
+    let result = protected_bid(4 * SUI_DECIMALS, MatchProtection { max_fills: Some(2), ..Default::default() });
+    assert_eq!(result.termination_reason, TerminationReason::FillsCapped);
+    assert_eq!((result.fills.len(), result.remaining), (2, 2 * SUI_DECIMALS));
+
+    // $1.05 is 500 bps from the $1.00 touch
+    let result = protected_bid(4 * SUI_DECIMALS, MatchProtection { max_slippage_bps: Some(200), ..Default::default() });
+    assert_eq!(result.termination_reason, TerminationReason::SlippageBreached);
+    assert_eq!((result.fills.len(), result.remaining), (2, 2 * SUI_DECIMALS));
+
+    let result = protected_bid(
+        4 * SUI_DECIMALS,
+        MatchProtection { min_fill_qty: Some(2 * SUI_DECIMALS), ..Default::default() },
+    );
+    assert_eq!(result.termination_reason, TerminationReason::MinQtyNotMet);
+    assert_eq!((result.fills.len(), result.remaining), (0, 4 * SUI_DECIMALS));
+
+    let result = protected_bid(5 * SUI_DECIMALS, MatchProtection { cancel_on_partial: true, ..Default::default() });
+    assert_eq!(result.termination_reason, TerminationReason::PartialCancelled);
+    assert_eq!((result.fills.len(), result.remaining), (3, SUI_DECIMALS));
+
+    // Without cancel_on_partial, exhausting the book is a normal completion
+    let result = protected_bid(5 * SUI_DECIMALS, MatchProtection::default());
+    assert_eq!(result.termination_reason, TerminationReason::Complete);
+    assert_eq!(result.remaining, SUI_DECIMALS);
+}
+
+#[test]
+fn test_match_protection_combined_constraints() {
+    // The slippage limit is reached before the fill cap or the partial cancel
+    let protection = MatchProtection {
+        max_fills: Some(3),
+        max_slippage_bps: Some(200),
+        min_fill_qty: Some(SUI_DECIMALS),
+        cancel_on_partial: true,
+    };
+    let result = protected_bid(5 * SUI_DECIMALS, protection);
+    assert_eq!(result.termination_reason, TerminationReason::SlippageBreached);
+    assert_eq!((result.fills.len(), result.remaining), (2, 3 * SUI_DECIMALS));
+
+    // A loose slippage limit lets the fill cap trigger first
+    let result = protected_bid(5 * SUI_DECIMALS, MatchProtection { max_fills: Some(2), max_slippage_bps: Some(1_000), ..protection });
+    assert_eq!(result.termination_reason, TerminationReason::FillsCapped);
+    assert_eq!((result.fills.len(), result.remaining), (2, 3 * SUI_DECIMALS));
+
+    // With every limit satisfied, the leftover quantity is cancelled
+    let result = protected_bid(5 * SUI_DECIMALS, MatchProtection { max_slippage_bps: Some(1_000), ..protection });
+    assert_eq!(result.termination_reason, TerminationReason::PartialCancelled);
+    assert_eq!((result.fills.len(), result.remaining), (3, SUI_DECIMALS));
+
+    // The minimum fill quantity applies to the taker's final, smaller fill too
+    let result = protected_bid(
+        5 * SUI_DECIMALS / 2,
+        MatchProtection { max_slippage_bps: Some(1_000), ..protection },
+    );
+    assert_eq!(result.termination_reason, TerminationReason::MinQtyNotMet);
+    assert_eq!((result.fills.len(), result.remaining), (2, SUI_DECIMALS / 2));
+}
+
+/// Places an order resting for `owner`
+fn quote(book: &mut Book, order_id: u128, owner: &str, is_bid: bool, price: u64) {
+    let order = Order { owner: owner.into(), side: is_bid.into(), ..alice_bid(order_id, price, 10 * SUI_DECIMALS) };
+    book.place_order(order).unwrap();
+}
+
+#[test]
+fn test_rank_market_makers() {
+    let clock = MockClock::new(NANOS_PER_SECOND);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone());
+
+    // "tight" quotes inside "wide" for the whole session
+    quote(&mut book, 1, "tight", true, 999_000);
+    quote(&mut book, 2, "tight", false, 1_001_000);
+    quote(&mut book, 3, "wide", true, 990_000);
+    quote(&mut book, 4, "wide", false, 1_010_000);
+
+    // "churner" rests a far bid for 1s out of every 10s and always cancels it
+    for cycle in 0..5 {
+        let order_id = 100 + cycle;
+        quote(&mut book, order_id, "churner", true, 900_000);
+        clock.advance(NANOS_PER_SECOND);
+        book.cancel_order(order_id, true).into_order().unwrap();
+        clock.advance(9 * NANOS_PER_SECOND);
+    }
+
+    // A taker lifts part of the tight ask; takers that never rest are not ranked
+    let taker = Order { owner: "taker".into(), ..alice_bid(200, 1_001_000, SUI_DECIMALS) };
+    assert_eq!(book.place_order(taker).unwrap().fill_count, 1);
+
+    // tight leads every component
+    assert!((book.compute_quote_quality_score("tight".into()) - 1.0).abs() < 1e-9);
+    // wide: no time at best or fills, a 10x wider spread, full uptime, no cancels
+    let wide = 0.2 * (0.1 + 1.0 + 1.0);
+    assert!((book.compute_quote_quality_score("wide".into()) - wide).abs() < 1e-9);
+    // churner: one-sided, 5s of 50s uptime, 5 cancels without a fill
+    let churner = 0.2 * (0.1 + 1.0 / 6.0);
+    assert!((book.compute_quote_quality_score("churner".into()) - churner).abs() < 1e-9);
+    assert_eq!(book.compute_quote_quality_score("taker".into()), 0.0);
+
+    let ranking: Vec<String> = book.rank_market_makers().into_iter().map(|(owner, _)| owner.to_string()).collect();
+    assert_eq!(ranking, vec!["tight", "wide", "churner"]);
+}
+
+#[test]
+fn test_export_risk_report_csv() {
+    let mut book = BookBuilder::default().build().unwrap();
+    quote(&mut book, 1, "mm", true, 990_000);
+    book.place_order(Order { owner: "mm".into(), ..snapshot_ask(2, 1_010_000, 5 * SUI_DECIMALS) }).unwrap();
+    // Lifts 2 SUI of mm's ask 100 bps from the $1.00 mid
+    book.place_order(Order { owner: "taker".into(), ..alice_bid(3, 1_010_000, 2 * SUI_DECIMALS) }).unwrap();
+    book.place_order(Order { owner: "hedger".into(), ..snapshot_ask(4, 1_050_000, 4 * SUI_DECIMALS) }).unwrap();
+
+    let mut output = Vec::new();
+    book.export_risk_report_csv(&mut output).unwrap();
+    let csv = String::from_utf8(output).unwrap();
+    assert!(csv.ends_with("\r\n"));
+
+    let mut lines = csv.split_terminator("\r\n");
+    let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+    assert_eq!(header.len(), 12);
+    let rows: Vec<HashMap<&str, &str>> = lines
+        .map(|line| header.iter().copied().zip(line.split(',')).collect())
+        .collect();
+    let owners: Vec<&str> = rows.iter().map(|row| row["owner"]).collect();
+    assert_eq!(owners, vec!["hedger", "mm", "taker"]);
+
+    let mm = &rows[1];
+    assert_eq!(mm["open_bid_orders"], "1");
+    assert_eq!(mm["open_ask_orders"], "1");
+    assert_eq!(mm["total_bid_notional"], "9900000");
+    assert_eq!(mm["total_ask_notional"], "3030000");
+    assert_eq!(mm["net_exposure"], "6870000");
+    assert_eq!(mm["fill_count"], "1");
+    assert_eq!(mm["maker_volume"], "2000000000");
+    assert_eq!(mm["otr_ratio"], "2.0000");
+    assert_eq!(mm["margin_required"], "9900000");
+    assert_eq!(mm["effective_spread"], "200");
+
+    let hedger = &rows[0];
+    assert_eq!(hedger["net_exposure"], "-4200000");
+    assert_eq!(hedger["margin_required"], "4200000");
+    assert_eq!(hedger["otr_ratio"], "1.0000");
+
+    let taker = &rows[2];
+    assert_eq!(taker["open_bid_orders"], "0");
+    assert_eq!(taker["taker_volume"], "2000000000");
+    assert_eq!(taker["maker_volume"], "0");
+    assert_eq!(taker["effective_spread"], "0");
+
+    // A notional beyond u64 cannot be reported
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(5, u64::MAX, 2 * SUI_DECIMALS)).unwrap();
+    assert!(matches!(book.export_risk_report_csv(Vec::new()), Err(ExportError::ArithmeticOverflow)));
+}
+
+#[test]
+fn test_volatility_auction_mode_transition() {
+    let clock = MockClock::new(NANOS_PER_SECOND);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone());
+    assert_eq!(book.apply_volatility_auction(500).map(|result| result.clearing_price), None);
+
+    // Trades at $1.00 then $1.10 move the price 1_000 bps
+    trade_at(&mut book, 1, "bob", "carol", USDC_DECIMALS, SUI_DECIMALS);
+    trade_at(&mut book, 3, "bob", "carol", 1_100_000, SUI_DECIMALS);
+    assert!(book.apply_volatility_auction(2_000).is_none());
+    assert_eq!(book.mode(), BookMode::Continuous);
+    assert!(book.apply_volatility_auction(500).is_none());
+    let end_timestamp = 6 * NANOS_PER_SECOND;
+    assert_eq!(book.mode(), BookMode::VolatilityAuction { end_timestamp });
+
+    // Crossing orders are collected without matching
+    assert!(book.place_order_sync(alice_bid(10, 1_080_000, 2 * SUI_DECIMALS)).unwrap().is_empty());
+    assert!(book.place_order_sync(snapshot_ask(11, 1_040_000, SUI_DECIMALS)).unwrap().is_empty());
+    assert!(book.place_order_sync(snapshot_ask(12, 1_060_000, 2 * SUI_DECIMALS)).unwrap().is_empty());
+    assert!(book.check_auction_end(end_timestamp - 1).is_none());
+
+    // 2 SUI clear at both $1.06 and $1.08; $1.08 is closer to the last trade
+    clock.set(end_timestamp);
+    let result = book.apply_volatility_auction(500).unwrap();
+    assert_eq!(book.mode(), BookMode::Continuous);
+    assert_eq!(result.clearing_price, 1_080_000);
+    assert_eq!(result.matched_quantity, 2 * SUI_DECIMALS);
+    let matched: Vec<(u128, u128)> = result.fills.iter().map(|fill| (fill.maker_order_id, fill.taker_order_id)).collect();
+    assert_eq!(matched, vec![(11, 10), (12, 10)]);
+    assert!(result.fills.iter().all(|fill| fill.price == 1_080_000));
+
+    // Continuous matching resumes against the auction leftovers
+    let fills = book.place_order_sync(alice_bid(13, 1_060_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(fills.len(), 1);
+    assert_eq!(fills[0].maker_order_id, 12);
+    assert!(book.check_auction_end(u64::MAX).is_none());
+}
+
+#[test]
+fn test_market_maker_inventory_risk() {
+    let mut book = BookBuilder::default().build().unwrap();
+    // mm buys 10 SUI and sells 4 SUI, then quotes $0.99 / $1.01
+    trade_at(&mut book, 1, "bob", "mm", USDC_DECIMALS, 10 * SUI_DECIMALS);
+    trade_at(&mut book, 3, "mm", "carol", USDC_DECIMALS, 4 * SUI_DECIMALS);
+    quote(&mut book, 5, "quoter", true, 990_000);
+    quote(&mut book, 6, "quoter", false, 1_010_000);
+
+    assert_eq!(book.get_owner_position("mm".into()), OwnerPosition {
+        bought_quantity: 10 * SUI_DECIMALS,
+        sold_quantity: 4 * SUI_DECIMALS,
+        quote_spent: 10 * USDC_DECIMALS as u128,
+        quote_received: 4 * USDC_DECIMALS as u128,
+    });
+    assert_eq!(book.get_owner_position("bob".into()).net_quantity(), -10 * SUI_DECIMALS as i64);
+
+    // 6 SUI at 100 bps: sigma = 0.06 SUI, VaR = 2.33 * 0.06 SUI
+    let risk = book.compute_market_maker_inventory_risk("mm".into(), 100);
+    assert_eq!(risk.long_inventory, 10 * SUI_DECIMALS);
+    assert_eq!(risk.short_inventory, 4 * SUI_DECIMALS);
+    assert_eq!(risk.net_inventory, 6 * SUI_DECIMALS as i64);
+    assert!((risk.variance - 3.6e15).abs() < 1.0);
+    assert_eq!(risk.value_at_risk, 139_800_000);
+    assert_eq!(book.compute_market_maker_inventory_risk("nobody".into(), 100).value_at_risk, 0);
+
+    // Half spread 1%, sigma 1%: inventory is capped at 0.01 / (2 * gamma * 1e-4)
+    assert_eq!(book.optimal_inventory_skew("mm".into(), 100, 1e-8), 5 * SUI_DECIMALS as i64);
+    assert_eq!(book.optimal_inventory_skew("mm".into(), 100, 1e-9), 6 * SUI_DECIMALS as i64);
+    assert_eq!(book.optimal_inventory_skew("bob".into(), 100, 1e-8), -5 * SUI_DECIMALS as i64);
+    assert_eq!(book.optimal_inventory_skew("mm".into(), 0, 1e-8), 6 * SUI_DECIMALS as i64);
+}
+
+#[test]
+fn test_microstructure_report_matches_standalone_metrics() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!(book.order_book_microstructure_report(), MicrostructureReport {
+        quoted_spread: None,
+        effective_spread: None,
+        realized_spread: None,
+        price_impact: None,
+        kyle_lambda: None,
+        vpin: 0.0,
+        amihud_illiquidity: 0.0,
+        order_imbalance: 0.0,
+        book_depth_bps: 0.0,
+    });
+
+    // Takers alternately lift and hit a $0.99 / $1.01 market, leaving the mid at $1.00
+    quote(&mut book, 1, "mm", true, 990_000);
+    quote(&mut book, 2, "mm", false, 1_010_000);
+    for i in 0..12u128 {
+        let taker = if i % 2 == 0 {
+            alice_bid(10 + i, 1_010_000, SUI_DECIMALS)
+        } else {
+            snapshot_ask(10 + i, 990_000, SUI_DECIMALS)
+        };
+        assert_eq!(book.place_order(taker).unwrap().fill_count, 1);
+    }
+    // Tighter quotes of 2 SUI at $0.9995 and 1 SUI at $1.0005 keep the $1.00 mid
+    book.place_order(Order { quantity: 2 * SUI_DECIMALS, ..alice_bid(30, 999_500, SUI_DECIMALS) }).unwrap();
+    book.place_order(snapshot_ask(31, 1_000_500, SUI_DECIMALS)).unwrap();
+
+    let report = book.order_book_microstructure_report();
+    assert_eq!(report, MicrostructureReport {
+        quoted_spread: book.quoted_spread(),
+        effective_spread: book.effective_spread(),
+        realized_spread: book.realized_spread(),
+        price_impact: book.price_impact(),
+        kyle_lambda: book.kyle_lambda(),
+        vpin: book.vpin(),
+        amihud_illiquidity: book.amihud_illiquidity(),
+        order_imbalance: book.order_imbalance(),
+        book_depth_bps: book.book_depth_bps(10),
+    });
+
+    assert_eq!(report.quoted_spread, Some(1_000));
+    // Every fill is 1% from a mid that never moves
+    assert!((report.effective_spread.unwrap() - 200.0).abs() < 1e-9);
+    assert!((report.realized_spread.unwrap() - 200.0).abs() < 1e-9);
+    assert_eq!(report.price_impact, Some(0.0));
+    assert_eq!(report.kyle_lambda, Some(0.0));
+    assert!(report.amihud_illiquidity > 0.0);
+    assert!((0.0..=1.0).contains(&report.vpin));
+    // 6 SUI of bids against 5 SUI of asks
+    assert!((report.order_imbalance - 1.0 / 11.0).abs() < 1e-12);
+    assert_eq!(report.book_depth_bps, 3.0 * SUI_DECIMALS as f64);
+    assert_eq!(book.book_depth_bps(100), 11.0 * SUI_DECIMALS as f64);
+}
+
+#[test]
+fn test_iter_order_ids_in_priority_order() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert!(book.get_all_order_ids_by_side(true).is_empty());
+
+    // Same-price orders keep arrival order behind better prices
+    for (order_id, price) in [(1, 990_000), (2, 995_000), (3, 990_000), (4, 980_000), (5, 995_000)] {
+        book.place_order(alice_bid(order_id, price, SUI_DECIMALS)).unwrap();
+    }
+    for (order_id, price) in [(11, 1_020_000), (12, 1_010_000), (13, 1_010_000)] {
+        book.place_order(snapshot_ask(order_id, price, SUI_DECIMALS)).unwrap();
+    }
+
+    for (is_bid, expected) in [(true, vec![2, 5, 1, 3, 4]), (false, vec![12, 13, 11])] {
+        let cloned_ids: Vec<u128> = book.clone_side(is_bid).iter().map(|order| order.order_id).collect();
+        assert_eq!(cloned_ids, expected);
+        assert_eq!(book.iter_order_ids(is_bid).collect::<Vec<_>>(), cloned_ids);
+        assert_eq!(book.get_all_order_ids_by_side(is_bid), cloned_ids);
+    }
+
+    // Readers can interleave freely while nothing mutates the book
+    let mut orders = book.iter_orders(true);
+    for order_id in book.iter_order_ids(true) {
+        assert_eq!(book.get_all_order_ids_by_side(true).len(), 5);
+        assert_eq!(orders.next().unwrap().order_id, order_id);
+    }
+    assert!(orders.next().is_none());
+}
+
+#[test]
+fn test_place_order_with_smart_routing() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(Order { owner: "mm".into(), ..snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+    book.place_order(Order { owner: "mm".into(), ..snapshot_ask(2, 1_020_000, 2 * SUI_DECIMALS) }).unwrap();
+
+    // $1.00 reaches 1 SUI and $1.01 still 1 SUI; $1.02 reaches all 3
+    let bid = alice_bid(3, USDC_DECIMALS, 3 * SUI_DECIMALS);
+    assert_eq!(book.simulate_match(&bid, 0).len(), 1);
+    let result = book.place_order_with_smart_routing(bid, &[1_010_000, 0, 1_020_000, 1_050_000]).unwrap();
+    assert_eq!(result.filled_at_price, Some(1_020_000));
+    assert_eq!(result.prices_tried, 3);
+    assert_eq!(result.fills.len(), 2);
+    assert_eq!(result.fills[1].quote_quantity, 2_040_000);
+
+    // Simulations left the book untouched, so only the final match traded
+    assert_eq!(book.get_owner_position("alice".into()).bought_quantity, 3 * SUI_DECIMALS);
+    assert_eq!(book.iter_orders(false).count(), 0);
+
+    // With no sufficient price, nothing is placed
+    book.place_order(Order { owner: "mm".into(), ..snapshot_ask(4, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+    let result = book.place_order_with_smart_routing(alice_bid(5, 990_000, 2 * SUI_DECIMALS), &[USDC_DECIMALS]).unwrap();
+    assert_eq!(result.filled_at_price, None);
+    assert_eq!(result.prices_tried, 2);
+    assert!(result.fills.is_empty());
+    assert_eq!(book.order_count(), (0, 1));
+
+    // The chosen price is placed like any order, so pre-trade hooks still run
+    let calls = Arc::new(AtomicUsize::new(0));
+    book.add_pre_trade_hook(quantity_limit_hook(&calls, SUI_DECIMALS / 2));
+    let result = book.place_order_with_smart_routing(alice_bid(6, USDC_DECIMALS, SUI_DECIMALS), &[]);
+    assert!(matches!(result, Err(PlaceError::Book(BookError::Rejected(_)))));
+    assert_eq!(book.order_count(), (0, 1));
+    let result = book.place_order_with_smart_routing(alice_bid(7, USDC_DECIMALS, SUI_DECIMALS / 2), &[]).unwrap();
+    assert_eq!((result.filled_at_price, result.fills.len()), (Some(USDC_DECIMALS), 1));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+#[cfg(feature = "fingerprint")]
+fn test_order_book_fingerprint() {
+    let build = |ask_first: bool| {
+        let mut book = BookBuilder::default().build().unwrap();
+        let bid = alice_bid(1, USDC_DECIMALS, 2 * SUI_DECIMALS);
+        let ask = snapshot_ask(2, 1_010_000, SUI_DECIMALS);
+        let (first, second) = if ask_first { (ask, bid) } else { (bid, ask) };
+        book.place_order(first).unwrap();
+        book.place_order(second).unwrap();
+        book
+    };
+
+    // Insertion order does not affect the fingerprint
+    let mut book = build(false);
+    let other = build(true);
+    assert!(book.fingerprint_matches(&other));
+    assert!(!book.fingerprint_matches(&BookBuilder::default().build().unwrap()));
+
+    // A partial fill of the resting bid changes it
+    let before = book.get_order_book_fingerprint();
+    let fills = book.match_order(Order { owner: "bob".into(), ..snapshot_ask(3, USDC_DECIMALS, SUI_DECIMALS) }, 0).fills;
+    assert_eq!(fills.len(), 1);
+    assert_ne!(book.get_order_book_fingerprint(), before);
+    assert!(!book.fingerprint_matches(&other));
+}
+
+#[test]
+fn test_replay_with_latency_injection() {
+    // The maker expires 1ms after it is placed and the taker arrives 0.5ms later
+    let maker = Order { expire_timestamp: NANOS_PER_SECOND + 1_000_000, ..snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS) };
+    let taker = Order { owner: "bob".into(), ..alice_bid(2, USDC_DECIMALS, SUI_DECIMALS) };
+    let events = vec![
+        OrderEvent::Place { timestamp: NANOS_PER_SECOND, order: maker },
+        OrderEvent::Place { timestamp: NANOS_PER_SECOND + 500_000, order: taker },
+        OrderEvent::Cancel { timestamp: NANOS_PER_SECOND + 600_000, order_id: 2, is_bid: true },
+    ];
+
+    let book = BookBuilder::default().build().unwrap();
+    let result = book.replay_with_latency_injection(&events, 100_000);
+    assert_eq!(result.fills_without_latency.len(), 1);
+    assert_eq!(result.fills_with_latency.len(), 1);
+    assert_eq!(result.fills_difference, 0);
+
+    // 0.6ms of latency delivers the taker after the maker has expired
+    let result = book.replay_with_latency_injection(&events, 600_000);
+    assert_eq!(result.fills_without_latency[0].timestamp, NANOS_PER_SECOND + 500_000);
+    assert!(result.fills_with_latency.is_empty());
+    assert_eq!(result.fills_difference, 1);
+
+    // Replays leave the source book untouched
+    assert_eq!(book.iter_orders(false).count(), 0);
+}
+
+/// Rests three bid and three ask levels one tick step apart with the given weights
+fn skewed_book(base_price: u64, step: u64, weights: [u64; 3]) -> Book {
+    let mut book = BookBuilder::default().build().unwrap();
+    for (level, weight) in weights.into_iter().enumerate() {
+        let offset = step * level as u64;
+        let quantity = weight * SUI_DECIMALS;
+        book.place_order(alice_bid(level as u128 + 1, base_price + offset, quantity)).unwrap();
+        book.place_order(snapshot_ask(level as u128 + 11, 2 * base_price + offset, quantity)).unwrap();
+    }
+    book
+}
+
+#[test]
+fn test_compute_order_book_skewness() {
+    // Equal weight at each level is symmetric
+    let (bid_skewness, ask_skewness) = skewed_book(USDC_DECIMALS, 10_000, [1, 1, 1]).compute_order_book_skewness();
+    assert!(bid_skewness.abs() < 1e-9 && ask_skewness.abs() < 1e-9);
+
+    // Weights 1:4:4 from the lowest price have a lower tail, skewness -0.5
+    let (bid_skewness, ask_skewness) = skewed_book(USDC_DECIMALS, 10_000, [1, 4, 4]).compute_order_book_skewness();
+    assert!((bid_skewness + 0.5).abs() < 1e-9);
+    assert!((ask_skewness + 0.5).abs() < 1e-9);
+
+    // Weights 4:4:1 mirror it
+    let (bid_skewness, ask_skewness) = skewed_book(USDC_DECIMALS, 10_000, [4, 4, 1]).compute_order_book_skewness();
+    assert!((bid_skewness - 0.5).abs() < 1e-9);
+    assert!((ask_skewness - 0.5).abs() < 1e-9);
+
+    // Prices one tick apart near u64::MAX / 4 still give the exact result
+    let (bid_skewness, _) = skewed_book(u64::MAX / 4 / 1_000 * 1_000, 1_000, [4, 4, 1]).compute_order_book_skewness();
+    assert!((bid_skewness - 0.5).abs() < 1e-9);
+
+    // Fewer than 3 levels
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, 990_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.compute_order_book_skewness(), (0.0, 0.0));
+}
+
+#[test]
+fn test_enforce_lot_size_on_amendment() {
+    let lot_size = SUI_DECIMALS / 10;
+    let mut book = BookBuilder::default().lot_size(lot_size).build().unwrap();
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+
+    // Exact multiple is applied to the resting order
+    assert!(book.enforce_lot_size_on_amendment(1, true, 3 * lot_size).is_ok());
+    assert_eq!(book.iter_orders(true).next().unwrap().quantity, 3 * lot_size);
+
+    // One unit above is rejected and leaves the order unchanged
+    let err = book.enforce_lot_size_on_amendment(1, true, 3 * lot_size + 1).unwrap_err();
+    assert!(matches!(err, BookError::LotSizeViolation { submitted, lot_size: size } if submitted == 3 * lot_size + 1 && size == lot_size));
+    assert_eq!(book.iter_orders(true).next().unwrap().quantity, 3 * lot_size);
+
+    assert!(matches!(book.enforce_lot_size_on_amendment(1, false, lot_size), Err(BookError::OrderNotFound(1))));
+    book.match_order(Order { owner: "bob".into(), ..snapshot_ask(2, USDC_DECIMALS, lot_size) }, 0);
+    assert!(matches!(
+        book.enforce_lot_size_on_amendment(1, true, lot_size),
+        Err(BookError::QuantityBelowFilled { filled_quantity, .. }) if filled_quantity == lot_size
+    ));
+
+    assert_eq!(book.round_to_lot_size(0), 0);
+    assert_eq!(book.round_to_lot_size(lot_size - 1), 0);
+    assert_eq!(book.round_to_lot_size(lot_size), lot_size);
+    assert_eq!(book.round_to_lot_size(2 * lot_size - 1), lot_size);
+    assert_eq!(book.round_to_lot_size(u64::MAX), u64::MAX / lot_size * lot_size);
+
+    // Below the minimum size and off the quantity increment get their own errors
+    let mut book = BookBuilder::default().lot_size(lot_size).min_size(2 * lot_size).min_qty_increment(2 * lot_size).build().unwrap();
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert_eq!(
+        book.enforce_lot_size_on_amendment(1, true, lot_size),
+        Err(BookError::QuantityBelowMinimum { submitted: lot_size, min_size: 2 * lot_size })
+    );
+    assert_eq!(
+        book.enforce_lot_size_on_amendment(1, true, 3 * lot_size),
+        Err(BookError::QtyIncrementViolation { submitted: 3 * lot_size, min_qty_increment: 2 * lot_size })
+    );
+    assert_eq!(book.amend_order(1, true, None, Some(3 * lot_size)), Err(AmendError::QuantityNotIncrementAligned));
+    assert!(book.enforce_lot_size_on_amendment(1, true, 4 * lot_size).is_ok());
+}
+
+#[test]
+fn test_apply_market_reset() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, 1_005_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(3, 980_000, SUI_DECIMALS + SUI_DECIMALS / 2)).unwrap();
+    book.place_order(snapshot_ask(4, 1_015_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(5, 1_400_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(6, 1_600_000, SUI_DECIMALS)).unwrap();
+    book.match_order(Order { owner: "bob".into(), ..snapshot_ask(7, 1_005_000, SUI_DECIMALS / 2) }, 0);
+
+    // A 1 cent tick, 1 SUI lot and $1.50 cap for the next session
+    let fee_schedule = FeeSchedule { maker_bps: 5, taker_bps: 10 };
+    let params = MarketParams {
+        tick_size: 10_000,
+        lot_size: SUI_DECIMALS,
+        max_price: 1_500_000,
+        fee_schedule,
+        ..Default::default()
+    };
+    let result = book.apply_market_reset(params, 5 * NANOS_PER_SECOND).unwrap();
+    assert_eq!(result, MarketResetResult { orders_revalidated: 2, orders_cancelled_invalid: 2, orders_repriced: 2 });
+    assert_eq!(book.statistics().total_orders_cancelled, 0);
+    assert!(book.order_status(3, true).is_none());
+
+    // The bid moves down and the ask up, away from the spread
+    let prices = |book: &Book, is_bid| book.iter_orders(is_bid).map(|order| (order.order_id, order.price)).collect::<Vec<_>>();
+    assert_eq!(prices(&book, true), vec![(2, 1_000_000), (1, 990_000)]);
+    assert_eq!(prices(&book, false), vec![(4, 1_020_000), (5, 1_400_000)]);
+    assert_eq!(book.iter_orders(true).next().unwrap().filled_quantity, SUI_DECIMALS / 2);
+
+    let resets = book.market_resets();
+    assert_eq!(resets.len(), 1);
+    assert_eq!(resets[0].timestamp, 5 * NANOS_PER_SECOND);
+    assert_eq!(resets[0].params.fee_schedule, fee_schedule);
+    assert!(book.export_to_influxdb_line_protocol("book", 0).contains("session_volume=0i"));
+
+    // Repriced orders keep matching at their new prices
+    let fills = book.match_order(Order { owner: "bob".into(), ..alice_bid(8, 1_020_000, SUI_DECIMALS) }, 0).fills;
+    assert_eq!(fills[0].price, 1_020_000);
+
+    // Unchanged parameters revalidate everything
+    let result = book.apply_market_reset(book.market_resets()[0].params.clone(), 6 * NANOS_PER_SECOND).unwrap();
+    assert_eq!(result, MarketResetResult { orders_revalidated: 3, ..Default::default() });
+
+    // Parameters the builder would reject leave the book as it was
+    let zero_tick = MarketParams { tick_size: 0, ..Default::default() };
+    assert_eq!(book.apply_market_reset(zero_tick, 7 * NANOS_PER_SECOND), Err(BuilderError::ZeroTickSize));
+    let zero_lot = MarketParams { lot_size: 0, ..Default::default() };
+    assert_eq!(book.apply_market_reset(zero_lot, 7 * NANOS_PER_SECOND), Err(BuilderError::ZeroLotSize));
+    assert_eq!(book.market_resets().len(), 2);
+    assert_eq!(book.order_count(), (2, 1));
+}
+
+#[test]
+fn test_market_reset_revalidates_held_orders() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+    let stop_limit = OrderType::StopLimit { trigger_price: 1_100_000, limit_price: 1_105_000 };
+    book.place_order(alice_bid(2, USDC_DECIMALS, SUI_DECIMALS).with_order_type(stop_limit)).unwrap();
+    book.place_order(alice_bid(3, USDC_DECIMALS, SUI_DECIMALS / 2).with_order_type(OrderType::Stop { trigger_price: 1_100_000 }))
+        .unwrap();
+    let held = alice_bid(4, 905_000, SUI_DECIMALS);
+    let result = book.conditional_place(held, PlaceCondition::IfBestAskAtOrBelow(900_000), 0);
+    assert!(matches!(result, ConditionalResult::ConditionNotMet));
+
+    // The stop limit and conditional bid move down to the 1 cent tick; the half-lot stop is dropped
+    let params = MarketParams { tick_size: 10_000, lot_size: SUI_DECIMALS, ..Default::default() };
+    let result = book.apply_market_reset(params, 0).unwrap();
+    assert_eq!(result, MarketResetResult { orders_revalidated: 1, orders_cancelled_invalid: 1, orders_repriced: 2 });
+    let stops: Vec<_> = book.stop_orders().iter().map(|order| (order.order_id, order.order_type)).collect();
+    assert_eq!(stops, vec![(2, OrderType::StopLimit { trigger_price: 1_100_000, limit_price: 1_100_000 })]);
+    assert_eq!(book.conditional_orders()[0].0.price, 900_000);
+}
+
+#[test]
+fn test_market_reset_events_replay() {
+    let mut book = BookBuilder::default().build().unwrap().with_event_recording();
+    book.place_order(alice_bid(1, 990_500, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(2, 1_010_000, SUI_DECIMALS / 2)).unwrap();
+    let params = MarketParams { tick_size: 10_000, lot_size: SUI_DECIMALS, ..Default::default() };
+    let result = book.apply_market_reset(params, 5).unwrap();
+
+    let reset = book.events().iter().find_map(|event| match event {
+        BookEvent::MarketReset(reset) => Some(reset),
+        _ => None,
+    });
+    assert_eq!(reset.map(|reset| (reset.timestamp, reset.result)), Some((5, result)));
+    assert!(!book.events().iter().any(|event| matches!(event, BookEvent::Cancelled { .. })));
+
+    // Replaying the reset reprices and removes the same orders
+    let (replayed, _) = Book::replay(book.events());
+    assert_eq!(replayed.market_resets().len(), 1);
+    assert_eq!(replayed.get_all_order_ids_by_side(true), vec![1]);
+    assert_eq!(replayed.best_bid(), Some(990_000));
+    assert_eq!(replayed.order_count(), (1, 0));
+}
+
+#[test]
+fn test_fill_simulation_report() {
+    let params = MarketParams { fee_schedule: FeeSchedule { maker_bps: 0, taker_bps: 10 }, ..Default::default() };
+    let mut book = Book::new_with_params(params);
+    book.place_order(snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(2, 1_020_000, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..alice_bid(3, 980_000, SUI_DECIMALS) }).unwrap();
+
+    // 1 SUI at $1.00 and 1 SUI at $1.02
+    let report = book.fill_simulation_report(&Order { owner: "bob".into(), ..alice_bid(4, 1_020_000, 2 * SUI_DECIMALS) }, 0);
+    assert_eq!(report, FillSimulationReport {
+        would_fill_qty: 2 * SUI_DECIMALS,
+        would_rest_qty: 0,
+        estimated_avg_price: 1_010_000,
+        estimated_taker_fee: 1_000 + 1_020,
+        estimated_market_impact_bps: 100.0,
+        fills_count: 2,
+        crosses_levels: 2,
+        post_trade_spread: Some(40_000),
+        post_trade_best_bid: Some(980_000),
+        post_trade_best_ask: Some(1_020_000),
+    });
+
+    // Sweeping the asks leaves 1 SUI resting as the new best bid
+    let report = book.fill_simulation_report(&Order { owner: "bob".into(), ..alice_bid(4, 1_020_000, 4 * SUI_DECIMALS) }, 0);
+    assert_eq!(report.would_fill_qty, 3 * SUI_DECIMALS);
+    assert_eq!(report.would_rest_qty, SUI_DECIMALS);
+    assert_eq!(report.estimated_avg_price, 1_013_333);
+    assert_eq!(report.post_trade_best_bid, Some(1_020_000));
+    assert_eq!(report.post_trade_best_ask, None);
+    assert_eq!(report.post_trade_spread, None);
+
+    // A passive ask only changes the best ask
+    let report = book.fill_simulation_report(&snapshot_ask(5, 990_000, SUI_DECIMALS), 0);
+    assert_eq!(report.fills_count, 0);
+    assert_eq!(report.estimated_avg_price, 0);
+    assert_eq!(report.estimated_market_impact_bps, 0.0);
+    assert_eq!(report.post_trade_best_ask, Some(990_000));
+    assert_eq!(report.post_trade_spread, Some(10_000));
+
+    // Nothing was executed
+    assert_eq!(book.iter_orders(false).count(), 2);
+
+    // An immediate-or-cancel order rests nothing, and an iceberg filled once per
+    // peak is exhausted by the sum of its fills
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(snapshot_ask(1, USDC_DECIMALS, 2 * SUI_DECIMALS).with_iceberg_peak(SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(2, 1_020_000, SUI_DECIMALS)).unwrap();
+    let taker = Order { owner: "bob".into(), ..alice_bid(3, USDC_DECIMALS, 3 * SUI_DECIMALS) };
+    let report = book.fill_simulation_report(&taker.with_order_type(OrderType::ImmediateOrCancel), 0);
+    assert_eq!(report.fills_count, 2);
+    assert_eq!(report.would_fill_qty, 2 * SUI_DECIMALS);
+    assert_eq!(report.would_rest_qty, 0);
+    assert_eq!(report.post_trade_best_bid, None);
+    assert_eq!(report.post_trade_best_ask, Some(1_020_000));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_subscribe_to_price_level_changes() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(snapshot_ask(1, 1_010_000, SUI_DECIMALS)).unwrap();
+
+    // Levels existing before subscribing are the baseline
+    let mut events = book.subscribe_to_price_level_changes();
+    book.place_order(alice_bid(2, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(3, USDC_DECIMALS, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(4, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.cancel_order(2, true);
+    book.match_order(Order { owner: "bob".into(), ..snapshot_ask(5, USDC_DECIMALS, 2 * SUI_DECIMALS) }, 0);
+    book.cancel_order(1, false);
+    book.cancel_order(99, false);
+
+    let level = |side, price, new_total_qty, change| PriceLevelEvent { side, price, new_total_qty, change };
+    let mut received = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        received.push(event);
+    }
+    assert_eq!(received, vec![
+        level(Side::Bid, USDC_DECIMALS, SUI_DECIMALS, LevelChange::Added(SUI_DECIMALS)),
+        level(Side::Bid, USDC_DECIMALS, 3 * SUI_DECIMALS, LevelChange::Added(2 * SUI_DECIMALS)),
+        level(Side::Ask, 1_010_000, 2 * SUI_DECIMALS, LevelChange::Added(SUI_DECIMALS)),
+        level(Side::Bid, USDC_DECIMALS, 2 * SUI_DECIMALS, LevelChange::Reduced(SUI_DECIMALS)),
+        level(Side::Bid, USDC_DECIMALS, 0, LevelChange::Removed),
+        level(Side::Ask, 1_010_000, SUI_DECIMALS, LevelChange::Reduced(SUI_DECIMALS)),
+    ]);
+}
+
+#[test]
+fn test_flush() {
+    let fee_schedule = FeeSchedule { maker_bps: MAKER_FEE, taker_bps: TAKER_FEE };
+    let mut book = BookBuilder::default().tick_size(1_000).fee_schedule(fee_schedule).build().unwrap().with_clock(MockClock::new(0));
+    book.place_order(alice_bid(1, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { expire_timestamp: 100, ..alice_bid(2, 980_000, SUI_DECIMALS) }).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(3, 1_010_000, 2 * SUI_DECIMALS) }).unwrap();
+    book.place_order(alice_bid(4, 1_010_000, SUI_DECIMALS)).unwrap();
+    #[cfg(feature = "tokio")]
+    let mut levels = book.subscribe_to_price_level_changes();
+    let last_seq = book.snapshot_changelog(0).last().unwrap().0;
+
+    let snapshot = book.flush_with_snapshot();
+    let prices = |levels: &[PriceLevel]| levels.iter().map(|level| level.price).collect::<Vec<_>>();
+    assert_eq!((prices(&snapshot.bids), prices(&snapshot.asks)), (vec![990_000, 980_000], vec![1_010_000]));
+
+    assert_eq!((book.best_bid(), book.best_ask(), book.order_count()), (None, None, (0, 0)));
+    assert_eq!(book.unfilled_orders().count(), 0);
+    assert_eq!(book.orders_for_owner("alice".into()).count(), 0);
+    assert_eq!(book.drain_fills().count(), 0);
+    assert_eq!(book.drain_events().count(), 0);
+    assert_eq!((book.vwap(10), book.last_trade_price()), (None, None));
+    assert_eq!(*book.statistics(), BookStats::default());
+    assert!(book.position_for_owner("alice".into()).is_none());
+    assert!(book.purge_expired(u64::MAX).is_empty());
+
+    // Replicas and level subscribers see everything go
+    let changes: Vec<OrderBookChange> = book.snapshot_changelog(last_seq).into_iter().map(|(_, change)| change).collect();
+    assert_eq!(changes, vec![
+        OrderBookChange::OrderCancelled(1),
+        OrderBookChange::OrderCancelled(2),
+        OrderBookChange::OrderCancelled(3),
+    ]);
+    #[cfg(feature = "tokio")]
+    {
+        let mut removed = Vec::new();
+        while let Ok(event) = levels.try_recv() {
+            assert_eq!(event.change, LevelChange::Removed);
+            removed.push(event.price);
+        }
+        removed.sort();
+        assert_eq!(removed, vec![980_000, 990_000, 1_010_000]);
+    }
+
+    // Tick size and fees survive
+    let result = book.place_order(alice_bid(5, 990_500, SUI_DECIMALS));
+    assert!(matches!(result, Err(PlaceError::PriceNotTickAligned)));
+    book.place_order(snapshot_ask(6, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    let fills = book.place_order_sync(Order { owner: "bob".into(), ..alice_bid(7, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+    assert_eq!(fills[0].maker_fee, fee_schedule.maker_fee(SUI_DECIMALS));
+}
+
+#[test]
+fn test_compute_order_flow_imbalance_signal() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(3, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(4, 1_020_000, SUI_DECIMALS)).unwrap();
+
+    // The first call records the baseline
+    assert_eq!(book.compute_order_flow_imbalance_signal(2), OFISignal {
+        per_level: vec![0.0, 0.0],
+        aggregate: 0.0,
+        predicted_direction: None,
+    });
+
+    // One more SUI bid at the touch, and the second ask level disappears
+    book.place_order(alice_bid(5, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.cancel_order(4, false);
+    let sui = SUI_DECIMALS as f64;
+    let signal = book.compute_order_flow_imbalance_signal(2);
+    assert_eq!(signal.per_level, vec![sui, sui]);
+    assert_eq!(signal.aggregate, 2.0 * sui);
+    assert_eq!(signal.predicted_direction, Some(Side::Bid));
+
+    // A 3 SUI ask improving the touch pushes $1.01 to the second level
+    book.place_order(snapshot_ask(6, 1_005_000, 3 * SUI_DECIMALS)).unwrap();
+    let signal = book.compute_order_flow_imbalance_signal(2);
+    assert_eq!(signal.per_level, vec![-3.0 * sui, -sui]);
+    assert_eq!(signal.aggregate, -4.0 * sui);
+    assert_eq!(signal.predicted_direction, Some(Side::Ask));
+
+    // After a reset the next call is a new baseline
+    book.cancel_order(6, false);
+    book.reset_ofi_state();
+    assert_eq!(book.compute_order_flow_imbalance_signal(2).aggregate, 0.0);
+}
+
+#[test]
+fn test_get_depth_imbalance_by_price_range() {
+    assert_eq!(BookBuilder::default().build().unwrap().get_depth_imbalance_by_price_range(10, 100), (0.0, 0.0));
+
+    // Symmetric 1 SUI quotes 5 bps either side of a $1 mid
+    let thin_touch = || {
+        let mut book = BookBuilder::default().build().unwrap();
+        book.place_order(alice_bid(1, 999_500, SUI_DECIMALS)).unwrap();
+        book.place_order(snapshot_ask(2, 1_000_500, SUI_DECIMALS)).unwrap();
+        book
+    };
+
+    // A heavy bid 50 bps away and one beyond the far band
+    let mut book = thin_touch();
+    book.place_order(alice_bid(3, 995_000, 3 * SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(4, 980_000, 10 * SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(5, 1_005_000, SUI_DECIMALS)).unwrap();
+    let (near_imbalance, far_imbalance) = book.get_depth_imbalance_by_price_range(10, 100);
+    assert_eq!(near_imbalance, 0.0);
+    assert_eq!(far_imbalance, 0.5);
+
+    // And a heavy far ask
+    let mut book = thin_touch();
+    book.place_order(snapshot_ask(3, 1_005_000, 4 * SUI_DECIMALS)).unwrap();
+    let (near_imbalance, far_imbalance) = book.get_depth_imbalance_by_price_range(10, 100);
+    assert_eq!(near_imbalance, 0.0);
+    assert_eq!(far_imbalance, -1.0);
+
+    // Widening the near band moves the far levels into it
+    assert_eq!(book.get_depth_imbalance_by_price_range(100, 100), (-4.0 / 6.0, 0.0));
+}
+
+/// Applies replicated changes to a replica's view of resting orders
+fn apply_changes(replica: &mut HashMap<u128, (u64, u64, bool)>, changes: &[(u64, OrderBookChange)]) -> u64 {
+    for (_, change) in changes {
+        match *change {
+            OrderBookChange::OrderPlaced(order_id, price, quantity, is_bid) => {
+                replica.insert(order_id, (price, quantity, is_bid));
+            }
+            OrderBookChange::OrderPartialFill(order_id, remaining) => replica.get_mut(&order_id).unwrap().1 = remaining,
+            OrderBookChange::OrderCancelled(order_id) | OrderBookChange::OrderFullFill(order_id) => {
+                replica.remove(&order_id);
+            }
+        }
+    }
+    changes.last().map_or(0, |(seq, _)| *seq)
+}
+
+#[test]
+fn test_market_depth_imbalance() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!((book.market_depth_imbalance(5), book.bid_ask_ratio(5)), (0.0, 0.0));
+
+    // 3 SUI bid at the top over two orders and 1 more a level down; 1 SUI asked
+    book.place_order(alice_bid(1, 990_000, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(3, 980_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.market_depth_imbalance(1), 1.0);
+    assert_eq!(book.bid_ask_ratio(1), f64::INFINITY);
+    book.place_order(snapshot_ask(4, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(5, 1_030_000, 4 * SUI_DECIMALS)).unwrap();
+
+    assert!((book.market_depth_imbalance(1) - 0.5).abs() < f64::EPSILON);
+    assert!((book.bid_ask_ratio(1) - 3.0).abs() < f64::EPSILON);
+    assert!((book.market_depth_imbalance(2) + 1.0 / 9.0).abs() < f64::EPSILON);
+    assert!((book.bid_ask_ratio(2) - 0.8).abs() < f64::EPSILON);
+    assert_eq!(book.market_depth_imbalance(10), book.market_depth_imbalance(2));
+    assert_eq!(book.market_depth_imbalance(0), 0.0);
+}
+
+#[test]
+fn test_snapshot_changelog() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, 990_000, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(3, 1_010_000, SUI_DECIMALS)).unwrap();
+
+    let mut replica = HashMap::new();
+    let last_seq = apply_changes(&mut replica, &book.snapshot_changelog(0));
+    assert_eq!(last_seq, 3);
+
+    // A taker fills bid 1 and half of bid 2, then the ask is cancelled
+    book.match_order(Order { owner: "bob".into(), ..snapshot_ask(4, 990_000, 2 * SUI_DECIMALS) }, 0);
+    book.cancel_order(3, false);
+    book.place_order(snapshot_ask(5, 1_020_000, SUI_DECIMALS)).unwrap();
+
+    let diff = book.snapshot_changelog(last_seq);
+    assert_eq!(diff, vec![
+        (4, OrderBookChange::OrderFullFill(1)),
+        (5, OrderBookChange::OrderPartialFill(2, SUI_DECIMALS)),
+        (6, OrderBookChange::OrderCancelled(3)),
+        (7, OrderBookChange::OrderPlaced(5, 1_020_000, SUI_DECIMALS, false)),
+    ]);
+    apply_changes(&mut replica, &diff);
+
+    let primary: HashMap<u128, (u64, u64, bool)> = book
+        .iter_orders(true)
+        .chain(book.iter_orders(false))
+        .map(|order| (order.order_id, (order.price, order.remaining_quantity(), order.is_bid())))
+        .collect();
+    assert_eq!(replica, primary);
+    assert!(book.snapshot_changelog(7).is_empty());
+
+    // Only the latest 10k changes are kept
+    for order_id in 10..5_010 {
+        book.place_order(alice_bid(order_id, 980_000, SUI_DECIMALS)).unwrap();
+        book.cancel_order(order_id, true);
+    }
+    let retained = book.snapshot_changelog(0);
+    assert_eq!(retained.len(), 10_000);
+    assert_eq!(retained[0].0, 8);
+    assert_eq!(retained.last().unwrap().0, 10_007);
+}
+
+/// Asks of 1 SUI at $1.00 and $1.02
+fn two_ask_book() -> Book {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(2, 1_020_000, SUI_DECIMALS)).unwrap();
+    book
+}
+
+/// Places a bid of the given type on `two_ask_book`, returning the fill prices and
+/// the book afterwards
+fn place_typed_bid(order_type: OrderType, price: u64, quantity: u64) -> (Vec<u64>, Book) {
+    let mut book = two_ask_book();
+    let order = Order { owner: "bob".into(), order_type, ..alice_bid(10, price, quantity) };
+    let fills = book.place_order_sync(order).unwrap();
+    (fills.iter().map(|fill| fill.price).collect(), book)
+}
+
+#[test]
+fn test_limit_orders_rest_remainder() {
+    let (prices, book) = place_typed_bid(OrderType::Limit, 1_020_000, 3 * SUI_DECIMALS);
+    assert_eq!(prices, vec![USDC_DECIMALS, 1_020_000]);
+    assert_eq!(book.iter_orders(true).next().unwrap().remaining_quantity(), SUI_DECIMALS);
+
+    let (prices, book) = place_typed_bid(OrderType::Limit, USDC_DECIMALS, SUI_DECIMALS);
+    assert_eq!(prices, vec![USDC_DECIMALS]);
+    assert_eq!(book.iter_orders(true).count(), 0);
+
+    let (prices, book) = place_typed_bid(OrderType::Limit, 990_000, SUI_DECIMALS);
+    assert!(prices.is_empty());
+    assert_eq!(book.iter_orders(true).count(), 1);
+}
+
+#[test]
+fn test_market_orders_ignore_price() {
+    // The $0.000001 limit is ignored and the remainder is cancelled
+    let (prices, book) = place_typed_bid(OrderType::Market, 1, 3 * SUI_DECIMALS);
+    assert_eq!(prices, vec![USDC_DECIMALS, 1_020_000]);
+    assert_eq!(book.iter_orders(true).count(), 0);
+
+    let (prices, book) = place_typed_bid(OrderType::Market, 1, SUI_DECIMALS);
+    assert_eq!(prices, vec![USDC_DECIMALS]);
+    assert_eq!(book.iter_orders(false).count(), 1);
+
+    let mut book = BookBuilder::default().build().unwrap();
+    let order = Order { order_type: OrderType::Market, ..alice_bid(1, USDC_DECIMALS, SUI_DECIMALS) };
+    assert!(matches!(book.place_order(order), Err(PlaceError::Book(BookError::NoLiquidity))));
+    assert_eq!(book.iter_orders(true).count(), 0);
+}
+
+#[test]
+fn test_immediate_or_cancel_orders_never_rest() {
+    let (prices, book) = place_typed_bid(OrderType::ImmediateOrCancel, 1_020_000, 3 * SUI_DECIMALS);
+    assert_eq!(prices, vec![USDC_DECIMALS, 1_020_000]);
+    assert_eq!(book.iter_orders(true).count(), 0);
+
+    let (prices, book) = place_typed_bid(OrderType::ImmediateOrCancel, USDC_DECIMALS, SUI_DECIMALS);
+    assert_eq!(prices, vec![USDC_DECIMALS]);
+    assert_eq!(book.iter_orders(false).count(), 1);
+
+    let (prices, book) = place_typed_bid(OrderType::ImmediateOrCancel, 990_000, SUI_DECIMALS);
+    assert!(prices.is_empty());
+    assert_eq!(book.iter_orders(true).count(), 0);
+}
+
+#[test]
+fn test_fill_or_kill_orders_fill_completely_or_not_at_all() {
+    // 3 SUI cannot be filled, so neither ask is touched
+    let (prices, book) = place_typed_bid(OrderType::FillOrKill, 1_020_000, 3 * SUI_DECIMALS);
+    assert!(prices.is_empty());
+    assert_eq!(book.iter_orders(false).count(), 2);
+    assert_eq!(book.iter_orders(true).count(), 0);
+
+    let (prices, book) = place_typed_bid(OrderType::FillOrKill, 1_020_000, 2 * SUI_DECIMALS);
+    assert_eq!(prices, vec![USDC_DECIMALS, 1_020_000]);
+    assert_eq!(book.iter_orders(false).count(), 0);
+
+    let (prices, book) = place_typed_bid(OrderType::FillOrKill, 990_000, SUI_DECIMALS);
+    assert!(prices.is_empty());
+    assert_eq!(book.iter_orders(true).count(), 0);
+}
+
+#[test]
+fn test_fill_or_kill_with_own_order_at_touch() {
+    // The taker's own ask would stop or skip matching, so the bob ask ahead of it is
+    // not partially taken
+    for stp_mode in [STPMode::CancelMaker, STPMode::CancelTaker, STPMode::CancelBoth] {
+        let mut book = BookBuilder::default().build().unwrap();
+        book.place_order(Order { owner: "bob".into(), ..snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+        book.place_order(snapshot_ask(2, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+        let taker = alice_bid(3, USDC_DECIMALS, 2 * SUI_DECIMALS)
+            .with_order_type(OrderType::FillOrKill)
+            .with_stp_mode(stp_mode);
+        assert!(book.place_order_sync(taker).unwrap().is_empty());
+        assert_eq!(book.get_all_order_ids_by_side(false), vec![1, 2]);
+        assert_eq!(book.order_status(1, Side::Ask).unwrap().filled_quantity(), 0);
+    }
+}
+
+#[test]
+fn test_fees_charged_on_fills() {
+    let fee_schedule = FeeSchedule { maker_bps: MAKER_FEE, taker_bps: TAKER_FEE };
+    let mut book = BookBuilder::default().fee_schedule(fee_schedule).build().unwrap();
+
+    // 10 SUI at $5: 0.05 SUI from the maker and $0.50 from the taker
+    book.place_order(snapshot_ask(1, 5 * USDC_DECIMALS, 10 * SUI_DECIMALS)).unwrap();
+    let fills = book.place_order_sync(Order { owner: "bob".into(), ..alice_bid(2, 5 * USDC_DECIMALS, 4 * SUI_DECIMALS) }).unwrap();
+    assert_eq!(fills[0].maker_fee, 20_000_000);
+    assert_eq!(fills[0].taker_fee, 200_000);
+    let fills = book.match_order(Order { owner: "bob".into(), ..alice_bid(3, 5 * USDC_DECIMALS, 6 * SUI_DECIMALS) }, 0).fills;
+    assert_eq!((fills[0].maker_fee, fills[0].taker_fee), (30_000_000, 300_000));
+    assert_eq!(book.total_fees_collected(), (50_000_000, 500_000));
+
+    // Fees round down to zero below 200 base units and 100 quote units
+    assert_eq!(fee_schedule.maker_fee(199), 0);
+    assert_eq!(fee_schedule.maker_fee(200), 1);
+    assert_eq!(fee_schedule.taker_fee(99), 0);
+    assert_eq!(fee_schedule.taker_fee(100), 1);
+
+    // 1 SUI at $0.000001 is worth one quote unit, too little for a taker fee
+    book.place_order(snapshot_ask(4, 1, SUI_DECIMALS)).unwrap();
+    let fills = book.match_order(alice_bid(5, 1, SUI_DECIMALS), 0).fills;
+    assert_eq!((fills[0].quote_quantity, fills[0].taker_fee), (1, 0));
+    assert_eq!(fills[0].maker_fee, SUI_DECIMALS / 200);
+
+    // Large quantities do not overflow the intermediate product
+    assert_eq!(fee_schedule.maker_fee(u64::MAX), u64::MAX / 200);
+    assert_eq!(BookBuilder::default().build().unwrap().total_fees_collected(), (0, 0));
+}
+
+#[test]
+fn test_cancel_order_result() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, USDC_DECIMALS, 3 * SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(3, 990_000, 3 * SUI_DECIMALS + SUI_DECIMALS / 2) }).unwrap();
+
+    // Bid 1 filled completely, bid 2 has half a SUI left
+    assert!(matches!(book.cancel_order(1, true), CancelResult::AlreadyFilled));
+    let CancelResult::Cancelled(cancelled) = book.cancel_order(2, true) else {
+        panic!("partially filled bid should be cancelled");
+    };
+    assert_eq!(cancelled.remaining_quantity(), SUI_DECIMALS / 2);
+
+    // The taker filled on arrival, and cancelled orders are not reported as filled
+    assert!(matches!(book.cancel_order(3, false), CancelResult::AlreadyFilled));
+    assert!(matches!(book.cancel_order(2, true), CancelResult::NotFound));
+    assert!(matches!(book.cancel_order(99, true), CancelResult::NotFound));
+}
+
+#[test]
+fn test_order_side() {
+    assert_eq!(OrderSide::from(true), Side::Bid);
+    assert_eq!(OrderSide::from(false), Side::Ask);
+
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(2, 1_010_000, SUI_DECIMALS)).unwrap();
+    let ask = Order::new_limit(3, 1_020_000, SUI_DECIMALS, "bob", u64::MAX, Side::Ask).unwrap();
+    assert_eq!(ask.side(), Side::Ask);
+    assert!(!ask.is_bid());
+    book.place_order(ask).unwrap();
+
+    // Sides and bools address the same orders
+    assert_eq!(book.get_all_order_ids_by_side(Side::Ask), book.get_all_order_ids_by_side(false));
+    assert_eq!(book.get_all_order_ids_by_side(Side::Ask), vec![2, 3]);
+    assert_eq!(book.order_status(1, Side::Bid).map(Order::side), Some(Side::Bid));
+    assert!(book.order_status(1, Side::Ask).is_none());
+    assert!(matches!(book.cancel_order(3, Side::Ask), CancelResult::Cancelled(_)));
+    assert_eq!(book.total_resting_quantity(Side::Ask), SUI_DECIMALS);
+    assert_eq!(book.total_resting_quantity(Side::Bid), SUI_DECIMALS);
+}
+
+#[test]
+fn test_get_price_level_detail() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(3, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(1, USDC_DECIMALS, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(4, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, USDC_DECIMALS, 3 * SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(5, 1_010_000, SUI_DECIMALS)).unwrap();
+
+    let level: Vec<u128> = book.get_price_level_detail(USDC_DECIMALS, Side::Bid).iter().map(|order| order.order_id).collect();
+    assert_eq!(level, vec![3, 1, 2]);
+    assert_eq!(book.get_price_level_detail(990_000, Side::Bid).len(), 1);
+    assert!(book.get_price_level_detail(USDC_DECIMALS, Side::Ask).is_empty());
+    assert_eq!(book.get_price_level_detail(1_010_000, Side::Ask)[0].order_id, 5);
+}
+
+#[test]
+fn test_order_queue_position() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, USDC_DECIMALS, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(3, USDC_DECIMALS, 3 * SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(4, 1_010_000, SUI_DECIMALS)).unwrap();
+
+    let position = |book: &Book, order_id| book.order_queue_position(order_id, Side::Bid).unwrap();
+    assert_eq!(
+        position(&book, 3),
+        QueuePosition { position: 2, orders_ahead: 2, quantity_ahead: 3 * SUI_DECIMALS, total_at_level: 3 }
+    );
+    assert_eq!([1, 2, 3].map(|order_id| position(&book, order_id).position), [0, 1, 2]);
+    assert_eq!(position(&book, 4).total_at_level, 1);
+    assert_eq!(book.order_queue_position(1, Side::Ask), None);
+
+    // Partial fills shrink the quantity ahead, and cancels move later orders up
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(5, USDC_DECIMALS, 2 * SUI_DECIMALS) }).unwrap();
+    assert_eq!(position(&book, 3).quantity_ahead, 2 * SUI_DECIMALS);
+    book.cancel_order(2, Side::Bid);
+    assert_eq!(position(&book, 3), QueuePosition { position: 1, orders_ahead: 1, quantity_ahead: SUI_DECIMALS, total_at_level: 2 });
+}
+
+#[test]
+fn test_iceberg_orders() {
+    let mut book = BookBuilder::default().build().unwrap();
+    let iceberg = |order_id| snapshot_ask(order_id, USDC_DECIMALS, 3 * SUI_DECIMALS).with_iceberg_peak(SUI_DECIMALS);
+    let buy = |book: &mut Book, order_id, quantity| -> Vec<(u128, u64)> {
+        let taker = Order { owner: "carol".into(), ..alice_bid(order_id, USDC_DECIMALS, quantity) };
+        book.place_order_sync(taker).unwrap().iter().map(|fill| (fill.maker_order_id, fill.base_quantity)).collect()
+    };
+    let queue = |book: &Book| -> Vec<(u128, u64)> {
+        let level = book.get_price_level_detail(USDC_DECIMALS, Side::Ask);
+        level.iter().map(|order| (order.order_id, order.visible_quantity())).collect()
+    };
+    let zero_peak = snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS).with_iceberg_peak(0);
+    assert_eq!(book.place_order(zero_peak), Err(PlaceError::InvalidIcebergPeak));
+
+    // Only the first of three 1 SUI tranches is shown
+    book.place_order(iceberg(1)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(2, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+    assert_eq!(book.snapshot(1, 0).asks[0].total_quantity, 2 * SUI_DECIMALS);
+    assert_eq!(book.ask_levels().next().unwrap().total_quantity, 2 * SUI_DECIMALS);
+
+    // Each filled tranche shows the next at the back of the queue
+    assert_eq!(buy(&mut book, 10, SUI_DECIMALS), vec![(1, SUI_DECIMALS)]);
+    assert_eq!(queue(&book), vec![(2, SUI_DECIMALS), (1, SUI_DECIMALS)]);
+    assert_eq!(buy(&mut book, 11, 3 * SUI_DECIMALS / 2), vec![(2, SUI_DECIMALS), (1, SUI_DECIMALS / 2)]);
+    assert_eq!(queue(&book), vec![(1, SUI_DECIMALS / 2)]);
+    assert_eq!(buy(&mut book, 12, SUI_DECIMALS / 2), vec![(1, SUI_DECIMALS / 2)]);
+    assert_eq!(queue(&book), vec![(1, SUI_DECIMALS)]);
+    assert_eq!(book.order_status(1, Side::Ask).unwrap().remaining_quantity(), SUI_DECIMALS);
+    assert_eq!(buy(&mut book, 13, SUI_DECIMALS), vec![(1, SUI_DECIMALS)]);
+    assert!(book.order_status(1, Side::Ask).is_none());
+
+    // A large taker cycles through every tranche in one match
+    book.place_order(iceberg(20)).unwrap();
+    assert_eq!(buy(&mut book, 21, 3 * SUI_DECIMALS), vec![(20, SUI_DECIMALS); 3]);
+    assert_eq!(book.order_count(), (0, 0));
+
+    // Each tranche counts against a fill-or-kill taker's fill limit before it matches
+    book.place_order(iceberg(30)).unwrap();
+    let fill_or_kill = |order_id, max_fills| {
+        Order { owner: "carol".into(), ..alice_bid(order_id, USDC_DECIMALS, 3 * SUI_DECIMALS) }
+            .with_order_type(OrderType::FillOrKill)
+            .with_max_fills(max_fills)
+    };
+    assert!(book.place_order_sync(fill_or_kill(31, 2)).unwrap().is_empty());
+    assert_eq!(book.order_status(30, Side::Ask).unwrap().remaining_quantity(), 3 * SUI_DECIMALS);
+    assert_eq!(book.place_order_sync(fill_or_kill(32, 3)).unwrap().len(), 3);
+    assert_eq!(book.order_count(), (0, 0));
+}
+
+#[test]
+fn test_amend_order_priority() {
+    let mut book = BookBuilder::default().tick_size(10_000).build().unwrap();
+    book.place_order(alice_bid(1, USDC_DECIMALS, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(3, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(4, 1_020_000, SUI_DECIMALS)).unwrap();
+
+    // Reducing keeps bid 1 first; increasing sends bid 2 behind bid 3
+    book.amend_order(1, true, None, Some(SUI_DECIMALS)).unwrap();
+    book.amend_order(2, true, None, Some(3 * SUI_DECIMALS)).unwrap();
+    assert_eq!(book.get_all_order_ids_by_side(true), vec![1, 3, 2]);
+    let fills = book.match_order(Order { owner: "bob".into(), ..snapshot_ask(5, USDC_DECIMALS, SUI_DECIMALS) }, 0).fills;
+    assert_eq!(fills[0].maker_order_id, 1);
+    assert_eq!(fills[0].base_quantity, SUI_DECIMALS);
+
+    // Moving bid 3 up a tick makes it the best bid
+    book.amend_order(3, true, Some(1_010_000), None).unwrap();
+    assert_eq!(book.get_all_order_ids_by_side(true), vec![3, 2]);
+
+    assert_eq!(book.amend_order(1, true, None, Some(SUI_DECIMALS)), Err(AmendError::NotFound));
+    assert_eq!(book.amend_order(3, false, None, None), Err(AmendError::NotFound));
+    assert_eq!(book.amend_order(3, true, Some(1_015_000), None), Err(AmendError::InvalidPrice));
+    assert_eq!(book.amend_order(3, true, Some(1_020_000), None), Err(AmendError::InvalidPrice));
+    assert_eq!(book.amend_order(3, true, None, Some(0)), Err(AmendError::QuantityBelowMinimum));
+
+    book.match_order(Order { owner: "bob".into(), ..snapshot_ask(6, 1_010_000, SUI_DECIMALS / 2) }, 0);
+    assert_eq!(
+        book.amend_order(3, true, None, Some(SUI_DECIMALS / 2)),
+        Err(AmendError::QuantityBelowFilled { submitted: SUI_DECIMALS / 2, filled_quantity: SUI_DECIMALS / 2 })
+    );
+}
+
+#[test]
+fn test_conditional_place() {
+    let mut book = BookBuilder::default().tick_size(10_000).build().unwrap();
+    book.place_order(alice_bid(1, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(2, 1_010_000, SUI_DECIMALS) }).unwrap();
+
+    // carol only wants to bid $1 once someone offers at or below it
+    let carol_bid = Order { owner: "carol".into(), ..alice_bid(10, USDC_DECIMALS, SUI_DECIMALS) };
+    let condition = PlaceCondition::IfBestAskAtOrBelow(USDC_DECIMALS);
+    assert!(matches!(book.conditional_place(carol_bid, condition, 0), ConditionalResult::ConditionNotMet));
+    assert_eq!(book.order_status(10, Side::Bid).map(|order| order.order_id), None);
+    assert!(matches!(
+        book.conditional_place(alice_bid(11, 995_000, SUI_DECIMALS), PlaceCondition::Always, 0),
+        ConditionalResult::Rejected(PlaceError::PriceNotTickAligned)
+    ));
+
+    // Orders that leave the best ask alone do not fire it
+    book.place_order(alice_bid(3, 980_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(4, 1_020_000, SUI_DECIMALS) }).unwrap();
+    assert_eq!(book.conditional_orders().len(), 1);
+
+    // dave's sell sweeps the bid and rests at $0.98, which carol's bid then lifts
+    let dave_ask = Order { owner: "dave".into(), ..snapshot_ask(5, 980_000, 3 * SUI_DECIMALS) };
+    let fills: Vec<(u128, u128, u64)> = book
+        .place_order_sync(dave_ask)
+        .unwrap()
+        .iter()
+        .map(|fill| (fill.maker_order_id, fill.taker_order_id, fill.price))
+        .collect();
+    assert_eq!(fills, vec![(1, 5, 990_000), (3, 5, 980_000), (5, 10, 980_000)]);
+    assert!(book.conditional_orders().is_empty());
+    assert_eq!(book.best_ask(), Some(1_010_000));
+
+    // A spread condition needs both sides quoted
+    let condition = PlaceCondition::IfSpreadAtOrBelow(30_000);
+    let result = book.conditional_place(alice_bid(12, 1_010_000, SUI_DECIMALS), condition, 0);
+    assert!(matches!(result, ConditionalResult::ConditionNotMet));
+    let fills = book.place_order_sync(alice_bid(13, 980_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(fills.iter().map(|fill| (fill.maker_order_id, fill.taker_order_id)).collect::<Vec<_>>(), vec![(2, 12)]);
+}
+
+#[test]
+fn test_stop_orders_cascade() {
+    let mut book = BookBuilder::default().build().unwrap();
+    for (order_id, price) in [(1, USDC_DECIMALS), (2, 950_000), (3, 900_000)] {
+        book.place_order(alice_bid(order_id, price, SUI_DECIMALS)).unwrap();
+    }
+    assert_eq!(book.last_trade_price(), None);
+
+    // A sell stop at $0.99, a sell stop-limit at $0.95 limited to $0.90 and a buy stop at $1.10
+    let stop = |order_id, is_bid: bool, order_type| Order { owner: "carol".into(), side: is_bid.into(), order_type, ..alice_bid(order_id, 1, SUI_DECIMALS) };
+    book.place_order(stop(10, false, OrderType::Stop { trigger_price: 990_000 })).unwrap();
+    book.place_order(stop(11, false, OrderType::StopLimit { trigger_price: 950_000, limit_price: 900_000 })).unwrap();
+    book.place_order(stop(12, true, OrderType::Stop { trigger_price: 1_100_000 })).unwrap();
+
+    // Trading at $1.00 triggers nothing
+    let fills = book.place_order_sync(Order { owner: "bob".into(), ..snapshot_ask(20, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+    assert_eq!(fills.len(), 1);
+    assert_eq!(book.stop_orders().len(), 3);
+
+    // $0.95 triggers the stop, whose fill at $0.90 triggers the stop-limit
+    let fills = book.place_order_sync(Order { owner: "bob".into(), ..snapshot_ask(21, 950_000, SUI_DECIMALS / 2) }).unwrap();
+    let trades: Vec<(u128, u64, u64)> = fills.iter().map(|fill| (fill.taker_order_id, fill.price, fill.base_quantity)).collect();
+    assert_eq!(trades, vec![
+        (21, 950_000, SUI_DECIMALS / 2),
+        (10, 950_000, SUI_DECIMALS / 2),
+        (10, 900_000, SUI_DECIMALS / 2),
+        (11, 900_000, SUI_DECIMALS / 2),
+    ]);
+    assert_eq!(book.last_trade_price(), Some(900_000));
+
+    // The stop-limit remainder rests at its limit and the buy stop stays dormant
+    let resting: Vec<(u128, u64)> = book.iter_orders(false).map(|order| (order.order_id, order.remaining_quantity())).collect();
+    assert_eq!(resting, vec![(11, SUI_DECIMALS / 2)]);
+    assert_eq!(book.iter_orders(true).count(), 0);
+    let dormant: Vec<u128> = book.stop_orders().iter().map(|order| order.order_id).collect();
+    assert_eq!(dormant, vec![12]);
+}
+
+#[test]
+fn test_snapshot_aggregates_levels() {
+    let mut book = BookBuilder::default().build().unwrap();
+    let bids = [(1, 990_000, 1), (2, 990_000, 2), (3, 980_000, 3), (4, 970_000, 4), (5, 970_000, 5)];
+    let asks = [(6, 1_010_000, 1), (7, 1_020_000, 2), (8, 1_020_000, 3), (9, 1_020_000, 4), (10, 1_030_000, 5)];
+    for (order_id, price, quantity) in bids {
+        book.place_order(alice_bid(order_id, price, quantity * SUI_DECIMALS)).unwrap();
+    }
+    for (order_id, price, quantity) in asks {
+        book.place_order(snapshot_ask(order_id, price, quantity * SUI_DECIMALS)).unwrap();
+    }
+
+    let snapshot = book.snapshot(10, 42);
+    let summary = |levels: &[PriceLevel]| {
+        levels.iter().map(|level| (level.price, level.total_quantity / SUI_DECIMALS, level.order_count)).collect::<Vec<_>>()
+    };
+    assert_eq!(summary(&snapshot.bids), vec![(990_000, 3, 2), (980_000, 3, 1), (970_000, 9, 2)]);
+    assert_eq!(summary(&snapshot.asks), vec![(1_010_000, 1, 1), (1_020_000, 9, 3), (1_030_000, 5, 1)]);
+    assert_eq!(snapshot.timestamp, 42);
+    let queue: Vec<u128> = snapshot.asks[1].orders.iter().map(|order| order.order_id).collect();
+    assert_eq!(queue, vec![7, 8, 9]);
+
+    // Depth limits the levels returned per side
+    let snapshot = book.snapshot(2, 42);
+    assert_eq!(summary(&snapshot.bids), vec![(990_000, 3, 2), (980_000, 3, 1)]);
+    assert_eq!(summary(&snapshot.asks), vec![(1_010_000, 1, 1), (1_020_000, 9, 3)]);
+    assert!(book.snapshot(0, 42).bids.is_empty());
+
+    // A full snapshot restores the same book
+    let mut restored = BookBuilder::default().build().unwrap();
+    restored.warm_up_with_snapshot(book.snapshot(usize::MAX, 42), true).unwrap();
+    assert_eq!(restored.get_all_order_ids_by_side(true), book.get_all_order_ids_by_side(true));
+    assert_eq!(restored.get_all_order_ids_by_side(false), book.get_all_order_ids_by_side(false));
+}
+
+#[test]
+fn test_best_prices_and_spread() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!((book.best_bid(), book.best_ask(), book.spread(), book.mid_price()), (None, None, None, None));
+
+    // One-sided
+    book.place_order(alice_bid(1, 999_999, SUI_DECIMALS)).unwrap();
+    assert_eq!((book.best_bid(), book.best_ask(), book.spread(), book.mid_price()), (Some(999_999), None, None, None));
+
+    // The midpoint of $0.999999 and $1.01 rounds down
+    book.place_order(snapshot_ask(2, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(3, 1_020_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.best_ask(), Some(1_010_000));
+    assert_eq!(book.spread(), Some(10_001));
+    assert_eq!(book.mid_price(), Some(1_004_999));
+
+    // Stale orders loaded without validation can leave the book crossed
+    let mut crossed = BookBuilder::default().build().unwrap();
+    crossed
+        .warm_up_with_snapshot(BookSnapshot {
+            bids: vec![snapshot_level(1_020_000, vec![alice_bid(1, 1_020_000, SUI_DECIMALS)])],
+            asks: vec![snapshot_level(USDC_DECIMALS, vec![snapshot_ask(2, USDC_DECIMALS, SUI_DECIMALS)])],
+            timestamp: 0,
+        }, false)
+        .unwrap();
+    assert_eq!((crossed.best_bid(), crossed.best_ask()), (Some(1_020_000), Some(USDC_DECIMALS)));
+    assert_eq!(crossed.spread(), None);
+    assert_eq!(crossed.mid_price(), Some(1_010_000));
+
+    // Prices near u64::MAX do not overflow the midpoint
+    let mut wide = BookBuilder::default().build().unwrap();
+    wide.place_order(alice_bid(1, u64::MAX - 2, 1)).unwrap();
+    wide.place_order(snapshot_ask(2, u64::MAX, 1)).unwrap();
+    assert_eq!(wide.mid_price(), Some(u64::MAX - 1));
+}
+
+#[test]
+fn test_vwap_over_recent_fills() {
+    let mut book = Book::new_with_params(MarketParams { max_fill_history: 2, ..Default::default() });
+    assert_eq!(book.vwap(10), None);
+
+    // Fills of 10.86 SUI at $1.234, 3.33 SUI at $1.235 and then 1 SUI at $1.30
+    let trades = [(1_234_000, 10_860_000_000), (1_235_000, 3_330_000_000), (1_300_000, SUI_DECIMALS)];
+    for (i, (price, quantity)) in trades.into_iter().enumerate() {
+        let order_id = 2 * i as u128;
+        book.place_order(Order { owner: "bob".into(), ..snapshot_ask(order_id + 1, price, quantity) }).unwrap();
+        book.place_order(alice_bid(order_id + 2, price, quantity)).unwrap();
+
+        if i == 1 {
+            assert_eq!(book.vwap(1), Some(1_235_000));
+            // 17_513_790 quote units over 14.19 SUI truncates $1.2342346 to $1.234234
+            assert_eq!(book.vwap(2), Some(1_234_234));
+            assert_eq!(book.vwap(100), Some(1_234_234));
+        }
+    }
+
+    // Only the last two fills are retained
+    assert_eq!(book.vwap(3), Some((1_235_000 * 3_330_000_000 + 1_300_000 * SUI_DECIMALS) / 4_330_000_000));
+    assert_eq!(book.vwap(0), None);
+}
+
+#[test]
+fn test_order_status_and_owner_orders() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, 990_000, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(2, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(3, 1_020_000, 3 * SUI_DECIMALS) }).unwrap();
+
+    let alice_orders: Vec<u128> = book.active_orders_for_owner("alice".into()).map(|order| order.order_id).collect();
+    assert_eq!(alice_orders, vec![1, 2]);
+    assert_eq!(book.active_orders_for_owner("carol".into()).count(), 0);
+
+    // Bob partially fills alice's bid
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(4, 990_000, SUI_DECIMALS / 2) }).unwrap();
+    let bid = book.order_status(1, true).unwrap();
+    assert_eq!((bid.filled_quantity, bid.remaining_quantity()), (SUI_DECIMALS / 2, 3 * SUI_DECIMALS / 2));
+    assert_eq!(bid.expire_timestamp, u64::MAX);
+    assert!(book.order_status(1, false).is_none());
+
+    // Carol lifts alice's ask entirely, removing it from the book
+    book.place_order(Order { owner: "carol".into(), ..alice_bid(5, 1_010_000, SUI_DECIMALS) }).unwrap();
+    assert!(book.order_status(2, false).is_none());
+    assert_eq!(book.active_orders_for_owner("alice".into()).map(|order| order.order_id).collect::<Vec<_>>(), vec![1]);
+    assert!(book.order_status(99, true).is_none());
+}
+
+#[test]
+fn test_address_round_trip() {
+    let alice = Address::from("alice");
+    assert_eq!(alice.to_string(), "alice");
+    assert_eq!(Address::from(alice.to_string().as_str()), alice);
+    assert_eq!(&alice.0[..6], b"alice\0");
+
+    // Names longer than 32 bytes are truncated
+    let long_name = "a".repeat(40);
+    assert_eq!(Address::from(long_name.as_str()).to_string(), "a".repeat(32));
+
+    let hex = "0x7a3b9f0c2d4e6f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f7a8";
+    let address = Address::from_hex(hex).unwrap();
+    assert_eq!(address.to_hex(), hex);
+    assert_eq!(address.to_string(), hex);
+    assert_eq!(Address::from_hex(&address.to_hex()), Ok(address));
+    assert_eq!(Address::from_hex(&hex[2..]), Ok(address));
+
+    // Short SUI addresses are left-padded
+    let framework = Address::from_hex("0x2").unwrap();
+    assert_eq!(framework.0[31], 2);
+    assert!(framework.0[..31].iter().all(|&byte| byte == 0));
+
+    assert_eq!(Address::from_hex("0x"), Err(AddressError::InvalidLength(0)));
+    assert_eq!(Address::from_hex(&format!("0x{}", "1".repeat(65))), Err(AddressError::InvalidLength(65)));
+    assert_eq!(Address::from_hex("0x12g4"), Err(AddressError::InvalidHexDigit('g')));
+}
+
+#[test]
+fn test_priority_ignores_order_ids() {
+    let mut book = BookBuilder::default().build().unwrap();
+    // IDs run against arrival order on both sides, and the sides interleave
+    book.place_order(alice_bid(90, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(80, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(50, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(40, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(10, 1_000_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(5, 1_020_000, SUI_DECIMALS)).unwrap();
+
+    assert_eq!(book.get_all_order_ids_by_side(true), vec![10, 90, 50]);
+    assert_eq!(book.get_all_order_ids_by_side(false), vec![80, 40, 5]);
+
+    let sell = Order { owner: "bob".into(), ..snapshot_ask(100, 990_000, 2 * SUI_DECIMALS) };
+    let makers: Vec<u128> = book.place_order_sync(sell).unwrap().iter().map(|fill| fill.maker_order_id).collect();
+    assert_eq!(makers, vec![10, 90]);
+}
+
+#[test]
+fn test_market_order_constructor() {
+    let mut book = BookBuilder::default().build().unwrap();
+    let no_liquidity = PlaceError::Book(BookError::NoLiquidity);
+    assert_eq!(book.place_order(Order::new_market(1, SUI_DECIMALS, "bob", true).unwrap()).unwrap_err(), no_liquidity);
+    assert_eq!(book.place_order(Order::new_market(2, SUI_DECIMALS, "bob", false).unwrap()).unwrap_err(), no_liquidity);
+    assert!(matches!(Order::new_market(2, 0, "bob", false), Err(OrderError::QuantityBelowMinimum)));
+
+    book.place_order(alice_bid(3, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(4, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(5, 1_500_000, SUI_DECIMALS)).unwrap();
+
+    // A market buy sweeps every ask level and drops what it cannot fill
+    let buy = Order::new_market(6, 3 * SUI_DECIMALS, "bob", true).unwrap();
+    assert_eq!((buy.price(), buy.expire_timestamp(), buy.order_type()), (u64::MAX, 0, OrderType::Market));
+    let fills = book.place_order_sync(buy).unwrap();
+    assert_eq!(fills.iter().map(|fill| fill.price).collect::<Vec<_>>(), vec![1_010_000, 1_500_000]);
+    assert!(fills.iter().all(|fill| !fill.price_improved && fill.taker_owner == "bob".into()));
+    assert_eq!(book.best_ask(), None);
+    assert!(book.order_status(6, true).is_none());
+
+    let sell = Order::new_market(7, SUI_DECIMALS, "bob", false).unwrap();
+    assert_eq!(sell.price(), 0);
+    let fills = book.place_order_sync(sell).unwrap();
+    assert_eq!((fills.len(), fills[0].price), (1, 990_000));
+    assert_eq!(book.place_order(Order::new_market(8, SUI_DECIMALS, "bob", false).unwrap()).unwrap_err(), no_liquidity);
+}
+
+/// Matches a 1 SUI alice bid at $1.01 with the given STP mode against alice's own ask
+/// at $1.00 resting ahead of bob's ask at $1.01
+fn self_trade(stp_mode: STPMode) -> (MatchResult, Book) {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(2, 1_010_000, SUI_DECIMALS) }).unwrap();
+    let result = book.match_order(Order { stp_mode, ..alice_bid(3, 1_010_000, SUI_DECIMALS) }, 0);
+    (result, book)
+}
+
+#[test]
+fn test_self_trade_prevention_modes() {
+    let ids = |orders: &[Order]| orders.iter().map(|order| order.order_id).collect::<Vec<_>>();
+
+    let (result, _) = self_trade(STPMode::None);
+    assert_eq!((result.fills.len(), result.fills[0].maker_order_id), (1, 1));
+    assert!(result.stp_cancelled.is_empty());
+
+    // Alice's own level is skipped and the taker fills against bob's order behind it
+    let (result, book) = self_trade(STPMode::CancelMaker);
+    assert_eq!(ids(&result.stp_cancelled), vec![1]);
+    assert_eq!(result.stp_cancelled[0].cancellation_timestamp, Some(0));
+    assert_eq!((result.fills.len(), result.fills[0].maker_order_id, result.fills[0].price), (1, 2, 1_010_000));
+    assert!(book.order_status(1, false).is_none());
+
+    let (result, book) = self_trade(STPMode::CancelTaker);
+    assert!(result.fills.is_empty());
+    assert_eq!(ids(&result.stp_cancelled), vec![3]);
+    assert_eq!(book.get_all_order_ids_by_side(false), vec![1, 2]);
+
+    let (result, book) = self_trade(STPMode::CancelBoth);
+    assert!(result.fills.is_empty());
+    assert_eq!(ids(&result.stp_cancelled), vec![1, 3]);
+    assert_eq!(book.get_all_order_ids_by_side(false), vec![2]);
+
+    // A cancelled taker does not rest its remainder
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(snapshot_ask(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    let taker = Order { stp_mode: STPMode::CancelTaker, ..alice_bid(2, USDC_DECIMALS, SUI_DECIMALS) };
+    assert!(book.place_order_sync(taker).unwrap().is_empty());
+    assert!(book.order_status(2, true).is_none());
+    assert!(book.order_status(1, false).is_some());
+}
+
+#[test]
+fn test_book_builder() {
+    assert!(matches!(BookBuilder::default().tick_size(0).build(), Err(BuilderError::ZeroTickSize)));
+    assert!(matches!(BookBuilder::default().lot_size(0).build(), Err(BuilderError::ZeroLotSize)));
+    assert!(matches!(BookBuilder::default().min_size(0).build(), Err(BuilderError::ZeroMinSize)));
+    assert!(matches!(BookBuilder::default().min_qty_increment(0).build(), Err(BuilderError::ZeroQtyIncrement)));
+    assert!(matches!(BookBuilder::default().max_fills(0).build(), Err(BuilderError::ZeroMaxFills)));
+
+    let mut book = BookBuilder::default()
+        .tick_size(1_000)
+        .lot_size(SUI_DECIMALS / 10)
+        .min_size(SUI_DECIMALS)
+        .stp_mode(STPMode::CancelMaker)
+        .build()
+        .unwrap();
+    let result = book.place_order(alice_bid(1, 1_000_500, SUI_DECIMALS));
+    assert!(matches!(result, Err(PlaceError::PriceNotTickAligned)));
+    let result = book.place_order(alice_bid(2, USDC_DECIMALS, SUI_DECIMALS / 2));
+    assert!(matches!(result, Err(PlaceError::QuantityBelowMinimum)));
+
+    // Orders without their own mode take the book's self-trade prevention
+    book.place_order(snapshot_ask(3, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert!(book.place_order_sync(alice_bid(4, USDC_DECIMALS, SUI_DECIMALS)).unwrap().is_empty());
+    assert!(book.order_status(3, false).is_none());
+    assert_eq!(book.order_status(4, true).unwrap().stp_mode(), STPMode::CancelMaker);
+}
+
+#[test]
+fn test_total_resting_value() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!((book.total_resting_quantity(false), book.total_resting_value(false), book.order_count()), (0, 0, (0, 0)));
+
+    // 10.86 SUI at $1.234 and 1 SUI at $1.20 on the bid
+    book.place_order(snapshot_ask(1, 1_234_000, 10_860_000_000)).unwrap();
+    book.place_order(alice_bid(2, 1_200_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.order_count(), (1, 1));
+    assert_eq!(book.total_resting_quantity(false), 10_860_000_000);
+    assert_eq!(book.total_resting_value(false), 10_860_000_000 * 1_234_000);
+    assert_eq!(book.total_resting_value(false) / FLOAT_SCALING as u128, 13_401_240);
+    assert_eq!(book.total_resting_value(true), SUI_DECIMALS as u128 * 1_200_000);
+
+    // A 3.33 SUI partial fill leaves 7.53 SUI
+    book.place_order(Order { owner: "bob".into(), ..alice_bid(3, 1_234_000, 3_330_000_000) }).unwrap();
+    assert_eq!(book.total_resting_quantity(false), 7_530_000_000);
+    assert_eq!(book.total_resting_value(false), 7_530_000_000 * 1_234_000);
+    assert_eq!(book.total_resting_value(false) / FLOAT_SCALING as u128, 9_292_020);
+
+    assert!(matches!(book.cancel_order(1, false), CancelResult::Cancelled(_)));
+    assert_eq!((book.total_resting_quantity(false), book.total_resting_value(false)), (0, 0));
+    assert_eq!(book.order_count(), (1, 0));
+
+    // Values beyond u64 do not overflow
+    book.place_order(alice_bid(4, u64::MAX, u64::MAX)).unwrap();
+    assert_eq!(book.total_resting_value(true), SUI_DECIMALS as u128 * 1_200_000 + u64::MAX as u128 * u64::MAX as u128);
+}
+
+#[test]
+fn test_purge_expired() {
+    let mut book = BookBuilder::default().build().unwrap().with_clock(MockClock::new(0));
+    book.place_order(alice_bid(1, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { expire_timestamp: 100, ..alice_bid(2, 980_000, SUI_DECIMALS) }).unwrap();
+    book.place_order(snapshot_ask(3, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { expire_timestamp: 200, ..snapshot_ask(4, 1_020_000, SUI_DECIMALS) }).unwrap();
+    book.place_order(Order { expire_timestamp: 300, ..snapshot_ask(5, 1_030_000, SUI_DECIMALS) }).unwrap();
+
+    assert!(book.purge_expired(100).is_empty());
+
+    // Deep orders are removed even though matching never reached them
+    let purged: Vec<u128> = book.purge_expired(201).iter().map(|order| order.order_id).collect();
+    assert_eq!(purged, vec![2, 4]);
+    assert_eq!(book.get_all_order_ids_by_side(true), vec![1]);
+    assert_eq!(book.get_all_order_ids_by_side(false), vec![3, 5]);
+    assert!(matches!(book.cancel_order(4, false), CancelResult::NotFound));
+    assert!(book.purge_expired(201).is_empty());
+}
+
+#[test]
+fn test_unfilled_orders() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!(book.unfilled_orders().count(), 0);
+    book.place_order(alice_bid(1, 980_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(3, 1_020_000, SUI_DECIMALS)).unwrap();
+    book.place_order(snapshot_ask(4, 1_010_000, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..alice_bid(5, 1_010_000, SUI_DECIMALS) }).unwrap();
+
+    let (bids, asks) = book.order_count();
+    assert_eq!(book.unfilled_orders().count(), bids + asks);
+    assert!(book.unfilled_orders().all(|order| !order.is_filled()));
+    assert_eq!(book.unfilled_order_ids().collect::<Vec<_>>(), vec![2, 1, 4, 3]);
+    assert_eq!(book.unfilled_orders().find(|order| order.order_id == 4).unwrap().filled_quantity(), SUI_DECIMALS);
+}
+
+#[test]
+fn test_price_ladder() {
+    let mut book = BookBuilder::default().tick_size(1_000).build().unwrap();
+    book.place_order(Order { owner: "bob".into(), ..alice_bid(7, 900_000, SUI_DECIMALS) }).unwrap();
+
+    let ids = book.price_ladder("mm".into(), USDC_DECIMALS, 100, 3, SUI_DECIMALS, u64::MAX).unwrap();
+    assert_eq!(ids, vec![8, 9, 10, 11, 12, 13]);
+    assert_eq!(book.orders_for_owner("mm".into()).count(), 6);
+
+    // 50 bps either side of the mid, then one tick further out per level
+    let snapshot = book.snapshot(3, 0);
+    let prices = |levels: &[PriceLevel]| levels.iter().map(|level| level.price).collect::<Vec<_>>();
+    assert_eq!(prices(&snapshot.bids), vec![995_000, 994_000, 993_000]);
+    assert_eq!(prices(&snapshot.asks), vec![1_005_000, 1_006_000, 1_007_000]);
+    for (bid, ask) in snapshot.bids.iter().zip(&snapshot.asks) {
+        assert_eq!(USDC_DECIMALS - bid.price, ask.price - USDC_DECIMALS);
+        assert_eq!(bid.total_quantity, ask.total_quantity);
+    }
+
+    // A ladder reaching below one tick is rejected before anything is placed
+    let result = book.price_ladder("mm".into(), USDC_DECIMALS, 100, 996, SUI_DECIMALS, u64::MAX);
+    assert!(matches!(result, Err(PlaceError::PriceBelowMinimum)));
+    assert_eq!(book.order_count(), (4, 3));
+
+    // Order construction errors keep their cause
+    let result = book.price_ladder("mm".into(), USDC_DECIMALS, 100, 1, SUI_DECIMALS, 0);
+    assert_eq!(result, Err(PlaceError::Order(OrderError::ZeroExpiry)));
+    assert_eq!(book.order_count(), (4, 3));
+
+    // IDs of filled, held stop and held conditional orders are not reused
+    let mut book = BookBuilder::default().tick_size(1_000).build().unwrap();
+    book.place_order(snapshot_ask(20, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..alice_bid(21, USDC_DECIMALS, SUI_DECIMALS) }).unwrap();
+    let stop = alice_bid(22, 1_100_000, SUI_DECIMALS).with_order_type(OrderType::Stop { trigger_price: 1_100_000 });
+    book.place_order(stop).unwrap();
+    let held = alice_bid(23, 900_000, SUI_DECIMALS);
+    let result = book.conditional_place(held, PlaceCondition::IfBestAskAtOrBelow(900_000), 0);
+    assert!(matches!(result, ConditionalResult::ConditionNotMet));
+    assert_eq!(book.order_count(), (0, 0));
+    let ids = book.price_ladder("mm".into(), USDC_DECIMALS, 100, 1, SUI_DECIMALS, u64::MAX).unwrap();
+    assert_eq!(ids, vec![24, 25]);
+}
+
+#[test]
+fn test_cancel_all_for_owner() {
+    let mut book = BookBuilder::default().build().unwrap().with_clock(MockClock::new(0));
+    book.place_order(alice_bid(1, 980_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(2, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..alice_bid(3, 990_000, SUI_DECIMALS) }).unwrap();
+    book.place_order(snapshot_ask(4, 1_010_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(5, 1_020_000, SUI_DECIMALS) }).unwrap();
+
+    let cancelled: Vec<u128> = book.cancel_all_for_owner("alice".into()).iter().map(|order| order.order_id).collect();
+    assert_eq!(cancelled, vec![2, 1, 4]);
+    for order_id in cancelled {
+        assert!(book.order_status(order_id, true).is_none() && book.order_status(order_id, false).is_none());
+    }
+    assert_eq!(book.get_all_order_ids_by_side(true), vec![3]);
+    assert_eq!(book.get_all_order_ids_by_side(false), vec![5]);
+    assert!(book.cancel_all_for_owner("alice".into()).is_empty());
+
+    book.place_order(Order { expire_timestamp: 100, ..alice_bid(6, 900_000, SUI_DECIMALS) }).unwrap();
+    let expired: Vec<u128> = book.cancel_all_expired(101).iter().map(|order| order.order_id).collect();
+    assert_eq!(expired, vec![6]);
+}
+
+#[test]
+fn test_orders_for_owner() {
+    let mut book = BookBuilder::default().build().unwrap().with_clock(MockClock::new(0));
+    let ids = |book: &Book, owner: &str| book.orders_for_owner(owner.into()).map(|order| order.order_id).collect::<Vec<_>>();
+    book.place_order(alice_bid(1, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { expire_timestamp: 100, ..alice_bid(2, 980_000, SUI_DECIMALS) }).unwrap();
+    book.place_order(snapshot_ask(3, 1_010_000, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(4, 1_020_000, SUI_DECIMALS) }).unwrap();
+    assert_eq!(ids(&book, "alice"), vec![3, 1, 2]);
+    assert!(ids(&book, "carol").is_empty());
+
+    // Partially filled orders stay indexed; filled, cancelled and expired ones do not
+    book.place_order(Order { owner: "carol".into(), ..alice_bid(5, 1_010_000, SUI_DECIMALS) }).unwrap();
+    assert_eq!(book.orders_for_owner("alice".into()).next().unwrap().remaining_quantity(), SUI_DECIMALS);
+    book.place_order(Order { owner: "carol".into(), ..alice_bid(6, 1_020_000, 3 * SUI_DECIMALS) }).unwrap();
+    assert_eq!(ids(&book, "alice"), vec![1, 2]);
+    assert!(ids(&book, "bob").is_empty());
+    book.cancel_order(1, true);
+    book.purge_expired(101);
+    assert!(ids(&book, "alice").is_empty());
+    assert_eq!(ids(&book, "carol"), vec![6]);
+}
+
+#[test]
+fn test_place_order_purges_expired_beyond_threshold() {
+    let clock = MockClock::new(0);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone()).with_gc_threshold(2);
+    for order_id in 1..=2 {
+        book.place_order(Order { expire_timestamp: 10, ..alice_bid(order_id, 900_000, SUI_DECIMALS) }).unwrap();
+    }
+
+    // Two expired orders are tolerated
+    clock.advance(20);
+    book.place_order(alice_bid(3, 990_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.order_count(), (3, 0));
+
+    // A third crosses the threshold and the next placement purges all three
+    book.place_order(Order { expire_timestamp: 30, ..alice_bid(4, 900_000, SUI_DECIMALS) }).unwrap();
+    clock.advance(20);
+    book.place_order(snapshot_ask(5, 1_010_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.get_all_order_ids_by_side(true), vec![3]);
+
+    // Orders that left the book some other way do not count toward the threshold
+    for order_id in 6..=8 {
+        book.place_order(Order { expire_timestamp: 50, ..alice_bid(order_id, 900_000, SUI_DECIMALS) }).unwrap();
+    }
+    assert!(matches!(book.cancel_order(6, true), CancelResult::Cancelled(_)));
+    clock.advance(20);
+    book.place_order(snapshot_ask(9, 1_020_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.get_all_order_ids_by_side(true), vec![3, 7, 8]);
+}
+
+/// Resting orders on both sides in priority order, for comparing books
+#[cfg(feature = "serde")]
+fn resting_orders(book: &Book) -> String {
+    format!("{:?} {:?}", book.clone_side(true), book.clone_side(false))
+}
+
+#[cfg(feature = "serde")]
+proptest::proptest! {
+    #![proptest_config(proptest::prelude::ProptestConfig::with_cases(8))]
+
+    #[test]
+    fn test_serde_round_trip(
+        orders in proptest::collection::vec((proptest::bool::ANY, 900u64..1_100, 1u64..10, 0u8..3), 1_000),
+    ) {
+        let mut book = BookBuilder::default().build().unwrap().with_clock(MockClock::new(0));
+        for (i, (is_bid, price, quantity, owner)) in orders.into_iter().enumerate() {
+            let order = Order {
+                owner: ["alice", "bob", "carol"][owner as usize].into(),
+                side: is_bid.into(),
+                ..alice_bid(i as u128, price * 1_000, quantity * SUI_DECIMALS)
+            };
+            book.place_order(order).unwrap();
+        }
+
+        let from_json = Book::from_json(&book.to_json().unwrap()).unwrap();
+        let from_bytes = Book::from_bytes(&book.to_bytes().unwrap()).unwrap();
+        proptest::prop_assert_eq!(resting_orders(&from_json), resting_orders(&book));
+        proptest::prop_assert_eq!(resting_orders(&from_bytes), resting_orders(&book));
+        proptest::prop_assert_eq!(from_bytes.order_count(), book.order_count());
+
+        // Restored keys keep matching in the same priority
+        let sweep = |mut book: Book| {
+            let taker = Order { owner: "dave".into(), ..alice_bid(10_000, 2 * USDC_DECIMALS, 50 * SUI_DECIMALS) };
+            let fills = book.match_order(taker, 0).fills;
+            fills.iter().map(|fill| (fill.maker_order_id, fill.base_quantity)).collect::<Vec<_>>()
+        };
+        let expected = sweep(book);
+        proptest::prop_assert_eq!(sweep(from_json), expected.clone());
+        proptest::prop_assert_eq!(sweep(from_bytes), expected);
+    }
+}
+
+/// An operation in a randomised matching session
+#[derive(Debug, Clone)]
+enum FuzzOp {
+    Place { is_bid: bool, price: u64, quantity: u64 },
+    Cancel { index: usize },
+}
+
+fn fuzz_op() -> impl proptest::strategy::Strategy<Value = FuzzOp> {
+    use proptest::prelude::*;
+    prop_oneof![
+        3 => (any::<bool>(), 950u64..1_050, 1u64..10)
+            .prop_map(|(is_bid, price, quantity)| FuzzOp::Place { is_bid, price, quantity }),
+        1 => any::<usize>().prop_map(|index| FuzzOp::Cancel { index }),
+    ]
+}
+
+proptest::proptest! {
+    #![proptest_config(proptest::prelude::ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_match_order_invariants(ops in proptest::collection::vec(fuzz_op(), 1..200)) {
+        let mut book = BookBuilder::default().build().unwrap().with_clock(MockClock::new(0));
+        let mut placed: Vec<(u128, bool)> = Vec::new();
+        let (mut placed_base, mut filled_base, mut cancelled_base) = (0u128, 0u128, 0u128);
+
+        for op in ops {
+            match op {
+                FuzzOp::Place { is_bid, price, quantity } => {
+                    // A distinct owner per order keeps self-trade prevention out of the picture
+                    let order_id = placed.len() as u128;
+                    let order = Order {
+                        owner: format!("trader{}", order_id).as_str().into(),
+                        side: is_bid.into(),
+                        ..alice_bid(order_id, price * 1_000, quantity * SUI_DECIMALS)
+                    };
+                    placed_base += order.quantity() as u128;
+                    placed.push((order_id, is_bid));
+                    for fill in book.place_order_sync(order).unwrap() {
+                        proptest::prop_assert_eq!(fill.quote_quantity, fill.base_quantity as u128 * fill.price as u128 / FLOAT_SCALING as u128);
+                        filled_base += fill.base_quantity as u128;
+                    }
+                }
+                FuzzOp::Cancel { index } => {
+                    if placed.is_empty() {
+                        continue;
+                    }
+                    let (order_id, is_bid) = placed[index % placed.len()];
+                    if let CancelResult::Cancelled(order) = book.cancel_order(order_id, is_bid) {
+                        cancelled_base += order.remaining_quantity() as u128;
+                    }
+                }
+            }
+
+            // The resting book never crosses
+            if let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask()) {
+                proptest::prop_assert!(bid < ask, "crossed book: bid {} >= ask {}", bid, ask);
+            }
+
+            // Every fill consumes its base quantity from both the maker and the taker
+            let mut resting_base = 0u128;
+            for order in book.iter_orders(true).chain(book.iter_orders(false)) {
+                proptest::prop_assert!(order.filled_quantity() <= order.quantity());
+                resting_base += order.remaining_quantity() as u128;
+            }
+            proptest::prop_assert_eq!(resting_base, placed_base - 2 * filled_base - cancelled_base);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_addresses_and_transient_state() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, 990_000, SUI_DECIMALS)).unwrap();
+    let json = book.to_json().unwrap();
+    assert!(json.contains(&Address::from("alice").to_hex()));
+
+    // Level subscriptions start afresh on a restored book
+    #[cfg(feature = "tokio")]
+    {
+        let mut restored = Book::from_json(&json).unwrap();
+        let mut events = restored.subscribe_to_price_level_changes();
+        restored.place_order(alice_bid(2, 990_000, SUI_DECIMALS)).unwrap();
+        assert_eq!(events.try_recv().unwrap().new_total_qty, 2 * SUI_DECIMALS);
+    }
+
+    assert!(Book::from_json("{").is_err());
+    assert!(Book::from_bytes(&[1, 2, 3]).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_event_stream_catch_up() {
+    use crate::book::SequencedEvent;
+
+    let clock = MockClock::new(0);
+    let mut publisher = BookBuilder::default().build().unwrap().with_clock(clock.clone()).with_event_stream();
+    let mut fills = Vec::new();
+    let mut order_id = 0;
+    while publisher.events_since(0).len() < 500 {
+        order_id += 2;
+        clock.advance(NANOS_PER_SECOND);
+        let price = 990_000 + (order_id as u64 % 7) * 10_000;
+        let ask = Order { owner: "bob".into(), ..snapshot_ask(order_id, price, 2 * SUI_DECIMALS) };
+        fills.extend(publisher.place_order_sync(ask).unwrap());
+        fills.extend(publisher.place_order_sync(alice_bid(order_id + 1, 1_050_000, 3 * SUI_DECIMALS)).unwrap());
+    }
+    let published = publisher.events_since(0);
+    assert!(published.iter().map(|event| event.sequence_number).eq(1..=published.len() as u64));
+
+    // The subscriber missed everything after sequence 250 and catches up over the wire
+    let wire = serde_json::to_string(publisher.events_since(250)).unwrap();
+    let caught_up: Vec<SequencedEvent> = serde_json::from_str(&wire).unwrap();
+    assert_eq!(caught_up.first().map(|event| event.sequence_number), Some(251));
+    assert_eq!(caught_up.len(), published.len() - 250);
+
+    let filled = |events: &[SequencedEvent]| -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match &event.event {
+                BookEvent::Filled(fill) => Some(format!("{fill:?}")),
+                _ => None,
+            })
+            .collect()
     };
+    let missed = filled(&published[..250]).len();
+    let original: Vec<String> = fills.iter().map(|fill| format!("{fill:?}")).collect();
+    assert_eq!(filled(&caught_up), original[missed..]);
 
-    // Place the maker order
-    book.place_order(maker_order);
-    
-    // Match the taker order
-    let fills = book.match_order(taker_order.clone(), 0);
-    
+    // Every batch opens with the placement that caused it
+    for batch in caught_up.chunk_by(|a, b| a.batch_id == b.batch_id).skip(1) {
+        assert!(matches!(batch[0].event, BookEvent::Placed(_)), "{batch:?}");
+    }
+    assert!(publisher.events_since(published.len() as u64).is_empty());
+}
+
+#[test]
+fn test_replay_reproduces_recorded_session() {
+    let clock = MockClock::new(0);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone()).with_event_recording();
+    let owners = ["alice", "bob", "carol", "dave"];
+    let mut fills = Vec::new();
+    let mut placed = Vec::new();
+    for i in 0..10_000u64 {
+        let now = (i + 1) * 1_000;
+        clock.set(now);
+        let mix = i.wrapping_mul(2_654_435_761) % 1_000;
+        let is_bid = mix % 2 == 0;
+        let order = Order {
+            owner: owners[(mix % 4) as usize].into(),
+            side: is_bid.into(),
+            expire_timestamp: if mix % 5 == 0 { now + 20_000 } else { u64::MAX },
+            ..alice_bid(u128::from(i), 990_000 + (mix % 21) * 1_000, (1 + mix % 7) * SUI_DECIMALS)
+        };
+        if let Ok(order_fills) = book.place_order_sync(order) {
+            fills.extend(order_fills);
+        }
+        placed.push((u128::from(i), is_bid));
+        if mix % 3 == 0 {
+            let (order_id, is_bid) = placed[(mix as usize * 7) % placed.len()];
+            book.cancel_order(order_id, is_bid);
+        }
+    }
+    book.purge_expired(10_001 * 1_000);
+    assert!(book.events().iter().any(|event| matches!(event, BookEvent::Cancelled { .. })));
+    assert!(book.events().iter().any(|event| matches!(event, BookEvent::Expired { .. })));
+
+    let (replayed, replayed_fills) = Book::replay(book.events());
+    let fill_key = |fill: &Fill| (fill.maker_order_id, fill.taker_order_id, fill.price, fill.base_quantity);
+    assert!(!fills.is_empty());
+    assert_eq!(
+        replayed_fills.iter().map(fill_key).collect::<Vec<_>>(),
+        fills.iter().map(fill_key).collect::<Vec<_>>()
+    );
+    assert_eq!(replayed.order_count(), book.order_count());
+    for is_bid in [true, false] {
+        assert_eq!(replayed.total_resting_quantity(is_bid), book.total_resting_quantity(is_bid));
+    }
+    assert_eq!(replayed.best_bid(), book.best_bid());
+    assert_eq!(replayed.best_ask(), book.best_ask());
+
+    // Recording is off by default
+    assert!(BookBuilder::default().build().unwrap().events().is_empty());
+}
+
+#[test]
+fn test_multi_book_pairs_are_independent() {
+    let sui_usdc = PairId::new("SUI", "USDC");
+    let deep_usdc = PairId::new("DEEP", "USDC");
+    let mut books = MultiBook::new();
+    assert_eq!(
+        books.place_order(sui_usdc.clone(), alice_bid(1, 990_000, SUI_DECIMALS)).unwrap_err(),
+        PairError::UnknownPair(sui_usdc.clone())
+    );
+    assert!(books.aggregate_snapshot(sui_usdc.clone(), 5).is_none());
+
+    books.get_or_create_book(sui_usdc.clone());
+    books.get_or_create_book(deep_usdc.clone());
+    books.place_order(sui_usdc.clone(), alice_bid(1, 990_000, SUI_DECIMALS)).unwrap();
+    books.place_order(deep_usdc.clone(), alice_bid(1, 20_000, 3 * SUI_DECIMALS)).unwrap();
+
+    // An ask crossing SUI's bid does not touch DEEP's book, even with a clashing order ID
+    let ask = Order { owner: "bob".into(), side: Side::Ask, ..alice_bid(2, 10_000, SUI_DECIMALS) };
+    let fills = books.place_order(sui_usdc.clone(), ask).unwrap();
     assert_eq!(fills.len(), 1);
-    assert_eq!(fills[0].base_quantity, 5 * SUI_DECIMALS);
-    assert_eq!(fills[0].quote_quantity, 25 * USDC_DECIMALS);
-    assert_eq!(fills[0].maker_order_id, 2);
-    assert_eq!(fills[0].taker_order_id, 1);
+    assert_eq!(fills[0].price, 990_000);
+    assert_eq!(books.book(&sui_usdc).unwrap().order_count(), (0, 0));
+    assert_eq!(books.book(&deep_usdc).unwrap().order_count(), (1, 0));
+
+    let deep = books.aggregate_snapshot(deep_usdc.clone(), 5).unwrap();
+    assert_eq!(deep.bids.len(), 1);
+    assert_eq!(deep.bids[0].price, 20_000);
+    assert_eq!(deep.bids[0].total_quantity, 3 * SUI_DECIMALS);
+    assert!(deep.asks.is_empty());
+    assert!(books.aggregate_snapshot(sui_usdc.clone(), 5).unwrap().bids.is_empty());
+
+    // Pair errors wrap the book's own rejection
+    let market = Order::new_market(3, SUI_DECIMALS, "bob", false).unwrap();
+    assert_eq!(books.place_order(sui_usdc, market).unwrap_err(), PairError::Place(PlaceError::Book(BookError::NoLiquidity)));
 }
 
 #[test]
-fn test_full_fill_bid() {
-    let mut book = Book::new();
-    
-    // Create a bid order: Buy 10 SUI at $5/SUI
-    let taker_order = Order {
-        order_id: 1,
-        price: 5 * USDC_DECIMALS,
-        quantity: 10 * SUI_DECIMALS,
-        filled_quantity: 0,
-        owner: "alice".to_string(),
-        expire_timestamp: u64::MAX,
-        is_bid: true,
-    };
+fn test_place_batch_matches_within_batch() {
+    let mut book = BookBuilder::default().build().unwrap();
+    let ask = Order { owner: "bob".into(), side: Side::Ask, ..alice_bid(1, USDC_DECIMALS, 2 * SUI_DECIMALS) };
+    let zero_quantity = Order { quantity: 0, ..alice_bid(2, USDC_DECIMALS, SUI_DECIMALS) };
+    let zero_price = Order { price: 0, ..alice_bid(3, USDC_DECIMALS, SUI_DECIMALS) };
+    let results = book.place_batch(
+        vec![ask, zero_quantity, zero_price, alice_bid(4, USDC_DECIMALS, 3 * SUI_DECIMALS)],
+        42,
+    );
 
-    // Create an ask order: Sell 50 SUI at $5/SUI
-    let maker_order = Order {
-        order_id: 2,
-        price: 5 * USDC_DECIMALS,
-        quantity: 50 * SUI_DECIMALS,
-        filled_quantity: 0,
-        owner: "bob".to_string(),
-        expire_timestamp: u64::MAX,
-        is_bid: false,
+    assert_eq!(results.iter().map(|result| result.order_id).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    assert!(results[0].fills.is_empty() && results[0].error.is_none());
+    assert_eq!(results[1].error, Some(PlaceError::QuantityBelowMinimum));
+    assert_eq!(results[2].error, Some(PlaceError::PriceBelowMinimum));
+
+    // The last bid fills against the ask placed earlier in the same batch and rests the rest
+    assert!(results[3].error.is_none());
+    assert_eq!(results[3].fills.len(), 1);
+    assert_eq!(results[3].fills[0].maker_order_id, 1);
+    assert_eq!(results[3].fills[0].base_quantity, 2 * SUI_DECIMALS);
+    assert_eq!(results[3].fills[0].timestamp, 42);
+    let resting = book.order_status(4, true).unwrap();
+    assert_eq!((resting.remaining_quantity(), resting.submission_timestamp), (SUI_DECIMALS, 42));
+    assert_eq!(book.order_count(), (1, 0));
+
+    let results = book.place_batch(vec![Order::new_market(5, SUI_DECIMALS, "carol", true).unwrap()], 43);
+    assert_eq!(results[0].error, Some(PlaceError::Book(BookError::NoLiquidity)));
+}
+
+#[test]
+fn test_top_of_book_changed_events() {
+    let clock = MockClock::new(0);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone());
+    let top_events = |book: &mut Book| {
+        book.drain_events()
+            .filter_map(|event| match event {
+                BookEvent::TopOfBookChanged(top) => Some(top),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
     };
+    book.place_order(alice_bid(1, 990_000, SUI_DECIMALS)).unwrap();
+    clock.set(5);
+    book.place_order(snapshot_ask(2, 1_010_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(
+        top_events(&mut book),
+        vec![
+            TopOfBookEvent { new_best_bid: Some(990_000), new_best_ask: None, timestamp: 0 },
+            TopOfBookEvent { new_best_bid: Some(990_000), new_best_ask: Some(1_010_000), timestamp: 5 },
+        ]
+    );
 
-    book.place_order(maker_order);
-    let fills = book.match_order(taker_order.clone(), 0);
-    
-    assert_eq!(fills.len(), 1);
-    assert_eq!(fills[0].base_quantity, 10 * SUI_DECIMALS);
-    assert_eq!(fills[0].quote_quantity, 50 * USDC_DECIMALS);
+    // Orders and cancellations behind the top leave no trace
+    for order_id in 0..1_000u128 {
+        let is_bid = order_id.is_multiple_of(2);
+        let price = if is_bid { 900_000 - order_id as u64 * 10 } else { 1_100_000 + order_id as u64 * 10 };
+        book.place_order(Order { side: is_bid.into(), ..alice_bid(order_id + 10, price, SUI_DECIMALS) }).unwrap();
+    }
+    for order_id in (10..1_010u128).step_by(3) {
+        book.cancel_order(order_id, order_id.is_multiple_of(2));
+    }
+    assert!(top_events(&mut book).is_empty());
+
+    // Neither do a resting order that ties the best bid or a taker that leaves it in place
+    book.place_order(alice_bid(2_000, 990_000, SUI_DECIMALS)).unwrap();
+    book.place_order(Order { side: Side::Ask, ..alice_bid(2_001, 990_000, SUI_DECIMALS) }).unwrap();
+    assert!(top_events(&mut book).is_empty());
+
+    book.cancel_order(2, false);
+    assert_eq!(top_events(&mut book)[0].new_best_ask, Some(1_100_010));
 }
 
 #[test]
-fn test_precision_matching() {
-    let mut book = Book::new();
-    
-    // Create a bid order: Buy 10.86 SUI at $1.234/SUI
-    let taker_order = Order {
-        order_id: 1,
-        price: 1_234_000,  // $1.234
-        quantity: 10_860_000_000, // 10.86 SUI
-        filled_quantity: 0,
-        owner: "alice".to_string(),
-        expire_timestamp: u64::MAX,
-        is_bid: true,
+fn test_drain_events_counts_orders_and_fills() {
+    let mut book = BookBuilder::default().event_buffer(5_000).build().unwrap();
+    let mut rng = Lcg::new(7);
+    let mut fill_count = 0;
+    for order_id in 0..1_000 {
+        let roll = rng.next_u64();
+        let is_bid = roll.is_multiple_of(2);
+        let order = Order {
+            owner: if is_bid { "alice".into() } else { "bob".into() },
+            side: is_bid.into(),
+            ..alice_bid(order_id, 990_000 + (roll >> 8) % 20 * 1_000, SUI_DECIMALS)
+        };
+        fill_count += book.place_order(order).unwrap().fill_count;
+    }
+    assert!(fill_count > 0);
+
+    let events: Vec<BookEvent> = book.drain_events().collect();
+    let top_changes = events.iter().filter(|event| matches!(event, BookEvent::TopOfBookChanged(_))).count();
+    assert!(top_changes > 0);
+    assert_eq!(events.len(), 1_000 + fill_count + top_changes);
+    assert_eq!(events.iter().filter(|event| matches!(event, BookEvent::Filled(_))).count(), fill_count);
+    assert!(matches!(&events[0], BookEvent::Placed(order) if order.order_id == 0));
+    assert_eq!(book.drain_events().count(), 0);
+    assert_eq!(book.dropped_event_count(), 0);
+
+    // Cancels are buffered too, and a full buffer drops its oldest events. The best
+    // bid level holds other orders, so the cancel leaves the top of the book alone.
+    let mut book = book.with_event_buffer_capacity(2);
+    let resting = book.iter_orders(true).next().unwrap().order_id;
+    book.cancel_order(resting, true);
+    book.place_order(alice_bid(1_000, 10_000, SUI_DECIMALS)).unwrap();
+    book.place_order(alice_bid(1_001, 10_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.dropped_event_count(), 1);
+    let ids: Vec<u128> = book
+        .drain_events()
+        .map(|event| match event {
+            BookEvent::Placed(order) => order.order_id,
+            other => panic!("unexpected event {other:?}"),
+        })
+        .collect();
+    assert_eq!(ids, vec![1_000, 1_001]);
+}
+
+#[test]
+fn test_max_fills_per_match() {
+    let makers = |book: &mut Book| {
+        for order_id in 0..150 {
+            let ask = Order { owner: "bob".into(), side: Side::Ask, ..alice_bid(order_id, USDC_DECIMALS, SUI_DECIMALS) };
+            book.place_order(ask).unwrap();
+        }
     };
+    let taker = |max_fills| Order { max_fills, ..alice_bid(1_000, USDC_DECIMALS, 150 * SUI_DECIMALS) };
 
-    // Create an ask order: Sell 10.86 SUI at $1.234/SUI
-    let maker_order = Order {
-        order_id: 2,
-        price: 1_234_000,
-        quantity: 10_860_000_000,
-        filled_quantity: 0,
-        owner: "bob".to_string(),
-        expire_timestamp: u64::MAX,
-        is_bid: false,
+    // The book-level default stops the taker after 100 fills
+    let mut book = BookBuilder::default().build().unwrap();
+    makers(&mut book);
+    let fills = book.place_order_sync(taker(None)).unwrap();
+    assert_eq!(fills.len(), 100);
+    assert_eq!(book.order_status(1_000, true).unwrap().remaining_quantity(), 50 * SUI_DECIMALS);
+    assert_eq!(book.order_count(), (1, 50));
+
+    // An order-level limit overrides the book's in either direction
+    let mut book = BookBuilder::default().build().unwrap();
+    makers(&mut book);
+    assert_eq!(book.place_order(taker(Some(150))).unwrap().fill_count, 150);
+    assert_eq!(book.order_count(), (0, 0));
+
+    let mut book = BookBuilder::default().max_fills(200).build().unwrap();
+    makers(&mut book);
+    assert_eq!(book.simulate_match(&taker(Some(100)), 0).len(), 100);
+    assert_eq!(book.place_order(taker(None)).unwrap().fill_count, 150);
+}
+
+#[test]
+fn test_two_sided_quote() {
+    let mut book = BookBuilder::default().tick_size(10_000).build().unwrap();
+    let quote = |bid_id, bid_price, ask_id, ask_price| {
+        let bid = Order { owner: "mm".into(), ..alice_bid(bid_id, bid_price, 2 * SUI_DECIMALS) };
+        (bid, Order { owner: "mm".into(), ..snapshot_ask(ask_id, ask_price, 2 * SUI_DECIMALS) })
     };
+    book.place_order(Order { owner: "carol".into(), ..snapshot_ask(1, 1_020_000, SUI_DECIMALS) }).unwrap();
+    let stop = OrderType::StopLimit { trigger_price: 1_020_000, limit_price: 1_100_000 };
+    book.place_order(Order { owner: "bob".into(), order_type: stop, ..alice_bid(2, 1_100_000, SUI_DECIMALS) }).unwrap();
 
-    book.place_order(maker_order);
-    let fills = book.match_order(taker_order.clone(), 0);
-    
-    assert_eq!(fills.len(), 1);
-    assert_eq!(fills[0].base_quantity, 10_860_000_000);
-    assert_eq!(fills[0].quote_quantity, 13_401_240); // 10.86 * $1.234
+    let (bid, ask) = quote(10, 1_050_000, 11, 1_050_000);
+    assert!(matches!(book.two_sided_quote(bid, ask, 0), Err(QuoteError::SelfCrossing)));
+    let (bid, ask) = quote(10, 1_020_000, 11, 1_055_000);
+    assert!(matches!(book.two_sided_quote(bid, ask, 0), Err(QuoteError::Invalid(PlaceError::PriceNotTickAligned))));
+    let (bid, ask) = quote(10, 1_020_000, 11, 1_050_000);
+    assert!(matches!(book.two_sided_quote(ask.clone(), bid.clone(), 0), Err(QuoteError::WrongSide)));
+    assert_eq!(book.order_count(), (0, 1));
+
+    // The bid lifts carol's ask, which triggers bob's stop above the new ask, so both
+    // sides trade half their size and rest the rest
+    let result = book.two_sided_quote(bid, ask, 0).unwrap();
+    let makers = |fills: &[Fill]| fills.iter().map(|fill| (fill.maker_order_id, fill.base_quantity)).collect::<Vec<_>>();
+    assert_eq!(makers(&result.bid_fills), vec![(1, SUI_DECIMALS)]);
+    assert_eq!(makers(&result.ask_fills), vec![(2, SUI_DECIMALS)]);
+    assert!(result.bid_resting && result.ask_resting);
+    assert_eq!((book.best_bid(), book.best_ask()), (Some(1_020_000), Some(1_050_000)));
+    assert_eq!(book.order_status(11, Side::Ask).unwrap().remaining_quantity(), SUI_DECIMALS);
 }
 
 #[test]
-fn test_multiple_fills() {
-    let mut book = Book::new();
-    
-    // Taker: ask order with quantity 10 at price $1
-    let taker_order = Order {
-        order_id: 1,
-        price: USDC_DECIMALS, // $1
-        quantity: 10 * SUI_DECIMALS,
-        filled_quantity: 0,
-        owner: "alice".to_string(),
-        expire_timestamp: u64::MAX,
-        is_bid: false,
+fn test_bulk_update() {
+    let mut book = BookBuilder::default().tick_size(10_000).build().unwrap();
+    let carol_ask = |order_id, price| Order { owner: "carol".into(), ..snapshot_ask(order_id, price, SUI_DECIMALS) };
+    for level in 0..20u64 {
+        book.place_order(carol_ask(level as u128 + 1, 1_010_000 + level * 10_000)).unwrap();
+    }
+    book.place_order(Order { owner: "bob".into(), ..alice_bid(100, USDC_DECIMALS, 3 * SUI_DECIMALS / 2) }).unwrap();
+
+    // carol moves her 20-level ladder down two ticks, through bob's bid
+    let cancels = (1..=20).map(|order_id| (order_id, OrderSide::Ask)).collect();
+    let mut places: Vec<Order> = (0..20u64).map(|level| carol_ask(level as u128 + 21, 990_000 + level * 10_000)).collect();
+    places.push(carol_ask(41, 995_000));
+    let result = book.bulk_update(cancels, places, 0);
+
+    assert_eq!(result.cancelled.iter().map(|order| order.order_id).collect::<Vec<_>>(), (1..=20).collect::<Vec<_>>());
+    let fills: Vec<(u128, u64)> = result.fills.iter().map(|fill| (fill.maker_order_id, fill.base_quantity)).collect();
+    assert_eq!(fills, vec![(100, SUI_DECIMALS), (100, SUI_DECIMALS / 2)]);
+    assert!(matches!(result.rejected.as_slice(), [(order, PlaceError::PriceNotTickAligned)] if order.order_id == 41));
+
+    // None of the old quotes traded, and the partly filled new one rests at the top
+    assert_eq!(book.order_count(), (0, 19));
+    assert_eq!(book.best_ask(), Some(USDC_DECIMALS));
+    assert_eq!(book.order_status(22, Side::Ask).unwrap().remaining_quantity(), SUI_DECIMALS / 2);
+    assert!(book.bulk_update(vec![(1, OrderSide::Ask)], Vec::new(), 0).cancelled.is_empty());
+}
+
+#[test]
+fn test_non_exhaustive_enums() {
+    // Matches written the way downstream crates must write them, with a wildcard arm
+    let mut book = BookBuilder::default().tick_size(USDC_DECIMALS / 100).build().unwrap();
+    let error = book.place_order(alice_bid(1, USDC_DECIMALS + 1, SUI_DECIMALS)).unwrap_err();
+    let rejected = match error {
+        PlaceError::PriceNotTickAligned => "tick",
+        PlaceError::Book(_) => "book",
+        _ => "other",
     };
+    assert_eq!(rejected, "tick");
 
-    // Maker1: bid order with quantity 1.001001 at price $1.001
-    let maker_order1 = Order {
-        order_id: 2,
-        price: 1_001_000,
-        quantity: 1_001_001_000,
-        filled_quantity: 0,
-        owner: "bob".to_string(),
-        expire_timestamp: u64::MAX,
-        is_bid: true,
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    let cancelled = match book.cancel_order(1, Side::Bid) {
+        CancelResult::Cancelled(order) => Some(order.order_id),
+        _ => None,
     };
+    assert_eq!(cancelled, Some(1));
 
-    // Maker2: bid order with quantity 1 at price $1
-    let maker_order2 = Order {
-        order_id: 3,
-        price: USDC_DECIMALS,
-        quantity: SUI_DECIMALS,
-        filled_quantity: 0,
-        owner: "charlie".to_string(),
-        expire_timestamp: u64::MAX,
-        is_bid: true,
+    let stop_reason = book.match_order(alice_bid(2, USDC_DECIMALS, SUI_DECIMALS), 0).stop_reason;
+    let stopped = match stop_reason {
+        StopReason::TakerFullyFilled => "filled",
+        StopReason::NoCrossableOrders => "uncrossed",
+        _ => "other",
     };
+    assert_eq!(stopped, "uncrossed");
+}
 
-    book.place_order(maker_order1);
-    book.place_order(maker_order2);
-    let fills = book.match_order(taker_order.clone(), 0);
-    
-    assert_eq!(fills.len(), 2);
-    // First fill should be at better price ($1.001)
-    assert_eq!(fills[0].base_quantity, 1_001_001_000);
-    assert_eq!(fills[0].quote_quantity, 1_002_002_001);
-    // Second fill at $1
-    assert_eq!(fills[1].base_quantity, SUI_DECIMALS);
-    assert_eq!(fills[1].quote_quantity, USDC_DECIMALS);
+#[test]
+fn test_match_stop_reasons() {
+    let bob_ask = |order_id, price| Order { owner: "bob".into(), side: Side::Ask, ..alice_bid(order_id, price, SUI_DECIMALS) };
+
+    // 101 makers at one price leave one behind once the 100-fill limit is hit
+    let clock = MockClock::new(0);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone());
+    for order_id in 1..=101 {
+        book.place_order(bob_ask(order_id, USDC_DECIMALS)).unwrap();
+    }
+    let result = book.match_order(alice_bid(200, USDC_DECIMALS, 101 * SUI_DECIMALS), 0);
+    assert_eq!((result.fills.len(), result.stop_reason), (100, StopReason::MaxFillsReached));
+
+    let result = book.match_order(alice_bid(201, USDC_DECIMALS, SUI_DECIMALS / 2), 0);
+    assert_eq!(result.stop_reason, StopReason::TakerFullyFilled);
+    let result = book.match_order(alice_bid(202, 990_000, SUI_DECIMALS), 0);
+    assert_eq!((result.fills.len(), result.stop_reason), (0, StopReason::NoCrossableOrders));
+
+    // A taker arriving after its expiry is neither matched nor rested
+    book.place_order(bob_ask(203, USDC_DECIMALS)).unwrap();
+    let expired = Order { expire_timestamp: 5, ..alice_bid(204, USDC_DECIMALS, 2 * SUI_DECIMALS) };
+    let result = book.match_order(expired.clone(), 10);
+    assert_eq!((result.fills.len(), result.stop_reason), (0, StopReason::TakerExpired));
+    clock.set(10);
+    let outcome = book.place_order(expired).unwrap();
+    assert_eq!(outcome, PlaceOutcome { fill_count: 0, stop_reason: Some(StopReason::TakerExpired) });
+    assert!(book.order_status(204, Side::Bid).is_none());
+
+    let mut book = BookBuilder::default().build().unwrap().with_circuit_breaker(USDC_DECIMALS, 500);
+    book.place_order(bob_ask(1, USDC_DECIMALS)).unwrap();
+    book.place_order(bob_ask(2, 1_100_000)).unwrap();
+    let outcome = book.place_order(alice_bid(3, 1_100_000, 2 * SUI_DECIMALS)).unwrap();
+    assert_eq!(outcome, PlaceOutcome { fill_count: 1, stop_reason: Some(StopReason::CircuitBreakerTripped) });
+
+    // Stops are held rather than matched
+    let stop = Order { order_type: OrderType::Stop { trigger_price: 1_200_000 }, ..alice_bid(4, 1_200_000, SUI_DECIMALS) };
+    assert_eq!(book.place_order(stop).unwrap().stop_reason, None);
 }
 
 #[test]
-#[should_panic]
-fn test_invalid_price() {
-    let mut book = Book::new();
-    
-    let order = Order {
-        order_id: 1,
-        price: 0, // Invalid price
-        quantity: SUI_DECIMALS,
-        filled_quantity: 0,
-        owner: "alice".to_string(),
-        expire_timestamp: u64::MAX,
-        is_bid: true,
-    };
+fn test_circuit_breaker_halts_matching() {
+    // Fills may trade at most 5% from $1
+    let mut book = BookBuilder::default().build().unwrap().with_circuit_breaker(USDC_DECIMALS, 500);
+    for (order_id, price) in [(1, USDC_DECIMALS), (2, 1_020_000), (3, 1_100_000)] {
+        let ask = Order { owner: "bob".into(), side: Side::Ask, ..alice_bid(order_id, price, SUI_DECIMALS) };
+        book.place_order(ask).unwrap();
+    }
 
-    book.place_order(order);
+    // The third level is 10% away, so matching stops with a third of the taker unmatched
+    let mut taker = alice_bid(4, 1_200_000, 3 * SUI_DECIMALS);
+    let fills = book.match_order(taker.clone(), 0).fills;
+    assert_eq!(fills.iter().map(|fill| fill.maker_order_id).collect::<Vec<_>>(), vec![1, 2]);
+    taker.filled_quantity = fills.iter().map(|fill| fill.base_quantity).sum();
+    assert_eq!(taker.remaining_quantity(), SUI_DECIMALS);
+    assert!(book.circuit_breaker().unwrap().tripped);
+    assert_eq!(book.order_count(), (0, 1));
+
+    // While tripped, crossing orders are dropped rather than resting through the asks
+    assert!(book.place_order_sync(alice_bid(5, 1_100_000, SUI_DECIMALS)).unwrap().is_empty());
+    assert_eq!(book.order_count(), (0, 1));
+    book.place_order(alice_bid(6, 990_000, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.order_count(), (1, 1));
+
+    book.reset_circuit_breaker(1_080_000);
+    let fills = book.place_order_sync(alice_bid(7, 1_100_000, SUI_DECIMALS)).unwrap();
+    assert_eq!((fills.len(), fills[0].price), (1, 1_100_000));
+    assert!(!book.circuit_breaker().unwrap().tripped);
 }
 
 #[test]
-#[should_panic]
-fn test_invalid_quantity() {
-    let mut book = Book::new();
-    
-    let order = Order {
-        order_id: 1,
-        price: USDC_DECIMALS,
-        quantity: 0, // Invalid quantity
-        filled_quantity: 0,
-        owner: "alice".to_string(),
-        expire_timestamp: u64::MAX,
-        is_bid: true,
+fn test_position_round_trip() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!(book.position_for_owner("alice".into()), None);
+
+    // alice buys 3 SUI at $1, then sells them back at the same price
+    trade_at(&mut book, 1, "bob", "alice", USDC_DECIMALS, 3 * SUI_DECIMALS);
+    assert_eq!(book.net_base("alice".into()), 3 * SUI_DECIMALS as i64);
+    assert_eq!(book.position_for_owner("alice".into()).unwrap().net_quote(), -3 * USDC_DECIMALS as i64);
+    trade_at(&mut book, 3, "alice", "carol", USDC_DECIMALS, 3 * SUI_DECIMALS);
+
+    let position = book.position_for_owner("alice".into()).unwrap();
+    assert_eq!(*position, OwnerPosition {
+        bought_quantity: 3 * SUI_DECIMALS,
+        sold_quantity: 3 * SUI_DECIMALS,
+        quote_spent: 3 * USDC_DECIMALS as u128,
+        quote_received: 3 * USDC_DECIMALS as u128,
+    });
+    assert_eq!(book.net_base("alice".into()), 0);
+    assert_eq!(position.net_quote(), 0);
+    assert_eq!(book.net_base("bob".into()), -3 * SUI_DECIMALS as i64);
+    assert_eq!(book.net_base("dave".into()), 0);
+}
+
+#[test]
+fn test_level_iterators() {
+    let mut book = BookBuilder::default().build().unwrap();
+    for (order_id, price) in [(1, 980_000), (2, 990_000), (3, 980_000), (4, 990_000), (5, 970_000)] {
+        book.place_order(alice_bid(order_id, price, SUI_DECIMALS)).unwrap();
+    }
+    for (order_id, price) in [(6, 1_020_000), (7, 1_010_000), (8, 1_010_000)] {
+        book.place_order(snapshot_ask(order_id, price, 2 * SUI_DECIMALS)).unwrap();
+    }
+
+    let level = |price, total_quantity, order_count| LevelSummary { price, total_quantity, order_count };
+    assert_eq!(book.bid_levels().collect::<Vec<_>>(), vec![
+        level(990_000, 2 * SUI_DECIMALS, 2),
+        level(980_000, 2 * SUI_DECIMALS, 2),
+        level(970_000, SUI_DECIMALS, 1),
+    ]);
+    assert_eq!(book.ask_levels().collect::<Vec<_>>(), vec![
+        level(1_010_000, 4 * SUI_DECIMALS, 2),
+        level(1_020_000, 2 * SUI_DECIMALS, 1),
+    ]);
+
+    // A cancel and a partial fill both shrink their level's aggregate
+    book.cancel_order(3, true);
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(9, 990_000, SUI_DECIMALS / 2) }).unwrap();
+    assert_eq!(book.bid_levels().take(2).collect::<Vec<_>>(), vec![
+        level(990_000, 3 * SUI_DECIMALS / 2, 2),
+        level(980_000, SUI_DECIMALS, 1),
+    ]);
+    assert_eq!(BookBuilder::default().build().unwrap().ask_levels().next(), None);
+}
+
+#[test]
+fn test_price_distribution() {
+    let mut book = BookBuilder::default().tick_size(1).lot_size(1).min_size(1).build().unwrap();
+    for (order_id, (price, quantity)) in [(100, 1), (200, 2), (300, 3)].into_iter().enumerate() {
+        book.place_order(snapshot_ask(order_id as u128 + 1, price, quantity)).unwrap();
+        book.place_order(alice_bid(order_id as u128 + 11, price / 100, quantity)).unwrap();
+    }
+    book.place_order(snapshot_ask(4, 300, 3)).unwrap();
+
+    assert_eq!(book.price_distribution(Side::Ask), BTreeMap::from([(100, 1), (200, 2), (300, 6)]));
+    assert_eq!(book.price_distribution(Side::Bid), BTreeMap::from([(1, 1), (2, 2), (3, 3)]));
+
+    // (100 * 1 + 200 * 2 + 300 * 6) / 9 and (100 * 1 + 200 * 2) / 3, rounded down
+    assert_eq!(book.quantity_weighted_average_price(Side::Ask, 3), Some(2_300 / 9));
+    assert_eq!(book.quantity_weighted_average_price(Side::Ask, 2), Some(500 / 3));
+    // Bids are weighted from the best level down: (3 * 3 + 2 * 2) / 5
+    assert_eq!(book.quantity_weighted_average_price(Side::Bid, 2), Some(13 / 5));
+    assert_eq!(book.quantity_weighted_average_price(Side::Bid, 0), None);
+    assert_eq!(BookBuilder::default().build().unwrap().quantity_weighted_average_price(Side::Ask, 3), None);
+}
+
+#[test]
+fn test_twap_oracle() {
+    let clock = MockClock::new(0);
+    let params = MarketParams { twap_window_secs: 60, ..Default::default() };
+    let mut book = Book::new_with_params(params).with_clock(clock.clone());
+    assert_eq!(book.twap(0), None);
+
+    trade_at(&mut book, 1, "bob", "carol", USDC_DECIMALS, SUI_DECIMALS);
+    clock.set(30 * NANOS_PER_SECOND);
+    trade_at(&mut book, 3, "bob", "carol", 1_020_000, SUI_DECIMALS);
+    assert_eq!(book.twap(30 * NANOS_PER_SECOND), Some(1_010_000));
+    assert_eq!(book.twap_cumulative(), USDC_DECIMALS as u128 * 30 * NANOS_PER_SECOND as u128);
+
+    // The first fill leaves the 60s window once the third arrives at 90s
+    clock.set(90 * NANOS_PER_SECOND);
+    trade_at(&mut book, 5, "bob", "carol", 1_060_000, SUI_DECIMALS);
+    assert_eq!(book.twap(90 * NANOS_PER_SECOND), Some(1_040_000));
+    assert_eq!(book.twap_cumulative(), 1_020_000 * 60 * NANOS_PER_SECOND as u128);
+    assert_eq!(book.twap(100 * NANOS_PER_SECOND), Some(1_060_000));
+    assert_eq!(book.twap(200 * NANOS_PER_SECOND), None);
+}
+
+#[test]
+fn test_rolling_volume() {
+    let clock = MockClock::new(0);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone());
+    assert_eq!(book.rolling_volume(0, 3600), (0, 0));
+
+    // 1, 2 and 3 SUI traded at $1, $2 and $3 an hour apart
+    for (hour, order_id) in [(0, 1), (1, 3), (2, 5)] {
+        clock.set(hour * 3600 * NANOS_PER_SECOND);
+        trade_at(&mut book, order_id, "bob", "carol", (hour + 1) * USDC_DECIMALS, (hour + 1) * SUI_DECIMALS);
+    }
+
+    let now = 7200 * NANOS_PER_SECOND;
+    assert_eq!(book.rolling_volume(now, 3600), (5 * SUI_DECIMALS, 13 * USDC_DECIMALS as u128));
+    assert_eq!(book.rolling_volume(now, 24 * 3600), (6 * SUI_DECIMALS, 14 * USDC_DECIMALS as u128));
+    assert_eq!(book.rolling_volume(now, 0), (3 * SUI_DECIMALS, 9 * USDC_DECIMALS as u128));
+    // Fills after the end of the window are left out
+    assert_eq!(book.rolling_volume(3600 * NANOS_PER_SECOND, 3600), (3 * SUI_DECIMALS, 5 * USDC_DECIMALS as u128));
+}
+
+#[test]
+fn test_fair_value_spread() {
+    let mut book = BookBuilder::default().tick_size(10_000).build().unwrap();
+    assert_eq!(book.fair_value_spread(USDC_DECIMALS), None);
+    book.place_order(alice_bid(1, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert_eq!(book.fair_value_spread(USDC_DECIMALS), None);
+    book.place_order(snapshot_ask(2, 1_040_000, SUI_DECIMALS)).unwrap();
+
+    // Fair value inside the spread sits above the bid and below the ask
+    let spread = book.fair_value_spread(1_010_000).unwrap();
+    assert_eq!(
+        spread,
+        FairValueSpread { distance_from_best_bid: 1, distance_from_best_ask: -3, distance_from_mid: -1 }
+    );
+
+    // The market fell to $0.95, leaving the bid stale 5 ticks above fair value
+    let spread = book.fair_value_spread(950_000).unwrap();
+    assert_eq!(
+        spread,
+        FairValueSpread { distance_from_best_bid: -5, distance_from_best_ask: -9, distance_from_mid: -7 }
+    );
+}
+
+#[test]
+fn test_export_fills() {
+    let clock = MockClock::new(0);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone());
+    let trades = [
+        (1, "bob", "carol", USDC_DECIMALS, 3),
+        (3, "dave", "carol", 2 * USDC_DECIMALS, 1),
+        (5, "bob", "erin", 1_500_000, 2),
+        (7, "dave", "erin", 500_000, 4),
+    ];
+    for (second, (order_id, maker, taker, price, quantity)) in (1..).zip(trades) {
+        clock.set(second * NANOS_PER_SECOND);
+        trade_at(&mut book, order_id, maker, taker, price, quantity * SUI_DECIMALS);
+    }
+    let makers = |filter| book.export_fills(filter).iter().map(|fill| fill.maker_order_id).collect::<Vec<_>>();
+
+    assert_eq!(makers(FillFilter::default()), vec![1, 3, 5, 7]);
+    assert_eq!(makers(FillFilter { owner: Some("bob".into()), ..FillFilter::default() }), vec![1, 5]);
+    assert_eq!(makers(FillFilter { owner: Some("erin".into()), ..FillFilter::default() }), vec![5, 7]);
+    assert_eq!(makers(FillFilter { from_timestamp: Some(2 * NANOS_PER_SECOND), ..FillFilter::default() }), vec![3, 5, 7]);
+    assert_eq!(makers(FillFilter { to_timestamp: Some(2 * NANOS_PER_SECOND), ..FillFilter::default() }), vec![1, 3]);
+    assert_eq!(makers(FillFilter { min_base_quantity: Some(3 * SUI_DECIMALS), ..FillFilter::default() }), vec![1, 7]);
+    assert_eq!(makers(FillFilter { sort_by: FillSortKey::BaseQuantity, ..FillFilter::default() }), vec![3, 5, 1, 7]);
+    assert_eq!(makers(FillFilter { sort_by: FillSortKey::Price, ..FillFilter::default() }), vec![7, 1, 5, 3]);
+
+    // Criteria are combined
+    let filter = FillFilter {
+        owner: Some("carol".into()),
+        from_timestamp: Some(2 * NANOS_PER_SECOND),
+        to_timestamp: Some(3 * NANOS_PER_SECOND),
+        ..FillFilter::default()
+    };
+    assert_eq!(makers(filter), vec![3]);
+    let filter = FillFilter {
+        owner: Some("dave".into()),
+        min_base_quantity: Some(2 * SUI_DECIMALS),
+        sort_by: FillSortKey::Price,
+        ..FillFilter::default()
     };
+    assert_eq!(makers(filter), vec![7]);
+    assert!(makers(FillFilter { from_timestamp: Some(5 * NANOS_PER_SECOND), ..FillFilter::default() }).is_empty());
+}
+
+#[test]
+fn test_expected_fill_time() {
+    let clock = MockClock::new(0);
+    let mut book = BookBuilder::default().build().unwrap().with_clock(clock.clone());
+
+    // Buyers take 1 SUI of asks every second
+    for second in 1..=10u64 {
+        clock.set(second * NANOS_PER_SECOND);
+        trade_at(&mut book, second as u128 * 2, "bob", "carol", USDC_DECIMALS, SUI_DECIMALS);
+        if second == 9 {
+            book.place_order(Order { owner: "bob".into(), ..snapshot_ask(100, 1_010_000, 3 * SUI_DECIMALS) }).unwrap();
+            book.place_order(Order { owner: "dave".into(), ..snapshot_ask(101, 1_010_000, SUI_DECIMALS) }).unwrap();
+            book.place_order(Order { owner: "bob".into(), ..alice_bid(102, 990_000, SUI_DECIMALS) }).unwrap();
+            assert_eq!(book.expected_fill_time(101, Side::Ask, 9 * NANOS_PER_SECOND), None);
+        }
+    }
+
+    // 10 SUI over 9 seconds, with 3 SUI ahead of dave's 1 SUI, is about 3.6 seconds
+    let estimate = book.expected_fill_time(101, Side::Ask, 10 * NANOS_PER_SECOND).unwrap();
+    assert!(estimate > Duration::from_secs(3) && estimate < Duration::from_secs(4), "{estimate:?}");
+    assert!(book.expected_fill_time(100, Side::Ask, 10 * NANOS_PER_SECOND).unwrap() < estimate);
+    // No seller has taken a bid yet, and dave's order is not a bid
+    assert_eq!(book.expected_fill_time(102, Side::Bid, 10 * NANOS_PER_SECOND), None);
+    assert_eq!(book.expected_fill_time(101, Side::Bid, 10 * NANOS_PER_SECOND), None);
+}
+
+#[test]
+fn test_replace_order() {
+    let mut book = BookBuilder::default().build().unwrap();
+    book.place_order(alice_bid(1, 990_000, 2 * SUI_DECIMALS)).unwrap();
+    book.place_order(Order { owner: "bob".into(), ..snapshot_ask(2, 1_010_000, 2 * SUI_DECIMALS) }).unwrap();
+
+    // A missing original leaves the replacement out of the book
+    let result = book.replace_order(7, true, alice_bid(3, 1_000_000, SUI_DECIMALS), 10);
+    assert!(matches!(result, Err(ReplaceError::NotFound)));
+    let result = book.replace_order(1, false, alice_bid(3, 1_000_000, SUI_DECIMALS), 10);
+    assert!(matches!(result, Err(ReplaceError::NotFound)));
+
+    // A replacement that does not cross rests in place of the original
+    let result = book.replace_order(1, true, alice_bid(3, 1_000_000, SUI_DECIMALS), 10).unwrap();
+    assert_eq!(result.cancelled.order_id(), 1);
+    assert!(result.fills.is_empty());
+    assert_eq!(result.resting.unwrap().price(), 1_000_000);
+    assert!(book.order_status(1, true).is_none());
 
-    book.place_order(order);
+    // An invalid replacement keeps the original
+    let duplicate = snapshot_ask(2, 1_020_000, SUI_DECIMALS);
+    assert!(matches!(
+        book.replace_order(3, true, duplicate, 20),
+        Err(ReplaceError::Invalid(PlaceError::OrderAlreadyExists))
+    ));
+    assert_eq!(book.order_count(), (1, 1));
+
+    // A replacement that crosses fills completely and leaves nothing resting
+    let result = book.replace_order(3, true, alice_bid(4, 1_010_000, 2 * SUI_DECIMALS), 30).unwrap();
+    assert_eq!(result.cancelled.order_id(), 3);
+    assert_eq!(result.cancelled.cancellation_timestamp(), Some(30));
+    assert_eq!(result.fills.len(), 1);
+    assert_eq!((result.fills[0].maker_order_id, result.fills[0].base_quantity), (2, 2 * SUI_DECIMALS));
+    assert!(result.resting.is_none());
+    assert_eq!(book.order_count(), (0, 0));
+}
+
+#[test]
+fn test_book_statistics() {
+    let mut book = BookBuilder::default().fee_schedule(FeeSchedule { maker_bps: MAKER_FEE, taker_bps: TAKER_FEE }).build().unwrap();
+    assert_eq!(*book.statistics(), BookStats::default());
+
+    trade_at(&mut book, 1, "bob", "carol", 2 * USDC_DECIMALS, 10 * SUI_DECIMALS);
+    book.place_order(alice_bid(3, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    book.cancel_order(3, true);
+    book.cancel_order(3, true);
+
+    // A repeated cancel of the same order is not counted twice
+    assert_eq!(*book.statistics(), BookStats {
+        total_orders_placed: 3,
+        total_orders_cancelled: 1,
+        total_fills: 1,
+        total_base_volume: 10 * SUI_DECIMALS as u128,
+        total_quote_volume: 20 * USDC_DECIMALS as u128,
+        total_maker_fees: 50_000_000,
+        total_taker_fees: 200_000,
+    });
+}
+
+#[test]
+fn test_ticker() {
+    let mut book = BookBuilder::default().build().unwrap();
+    assert_eq!(book.ticker(7), Ticker { timestamp: 7, ..Default::default() });
+
+    for (order_id, price) in [(1, 2), (3, 5), (5, 1), (7, 4), (9, 3)] {
+        trade_at(&mut book, order_id, "bob", "carol", price * USDC_DECIMALS, SUI_DECIMALS);
+    }
+    book.place_order(alice_bid(11, USDC_DECIMALS / 2, SUI_DECIMALS)).unwrap();
+
+    assert_eq!(book.ticker(9), Ticker {
+        open: 2 * USDC_DECIMALS,
+        high: 5 * USDC_DECIMALS,
+        low: USDC_DECIMALS,
+        last: 3 * USDC_DECIMALS,
+        volume_base: 5 * SUI_DECIMALS as u128,
+        volume_quote: 15 * USDC_DECIMALS as u128,
+        num_trades: 5,
+        best_bid: Some(USDC_DECIMALS / 2),
+        best_ask: None,
+        timestamp: 9,
+    });
+
+    // A new session starts with a fresh ticker
+    book.apply_market_reset(MarketParams::default(), 10).unwrap();
+    assert_eq!(book.ticker(11), Ticker { best_bid: Some(USDC_DECIMALS / 2), timestamp: 11, ..Default::default() });
+}
+
+#[test]
+fn test_drain_fills() {
+    let mut book = BookBuilder::default().build().unwrap();
+    for order_id in 1..=3 {
+        book.place_order(snapshot_ask(order_id, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    }
+    assert_eq!(book.drain_fills().count(), 0);
+
+    // Fills from separate placements queue up in the order they happened
+    assert_eq!(book.place_order(alice_bid(4, USDC_DECIMALS, 2 * SUI_DECIMALS)).unwrap().fill_count, 2);
+    assert_eq!(book.place_order(alice_bid(5, USDC_DECIMALS, SUI_DECIMALS)).unwrap().fill_count, 1);
+    book.place_order(snapshot_ask(6, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+
+    // A synchronous placement takes only its own fills
+    let fills = book.place_order_sync(alice_bid(7, USDC_DECIMALS, SUI_DECIMALS)).unwrap();
+    assert_eq!(fills.iter().map(|fill| fill.maker_order_id).collect::<Vec<_>>(), vec![6]);
+
+    let drained: Vec<(u128, u128)> = book.drain_fills().map(|fill| (fill.taker_order_id, fill.maker_order_id)).collect();
+    assert_eq!(drained, vec![(4, 1), (4, 2), (5, 3)]);
+    assert_eq!(book.drain_fills().count(), 0);
 }
 
 /// Measures throughput of order processing
 #[test]
 fn test_order_throughput() {
-    let mut book = Book::new();
+    let mut book = BookBuilder::default().build().unwrap();
     let num_orders = 100_000; // Number of orders to process
     let mut total_fills = 0;
+    let mut total_base_volume = 0u128;
     
     // Create a mix of bid and ask orders
     let orders: Vec<Order> = (0..num_orders)
-        .map(|i| Order {
-            order_id: i as u128,
-            price: (1_000_000 + (i % 10) * 1000) as u64, // Vary price around $1
-            quantity: 1_000_000_000, // 1 SUI
-            filled_quantity: 0,
-            owner: format!("trader_{}", i),
-            expire_timestamp: u64::MAX,
-            is_bid: i % 2 == 0, // Alternate between bids and asks
-        })
+        .map(|i| Order::new_limit(
+            i as u128,
+            (1_000_000 + (i % 10) * 1000) as u64, // Vary price around $1
+            1_000_000_000, // 1 SUI
+            format!("trader_{}", i).as_str(),
+            u64::MAX,
+            i % 2 == 0, // Alternate between bids and asks
+        ).unwrap())
         .collect();
-    
+
     let start_time = Instant::now();
     
     // Process all orders
     for order in orders {
-        let fills = book.place_order(order);
+        let fills = book.place_order_sync(order).unwrap();
         total_fills += fills.len();
+        total_base_volume += fills.iter().map(|fill| fill.base_quantity as u128).sum::<u128>();
     }
     
     let elapsed = start_time.elapsed();
@@ -242,6 +4026,75 @@ fn test_order_throughput() {
     // Basic assertions to ensure the test is meaningful
     assert!(orders_per_second > 0.0);
     assert!(total_fills > 0);
+
+    let stats = book.statistics();
+    assert_eq!(stats.total_orders_placed, num_orders as u64);
+    assert_eq!(stats.total_fills, total_fills as u64);
+    assert_eq!(stats.total_base_volume, total_base_volume);
+}
+
+/// Compares cloning orders with an `Address` owner against the same orders with a
+/// heap-allocated `String` owner, as they were laid out before `Address`
+#[test]
+#[ignore = "wall-clock comparison, run with --ignored"]
+fn test_owner_clone_throughput() {
+    let orders: Vec<Order> = (0..100_000u64)
+        .map(|i| {
+            let owner = format!("trader_{}", i);
+            Order::new_limit(i as u128, USDC_DECIMALS, SUI_DECIMALS, owner.as_str(), u64::MAX, i.is_multiple_of(2)).unwrap()
+        })
+        .collect();
+    let string_owned: Vec<(Order, String)> = orders.iter().map(|order| (order.clone(), order.owner.to_string())).collect();
+    let address_owned: Vec<(Order, Address)> = orders.iter().map(|order| (order.clone(), order.owner)).collect();
+
+    let start_time = Instant::now();
+    let cloned_with_string = string_owned.clone();
+    let string_elapsed = start_time.elapsed();
+
+    let start_time = Instant::now();
+    let cloned_with_address = address_owned.clone();
+    let address_elapsed = start_time.elapsed();
+
+    println!("Owner Clone Results:");
+    println!("Orders cloned: {}", orders.len());
+    println!("With String owners: {:.2?}", string_elapsed);
+    println!("With Address owners: {:.2?}", address_elapsed);
+
+    assert_eq!(cloned_with_string.len(), cloned_with_address.len());
+    assert!(address_elapsed < string_elapsed, "Address took {:?}, String {:?}", address_elapsed, string_elapsed);
+}
+
+/// Compares looking up an owner's orders through the owner index against scanning the book
+#[test]
+fn test_owner_lookup_throughput() {
+    let mut book = BookBuilder::default().build().unwrap();
+    let num_orders = 100_000u64;
+    let num_owners = 1_000;
+    for i in 0..num_orders {
+        let is_bid = i.is_multiple_of(2);
+        let price = if is_bid { 900_000 - (i % 100) * 1000 } else { 1_100_000 + (i % 100) * 1000 };
+        let owner = format!("trader_{}", i % num_owners);
+        book.place_order(Order::new_limit(i as u128, price, SUI_DECIMALS, owner.as_str(), u64::MAX, is_bid).unwrap()).unwrap();
+    }
+    assert_eq!(book.total_resting_quantity(true) + book.total_resting_quantity(false), num_orders * SUI_DECIMALS);
+
+    // Each of the first 100 owners has 100 resting orders
+    let owners: Vec<Address> = (0..100).map(|i| format!("trader_{}", i).as_str().into()).collect();
+    let start_time = Instant::now();
+    let indexed: usize = owners.iter().map(|&owner| book.orders_for_owner(owner).count()).sum();
+    let indexed_elapsed = start_time.elapsed();
+
+    let start_time = Instant::now();
+    let scanned: usize = owners.iter().map(|&owner| book.active_orders_for_owner(owner).count()).sum();
+    let scan_elapsed = start_time.elapsed();
+
+    println!("Owner Lookup Results:");
+    println!("Resting orders: {}, owners looked up: {}", num_orders, owners.len());
+    println!("With index: {:.2?}", indexed_elapsed);
+    println!("Without index: {:.2?}", scan_elapsed);
+
+    assert_eq!((indexed, scanned), (10_000, 10_000));
+    assert!(indexed_elapsed < scan_elapsed, "index took {:?}, scan {:?}", indexed_elapsed, scan_elapsed);
 }
 
 /// Measures throughput with varying order book depths
@@ -250,7 +4103,7 @@ fn test_throughput_with_depth() {
     let depths = vec![10, 100, 1000, 10000];
     
     for depth in depths {
-        let mut book = Book::new();
+        let mut book = BookBuilder::default().build().unwrap();
         let num_orders = depth * 2; // Process 2x the depth in orders
         let mut total_fills = 0;
         
@@ -259,48 +4112,45 @@ fn test_throughput_with_depth() {
             let base_price = 1_000_000; // $1 base price
             
             // Add asks above base price
-            let ask = Order {
-                order_id: i as u128,
-                price: base_price + (i * 100) as u64,
-                quantity: 1_000_000_000,
-                filled_quantity: 0,
-                owner: format!("seller_{}", i),
-                expire_timestamp: u64::MAX,
-                is_bid: false,
-            };
-            book.place_order(ask);
+            let ask = Order::new_limit(
+                i as u128,
+                base_price + (i * 100) as u64,
+                1_000_000_000,
+                format!("seller_{}", i).as_str(),
+                u64::MAX,
+                false,
+            ).unwrap();
+            book.place_order(ask).unwrap();
             
             // Add bids below base price
-            let bid = Order {
-                order_id: (i + depth) as u128,
-                price: base_price - (i * 100) as u64,
-                quantity: 1_000_000_000,
-                filled_quantity: 0,
-                owner: format!("buyer_{}", i),
-                expire_timestamp: u64::MAX,
-                is_bid: true,
-            };
-            book.place_order(bid);
+            let bid = Order::new_limit(
+                (i + depth) as u128,
+                base_price - (i * 100) as u64,
+                1_000_000_000,
+                format!("buyer_{}", i).as_str(),
+                u64::MAX,
+                true,
+            ).unwrap();
+            book.place_order(bid).unwrap();
         }
         
         // Create test orders that will match against the book
         let orders: Vec<Order> = (0..num_orders)
-            .map(|i| Order {
-                order_id: (i + 2 * depth) as u128,
-                price: 1_000_000 + (if i % 2 == 0 { 1000 } else { -1000 }),
-                quantity: 1_000_000_000,
-                filled_quantity: 0,
-                owner: format!("trader_{}", i),
-                expire_timestamp: u64::MAX,
-                is_bid: i % 2 == 0,
-            })
+            .map(|i| Order::new_limit(
+                (i + 2 * depth) as u128,
+                if i % 2 == 0 { 1_001_000 } else { 999_000 },
+                1_000_000_000,
+                format!("trader_{}", i).as_str(),
+                u64::MAX,
+                i % 2 == 0,
+            ).unwrap())
             .collect();
         
         let start_time = Instant::now();
         
         // Process all orders
         for order in orders {
-            let fills = book.place_order(order);
+            let fills = book.place_order_sync(order).unwrap();
             total_fills += fills.len();
         }
         
@@ -324,24 +4174,23 @@ fn test_throughput_with_depth() {
 /// Measures latency distribution of order processing
 #[test]
 fn test_order_latency_distribution() {
-    let mut book = Book::new();
+    let mut book = BookBuilder::default().build().unwrap();
     let num_orders = 10_000;
     let mut latencies = Vec::with_capacity(num_orders);
     
     // Create and process orders while measuring individual latencies
     for i in 0..num_orders {
-        let order = Order {
-            order_id: i as u128,
-            price: 1_000_000 + (i % 10) * 1000,
-            quantity: 1_000_000_000,
-            filled_quantity: 0,
-            owner: format!("trader_{}", i),
-            expire_timestamp: u64::MAX,
-            is_bid: i % 2 == 0,
-        };
+        let order = Order::new_limit(
+            i as u128,
+            1_000_000 + ((i % 10) * 1000) as u64,
+            1_000_000_000,
+            format!("trader_{}", i).as_str(),
+            u64::MAX,
+            i % 2 == 0,
+        ).unwrap();
         
         let start_time = Instant::now();
-        book.place_order(order);
+        book.place_order(order).unwrap();
         latencies.push(start_time.elapsed());
     }
     
@@ -363,3 +4212,29 @@ fn test_order_latency_distribution() {
     assert!(p99 >= p95);
     assert!(p95 >= p50);
 }
+
+/// Measures how quickly expired orders are purged from a deep book
+#[test]
+fn test_purge_expired_throughput() {
+    let mut book = BookBuilder::default().build().unwrap().with_clock(MockClock::new(0));
+    let num_orders = 10_000;
+    for i in 0..num_orders {
+        let price = 500_000 + (i % 1_000) * 100;
+        let order = Order { expire_timestamp: 1, ..alice_bid(i as u128, price, SUI_DECIMALS) };
+        let order = if i % 2 == 0 { order } else { Order { side: Side::Ask, price: price + 600_000, ..order } };
+        book.place_order(order).unwrap();
+    }
+
+    let start_time = Instant::now();
+    let purged = book.purge_expired(2);
+    let elapsed = start_time.elapsed();
+    let orders_per_second = num_orders as f64 / elapsed.as_secs_f64();
+
+    println!("Purge Throughput Test Results:");
+    println!("Expired orders purged: {}", purged.len());
+    println!("Time elapsed: {:.2?}", elapsed);
+    println!("Orders purged per second: {:.2}", orders_per_second);
+
+    assert_eq!(purged.len(), num_orders as usize);
+    assert_eq!(book.order_count(), (0, 0));
+}
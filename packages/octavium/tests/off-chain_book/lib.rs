@@ -1 +1,5 @@
-pub mod book;
\ No newline at end of file
+#[path = "../../src/off-chain_book/book.rs"]
+pub mod book;
+
+#[cfg(test)]
+mod book_tests;